@@ -0,0 +1,49 @@
+//! The keys of the Jellyfish Merkle Tree whose root is the app hash, and verification of proofs
+//! against it.
+
+use anyhow::{anyhow, Result};
+use jmt::{
+    define_hasher,
+    hash::{CryptoHasher, HashValue},
+    proof::SparseMerkleProof,
+};
+use once_cell::sync::{Lazy, OnceCell};
+use penumbra_crypto::merkle;
+
+/// A key of the Jellyfish Merkle Tree, each of which is hashed with its own domain separator.
+pub enum Key {
+    /// The root of the note commitment tree after the block.
+    NoteCommitmentAnchor,
+}
+
+impl Key {
+    pub fn hash(self) -> HashValue {
+        match self {
+            Key::NoteCommitmentAnchor => {
+                let mut state = NoteCommitmentAnchorHasher::default();
+                state.update(b"");
+                state.finish()
+            }
+        }
+    }
+}
+
+define_hasher! {
+    (
+        NoteCommitmentAnchorHasher,
+        NOTE_COMMITMENT_ANCHOR_HASHER,
+        NOTE_COMMITMENT_ANCHOR_SEED,
+        b"nct"
+    )
+}
+
+/// Verifies a bincode-encoded JMT proof that the note commitment tree anchor committed in the
+/// given app hash is `anchor`.
+pub fn verify_anchor_proof(app_hash: &[u8], anchor: &merkle::Root, proof: &[u8]) -> Result<()> {
+    let app_hash =
+        HashValue::from_slice(app_hash).map_err(|_| anyhow!("app hash must be 32 bytes"))?;
+    let proof: SparseMerkleProof<merkle::Root> = bincode::deserialize(proof)?;
+    proof
+        .verify(app_hash, Key::NoteCommitmentAnchor.hash(), Some(anchor))
+        .map_err(|e| anyhow!("invalid note commitment anchor proof: {}", e))
+}
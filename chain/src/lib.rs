@@ -1 +1,2 @@
+pub mod app_hash;
 pub mod params;
@@ -3,9 +3,14 @@ use std::io::{Cursor, Read, Write};
 use ark_serialize::CanonicalDeserialize;
 use f4jumble::{f4jumble, f4jumble_inv};
 use penumbra_proto::{crypto as pb, serializers::bech32str};
+use rand_core::{CryptoRng, RngCore};
 use serde::{Deserialize, Serialize};
 
-use crate::{fmd, ka, keys::Diversifier, Fq};
+use crate::{
+    fmd, ka,
+    keys::{Diversifier, SpendKey, SpendSeed},
+    Fq,
+};
 
 // We pad addresses to 80 bytes (before jumbling and Bech32m encoding)
 // using this 5 byte padding.
@@ -57,6 +62,20 @@ impl Address {
         }
     }
 
+    /// Generates a valid address for a fresh, random spend key which is immediately discarded.
+    ///
+    /// Notes sent to such an address can't be received by anyone, which makes it the destination
+    /// of dummy outputs.
+    pub fn dummy<R: RngCore + CryptoRng>(rng: &mut R) -> Self {
+        let mut seed = [0u8; 32];
+        rng.fill_bytes(&mut seed);
+        let spend_key = SpendKey::new(SpendSeed(seed));
+        let (address, _dtk) = spend_key
+            .incoming_viewing_key()
+            .payment_address(rng.next_u64().into());
+        address
+    }
+
     pub fn diversifier(&self) -> &Diversifier {
         &self.d
     }
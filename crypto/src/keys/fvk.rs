@@ -1,6 +1,8 @@
+use anyhow::anyhow;
 use ark_ff::PrimeField;
 use decaf377::FieldExt;
 use once_cell::sync::Lazy;
+use penumbra_proto::serializers::bech32str;
 
 use super::{DiversifierKey, IncomingViewingKey, NullifierKey, OutgoingViewingKey};
 use crate::{
@@ -9,6 +11,8 @@ use crate::{
     Fq, Fr, Nullifier,
 };
 
+pub const FVK_LEN_BYTES: usize = 64;
+
 static IVK_DOMAIN_SEP: Lazy<Fq> = Lazy::new(|| Fq::from_le_bytes_mod_order(b"penumbra.derive.ivk"));
 
 /// The `FullViewingKey` allows one to identify incoming and outgoing notes only.
@@ -76,4 +80,91 @@ impl FullViewingKey {
     pub fn spend_verification_key(&self) -> &VerificationKey<SpendAuth> {
         &self.ak
     }
+
+    /// Encodes the full viewing key as its spend verification key followed by its nullifier key.
+    pub fn to_bytes(&self) -> [u8; FVK_LEN_BYTES] {
+        let mut bytes = [0; FVK_LEN_BYTES];
+        bytes[0..32].copy_from_slice(self.ak.as_ref());
+        bytes[32..64].copy_from_slice(&self.nk.0.to_bytes());
+        bytes
+    }
+}
+
+impl TryFrom<&[u8]> for FullViewingKey {
+    type Error = anyhow::Error;
+
+    fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
+        if bytes.len() != FVK_LEN_BYTES {
+            return Err(anyhow!(
+                "full viewing key must be {} bytes long",
+                FVK_LEN_BYTES
+            ));
+        }
+
+        let ak_bytes: [u8; 32] = bytes[0..32].try_into().expect("slice is 32 bytes long");
+        let ak = ak_bytes
+            .try_into()
+            .map_err(|_| anyhow!("invalid spend verification key"))?;
+        let nk = Fq::from_bytes(bytes[32..64].try_into().expect("slice is 32 bytes long"))
+            .map_err(|_| anyhow!("invalid nullifier key"))?;
+
+        Ok(Self::from_components(ak, NullifierKey(nk)))
+    }
+}
+
+impl std::fmt::Display for FullViewingKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(&bech32str::encode(
+            &self.to_bytes(),
+            bech32str::full_viewing_key::BECH32_PREFIX,
+            bech32str::Bech32m,
+        ))
+    }
+}
+
+impl std::str::FromStr for FullViewingKey {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        bech32str::decode(
+            s,
+            bech32str::full_viewing_key::BECH32_PREFIX,
+            bech32str::Bech32m,
+        )?
+        .as_slice()
+        .try_into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand_core::OsRng;
+
+    use super::*;
+    use crate::keys::{SeedPhrase, SpendKey, SpendSeed};
+
+    #[test]
+    fn full_viewing_key_bytes_round_trip() {
+        let sk = SpendKey::new(SpendSeed::from_seed_phrase(SeedPhrase::generate(OsRng), 0));
+        let fvk = sk.full_viewing_key();
+
+        let decoded = FullViewingKey::try_from(&fvk.to_bytes()[..]).unwrap();
+        assert_eq!(decoded.to_bytes(), fvk.to_bytes());
+        assert_eq!(
+            decoded.incoming().payment_address(0u64.into()).0,
+            fvk.incoming().payment_address(0u64.into()).0
+        );
+    }
+
+    #[test]
+    fn full_viewing_key_string_round_trip() {
+        let sk = SpendKey::new(SpendSeed::from_seed_phrase(SeedPhrase::generate(OsRng), 0));
+        let fvk = sk.full_viewing_key();
+
+        let encoded = fvk.to_string();
+        assert!(encoded.starts_with(bech32str::full_viewing_key::BECH32_PREFIX));
+        let decoded: FullViewingKey = encoded.parse().unwrap();
+        assert_eq!(decoded.to_bytes(), fvk.to_bytes());
+        assert!(encoded[1..].parse::<FullViewingKey>().is_err());
+    }
 }
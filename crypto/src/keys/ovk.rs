@@ -1,6 +1,43 @@
+use std::convert::TryFrom;
+
+use anyhow::anyhow;
+use rand_core::{CryptoRng, RngCore};
+
 pub const OVK_LEN_BYTES: usize = 32;
 
 /// Allows viewing outgoing notes, i.e., notes sent from the spending key this
 /// key is derived from.
 #[derive(Clone, Debug)]
 pub struct OutgoingViewingKey(pub(crate) [u8; OVK_LEN_BYTES]);
+
+impl OutgoingViewingKey {
+    /// Generate a random outgoing viewing key, which belongs to no spending key.
+    ///
+    /// Dummy outputs wrap their keys to such a key, so that nobody can recover them.
+    pub fn dummy<R: RngCore + CryptoRng>(rng: &mut R) -> Self {
+        let mut bytes = [0u8; OVK_LEN_BYTES];
+        rng.fill_bytes(&mut bytes);
+        OutgoingViewingKey(bytes)
+    }
+}
+
+impl AsRef<[u8; OVK_LEN_BYTES]> for OutgoingViewingKey {
+    fn as_ref(&self) -> &[u8; OVK_LEN_BYTES] {
+        &self.0
+    }
+}
+
+impl TryFrom<&[u8]> for OutgoingViewingKey {
+    type Error = anyhow::Error;
+
+    fn try_from(slice: &[u8]) -> Result<OutgoingViewingKey, Self::Error> {
+        let bytes: [u8; OVK_LEN_BYTES] = slice.try_into().map_err(|_| {
+            anyhow!(
+                "outgoing viewing key must be {} bytes, got {:?}",
+                OVK_LEN_BYTES,
+                slice.len()
+            )
+        })?;
+        Ok(OutgoingViewingKey(bytes))
+    }
+}
@@ -15,6 +15,7 @@ pub const NUM_BITS_PER_WORD: usize = 11;
 pub const NUM_BITS_PER_BYTE: usize = 8;
 
 /// A mnemonic seed phrase. Used to generate [`SpendSeed`]s.
+#[derive(Clone, Debug)]
 pub struct SeedPhrase(pub [String; NUM_WORDS]);
 
 impl SeedPhrase {
@@ -12,6 +12,7 @@ pub mod memo;
 pub mod merkle;
 pub mod note;
 mod nullifier;
+mod payment_request;
 mod prf;
 pub mod proofs;
 pub mod value;
@@ -19,6 +20,7 @@ pub mod value;
 pub use address::Address;
 pub use note::Note;
 pub use nullifier::Nullifier;
+pub use payment_request::{PaymentRequest, PAYMENT_REQUEST_SCHEME};
 pub use value::Value;
 
 // Temporary for v0 to v1 testnet address migration.
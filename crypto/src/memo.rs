@@ -7,7 +7,12 @@ use chacha20poly1305::{
 };
 use once_cell::sync::Lazy;
 
-use crate::{ka, keys::IncomingViewingKey, note::derive_symmetric_key, Address};
+use crate::{
+    ka,
+    keys::{IncomingViewingKey, OutgoingViewingKey},
+    note::{self, derive_symmetric_key},
+    value, Address,
+};
 
 pub const MEMO_CIPHERTEXT_LEN_BYTES: usize = 528;
 
@@ -44,7 +49,24 @@ impl TryFrom<String> for MemoPlaintext {
     }
 }
 
+impl std::fmt::Display for MemoPlaintext {
+    /// Formats the memo as text, dropping the zero padding and replacing any invalid UTF-8.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let len = self
+            .0
+            .iter()
+            .rposition(|&byte| byte != 0)
+            .map_or(0, |last| last + 1);
+        write!(f, "{}", String::from_utf8_lossy(&self.0[..len]))
+    }
+}
+
 impl MemoPlaintext {
+    /// Returns `true` if the memo is all zeros, i.e. no memo was set.
+    pub fn is_empty(&self) -> bool {
+        self.0.iter().all(|&byte| byte == 0)
+    }
+
     /// Encrypt a memo, returning its ciphertext.
     pub fn encrypt(&self, esk: &ka::Secret, address: &Address) -> MemoCiphertext {
         let epk = esk.diversified_public(address.diversified_generator());
@@ -77,30 +99,64 @@ impl MemoPlaintext {
             .key_agreement_with(epk)
             .map_err(|_| anyhow!("could not perform key agreement"))?;
 
-        let key = derive_symmetric_key(&shared_secret, epk);
-        let cipher = ChaCha20Poly1305::new(Key::from_slice(key.as_bytes()));
-        let nonce = Nonce::from_slice(&*MEMO_ENCRYPTION_NONCE);
-        let plaintext = cipher
-            .decrypt(nonce, ciphertext.0.as_ref())
-            .map_err(|_| anyhow!("decryption error"))?;
+        decrypt_with_shared_secret(ciphertext, &shared_secret, epk)
+    }
 
-        let plaintext_bytes: [u8; MEMO_LEN_BYTES] = plaintext
-            .try_into()
-            .map_err(|_| anyhow!("could not fit plaintext into memo size"))?;
+    /// Decrypt a `MemoCiphertext` using the sender's outgoing viewing key, given the wrapped key
+    /// and the note and value commitments of the output it was attached to.
+    pub fn decrypt_outgoing(
+        ciphertext: MemoCiphertext,
+        wrapped_ovk: &[u8],
+        cm: note::Commitment,
+        cv: value::Commitment,
+        ovk: &OutgoingViewingKey,
+        epk: &ka::Public,
+    ) -> Result<MemoPlaintext, anyhow::Error> {
+        let (transmission_key, esk, _) =
+            note::unwrap_outgoing_key(wrapped_ovk, cm, cv, ovk, epk)
+                .map_err(|_| anyhow!("could not unwrap the outgoing key"))?;
+        let shared_secret = esk
+            .key_agreement_with(&transmission_key)
+            .map_err(|_| anyhow!("could not perform key agreement"))?;
 
-        Ok(MemoPlaintext(plaintext_bytes))
+        decrypt_with_shared_secret(ciphertext, &shared_secret, epk)
     }
 }
 
+/// Decrypt a `MemoCiphertext` given the shared secret established with its recipient.
+fn decrypt_with_shared_secret(
+    ciphertext: MemoCiphertext,
+    shared_secret: &ka::SharedSecret,
+    epk: &ka::Public,
+) -> Result<MemoPlaintext, anyhow::Error> {
+    let key = derive_symmetric_key(shared_secret, epk);
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(key.as_bytes()));
+    let nonce = Nonce::from_slice(&*MEMO_ENCRYPTION_NONCE);
+    let plaintext = cipher
+        .decrypt(nonce, ciphertext.0.as_ref())
+        .map_err(|_| anyhow!("decryption error"))?;
+
+    let plaintext_bytes: [u8; MEMO_LEN_BYTES] = plaintext
+        .try_into()
+        .map_err(|_| anyhow!("could not fit plaintext into memo size"))?;
+
+    Ok(MemoPlaintext(plaintext_bytes))
+}
+
 #[derive(Clone, Debug)]
 pub struct MemoCiphertext(pub [u8; MEMO_CIPHERTEXT_LEN_BYTES]);
 
 #[cfg(test)]
 mod tests {
+    use ark_ff::UniformRand;
     use rand_core::OsRng;
 
     use super::*;
-    use crate::keys::{SeedPhrase, SpendKey, SpendSeed};
+    use crate::{
+        asset,
+        keys::{SeedPhrase, SpendKey, SpendSeed},
+        Fr, Note, Value,
+    };
 
     #[test]
     fn test_memo_encryption_and_decryption() {
@@ -127,4 +183,65 @@ mod tests {
 
         assert_eq!(plaintext, memo);
     }
+
+    #[test]
+    fn test_memo_recovery_with_ovk() {
+        let mut rng = OsRng;
+
+        let sender_seed = SpendSeed::from_seed_phrase(SeedPhrase::generate(&mut rng), 0);
+        let sender_sk = SpendKey::new(sender_seed);
+        let ovk = sender_sk.outgoing_viewing_key();
+
+        let recipient_seed = SpendSeed::from_seed_phrase(SeedPhrase::generate(&mut rng), 0);
+        let recipient_sk = SpendKey::new(recipient_seed);
+        let (dest, _dtk_d) = recipient_sk
+            .full_viewing_key()
+            .incoming()
+            .payment_address(0u64.into());
+
+        let value = Value {
+            amount: 10,
+            asset_id: asset::REGISTRY.parse_denom("upenumbra").unwrap().id(),
+        };
+        let note = Note::generate(&mut rng, &dest, value);
+        let esk = ka::Secret::new(&mut rng);
+        let cv = value.commit(Fr::rand(&mut rng));
+        let wrapped_ovk = note.encrypt_key(&esk, ovk, cv, dest.clue_key());
+        let epk = esk.diversified_public(dest.diversified_generator());
+
+        let memo = MemoPlaintext::try_from("Hi".to_string()).unwrap();
+        let plaintext = MemoPlaintext::decrypt_outgoing(
+            memo.encrypt(&esk, &dest),
+            &wrapped_ovk,
+            note.commit(),
+            cv,
+            ovk,
+            &epk,
+        )
+        .expect("can recover memo with ovk");
+        assert_eq!(plaintext, memo);
+
+        // Another wallet's OVK can't recover the memo.
+        let other_seed = SpendSeed::from_seed_phrase(SeedPhrase::generate(&mut rng), 0);
+        let other_sk = SpendKey::new(other_seed);
+        assert!(MemoPlaintext::decrypt_outgoing(
+            memo.encrypt(&esk, &dest),
+            &wrapped_ovk,
+            note.commit(),
+            cv,
+            other_sk.outgoing_viewing_key(),
+            &epk
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_memo_text_round_trip() {
+        let memo = MemoPlaintext::try_from("Hi".to_string()).unwrap();
+        assert_eq!(memo.to_string(), "Hi");
+        assert!(!memo.is_empty());
+
+        assert_eq!(MemoPlaintext::default().to_string(), "");
+        assert!(MemoPlaintext::default().is_empty());
+    }
 }
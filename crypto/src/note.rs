@@ -14,14 +14,17 @@ use serde::{Deserialize, Serialize};
 use thiserror;
 
 use crate::{
-    asset, ka,
+    asset, fmd, ka,
     keys::{Diversifier, IncomingViewingKey, OutgoingViewingKey},
-    value, Fq, Value,
+    value, Address, Fq, Value,
 };
 
 pub const NOTE_LEN_BYTES: usize = 116;
 pub const NOTE_CIPHERTEXT_BYTES: usize = 132;
-pub const OVK_WRAPPED_LEN_BYTES: usize = 80;
+/// The length of a wrapped outgoing cipher key, in version 1 of its format, which also wraps the
+/// clue key of the recipient address. Version 0 wrapped keys were 80 bytes, and aren't supported:
+/// the change of length is a consensus change, deployed with a new chain.
+pub const OVK_WRAPPED_LEN_BYTES: usize = 112;
 
 /// The nonce used for note encryption.
 pub static NOTE_ENCRYPTION_NONCE: Lazy<[u8; 12]> = Lazy::new(|| [0u8; 12]);
@@ -150,33 +153,26 @@ impl Note {
     }
 
     /// Generate encrypted outgoing cipher key for use with this note.
+    ///
+    /// The wrapped key contains the transmission key, the ephemeral secret key, and the clue key
+    /// of the recipient address, so that the holder of the `ovk` can recover both the note and the
+    /// address it was sent to.
     pub fn encrypt_key(
         &self,
         esk: &ka::Secret,
         ovk: &OutgoingViewingKey,
         cv: value::Commitment,
+        ck_d: &fmd::ClueKey,
     ) -> [u8; OVK_WRAPPED_LEN_BYTES] {
-        let cv_bytes: [u8; 32] = cv.into();
-        let cm_bytes: [u8; 32] = self.commit().into();
         let epk = esk.diversified_public(&self.diversified_generator());
-
-        // Use Blake2b-256 to derive an encryption key `ock` from the value commitment,
-        // note commitment, the ephemeral public key, and the outgoing viewing key.
-        let mut kdf_params = blake2b_simd::Params::new();
-        kdf_params.hash_length(32);
-        let mut kdf = kdf_params.to_state();
-        kdf.update(&ovk.0);
-        kdf.update(&cv_bytes);
-        kdf.update(&cm_bytes);
-        kdf.update(&epk.0);
-        let kdf_output = kdf.finalize();
-        let ock = Key::from_slice(kdf_output.as_bytes());
+        let ock = derive_outgoing_cipher_key(ovk, cv, self.commit(), &epk);
 
         let mut op = Vec::new();
         op.extend_from_slice(&self.transmission_key().0);
         op.extend_from_slice(&esk.to_bytes());
+        op.extend_from_slice(&ck_d.0);
 
-        let cipher = ChaCha20Poly1305::new(ock);
+        let cipher = ChaCha20Poly1305::new(Key::from_slice(ock.as_bytes()));
         let nonce = Nonce::from_slice(&*NOTE_ENCRYPTION_NONCE);
 
         let encryption_result = cipher
@@ -196,27 +192,50 @@ impl Note {
         ivk: &IncomingViewingKey,
         epk: &ka::Public,
     ) -> Result<Note, Error> {
-        if ciphertext.len() != NOTE_CIPHERTEXT_BYTES {
-            return Err(Error::DecryptionError);
-        }
-
         let shared_secret = ivk
             .key_agreement_with(epk)
             .map_err(|_| Error::DecryptionError)?;
 
-        let key = derive_symmetric_key(&shared_secret, epk);
-        let cipher = ChaCha20Poly1305::new(Key::from_slice(key.as_bytes()));
-        let nonce = Nonce::from_slice(&[0u8; 12]);
-        let plaintext = cipher
-            .decrypt(nonce, ciphertext.as_ref())
+        decrypt_with_shared_secret(ciphertext, &shared_secret, epk)
+    }
+
+    /// Decrypt a note ciphertext using the sender's outgoing viewing key, recovering both the
+    /// plaintext `Note` and the `Address` it was sent to.
+    ///
+    /// The note commitment `cm` and value commitment `cv` of the output are needed to derive the
+    /// outgoing cipher key, and the recovered note is checked against `cm`.
+    pub fn decrypt_outgoing(
+        ciphertext: &[u8],
+        wrapped_ovk: &[u8],
+        cm: Commitment,
+        cv: value::Commitment,
+        ovk: &OutgoingViewingKey,
+        epk: &ka::Public,
+    ) -> Result<(Note, Address), Error> {
+        let (transmission_key, esk, clue_key) = unwrap_outgoing_key(wrapped_ovk, cm, cv, ovk, epk)?;
+
+        let shared_secret = esk
+            .key_agreement_with(&transmission_key)
             .map_err(|_| Error::DecryptionError)?;
+        let note = decrypt_with_shared_secret(ciphertext, &shared_secret, epk)?;
 
-        let plaintext_bytes: [u8; NOTE_LEN_BYTES] =
-            plaintext.try_into().map_err(|_| Error::DecryptionError)?;
+        // Check that the wrapped key is consistent with the note it was published alongside.
+        if note.commit() != cm
+            || note.transmission_key() != transmission_key
+            || esk.diversified_public(&note.diversified_generator()) != *epk
+        {
+            return Err(Error::DecryptionError);
+        }
 
-        plaintext_bytes
-            .try_into()
-            .map_err(|_| Error::DecryptionError)
+        let address = Address::from_components(
+            note.diversifier(),
+            note.diversified_generator(),
+            transmission_key,
+            clue_key,
+        )
+        .ok_or(Error::InvalidTransmissionKey)?;
+
+        Ok((note, address))
     }
 
     pub fn commit(&self) -> Commitment {
@@ -247,6 +266,80 @@ pub(crate) fn derive_symmetric_key(
     kdf.finalize()
 }
 
+/// Use Blake2b-256 to derive an encryption key `ock` from the value commitment, note commitment,
+/// the ephemeral public key, and the outgoing viewing key.
+fn derive_outgoing_cipher_key(
+    ovk: &OutgoingViewingKey,
+    cv: value::Commitment,
+    cm: Commitment,
+    epk: &ka::Public,
+) -> blake2b_simd::Hash {
+    let cv_bytes: [u8; 32] = cv.into();
+    let cm_bytes: [u8; 32] = cm.into();
+
+    let mut kdf_params = blake2b_simd::Params::new();
+    kdf_params.hash_length(32);
+    let mut kdf = kdf_params.to_state();
+    kdf.update(&ovk.0);
+    kdf.update(&cv_bytes);
+    kdf.update(&cm_bytes);
+    kdf.update(&epk.0);
+
+    kdf.finalize()
+}
+
+/// Unwrap the key of an output with the sender's outgoing viewing key, returning the transmission
+/// key of the recipient, the ephemeral secret key and the clue key of the recipient.
+pub(crate) fn unwrap_outgoing_key(
+    wrapped_ovk: &[u8],
+    cm: Commitment,
+    cv: value::Commitment,
+    ovk: &OutgoingViewingKey,
+    epk: &ka::Public,
+) -> Result<(ka::Public, ka::Secret, fmd::ClueKey), Error> {
+    if wrapped_ovk.len() != OVK_WRAPPED_LEN_BYTES {
+        return Err(Error::DecryptionError);
+    }
+
+    let ock = derive_outgoing_cipher_key(ovk, cv, cm, epk);
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(ock.as_bytes()));
+    let nonce = Nonce::from_slice(&*NOTE_ENCRYPTION_NONCE);
+    let op = cipher
+        .decrypt(nonce, wrapped_ovk)
+        .map_err(|_| Error::DecryptionError)?;
+
+    let transmission_key = ka::Public::try_from(&op[0..32]).map_err(|_| Error::DecryptionError)?;
+    let esk = ka::Secret::try_from(&op[32..64]).map_err(|_| Error::DecryptionError)?;
+    let clue_key = fmd::ClueKey(op[64..96].try_into().map_err(|_| Error::DecryptionError)?);
+
+    Ok((transmission_key, esk, clue_key))
+}
+
+/// Decrypt a note ciphertext given the shared secret established with its recipient.
+fn decrypt_with_shared_secret(
+    ciphertext: &[u8],
+    shared_secret: &ka::SharedSecret,
+    epk: &ka::Public,
+) -> Result<Note, Error> {
+    if ciphertext.len() != NOTE_CIPHERTEXT_BYTES {
+        return Err(Error::DecryptionError);
+    }
+
+    let key = derive_symmetric_key(shared_secret, epk);
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(key.as_bytes()));
+    let nonce = Nonce::from_slice(&*NOTE_ENCRYPTION_NONCE);
+    let plaintext = cipher
+        .decrypt(nonce, ciphertext.as_ref())
+        .map_err(|_| Error::DecryptionError)?;
+
+    let plaintext_bytes: [u8; NOTE_LEN_BYTES] =
+        plaintext.try_into().map_err(|_| Error::DecryptionError)?;
+
+    plaintext_bytes
+        .try_into()
+        .map_err(|_| Error::DecryptionError)
+}
+
 impl From<&Note> for [u8; NOTE_LEN_BYTES] {
     fn from(note: &Note) -> [u8; NOTE_LEN_BYTES] {
         let mut bytes = [0u8; NOTE_LEN_BYTES];
@@ -344,6 +437,14 @@ mod test_serde {
         assert_eq!(commitment, deserialized);
     }
 
+    #[test]
+    fn roundtrip_string_zero() {
+        let commitment = Commitment::try_from([0; 32]).unwrap();
+        let string = commitment.to_string();
+        let parsed: Commitment = string.parse().unwrap();
+        assert_eq!(commitment, parsed);
+    }
+
     #[test]
     fn roundtrip_bincode_zero() {
         let commitment = Commitment::try_from([0; 32]).unwrap();
@@ -384,6 +485,21 @@ impl std::fmt::Debug for Commitment {
     }
 }
 
+impl std::fmt::Display for Commitment {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&hex::encode(&self.0.to_bytes()[..]))
+    }
+}
+
+impl std::str::FromStr for Commitment {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bytes = hex::decode(s)?;
+        Ok(Commitment::try_from(&bytes[..])?)
+    }
+}
+
 impl Commitment {
     pub fn new(
         note_blinding: Fq,
@@ -509,4 +625,52 @@ mod tests {
 
         assert!(Note::decrypt(&ciphertext, ivk2, &epk).is_err());
     }
+
+    #[test]
+    fn test_note_recovery_with_ovk() {
+        let mut rng = OsRng;
+
+        let sender_seed = SpendSeed::from_seed_phrase(SeedPhrase::generate(&mut rng), 0);
+        let sender_sk = SpendKey::new(sender_seed);
+        let ovk = sender_sk.outgoing_viewing_key();
+
+        let recipient_seed = SpendSeed::from_seed_phrase(SeedPhrase::generate(&mut rng), 0);
+        let recipient_sk = SpendKey::new(recipient_seed);
+        let (dest, _dtk_d) = recipient_sk
+            .full_viewing_key()
+            .incoming()
+            .payment_address(0u64.into());
+
+        let value = Value {
+            amount: 10,
+            asset_id: asset::REGISTRY.parse_denom("upenumbra").unwrap().id(),
+        };
+        let note = Note::generate(&mut rng, &dest, value);
+        let esk = ka::Secret::new(&mut rng);
+        let cv = value.commit(crate::Fr::rand(&mut rng));
+
+        let ciphertext = note.encrypt(&esk);
+        let wrapped_ovk = note.encrypt_key(&esk, ovk, cv, dest.clue_key());
+        let epk = esk.diversified_public(dest.diversified_generator());
+
+        let (recovered_note, recovered_address) =
+            Note::decrypt_outgoing(&ciphertext, &wrapped_ovk, note.commit(), cv, ovk, &epk)
+                .expect("can recover note with ovk");
+
+        assert_eq!(recovered_note, note);
+        assert_eq!(recovered_address, dest);
+
+        // Another wallet's OVK can't recover the note.
+        let other_seed = SpendSeed::from_seed_phrase(SeedPhrase::generate(&mut rng), 0);
+        let other_sk = SpendKey::new(other_seed);
+        assert!(Note::decrypt_outgoing(
+            &ciphertext,
+            &wrapped_ovk,
+            note.commit(),
+            cv,
+            other_sk.outgoing_viewing_key(),
+            &epk
+        )
+        .is_err());
+    }
 }
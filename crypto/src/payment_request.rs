@@ -0,0 +1,263 @@
+//! Payment requests, encoded as `penumbra:` URIs.
+//!
+//! A payment request has the form
+//!
+//! ```text
+//! penumbra:<address>[?<parameter>=<value>[&<parameter>=<value>...]]
+//! ```
+//!
+//! where the address is a bech32m-encoded [`Address`], and the optional parameters are:
+//!
+//! - `amount`: a typed value such as `10penumbra`, parsed with the units of the asset
+//!   [`REGISTRY`](asset::REGISTRY). It may be repeated to request several assets, but each asset
+//!   only once.
+//! - `memo`: the text of the memo to attach to the payment.
+//! - `label`: a human-readable description of the request, such as the name of the recipient.
+//!
+//! Parameter values are percent-encoded UTF-8. Unknown parameters are ignored, unless their name
+//! starts with `req-`, which marks them as required to understand the request.
+
+use std::{fmt::Write, str::FromStr};
+
+use anyhow::anyhow;
+
+use crate::{asset, memo::MEMO_LEN_BYTES, Address, Value};
+
+/// The URI scheme of payment requests.
+pub const PAYMENT_REQUEST_SCHEME: &str = "penumbra";
+
+/// A request for a payment to an address, optionally of particular amounts and with a memo.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PaymentRequest {
+    /// The address to pay.
+    pub address: Address,
+    /// The amounts requested, at most one of each asset. If empty, the payer chooses the amount.
+    pub amounts: Vec<Value>,
+    /// The text of the memo to attach to the payment, if any.
+    pub memo: Option<String>,
+    /// A human-readable description of the request, if any.
+    pub label: Option<String>,
+}
+
+impl PaymentRequest {
+    /// Creates a request for a payment of any amount to the given address.
+    pub fn new(address: Address) -> Self {
+        Self {
+            address,
+            amounts: Vec::new(),
+            memo: None,
+            label: None,
+        }
+    }
+
+    /// Formats the request as a `penumbra:` URI.
+    ///
+    /// Each amount is written in the unit of its denomination best suited to it, so the
+    /// denominations of the requested assets must be in the `cache`.
+    pub fn format(&self, cache: &asset::Cache) -> anyhow::Result<String> {
+        self.check()?;
+
+        let mut params = Vec::new();
+        for value in &self.amounts {
+            let amount = value
+                .try_format(cache)
+                .ok_or_else(|| anyhow!("unknown asset {}", value.asset_id))?;
+            params.push(("amount", amount));
+        }
+        if let Some(memo) = &self.memo {
+            params.push(("memo", memo.clone()));
+        }
+        if let Some(label) = &self.label {
+            params.push(("label", label.clone()));
+        }
+
+        let mut uri = format!("{}:{}", PAYMENT_REQUEST_SCHEME, self.address);
+        for (index, (name, value)) in params.into_iter().enumerate() {
+            let separator = if index == 0 { '?' } else { '&' };
+            write!(uri, "{}{}={}", separator, name, percent_encode(&value))?;
+        }
+        Ok(uri)
+    }
+
+    /// Checks that the amounts are non-zero and of distinct assets, and that the memo fits in a
+    /// note's memo field.
+    fn check(&self) -> anyhow::Result<()> {
+        for (index, value) in self.amounts.iter().enumerate() {
+            if value.amount == 0 {
+                return Err(anyhow!("payment request amounts must be non-zero"));
+            }
+            if self.amounts[..index]
+                .iter()
+                .any(|requested| requested.asset_id == value.asset_id)
+            {
+                return Err(anyhow!("payment request includes an asset more than once"));
+            }
+        }
+        if self
+            .memo
+            .as_ref()
+            .map_or(false, |memo| memo.len() > MEMO_LEN_BYTES)
+        {
+            return Err(anyhow!("payment request memo exceeds maximum memo size"));
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for PaymentRequest {
+    type Err = anyhow::Error;
+
+    /// Parses a `penumbra:` URI.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (scheme, rest) = s
+            .split_once(':')
+            .ok_or_else(|| anyhow!("payment request is missing the penumbra: scheme"))?;
+        if !scheme.eq_ignore_ascii_case(PAYMENT_REQUEST_SCHEME) {
+            return Err(anyhow!("unknown payment request scheme {}", scheme));
+        }
+        let (address, query) = rest.split_once('?').unwrap_or((rest, ""));
+        let address = address
+            .parse()
+            .map_err(|_| anyhow!("invalid address in payment request"))?;
+
+        let mut request = PaymentRequest::new(address);
+        for param in query.split('&').filter(|param| !param.is_empty()) {
+            let (name, value) = param
+                .split_once('=')
+                .ok_or_else(|| anyhow!("payment request parameter {} has no value", param))?;
+            let value = percent_decode(value)?;
+            match name {
+                "amount" => request.amounts.push(value.parse()?),
+                "memo" => {
+                    if request.memo.replace(value).is_some() {
+                        return Err(anyhow!("payment request has more than one memo"));
+                    }
+                }
+                "label" => {
+                    if request.label.replace(value).is_some() {
+                        return Err(anyhow!("payment request has more than one label"));
+                    }
+                }
+                _ if name.starts_with("req-") => {
+                    return Err(anyhow!(
+                        "payment request requires unsupported parameter {}",
+                        name
+                    ));
+                }
+                _ => {}
+            }
+        }
+
+        request.check()?;
+        Ok(request)
+    }
+}
+
+/// Percent-encodes every byte of the UTF-8 encoding of `s` except the unreserved characters of
+/// RFC 3986.
+fn percent_encode(s: &str) -> String {
+    let mut encoded = String::with_capacity(s.len());
+    for byte in s.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                encoded.push(byte as char)
+            }
+            _ => write!(encoded, "%{:02X}", byte).expect("can write to a string"),
+        }
+    }
+    encoded
+}
+
+/// Decodes a percent-encoded UTF-8 string, also accepting `+` for a space, as form encoding
+/// writes it.
+fn percent_decode(s: &str) -> anyhow::Result<String> {
+    let mut decoded = Vec::with_capacity(s.len());
+    let mut bytes = s.bytes();
+    while let Some(byte) = bytes.next() {
+        match byte {
+            b'%' => {
+                let digit = |byte: Option<u8>| byte.and_then(|byte| (byte as char).to_digit(16));
+                match (digit(bytes.next()), digit(bytes.next())) {
+                    (Some(high), Some(low)) => decoded.push((high * 16 + low) as u8),
+                    _ => return Err(anyhow!("invalid percent-encoding in payment request")),
+                }
+            }
+            b'+' => decoded.push(b' '),
+            _ => decoded.push(byte),
+        }
+    }
+    String::from_utf8(decoded).map_err(|_| anyhow!("payment request is not valid UTF-8"))
+}
+
+#[cfg(test)]
+mod tests {
+    use rand_core::OsRng;
+
+    use super::*;
+    use crate::keys::{SeedPhrase, SpendKey, SpendSeed};
+
+    fn address() -> Address {
+        let seed_phrase = SeedPhrase::generate(&mut OsRng);
+        let sk = SpendKey::new(SpendSeed::from_seed_phrase(seed_phrase, 0));
+        sk.full_viewing_key()
+            .incoming()
+            .payment_address(0u64.into())
+            .0
+    }
+
+    #[test]
+    fn payment_request_round_trip() {
+        let cache = [asset::REGISTRY.parse_denom("upenumbra").unwrap()]
+            .into_iter()
+            .collect::<asset::Cache>();
+        let request = PaymentRequest {
+            address: address(),
+            amounts: vec!["10penumbra".parse().unwrap()],
+            memo: Some("order #42!".to_string()),
+            label: Some("Coffee shop".to_string()),
+        };
+
+        let uri = request.format(&cache).unwrap();
+        assert_eq!(
+            uri,
+            format!(
+                "penumbra:{}?amount=10penumbra&memo=order%20%2342%21&label=Coffee%20shop",
+                request.address
+            )
+        );
+        assert_eq!(uri.parse::<PaymentRequest>().unwrap(), request);
+    }
+
+    #[test]
+    fn payment_request_parsing() {
+        let address = address();
+
+        let request: PaymentRequest = format!("penumbra:{}", address).parse().unwrap();
+        assert_eq!(request, PaymentRequest::new(address));
+
+        // Amounts are parsed with the registry's units, and unknown optional parameters ignored.
+        let request: PaymentRequest =
+            format!("PENUMBRA:{}?amount=1500mpenumbra&memo=a+b&foo=bar", address)
+                .parse()
+                .unwrap();
+        assert_eq!(request.amounts, vec!["1.5penumbra".parse().unwrap()]);
+        assert_eq!(request.memo.as_deref(), Some("a b"));
+        assert_eq!(request.label, None);
+    }
+
+    #[test]
+    fn payment_request_parsing_errors() {
+        let address = address();
+        for uri in [
+            format!("bitcoin:{}", address),
+            "penumbra:notanaddress".to_string(),
+            format!("penumbra:{}?amount=0penumbra", address),
+            format!("penumbra:{}?amount=1penumbra&amount=2penumbra", address),
+            format!("penumbra:{}?memo=a&memo=b", address),
+            format!("penumbra:{}?memo=%zz", address),
+            format!("penumbra:{}?req-expiry=100", address),
+        ] {
+            assert!(uri.parse::<PaymentRequest>().is_err(), "{}", uri);
+        }
+    }
+}
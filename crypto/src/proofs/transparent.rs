@@ -98,7 +98,13 @@ impl SpendProof {
             return Err(Error::MerklePathMismatch);
         }
 
-        // 2. Check the Merkle path leads to the expected anchor (`merkle::Root`).
+        // 2. Check the Merkle path leads to the expected anchor (`merkle::Root`), unless the note
+        // has zero value: as in Sapling, dummy spends of zero-value notes, which pad transactions
+        // to hide their arity, need not be in the note commitment tree. This is sound because
+        // the value commitment integrity check below binds the spend to a value of zero, so it
+        // contributes nothing to the transaction's balance, and the note commitment integrity
+        // check above binds the zero value to the note, so no note of non-zero value can skip
+        // this check. See the spec's "Dummy Spends and Outputs" section.
         let mut cur = self.note_commitment;
 
         // This logic is from `incrementalmerkletree`'s `compute_root_from_auth_path` function which is
@@ -118,7 +124,7 @@ impl SpendProof {
             lvl = lvl + 1;
         }
         let expected_root = merkle::Root(cur.0);
-        if expected_root != anchor {
+        if self.value.amount != 0 && expected_root != anchor {
             return Err(Error::MerkleRootMismatch);
         }
 
@@ -728,6 +734,59 @@ mod tests {
             .is_err());
     }
 
+    #[test]
+    fn test_spend_proof_verification_dummy_spend_success() {
+        let mut rng = OsRng;
+        let seed_phrase = SeedPhrase::generate(&mut rng);
+        let spend_seed = SpendSeed::from_seed_phrase(seed_phrase, 0);
+        let sk_sender = SpendKey::new(spend_seed);
+        let fvk_sender = sk_sender.full_viewing_key();
+        let ivk_sender = fvk_sender.incoming();
+        let (sender, _dtk_d) = ivk_sender.payment_address(0u64.into());
+
+        let zero_value = Value {
+            amount: 0,
+            asset_id: asset::REGISTRY.parse_denom("upenumbra").unwrap().id(),
+        };
+        let v_blinding = Fr::rand(&mut rng);
+
+        // The note of a dummy spend isn't in any note commitment tree.
+        let note = Note::generate(&mut rng, &sender, zero_value);
+        let note_commitment = note.commit();
+        let spend_auth_randomizer = Fr::rand(&mut rng);
+        let rsk = sk_sender.spend_auth_key().randomize(&spend_auth_randomizer);
+        let nk = *sk_sender.nullifier_key();
+        let ak = sk_sender.spend_auth_key().into();
+        let nct = merkle::BridgeTree::<note::Commitment, 32>::new(5);
+        let anchor = nct.root2();
+        let merkle_path = (
+            7.into(),
+            (0..merkle::DEPTH)
+                .map(|_| note::Commitment(Fq::rand(&mut rng)))
+                .collect(),
+        );
+
+        let proof = SpendProof {
+            merkle_path,
+            position: 7.into(),
+            g_d: *sender.diversified_generator(),
+            pk_d: *sender.transmission_key(),
+            value: zero_value,
+            v_blinding,
+            note_commitment,
+            note_blinding: note.note_blinding(),
+            spend_auth_randomizer,
+            ak,
+            nk,
+        };
+
+        let rk: VerificationKey<SpendAuth> = rsk.into();
+        let nf = nk.derive_nullifier(7.into(), &note_commitment);
+        assert!(proof
+            .verify(anchor, zero_value.commit(v_blinding), nf, rk)
+            .is_ok());
+    }
+
     #[test]
     fn test_spend_proof_verification_value_commitment_integrity_failure() {
         let mut rng = OsRng;
@@ -1,19 +1,27 @@
 use structopt::StructOpt;
 
+mod account;
 mod addr;
 mod balance;
+mod contact;
+mod note;
 mod stake;
 mod temp;
 mod tx;
 mod validator;
+mod view;
 mod wallet;
 
+pub use account::AccountCmd;
 pub use addr::AddrCmd;
 pub use balance::BalanceCmd;
+pub use contact::ContactCmd;
+pub use note::NoteCmd;
 pub use stake::StakeCmd;
 pub use temp::TmpCmd;
-pub use tx::TxCmd;
+pub use tx::{sign, TxCmd};
 pub use validator::ValidatorCmd;
+pub use view::ViewCmd;
 pub use wallet::WalletCmd;
 
 #[derive(Debug, StructOpt)]
@@ -24,6 +32,8 @@ pub enum Command {
     Wallet(WalletCmd),
     /// Manages addresses.
     Addr(AddrCmd),
+    /// Manages the accounts derived from the wallet's seed phrase.
+    Account(AccountCmd),
     /// Synchronizes the client, privately scanning the chain state.
     ///
     /// `pcli` syncs automatically prior to any action requiring chain state,
@@ -31,12 +41,18 @@ pub enum Command {
     Sync,
     /// Displays the current wallet balance.
     Balance(BalanceCmd),
+    /// Labels and freezes individual notes.
+    Note(NoteCmd),
+    /// Manages the address book of named addresses and validators.
+    Contact(ContactCmd),
     /// Manages a validator.
     Validator(ValidatorCmd),
     /// Manages delegations and undelegations.
     Stake(StakeCmd),
     /// Temporary commands for migrating address formats.
     Tmp(TmpCmd),
+    /// Runs or queries the local view service.
+    View(ViewCmd),
 }
 
 impl Command {
@@ -46,11 +62,37 @@ impl Command {
             Command::Tx(cmd) => cmd.needs_sync(),
             Command::Wallet(cmd) => cmd.needs_sync(),
             Command::Addr(cmd) => cmd.needs_sync(),
+            Command::Account(cmd) => cmd.needs_sync(),
             Command::Sync => true,
             Command::Balance(cmd) => cmd.needs_sync(),
+            Command::Note(cmd) => cmd.needs_sync(),
+            Command::Contact(cmd) => cmd.needs_sync(),
             Command::Validator(cmd) => cmd.needs_sync(),
             Command::Stake(cmd) => cmd.needs_sync(),
             Command::Tmp(cmd) => cmd.needs_sync(),
+            Command::View(cmd) => cmd.needs_sync(),
+        }
+    }
+
+    /// Determine if this command needs the spend key, and so the wallet password if the wallet
+    /// is encrypted.
+    ///
+    /// The wallet and view commands load the client state themselves, and decide this on their
+    /// own.
+    pub fn needs_spend_key(&self) -> bool {
+        match self {
+            Command::Tx(cmd) => cmd.needs_spend_key(),
+            Command::Account(cmd) => cmd.needs_spend_key(),
+            Command::Stake(cmd) => cmd.needs_spend_key(),
+            Command::Wallet(_)
+            | Command::Addr(_)
+            | Command::Sync
+            | Command::Balance(_)
+            | Command::Note(_)
+            | Command::Contact(_)
+            | Command::Validator(_)
+            | Command::Tmp(_)
+            | Command::View(_) => false,
         }
     }
 }
@@ -0,0 +1,145 @@
+use anyhow::{anyhow, Result};
+use comfy_table::{presets, Table};
+use penumbra_crypto::Value;
+use penumbra_wallet::{NoteSource, Payment};
+use rand_core::OsRng;
+use structopt::StructOpt;
+
+use crate::{wait_for_transaction, ClientStateFile, Opt};
+
+#[derive(Debug, StructOpt)]
+pub enum AccountCmd {
+    /// List the wallet's accounts.
+    List,
+    /// Derive a new account from the wallet's seed phrase.
+    ///
+    /// Funds sent to the new account before the wallet had synced up to the point it was created
+    /// are only found if `--rescan` is given, or after running `pcli wallet rescan`.
+    New {
+        /// A freeform label for the account, stored only locally.
+        label: String,
+        /// Rescan the chain on the next sync, to find funds sent to the new account before it was
+        /// created.
+        #[structopt(long)]
+        rescan: bool,
+        /// Optional. The height to rescan from, which becomes the wallet's birthday height.
+        /// Defaults to the current birthday height.
+        #[structopt(long, requires = "rescan")]
+        from: Option<u64>,
+    },
+    /// Transfer funds from the selected account to another account of the wallet.
+    Transfer {
+        /// The index of the account to transfer funds to.
+        #[structopt(long)]
+        to: u64,
+        /// The amounts to transfer, written as typed values 1.87penumbra, 12cubes, etc.
+        values: Vec<String>,
+        /// The transaction fee (paid in upenumbra).
+        #[structopt(long, default_value = "0")]
+        fee: u64,
+        /// Optional. Only spend funds originally received by the given address index.
+        #[structopt(long)]
+        source: Option<u64>,
+    },
+}
+
+impl AccountCmd {
+    /// Determine if this command requires a network sync before it executes.
+    pub fn needs_sync(&self) -> bool {
+        match self {
+            AccountCmd::List => false,
+            AccountCmd::New { .. } => false,
+            AccountCmd::Transfer { .. } => true,
+        }
+    }
+
+    /// Determine if this command needs the spend key, and so the wallet password if the wallet
+    /// is encrypted.
+    pub fn needs_spend_key(&self) -> bool {
+        match self {
+            AccountCmd::List => false,
+            // New accounts are derived from the seed phrase.
+            AccountCmd::New { .. } => true,
+            AccountCmd::Transfer { .. } => true,
+        }
+    }
+
+    pub async fn exec(&self, opt: &Opt, state: &mut ClientStateFile) -> Result<()> {
+        match self {
+            AccountCmd::List => {
+                let mut table = Table::new();
+                table.load_preset(presets::NOTHING);
+                table.set_header(vec!["Index", "Label", "Default Address"]);
+                for (index, label, wallet) in state.accounts() {
+                    let (_label, address) = wallet.address_by_index(0)?;
+                    table.add_row(vec![
+                        index.to_string(),
+                        label.to_string(),
+                        address.to_string(),
+                    ]);
+                }
+                println!("{}", table);
+            }
+            AccountCmd::New {
+                label,
+                rescan,
+                from,
+            } => {
+                let index = state.new_account(label.clone())?;
+                if *rescan {
+                    state.rescan(*from)?;
+                }
+                state.commit().await?;
+                println!("Created account {} ({})", index, label);
+                if *rescan {
+                    println!(
+                        "The wallet will be rescanned from height {} by the next sync",
+                        state.birthday_height().unwrap_or_default()
+                    );
+                }
+            }
+            AccountCmd::Transfer {
+                to,
+                values,
+                fee,
+                source,
+            } => {
+                if *to == opt.account {
+                    return Err(anyhow!(
+                        "cannot transfer funds from account {} to itself",
+                        to
+                    ));
+                }
+                let values = values
+                    .iter()
+                    .map(|v| v.parse())
+                    .collect::<Result<Vec<Value>, _>>()?;
+                let (_label, dest_address) = state.account(*to)?.address_by_index(0)?;
+
+                let payment = Payment {
+                    address: dest_address,
+                    values,
+                    memo: None,
+                };
+                let transaction = state.build_send(
+                    &mut OsRng,
+                    opt.account,
+                    &[payment],
+                    *fee,
+                    source.map_or(NoteSource::Any, NoteSource::Address),
+                )?;
+
+                opt.submit_transaction(&transaction).await?;
+                state.register_transaction(&transaction);
+                // Only commit the state if the transaction was submitted successfully, so that we
+                // don't store pending notes that will never appear on-chain.
+                state.commit().await?;
+                if opt.wait {
+                    wait_for_transaction(opt, state, transaction.id()).await?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
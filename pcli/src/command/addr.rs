@@ -1,5 +1,6 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use comfy_table::{presets, Table};
+use penumbra_crypto::{PaymentRequest, Value};
 use structopt::StructOpt;
 
 use crate::ClientStateFile;
@@ -22,6 +23,22 @@ pub enum AddrCmd {
         /// A freeform label for the address, stored only locally.
         label: String,
     },
+    /// Print a `penumbra:` payment request URI asking for a payment to one of your addresses.
+    Request {
+        /// The index of the address to be paid.
+        #[structopt(short, long, default_value = "0")]
+        index: u32,
+        /// Optional. The amount to request, written as a typed value such as 10penumbra. Repeat
+        /// it to request several assets; if omitted, the payer chooses the amount.
+        #[structopt(long)]
+        amount: Vec<String>,
+        /// Optional. The text of the memo the payer should attach to the payment.
+        #[structopt(long)]
+        memo: Option<String>,
+        /// Optional. A description of the request shown to the payer.
+        #[structopt(long)]
+        label: Option<String>,
+    },
 }
 
 impl AddrCmd {
@@ -31,23 +48,24 @@ impl AddrCmd {
             AddrCmd::List => false,
             AddrCmd::Show { .. } => false,
             AddrCmd::New { .. } => false,
+            AddrCmd::Request { .. } => false,
         }
     }
 
-    pub fn exec(&self, state: &mut ClientStateFile) -> Result<()> {
-        // Set up table (this won't be used with `show --addr-only`)
+    pub async fn exec(&self, account: u64, state: &mut ClientStateFile) -> Result<()> {
+        // Set up table (this won't be used with `show --addr-only` or `request`)
         let mut table = Table::new();
         table.load_preset(presets::NOTHING);
         table.set_header(vec!["Index", "Label", "Address"]);
 
         match self {
             AddrCmd::List => {
-                for (index, label, address) in state.wallet().addresses() {
+                for (index, label, address) in state.account(account)?.addresses() {
                     table.add_row(vec![index.to_string(), label, address.to_string()]);
                 }
             }
             AddrCmd::Show { index, addr_only } => {
-                let (label, address) = state.wallet().address_by_index(*index as usize)?;
+                let (label, address) = state.account(account)?.address_by_index(*index as usize)?;
 
                 if *addr_only {
                     println!("{}", address);
@@ -57,13 +75,44 @@ impl AddrCmd {
                 }
             }
             AddrCmd::New { label } => {
-                let (index, address, _dtk) = state.wallet_mut().new_address(label.clone());
-                state.commit()?;
+                let (index, address, _dtk) = state.account_mut(account)?.new_address(label.clone());
+                state.commit().await?;
                 table.add_row(vec![index.to_string(), label.clone(), address.to_string()]);
             }
+            AddrCmd::Request {
+                index,
+                amount,
+                memo,
+                label,
+            } => {
+                let (_, address) = state.account(account)?.address_by_index(*index as usize)?;
+                let request = PaymentRequest {
+                    address,
+                    amounts: amount
+                        .iter()
+                        .map(|v| v.parse())
+                        .collect::<Result<Vec<Value>, _>>()?,
+                    memo: memo.clone(),
+                    label: label.clone(),
+                };
+                // Amounts are written in the units of their denominations, which the wallet only
+                // knows once it has synced.
+                if let Some(value) = request
+                    .amounts
+                    .iter()
+                    .find(|value| state.asset_cache().get(&value.asset_id).is_none())
+                {
+                    return Err(anyhow!(
+                        "unknown asset {}; sync the wallet to learn the chain's assets",
+                        value.asset_id
+                    ));
+                }
+                println!("{}", request.format(state.asset_cache())?);
+                return Ok(()); // don't print the table
+            }
         }
 
-        // Print the table (we don't get here if `show --addr-only`)
+        // Print the table (we don't get here if `show --addr-only` or `request`)
         println!("{}", table);
 
         Ok(())
@@ -13,7 +13,7 @@ pub struct BalanceCmd {
     /// If set, does not attempt to synchronize the wallet before printing the balance.
     pub offline: bool,
     #[structopt(long)]
-    /// If set, prints the value of each note individually.
+    /// If set, prints the commitment, value, label and memo of each note individually.
     pub by_note: bool,
 }
 
@@ -23,6 +23,7 @@ struct FormattedTally {
     available: String,
     submitted_spend: String,
     submitted_change: String,
+    quarantined: String,
 }
 
 // Format a tally of notes as a set of strings.
@@ -32,6 +33,7 @@ struct FormattedTally {
 fn tally_format_notes<'a>(
     denom: &'a Denom,
     cache: &'a asset::Cache,
+    epoch_duration: u64,
     notes_groups: impl IntoIterator<Item = impl IntoIterator<Item = UnspentNote<'a>> + 'a> + 'a,
 ) -> impl IntoIterator<Item = FormattedTally> + 'a {
     notes_groups.into_iter().map(|notes| {
@@ -39,12 +41,19 @@ fn tally_format_notes<'a>(
         let mut unspent = 0;
         let mut submitted_spend = 0;
         let mut submitted_change = 0;
+        let mut quarantined = 0;
+        let mut unbonding_epoch = 0;
 
         for note in notes {
             *match note {
                 UnspentNote::Ready(_) => &mut unspent,
                 UnspentNote::SubmittedSpend(_) => &mut submitted_spend,
                 UnspentNote::SubmittedChange(_) => &mut submitted_change,
+                UnspentNote::Quarantined(quarantined_note) => {
+                    unbonding_epoch =
+                        unbonding_epoch.max(quarantined_note.unbonding_epoch(epoch_duration));
+                    &mut quarantined
+                }
             } += note.as_ref().amount();
         }
 
@@ -70,6 +79,17 @@ fn tally_format_notes<'a>(
             "".to_string()
         };
 
+        let quarantined = denom.value(quarantined);
+        let quarantined_string = if quarantined.amount > 0 {
+            format!(
+                "+{} (unbonding until epoch {})",
+                quarantined.try_format(cache).unwrap(),
+                unbonding_epoch
+            )
+        } else {
+            "".to_string()
+        };
+
         // The total amount, disregarding submitted transactions:
         let total = denom.value(submitted_change.amount + unspent);
 
@@ -81,39 +101,101 @@ fn tally_format_notes<'a>(
             available: available.try_format(cache).unwrap(),
             submitted_change: submitted_change_string,
             submitted_spend: submitted_spend_string,
+            quarantined: quarantined_string,
         }
     })
 }
 
+/// Returns the memo of a group of notes, if the group is a single note which has a memo.
+fn format_memo(state: &ClientState, notes: &[UnspentNote]) -> String {
+    match notes {
+        [note] => state
+            .memo(&note.as_ref().commit())
+            .map(ToString::to_string)
+            .unwrap_or_default(),
+        _ => String::default(),
+    }
+}
+
+/// Returns the commitment and the label of a group of notes, if the group is a single note,
+/// marking frozen notes.
+fn format_note(state: &ClientState, notes: &[UnspentNote]) -> (String, String) {
+    match notes {
+        [note] => {
+            let commitment = note.as_ref().commit();
+            let mut label = state
+                .note_label(&commitment)
+                .unwrap_or_default()
+                .to_string();
+            if state.is_frozen(&commitment) {
+                label = format!("{} (frozen)", label).trim_start().to_string();
+            }
+            (commitment.to_string(), label)
+        }
+        _ => (String::default(), String::default()),
+    }
+}
+
 impl BalanceCmd {
     pub fn needs_sync(&self) -> bool {
         !self.offline
     }
 
-    pub fn exec(&self, state: &ClientState) -> Result<()> {
+    pub fn exec(&self, account: u64, state: &ClientState) -> Result<()> {
         // Initialize the table
         let mut table = Table::new();
         table.load_preset(presets::NOTHING);
         let mut print_submitted_column = false; // This will become true if there are any submitted transactions
+        let mut print_quarantined_column = false; // This will become true if there are any quarantined notes
         let mut headers;
 
+        let epoch_duration = state
+            .chain_params()
+            .map(|params| params.epoch_duration)
+            .unwrap_or(1);
+
         if self.by_address {
-            for (address_id, by_denom) in state.unspent_notes_by_address_and_denom().into_iter() {
-                let (mut label, _) = state.wallet().address_by_index(address_id as usize)?;
+            for (address_id, by_denom) in state
+                .unspent_notes_by_address_and_denom(account)
+                .into_iter()
+            {
+                let (mut label, _) = state
+                    .account(account)?
+                    .address_by_index(address_id as usize)?;
                 for (denom, notes) in by_denom.into_iter() {
-                    let notes_groups = if self.by_note {
+                    let notes_groups: Vec<Vec<_>> = if self.by_note {
                         notes.into_iter().map(|n| vec![n]).collect()
                     } else {
                         vec![notes]
                     };
-                    let tallies = tally_format_notes(&denom, state.asset_cache(), notes_groups);
-                    for tally in tallies {
+                    let memos = notes_groups
+                        .iter()
+                        .map(|notes| (format_note(state, notes), format_memo(state, notes)))
+                        .collect::<Vec<_>>();
+                    let tallies = tally_format_notes(
+                        &denom,
+                        state.asset_cache(),
+                        epoch_duration,
+                        notes_groups,
+                    );
+                    for (tally, ((commitment, note_label), memo)) in tallies.into_iter().zip(memos)
+                    {
                         let mut row = vec![label.clone(), tally.total];
-                        if !tally.submitted_change.is_empty() || !tally.submitted_spend.is_empty() {
+                        if self.by_note {
+                            row.push(commitment);
+                            row.push(note_label);
+                            row.push(memo);
+                        }
+                        if !tally.submitted_change.is_empty()
+                            || !tally.submitted_spend.is_empty()
+                            || !tally.quarantined.is_empty()
+                        {
                             print_submitted_column = true;
+                            print_quarantined_column |= !tally.quarantined.is_empty();
                             row.push(tally.available);
                             row.push(tally.submitted_change);
                             row.push(tally.submitted_spend);
+                            row.push(tally.quarantined);
                         }
                         table.add_row(row);
 
@@ -126,25 +208,48 @@ impl BalanceCmd {
             // Set up headers for the table (a "Submitted" column will be added if there are any
             // submitted transactions)
             headers = vec!["Address", "Total"];
+            if self.by_note {
+                headers.push("Note");
+                headers.push("Label");
+                headers.push("Memo");
+            }
         } else {
-            for (denom, by_address) in state.unspent_notes_by_denom_and_address().into_iter() {
+            for (denom, by_address) in state
+                .unspent_notes_by_denom_and_address(account)
+                .into_iter()
+            {
                 let notes = by_address.into_values().flatten();
 
-                let notes_groups = if self.by_note {
+                let notes_groups: Vec<Vec<_>> = if self.by_note {
                     notes.map(|n| vec![n]).collect()
                 } else {
                     vec![notes.collect()]
                 };
+                let memos = notes_groups
+                    .iter()
+                    .map(|notes| (format_note(state, notes), format_memo(state, notes)))
+                    .collect::<Vec<_>>();
 
-                let tallies = tally_format_notes(&denom, state.asset_cache(), notes_groups);
+                let tallies =
+                    tally_format_notes(&denom, state.asset_cache(), epoch_duration, notes_groups);
 
-                for tally in tallies {
+                for (tally, ((commitment, note_label), memo)) in tallies.into_iter().zip(memos) {
                     let mut row = vec![tally.total];
-                    if !tally.submitted_change.is_empty() || !tally.submitted_spend.is_empty() {
+                    if self.by_note {
+                        row.push(commitment);
+                        row.push(note_label);
+                        row.push(memo);
+                    }
+                    if !tally.submitted_change.is_empty()
+                        || !tally.submitted_spend.is_empty()
+                        || !tally.quarantined.is_empty()
+                    {
                         print_submitted_column = true;
+                        print_quarantined_column |= !tally.quarantined.is_empty();
                         row.push(tally.available);
                         row.push(tally.submitted_change);
                         row.push(tally.submitted_spend);
+                        row.push(tally.quarantined);
                     }
                     table.add_row(row);
                 }
@@ -153,6 +258,11 @@ impl BalanceCmd {
             // Set up headers for the table (a "Submitted" column will be added if there are any
             // submitted transactions)
             headers = vec!["Total"];
+            if self.by_note {
+                headers.push("Note");
+                headers.push("Label");
+                headers.push("Memo");
+            }
         }
 
         // Add an "Available" and "Submitted" column if there are any submitted transactions
@@ -160,9 +270,27 @@ impl BalanceCmd {
             headers.push("Available");
             headers.push("Submitted");
         }
+        // Add an "Unbonding" column if there are any quarantined notes from undelegations
+        if print_quarantined_column {
+            headers.push("");
+            headers.push("Unbonding");
+        }
         table.set_header(headers);
         println!("{}", table);
 
+        // Let the user know if any of their undelegations were reverted by slashing
+        for reverted in state.reverted_notes() {
+            println!(
+                "Undelegation output of {} was reverted because validator {} was slashed",
+                reverted
+                    .note
+                    .value()
+                    .try_format(state.asset_cache())
+                    .unwrap(),
+                reverted.validator_identity_key,
+            );
+        }
+
         Ok(())
     }
 }
@@ -0,0 +1,71 @@
+use anyhow::Result;
+use comfy_table::{presets, Table};
+use penumbra_wallet::Contact;
+use structopt::StructOpt;
+
+use crate::ClientStateFile;
+
+#[derive(Debug, StructOpt)]
+pub enum ContactCmd {
+    /// Add a named address or validator to the address book.
+    ///
+    /// The name can then be used in place of the address or identity key in any other command.
+    Add {
+        /// The name of the contact, stored only locally.
+        name: String,
+        /// The address or validator identity key to name.
+        contact: Contact,
+    },
+    /// List the contacts in the address book.
+    List,
+    /// Remove a contact from the address book.
+    Remove {
+        /// The name of the contact.
+        name: String,
+    },
+}
+
+impl ContactCmd {
+    /// Determine if this command requires a network sync before it executes.
+    pub fn needs_sync(&self) -> bool {
+        match self {
+            ContactCmd::Add { .. } => false,
+            ContactCmd::List => false,
+            ContactCmd::Remove { .. } => false,
+        }
+    }
+
+    pub async fn exec(&self, state: &mut ClientStateFile) -> Result<()> {
+        match self {
+            ContactCmd::Add { name, contact } => {
+                state.add_contact(name.clone(), contact.clone())?;
+                state.commit().await?;
+                println!("Added contact {}", name);
+            }
+            ContactCmd::List => {
+                let mut table = Table::new();
+                table.load_preset(presets::NOTHING);
+                table.set_header(vec!["Name", "Kind", "Address or Identity Key"]);
+                for (name, contact) in state.contacts() {
+                    let kind = match contact {
+                        Contact::Address(_) => "address",
+                        Contact::Validator(_) => "validator",
+                    };
+                    table.add_row(vec![
+                        name.to_string(),
+                        kind.to_string(),
+                        contact.to_string(),
+                    ]);
+                }
+                println!("{}", table);
+            }
+            ContactCmd::Remove { name } => {
+                let contact = state.remove_contact(name)?;
+                state.commit().await?;
+                println!("Removed contact {} ({})", name, contact);
+            }
+        }
+
+        Ok(())
+    }
+}
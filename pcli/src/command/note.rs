@@ -0,0 +1,71 @@
+use anyhow::Result;
+use penumbra_crypto::note;
+use structopt::StructOpt;
+
+use crate::ClientStateFile;
+
+#[derive(Debug, StructOpt)]
+pub enum NoteCmd {
+    /// List the frozen notes.
+    Frozen,
+    /// Label a note, or remove its label if none is given.
+    Label {
+        /// The commitment of the note, as listed by `pcli balance --by-note`.
+        commitment: note::Commitment,
+        /// A freeform label for the note, stored only locally.
+        label: Option<String>,
+    },
+    /// Freeze a note, so that it is never spent automatically or swept.
+    ///
+    /// A frozen note can't be spent until it is unfrozen.
+    Freeze {
+        /// The commitment of the note, as listed by `pcli balance --by-note`.
+        commitment: note::Commitment,
+    },
+    /// Unfreeze a frozen note.
+    Unfreeze {
+        /// The commitment of the note.
+        commitment: note::Commitment,
+    },
+}
+
+impl NoteCmd {
+    /// Determine if this command requires a network sync before it executes.
+    pub fn needs_sync(&self) -> bool {
+        match self {
+            NoteCmd::Frozen => false,
+            NoteCmd::Label { .. } => false,
+            NoteCmd::Freeze { .. } => false,
+            NoteCmd::Unfreeze { .. } => false,
+        }
+    }
+
+    pub async fn exec(&self, state: &mut ClientStateFile) -> Result<()> {
+        match self {
+            NoteCmd::Frozen => {
+                for commitment in state.frozen_notes() {
+                    match state.note_label(commitment) {
+                        Some(label) => println!("{} {}", commitment, label),
+                        None => println!("{}", commitment),
+                    }
+                }
+            }
+            NoteCmd::Label { commitment, label } => {
+                state.set_note_label(*commitment, label.clone())?;
+                state.commit().await?;
+            }
+            NoteCmd::Freeze { commitment } => {
+                state.freeze_note(*commitment)?;
+                state.commit().await?;
+                println!("Froze note {}", commitment);
+            }
+            NoteCmd::Unfreeze { commitment } => {
+                state.unfreeze_note(*commitment)?;
+                state.commit().await?;
+                println!("Unfroze note {}", commitment);
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -1,24 +1,33 @@
-use std::collections::BTreeMap;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs::File,
+    io::Write,
+    path::PathBuf,
+};
 
 use anyhow::{anyhow, Context, Result};
 use comfy_table::{presets, Table};
 use futures::stream::TryStreamExt;
-use penumbra_crypto::Value;
-use penumbra_proto::{light_wallet::ValidatorInfoRequest, thin_wallet::ValidatorRateRequest};
+use penumbra_crypto::{asset, Value};
+use penumbra_proto::{
+    light_wallet::ValidatorInfoRequest,
+    thin_wallet::{ValidatorHistoryRequest, ValidatorRateRequest},
+};
 use penumbra_stake::{
-    DelegationToken, Epoch, IdentityKey, RateData, ValidatorInfo, STAKING_TOKEN_ASSET_ID,
-    STAKING_TOKEN_DENOM,
+    DelegationToken, IdentityKey, RateData, ValidatorEpochInfo, ValidatorInfo, ValidatorState,
+    STAKING_TOKEN_ASSET_ID, STAKING_TOKEN_DENOM,
 };
+use penumbra_wallet::UnspentNote;
 use rand_core::OsRng;
 use structopt::StructOpt;
 
-use crate::{ClientStateFile, Opt};
+use crate::{wait_for_transaction, ClientStateFile, Opt};
 
 #[derive(Debug, StructOpt)]
 pub enum StakeCmd {
     /// Deposit stake into a validator's delegation pool.
     Delegate {
-        /// The identity key of the validator to delegate to.
+        /// The identity key of the validator to delegate to, or the name of a contact.
         #[structopt(long)]
         to: String,
         /// The amount of stake to delegate.
@@ -43,10 +52,11 @@ pub enum StakeCmd {
     },
     /// Redelegate stake from one validator's delegation pool to another.
     Redelegate {
-        /// The identity key of the validator to withdraw delegation from.
+        /// The identity key of the validator to withdraw delegation from, or the name of a
+        /// contact.
         #[structopt(long)]
         from: String,
-        /// The identity key of the validator to delegate to.
+        /// The identity key of the validator to delegate to, or the name of a contact.
         #[structopt(long)]
         to: String,
         /// The amount of stake to delegate.
@@ -60,6 +70,21 @@ pub enum StakeCmd {
     },
     /// Display this wallet's delegations and their value.
     Show,
+    /// Display the staking rewards earned by this wallet's delegations.
+    ///
+    /// Rewards are realized when delegation tokens are spent, and unrealized while they are held.
+    Rewards {
+        /// The first epoch of the range to report rewards for.
+        #[structopt(long, default_value = "0")]
+        start_epoch: u64,
+        /// Optional. The last epoch of the range to report rewards for (defaults to the current
+        /// epoch).
+        #[structopt(long)]
+        end_epoch: Option<u64>,
+        /// Optional. Export the rewards of each delegation note to the given CSV file.
+        #[structopt(long)]
+        csv: Option<PathBuf>,
+    },
     /// Display all of the validators participating in the chain.
     ListValidators {
         /// Whether to show validators that are not currently part of the consensus set.
@@ -69,6 +94,26 @@ pub enum StakeCmd {
         #[structopt(short, long)]
         detailed: bool,
     },
+    /// Query information about a single validator.
+    Validator {
+        /// The identity key of the validator to query, or the name of a contact.
+        identity_key: String,
+        #[structopt(subcommand)]
+        cmd: ValidatorCmd,
+    },
+}
+
+#[derive(Debug, StructOpt)]
+pub enum ValidatorCmd {
+    /// Display the validator's exchange rate, reward rate, voting power and state in each epoch.
+    History {
+        /// The first epoch to display.
+        #[structopt(long, default_value = "0")]
+        start_epoch: u64,
+        /// Optional. The last epoch to display (defaults to the latest epoch).
+        #[structopt(long)]
+        end_epoch: Option<u64>,
+    },
 }
 
 impl StakeCmd {
@@ -76,6 +121,15 @@ impl StakeCmd {
         true
     }
 
+    /// Determine if this command needs the spend key, and so the wallet password if the wallet
+    /// is encrypted.
+    pub fn needs_spend_key(&self) -> bool {
+        matches!(
+            self,
+            StakeCmd::Delegate { .. } | StakeCmd::Undelegate { .. } | StakeCmd::Redelegate { .. }
+        )
+    }
+
     pub async fn exec(&self, opt: &Opt, state: &mut ClientStateFile) -> Result<()> {
         match self {
             StakeCmd::Delegate {
@@ -92,12 +146,11 @@ impl StakeCmd {
                     amount
                 };
 
-                let to = to.parse::<IdentityKey>()?;
+                let to = state.resolve_identity_key(to)?;
 
-                let current_epoch = Epoch::from_height(
-                    state.last_block_height().unwrap() as u64,
-                    state.chain_params().unwrap().epoch_duration,
-                );
+                let current_epoch = state
+                    .synced_epoch()
+                    .ok_or_else(|| anyhow!("the wallet has not been synced"))?;
                 let next_epoch = current_epoch.next();
 
                 let mut client = opt.thin_wallet_client().await?;
@@ -114,13 +167,23 @@ impl StakeCmd {
                     .into_inner()
                     .try_into()?;
 
-                let transaction =
-                    state.build_delegate(&mut OsRng, rate_data, unbonded_amount, *fee, *source)?;
+                let transaction = state.build_delegate(
+                    &mut OsRng,
+                    opt.account,
+                    rate_data,
+                    unbonded_amount,
+                    *fee,
+                    *source,
+                )?;
 
                 opt.submit_transaction(&transaction).await?;
+                state.register_transaction(&transaction);
                 // Only commit the state if the transaction was submitted successfully,
                 // so that we don't store pending notes that will never appear on-chain.
-                state.commit()?;
+                state.commit().await?;
+                if opt.wait {
+                    wait_for_transaction(opt, state, transaction.id()).await?;
+                }
             }
             StakeCmd::Undelegate {
                 amount,
@@ -142,10 +205,9 @@ impl StakeCmd {
 
                 let from = delegation_token.validator();
 
-                let current_epoch = Epoch::from_height(
-                    state.last_block_height().unwrap() as u64,
-                    state.chain_params().unwrap().epoch_duration,
-                );
+                let current_epoch = state
+                    .synced_epoch()
+                    .ok_or_else(|| anyhow!("the wallet has not been synced"))?;
                 let next_epoch = current_epoch.next();
 
                 let mut client = opt.thin_wallet_client().await?;
@@ -164,6 +226,7 @@ impl StakeCmd {
 
                 let transaction = state.build_undelegate(
                     &mut OsRng,
+                    opt.account,
                     rate_data,
                     delegation_amount,
                     *fee,
@@ -171,9 +234,13 @@ impl StakeCmd {
                 )?;
 
                 opt.submit_transaction(&transaction).await?;
+                state.register_transaction(&transaction);
                 // Only commit the state if the transaction was submitted successfully,
                 // so that we don't store pending notes that will never appear on-chain.
-                state.commit()?;
+                state.commit().await?;
+                if opt.wait {
+                    wait_for_transaction(opt, state, transaction.id()).await?;
+                }
             }
             StakeCmd::Redelegate { .. } => {
                 todo!()
@@ -194,7 +261,7 @@ impl StakeCmd {
                     .map(TryInto::try_into)
                     .collect::<Result<Vec<ValidatorInfo>, _>>()?;
 
-                let notes = state.unspent_notes_by_denom_and_address();
+                let notes = state.unspent_notes_by_denom_and_address(opt.account);
                 let mut total = 0;
 
                 let mut table = Table::new();
@@ -226,7 +293,7 @@ impl StakeCmd {
                     };
 
                     let unbonded = Value {
-                        amount: info.rate_data.unbonded_amount(delegation.amount),
+                        amount: info.rate_data.unbonded_amount(delegation.amount)?,
                         asset_id: *STAKING_TOKEN_ASSET_ID,
                     };
 
@@ -247,7 +314,12 @@ impl StakeCmd {
                         .get(&*STAKING_TOKEN_DENOM)
                         .unwrap_or(&BTreeMap::default())
                         .values()
-                        .flat_map(|notes| notes.iter().map(|n| n.as_ref().amount()))
+                        .flat_map(|notes| {
+                            notes
+                                .iter()
+                                .filter(|n| !matches!(n, UnspentNote::Quarantined(_)))
+                                .map(|n| n.as_ref().amount())
+                        })
                         .sum::<u64>(),
                     asset_id: *STAKING_TOKEN_ASSET_ID,
                 };
@@ -261,6 +333,60 @@ impl StakeCmd {
                     unbonded.try_format(state.asset_cache()).unwrap(),
                 ]);
 
+                // Undelegated stake remains quarantined until the unbonding period is over, and is
+                // lost if the validator is slashed before then.
+                let epoch_duration = state
+                    .chain_params()
+                    .ok_or_else(|| anyhow!("the wallet has not been synced"))?
+                    .epoch_duration;
+                for quarantined in state.quarantined_notes() {
+                    let name = validators
+                        .iter()
+                        .find(|v| v.validator.identity_key == quarantined.validator_identity_key)
+                        .map(|info| info.validator.name.clone())
+                        .unwrap_or_else(|| quarantined.validator_identity_key.to_string());
+
+                    total += quarantined.note.amount();
+
+                    table.add_row(vec![
+                        format!(
+                            "{} (unbonding until epoch {})",
+                            name,
+                            quarantined.unbonding_epoch(epoch_duration)
+                        ),
+                        quarantined
+                            .note
+                            .value()
+                            .try_format(state.asset_cache())
+                            .unwrap(),
+                        format!("{:.4}", 1.0),
+                        quarantined
+                            .note
+                            .value()
+                            .try_format(state.asset_cache())
+                            .unwrap(),
+                    ]);
+                }
+
+                for reverted in state.reverted_notes() {
+                    let name = validators
+                        .iter()
+                        .find(|v| v.validator.identity_key == reverted.validator_identity_key)
+                        .map(|info| info.validator.name.clone())
+                        .unwrap_or_else(|| reverted.validator_identity_key.to_string());
+
+                    table.add_row(vec![
+                        format!("{} (reverted: validator slashed)", name),
+                        String::new(),
+                        String::new(),
+                        reverted
+                            .note
+                            .value()
+                            .try_format(state.asset_cache())
+                            .unwrap(),
+                    ]);
+                }
+
                 let total = Value {
                     amount: total,
                     asset_id: *STAKING_TOKEN_ASSET_ID,
@@ -274,6 +400,192 @@ impl StakeCmd {
                 ]);
                 println!("{}", table);
             }
+            StakeCmd::Rewards {
+                start_epoch,
+                end_epoch,
+                csv,
+            } => {
+                let current_epoch = state
+                    .synced_epoch()
+                    .ok_or_else(|| anyhow!("the wallet has not been synced"))?
+                    .index;
+                let end_epoch = end_epoch.unwrap_or(current_epoch).min(current_epoch);
+
+                let mut light_client = opt.light_wallet_client().await?;
+                let validators = light_client
+                    .validator_info(ValidatorInfoRequest {
+                        show_inactive: true,
+                        chain_id: state.chain_id().unwrap_or_default(),
+                    })
+                    .await?
+                    .into_inner()
+                    .try_collect::<Vec<_>>()
+                    .await?
+                    .into_iter()
+                    .map(TryInto::try_into)
+                    .collect::<Result<Vec<ValidatorInfo>, _>>()?;
+
+                let records = state.delegation_records().collect::<Vec<_>>();
+
+                // Fetch the exchange rates of each validator we've delegated to over the range, so
+                // that we can value delegations held across its boundaries.
+                let mut client = opt.thin_wallet_client().await?;
+                let mut exchange_rates = BTreeMap::new();
+                for identity_key in records
+                    .iter()
+                    .map(|record| record.delegation_token.validator())
+                    .collect::<BTreeSet<_>>()
+                {
+                    let history = client
+                        .validator_history(ValidatorHistoryRequest {
+                            identity_key: Some(identity_key.clone().into()),
+                            start_epoch_index: *start_epoch,
+                            end_epoch_index: Some(end_epoch),
+                            chain_id: state.chain_id().unwrap_or_default(),
+                        })
+                        .await?
+                        .into_inner()
+                        .try_collect::<Vec<_>>()
+                        .await?
+                        .into_iter()
+                        .map(TryInto::try_into)
+                        .collect::<Result<Vec<ValidatorEpochInfo>, _>>()?;
+
+                    for info in history {
+                        exchange_rates.insert(
+                            (identity_key.clone(), info.rate_data.epoch_index),
+                            info.rate_data.validator_exchange_rate,
+                        );
+                    }
+                }
+
+                let mut csv_rows = Vec::new();
+                let mut rewards_by_validator = BTreeMap::<IdentityKey, (i128, i128)>::new();
+                let mut skipped = 0;
+
+                for record in records.iter() {
+                    let identity_key = record.delegation_token.validator();
+
+                    // The part of the range over which the note was held.
+                    let held_from = record.received_epoch.max(*start_epoch);
+                    let held_until = record
+                        .spent_epoch
+                        .map(|spent_epoch| spent_epoch.min(end_epoch))
+                        .unwrap_or(end_epoch);
+                    if held_from >= held_until {
+                        continue;
+                    }
+
+                    let exchange_rate_at = |epoch_index: u64| {
+                        if epoch_index == record.received_epoch {
+                            record.received_exchange_rate
+                        } else if Some(epoch_index) == record.spent_epoch {
+                            record.spent_exchange_rate
+                        } else {
+                            exchange_rates
+                                .get(&(identity_key.clone(), epoch_index))
+                                .copied()
+                        }
+                    };
+
+                    let (from_rate, until_rate) =
+                        match (exchange_rate_at(held_from), exchange_rate_at(held_until)) {
+                            (Some(from_rate), Some(until_rate)) => (from_rate, until_rate),
+                            _ => {
+                                skipped += 1;
+                                continue;
+                            }
+                        };
+
+                    let reward = record.note.amount() as i128
+                        * (until_rate as i128 - from_rate as i128)
+                        / 1_0000_0000;
+                    let realized =
+                        matches!(record.spent_epoch, Some(spent_epoch) if spent_epoch <= end_epoch);
+
+                    let totals = rewards_by_validator
+                        .entry(identity_key.clone())
+                        .or_default();
+                    if realized {
+                        totals.0 += reward;
+                    } else {
+                        totals.1 += reward;
+                    }
+
+                    csv_rows.push(vec![
+                        identity_key.to_string(),
+                        hex::encode(<[u8; 32]>::from(record.note_commitment)),
+                        record.note.amount().to_string(),
+                        held_from.to_string(),
+                        from_rate.to_string(),
+                        held_until.to_string(),
+                        until_rate.to_string(),
+                        if realized { reward } else { 0 }.to_string(),
+                        if realized { 0 } else { reward }.to_string(),
+                    ]);
+                }
+
+                let mut table = Table::new();
+                table.load_preset(presets::NOTHING);
+                table.set_header(vec!["Name", "Realized", "Unrealized", "Total"]);
+                for column in 1..=3 {
+                    table
+                        .get_column_mut(column)
+                        .unwrap()
+                        .set_cell_alignment(comfy_table::CellAlignment::Right);
+                }
+
+                let (mut total_realized, mut total_unrealized) = (0, 0);
+                for (identity_key, (realized, unrealized)) in rewards_by_validator {
+                    let name = validators
+                        .iter()
+                        .find(|v| v.validator.identity_key == identity_key)
+                        .map(|info| info.validator.name.clone())
+                        .unwrap_or_else(|| identity_key.to_string());
+
+                    table.add_row(vec![
+                        name,
+                        format_reward(realized, state.asset_cache()),
+                        format_reward(unrealized, state.asset_cache()),
+                        format_reward(realized + unrealized, state.asset_cache()),
+                    ]);
+
+                    total_realized += realized;
+                    total_unrealized += unrealized;
+                }
+
+                table.add_row(vec![
+                    "Total".to_string(),
+                    format_reward(total_realized, state.asset_cache()),
+                    format_reward(total_unrealized, state.asset_cache()),
+                    format_reward(total_realized + total_unrealized, state.asset_cache()),
+                ]);
+
+                println!(
+                    "Staking rewards from epoch {} to {}:",
+                    start_epoch, end_epoch
+                );
+                println!("{}", table);
+                if skipped > 0 {
+                    println!(
+                        "{} delegation note(s) omitted because their exchange rates are not yet known",
+                        skipped
+                    );
+                }
+
+                if let Some(path) = csv {
+                    let mut file = File::create(path)
+                        .with_context(|| format!("could not create {}", path.display()))?;
+                    writeln!(
+                        file,
+                        "validator,note_commitment,delegation_tokens,start_epoch,start_exchange_rate,end_epoch,end_exchange_rate,realized_rewards,unrealized_rewards"
+                    )?;
+                    for row in csv_rows {
+                        writeln!(file, "{}", row.join(","))?;
+                    }
+                    println!("Wrote rewards report to {}", path.display());
+                }
+            }
             StakeCmd::ListValidators {
                 show_inactive,
                 detailed,
@@ -339,6 +651,78 @@ impl StakeCmd {
                     }
                 }
 
+                println!("{}", table);
+            }
+            StakeCmd::Validator {
+                identity_key,
+                cmd:
+                    ValidatorCmd::History {
+                        start_epoch,
+                        end_epoch,
+                    },
+            } => {
+                let identity_key = state.resolve_identity_key(identity_key)?;
+
+                let mut client = opt.thin_wallet_client().await?;
+
+                let history = client
+                    .validator_history(ValidatorHistoryRequest {
+                        identity_key: Some(identity_key.into()),
+                        start_epoch_index: *start_epoch,
+                        // No end epoch requests all epochs up to the latest one.
+                        end_epoch_index: *end_epoch,
+                        chain_id: state.chain_id().unwrap_or_default(),
+                    })
+                    .await?
+                    .into_inner()
+                    .try_collect::<Vec<_>>()
+                    .await?
+                    .into_iter()
+                    .map(TryInto::try_into)
+                    .collect::<Result<Vec<ValidatorEpochInfo>, _>>()?;
+
+                let mut table = Table::new();
+                table.load_preset(presets::NOTHING);
+                table.set_header(vec![
+                    "Epoch",
+                    "Exch. Rate",
+                    "Reward Rate",
+                    "Voting Power",
+                    "State",
+                    "Delegation Tokens",
+                ]);
+
+                for info in history {
+                    let exchange_rate =
+                        info.rate_data.validator_exchange_rate as f64 / 1_0000_0000.0;
+                    let reward_rate = info.rate_data.validator_reward_rate as f64 / 1_0000_0000.0;
+
+                    // The initial rates of a new validator are recorded without a status.
+                    let (voting_power, validator_state) = match info.status {
+                        Some(status) => (
+                            status.voting_power.to_string(),
+                            match status.state {
+                                ValidatorState::Unbonding { unbonding_epoch } => {
+                                    format!("Unbonding (until epoch {})", unbonding_epoch)
+                                }
+                                state => format!("{:?}", state),
+                            },
+                        ),
+                        None => (String::new(), String::new()),
+                    };
+
+                    table.add_row(vec![
+                        info.rate_data.epoch_index.to_string(),
+                        format!("{:.4}", exchange_rate),
+                        format!("{:.4}%", 100.0 * reward_rate),
+                        voting_power,
+                        validator_state,
+                        info.delegation_token_supply
+                            .map(|supply| supply.to_string())
+                            .unwrap_or_default(),
+                    ]);
+                }
+
                 println!("{}", table);
             }
         }
@@ -346,3 +730,18 @@ impl StakeCmd {
         Ok(())
     }
 }
+
+/// Formats an amount of staking rewards in the staking token, which may be negative if the
+/// validator's exchange rate decreased.
+fn format_reward(amount: i128, cache: &asset::Cache) -> String {
+    let value = Value {
+        amount: amount.unsigned_abs() as u64,
+        asset_id: *STAKING_TOKEN_ASSET_ID,
+    };
+    let formatted = value.try_format(cache).unwrap();
+    if amount < 0 {
+        format!("-{}", formatted)
+    } else {
+        formatted
+    }
+}
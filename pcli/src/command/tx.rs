@@ -1,16 +1,102 @@
-use anyhow::{anyhow, Result};
-use penumbra_crypto::{memo, merkle::TreeExt, Value};
-use penumbra_transaction::Transaction;
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Context, Result};
+use comfy_table::{presets, Table};
+use penumbra_crypto::{
+    asset::{self, Denom},
+    memo,
+    merkle::TreeExt,
+    note, Address, Note, PaymentRequest, Value,
+};
+use penumbra_proto::Protobuf;
+use penumbra_stake::{IdentityKey, STAKING_TOKEN_DENOM};
+use penumbra_transaction::{ActionPlan, Builder, Transaction, TransactionPlan};
+use penumbra_wallet::{
+    ClientState, Contact, NoteSource, Payment, SubmittedTransactionStatus, TransactionHistoryEntry,
+};
 use rand_core::OsRng;
+use serde::Serialize;
 use structopt::StructOpt;
 
-use crate::{ClientStateFile, Opt};
+use crate::{state, wait_for_transaction, ClientStateFile, Opt};
 
 #[derive(Debug, StructOpt)]
 pub enum TxCmd {
     /// Send transaction to the node.
     Send {
-        /// The destination address to send funds to.
+        /// The destination address to send funds to, or the name of a contact.
+        #[structopt(long)]
+        to: String,
+        /// The amounts to send, written as typed values 1.87penumbra, 12cubes, etc.
+        values: Vec<String>,
+        /// The transaction fee (paid in upenumbra).
+        #[structopt(long, default_value = "0")]
+        fee: u64,
+        /// Optional. Only spend funds originally received by the given address index.
+        #[structopt(long)]
+        source: Option<u64>,
+        /// Optional. Spend exactly the notes with the given commitments, as listed by
+        /// `pcli balance --by-note`.
+        #[structopt(long, conflicts_with = "source")]
+        spend: Vec<note::Commitment>,
+        /// Optional. Set the transaction's memo field to the provided text.
+        #[structopt(long)]
+        memo: Option<String>,
+    },
+    /// Pays a `penumbra:` payment request URI, after showing the payment and asking for
+    /// confirmation.
+    Pay {
+        /// The payment request URI, as made by `pcli addr request`.
+        request: PaymentRequest,
+        /// The amounts to send, written as typed values 1.87penumbra, 12cubes, etc. Only given if
+        /// the request doesn't specify them.
+        values: Vec<String>,
+        /// The transaction fee (paid in upenumbra).
+        #[structopt(long, default_value = "0")]
+        fee: u64,
+        /// Optional. Only spend funds originally received by the given address index.
+        #[structopt(long)]
+        source: Option<u64>,
+        /// Optional. Spend exactly the notes with the given commitments, as listed by
+        /// `pcli balance --by-note`.
+        #[structopt(long, conflicts_with = "source")]
+        spend: Vec<note::Commitment>,
+        /// Send the payment without asking for confirmation.
+        #[structopt(short, long)]
+        yes: bool,
+    },
+    /// Sends payments to many recipients, listed in a CSV file, batching them into as few
+    /// transactions as possible.
+    ///
+    /// Each line of the file is `address,value[,memo]`, where the address may be the name of a
+    /// contact and the value is a typed value such as 1.87penumbra; a recipient paid in several
+    /// denominations has a line for each. Every line is checked, and every transaction built from
+    /// its own notes, before anything is sent, so the payments don't wait for the change of
+    /// earlier transactions.
+    SendBatch {
+        /// The CSV file listing the payments.
+        payments: PathBuf,
+        /// The fee of each transaction (paid in upenumbra).
+        #[structopt(long, default_value = "0")]
+        fee: u64,
+        /// The maximum number of payments in each transaction.
+        #[structopt(long, default_value = "16")]
+        max_payments: usize,
+    },
+    /// Plans a transaction sending funds, without signing it, and writes the plan to a file.
+    ///
+    /// The plan can be signed with `pcli tx sign` on a machine holding the spend key, and the
+    /// signed transaction submitted with `pcli tx broadcast`. The notes it spends are set aside
+    /// until then, or until the plan's expiry height if the transaction is never included in a
+    /// block.
+    Plan {
+        /// The file to write the transaction plan to.
+        #[structopt(short, long)]
+        output: PathBuf,
+        /// The destination address to send funds to, or the name of a contact.
         #[structopt(long)]
         to: String,
         /// The amounts to send, written as typed values 1.87penumbra, 12cubes, etc.
@@ -21,19 +107,74 @@ pub enum TxCmd {
         /// Optional. Only spend funds originally received by the given address index.
         #[structopt(long)]
         source: Option<u64>,
+        /// Optional. Spend exactly the notes with the given commitments, as listed by
+        /// `pcli balance --by-note`.
+        #[structopt(long, conflicts_with = "source")]
+        spend: Vec<note::Commitment>,
         /// Optional. Set the transaction's memo field to the provided text.
         #[structopt(long)]
         memo: Option<String>,
     },
+    /// Signs a transaction plan with the wallet's spend key, and writes the signed transaction to
+    /// a file.
+    ///
+    /// Signing needs neither network access nor a synced wallet.
+    Sign {
+        /// The file containing the transaction plan.
+        plan: PathBuf,
+        /// The file to write the signed transaction to.
+        #[structopt(short, long)]
+        output: PathBuf,
+    },
+    /// Submits a signed transaction to the node.
+    Broadcast {
+        /// The file containing the signed transaction.
+        transaction: PathBuf,
+    },
     /// Sweeps small notes of the same denomination into a few larger notes.
     ///
     /// Since Penumbra transactions reveal their arity (how many spends,
     /// outputs, etc), but transactions are unlinkable from each other, it is
     /// slightly preferable to sweep small notes into larger ones in an isolated
     /// "sweep" transaction, rather than at the point that they should be spent.
+    /// Transactions are padded with dummy spends and outputs only up to
+    /// `--min-spends` and `--min-outputs`, and padding doesn't hide their
+    /// arity under the current transparent proofs anyway.
+    ///
+    /// Frozen notes are never swept.
     ///
     /// Currently, only zero-fee sweep transactions are implemented.
     Sweep,
+    /// Lists the transactions which involved notes of this wallet.
+    History {
+        /// Only list transactions involving notes received by or spent from the given address
+        /// index.
+        #[structopt(long)]
+        address: Option<u64>,
+        /// Only list transactions involving notes of the given denomination.
+        #[structopt(long)]
+        asset: Option<String>,
+        /// Only list transactions included at or after this height.
+        #[structopt(long)]
+        start_height: Option<u64>,
+        /// Only list transactions included at or before this height.
+        #[structopt(long)]
+        end_height: Option<u64>,
+        /// If set, prints the history as JSON rather than as a table.
+        #[structopt(long)]
+        json: bool,
+        /// If set, does not attempt to synchronize the wallet before printing the history.
+        #[structopt(long)]
+        offline: bool,
+    },
+    /// Shows whether a transaction has been confirmed, is still pending, or has expired.
+    Status {
+        /// The hex-encoded ID of the transaction, or of a transaction plan.
+        id: String,
+        /// If set, does not attempt to synchronize the wallet before printing the status.
+        #[structopt(long)]
+        offline: bool,
+    },
 }
 
 impl TxCmd {
@@ -41,7 +182,31 @@ impl TxCmd {
     pub fn needs_sync(&self) -> bool {
         match self {
             TxCmd::Send { .. } => true,
+            TxCmd::Pay { .. } => true,
+            TxCmd::SendBatch { .. } => true,
+            TxCmd::Plan { .. } => true,
+            TxCmd::Sign { .. } => false,
+            TxCmd::Broadcast { .. } => false,
             TxCmd::Sweep { .. } => true,
+            TxCmd::History { offline, .. } => !offline,
+            TxCmd::Status { offline, .. } => !offline,
+        }
+    }
+
+    /// Determine if this command needs the spend key, and so the wallet password if the wallet
+    /// is encrypted.
+    pub fn needs_spend_key(&self) -> bool {
+        match self {
+            TxCmd::Send { .. } => true,
+            TxCmd::Pay { .. } => true,
+            TxCmd::SendBatch { .. } => true,
+            TxCmd::Plan { .. } => false,
+            // Signing loads the spend key by itself.
+            TxCmd::Sign { .. } => false,
+            TxCmd::Broadcast { .. } => false,
+            TxCmd::Sweep { .. } => true,
+            TxCmd::History { .. } => false,
+            TxCmd::Status { .. } => false,
         }
     }
 
@@ -52,6 +217,7 @@ impl TxCmd {
                 to,
                 fee,
                 source: from,
+                spend,
                 memo,
             } => {
                 // Parse all of the values provided.
@@ -59,31 +225,524 @@ impl TxCmd {
                     .iter()
                     .map(|v| v.parse())
                     .collect::<Result<Vec<Value>, _>>()?;
-                let to = to
-                    .parse()
-                    .map_err(|_| anyhow::anyhow!("address is invalid"))?;
+                let to = state.resolve_address(to)?;
+
+                let payment = Payment {
+                    address: to,
+                    values,
+                    memo: memo.clone(),
+                };
+                send(opt, state, payment, *fee, note_source(*from, spend)).await?;
+            }
+            TxCmd::Pay {
+                request,
+                values,
+                fee,
+                source: from,
+                spend,
+                yes,
+            } => {
+                let values = match (request.amounts.is_empty(), values.is_empty()) {
+                    (true, true) => {
+                        return Err(anyhow!(
+                            "the payment request doesn't specify an amount, so give the amounts \
+                            to send"
+                        ))
+                    }
+                    (true, false) => values
+                        .iter()
+                        .map(|v| v.parse())
+                        .collect::<Result<Vec<Value>, _>>()?,
+                    (false, true) => request.amounts.clone(),
+                    (false, false) => {
+                        return Err(anyhow!(
+                            "the payment request already specifies the amounts to send"
+                        ))
+                    }
+                };
+
+                if let Some(label) = &request.label {
+                    println!("Payment request: {}", label);
+                }
+                println!(
+                    "Pay {} to {}",
+                    values
+                        .iter()
+                        .map(|value| format_value(state, *value))
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                    format_contact(state, Contact::Address(request.address))
+                );
+                if let Some(memo) = &request.memo {
+                    println!("Memo: {}", memo);
+                }
+                println!(
+                    "Fee: {}",
+                    format_value(state, STAKING_TOKEN_DENOM.value(*fee))
+                );
+                if !yes && !confirm("Send this payment?")? {
+                    println!("Payment cancelled");
+                    return Ok(());
+                }
+
+                let payment = Payment {
+                    address: request.address,
+                    values,
+                    memo: request.memo.clone(),
+                };
+                send(opt, state, payment, *fee, note_source(*from, spend)).await?;
+            }
+            TxCmd::SendBatch {
+                payments,
+                fee,
+                max_payments,
+            } => {
+                send_batch(opt, state, payments, *fee, *max_payments).await?;
+            }
+            TxCmd::Plan {
+                output,
+                values,
+                to,
+                fee,
+                source: from,
+                spend,
+                memo,
+            } => {
+                let values = values
+                    .iter()
+                    .map(|v| v.parse())
+                    .collect::<Result<Vec<Value>, _>>()?;
+                let to = state.resolve_address(to)?;
 
-                let transaction =
-                    state.build_send(&mut OsRng, &values, *fee, to, *from, memo.clone())?;
+                let payment = Payment {
+                    address: to,
+                    values,
+                    memo: memo.clone(),
+                };
+                let plan = state.plan_send(
+                    &mut OsRng,
+                    opt.account,
+                    &[payment],
+                    *fee,
+                    note_source(*from, spend),
+                )?;
+                std::fs::write(output, plan.encode_to_vec())
+                    .with_context(|| format!("could not write plan to {}", output.display()))?;
+                // Track the plan until its expiry height, so that the notes it spends aren't spent
+                // again while it is being signed and broadcast.
+                let plan_id = state.register_plan(&plan);
+                state.commit().await?;
+                println!("Wrote transaction plan to {}", output.display());
+                println!(
+                    "The notes it spends are set aside until it is broadcast, or until height {} \
+                    if it is never included in a block (plan ID {})",
+                    plan.expiry_height,
+                    hex::encode(plan_id)
+                );
+            }
+            TxCmd::Sign { .. } => unreachable!("sign command is executed without client state"),
+            TxCmd::Broadcast { transaction } => {
+                let transaction = Transaction::decode(std::fs::read(transaction)?.as_slice())
+                    .context("could not parse signed transaction")?;
 
                 opt.submit_transaction(&transaction).await?;
-                // Only commit the state if the transaction was submitted
-                // successfully, so that we don't store pending notes that will
-                // never appear on-chain.
-                state.commit()?;
+                state.register_transaction(&transaction);
+                state.commit().await?;
+                if opt.wait {
+                    wait_for_transaction(opt, state, transaction.id()).await?;
+                }
             }
             TxCmd::Sweep => {
                 sweep(opt, state).await?;
             }
+            TxCmd::History {
+                address,
+                asset,
+                start_height,
+                end_height,
+                json,
+                ..
+            } => {
+                let denom = asset
+                    .as_ref()
+                    .map(|asset| {
+                        asset::REGISTRY
+                            .parse_denom(asset)
+                            .ok_or_else(|| anyhow!("invalid denomination {}", asset))
+                    })
+                    .transpose()?;
+
+                let state: &ClientState = state;
+                let entries = state
+                    .transaction_history()
+                    .filter(|entry| {
+                        start_height.map_or(true, |start| entry.height >= start)
+                            && end_height.map_or(true, |end| entry.height <= end)
+                            && address.map_or(true, |index| {
+                                involves_address(state, opt.account, entry, index)
+                            })
+                            && denom
+                                .as_ref()
+                                .map_or(true, |denom| involves_denom(entry, denom))
+                    })
+                    .collect::<Vec<_>>();
+
+                if *json {
+                    let entries = entries
+                        .iter()
+                        .map(|entry| HistoryJson::new(state, entry))
+                        .collect::<Vec<_>>();
+                    println!("{}", serde_json::to_string_pretty(&entries)?);
+                } else {
+                    print_history(state, &entries);
+                }
+            }
+            TxCmd::Status { id, .. } => {
+                let transaction_id: [u8; 32] = hex::decode(id)
+                    .ok()
+                    .and_then(|bytes| bytes.try_into().ok())
+                    .ok_or_else(|| anyhow!("invalid transaction id {}", id))?;
+
+                match state.submitted_transaction(&transaction_id) {
+                    Some(submitted) => match (submitted.status, submitted.expiry_height) {
+                        (SubmittedTransactionStatus::Confirmed { height }, _) => {
+                            println!("Transaction {} was confirmed at height {}", id, height)
+                        }
+                        (SubmittedTransactionStatus::Planned, _) => println!(
+                            "Transaction plan {} has not been broadcast from this wallet",
+                            id
+                        ),
+                        (SubmittedTransactionStatus::Pending, Some(expiry_height)) => println!(
+                            "Transaction {} is pending, and expires if it isn't included by \
+                            height {}",
+                            id, expiry_height
+                        ),
+                        (SubmittedTransactionStatus::Pending, None) => {
+                            println!("Transaction {} is pending", id)
+                        }
+                        (SubmittedTransactionStatus::Expired, _) => println!(
+                            "Transaction {} expired without being included in a block",
+                            id
+                        ),
+                        (SubmittedTransactionStatus::Conflicted { height }, _) => println!(
+                            "Transaction {} conflicts with another transaction spending the same \
+                            notes, which was included at height {}",
+                            id, height
+                        ),
+                    },
+                    // We may have scanned a transaction involving our notes which we didn't
+                    // submit from this wallet.
+                    None => match state.transaction_height(&transaction_id) {
+                        Some(height) => {
+                            println!("Transaction {} was included at height {}", id, height)
+                        }
+                        None => println!("Transaction {} is unknown to this wallet", id),
+                    },
+                }
+            }
         }
         Ok(())
     }
 }
 
+/// Builds and submits a transaction making a single payment.
+async fn send(
+    opt: &Opt,
+    state: &mut ClientStateFile,
+    payment: Payment,
+    fee: u64,
+    source: NoteSource,
+) -> Result<()> {
+    let transaction = state.build_send(&mut OsRng, opt.account, &[payment], fee, source)?;
+
+    opt.submit_transaction(&transaction).await?;
+    state.register_transaction(&transaction);
+    // Only commit the state if the transaction was submitted
+    // successfully, so that we don't store pending notes that will
+    // never appear on-chain.
+    state.commit().await?;
+    if opt.wait {
+        wait_for_transaction(opt, state, transaction.id()).await?;
+    }
+
+    Ok(())
+}
+
+/// Asks a yes-or-no question on the terminal, taking anything but yes as no.
+fn confirm(question: &str) -> Result<bool> {
+    print!("{} [y/N] ", question);
+    std::io::stdout().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
+
+/// A payment read from a batch payments file.
+struct BatchPayment {
+    /// The line of the file the payment was read from.
+    line: usize,
+    payment: Payment,
+}
+
+/// Parses a batch payments file, reporting every invalid line at once.
+fn parse_batch(state: &ClientState, contents: &str) -> Result<Vec<BatchPayment>> {
+    let mut payments = Vec::new();
+    let mut errors = Vec::new();
+    for (index, line) in contents.lines().enumerate() {
+        let line_number = index + 1;
+        let line = line.trim();
+        // Skip blank lines, comments, and a header line
+        if line.is_empty() || line.starts_with('#') || (index == 0 && line.starts_with("address,"))
+        {
+            continue;
+        }
+
+        let mut fields = line.splitn(3, ',').map(str::trim);
+        let address = fields.next().unwrap_or_default();
+        let value = fields.next().unwrap_or_default();
+        let memo = fields
+            .next()
+            .filter(|memo| !memo.is_empty())
+            .map(ToString::to_string);
+
+        let address: Address = match state.resolve_address(address) {
+            Ok(address) => address,
+            Err(err) => {
+                errors.push(format!("line {}: {}", line_number, err));
+                continue;
+            }
+        };
+        let value: Value = match value.parse() {
+            Ok(value) => value,
+            Err(err) => {
+                errors.push(format!(
+                    "line {}: invalid value {:?}: {}",
+                    line_number, value, err
+                ));
+                continue;
+            }
+        };
+        if value.amount == 0 {
+            errors.push(format!("line {}: the amount to send is zero", line_number));
+            continue;
+        }
+        if state.asset_cache().get(&value.asset_id).is_none() {
+            errors.push(format!(
+                "line {}: unknown asset {}",
+                line_number, value.asset_id
+            ));
+            continue;
+        }
+        if let Some(Err(err)) = memo.clone().map(memo::MemoPlaintext::try_from) {
+            errors.push(format!("line {}: {}", line_number, err));
+            continue;
+        }
+
+        payments.push(BatchPayment {
+            line: line_number,
+            payment: Payment {
+                address,
+                values: vec![value],
+                memo,
+            },
+        });
+    }
+
+    if !errors.is_empty() {
+        return Err(anyhow!("invalid payments file:\n{}", errors.join("\n")));
+    }
+    if payments.is_empty() {
+        return Err(anyhow!("the payments file lists no payments"));
+    }
+    Ok(payments)
+}
+
+/// Sends the payments listed in the file at `path`, in transactions of at most `max_payments`
+/// payments each, and prints the status of each payment.
+///
+/// Every transaction is built before any is submitted, each spending notes none of the earlier
+/// ones spend, so that nothing is sent unless the account can make every payment under the
+/// address linking policy, without waiting for the change of earlier transactions.
+async fn send_batch(
+    opt: &Opt,
+    state: &mut ClientStateFile,
+    path: &Path,
+    fee: u64,
+    max_payments: usize,
+) -> Result<()> {
+    if max_payments == 0 {
+        return Err(anyhow!("transactions must be allowed at least one payment"));
+    }
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("could not read payments from {}", path.display()))?;
+    let payments = parse_batch(state, &contents)?;
+    let batches = payments.chunks(max_payments).collect::<Vec<_>>();
+
+    // If any transaction can't be built, the state isn't committed, so the notes spent by those
+    // already built are left unspent.
+    let mut transactions = Vec::with_capacity(batches.len());
+    for batch in &batches {
+        let batch_payments = batch
+            .iter()
+            .map(|payment| payment.payment.clone())
+            .collect::<Vec<_>>();
+        let transaction = state
+            .build_send(
+                &mut OsRng,
+                opt.account,
+                &batch_payments,
+                fee,
+                NoteSource::Any,
+            )
+            .with_context(|| {
+                format!(
+                    "could not make the payments on lines {} to {}, so none were sent",
+                    batch[0].line,
+                    batch[batch.len() - 1].line
+                )
+            })?;
+        transactions.push(transaction);
+    }
+
+    // Submit each transaction in turn, stopping at the first failure, and returning the notes
+    // spent by that and any later transactions.
+    let mut statuses = vec!["not sent".to_string(); payments.len()];
+    let mut submitted = Vec::new();
+    let mut failed = false;
+    let mut transactions = transactions.into_iter().zip(&batches).enumerate();
+    for (index, (transaction, batch)) in transactions.by_ref() {
+        let first = index * max_payments;
+        match opt.submit_transaction(&transaction).await {
+            Ok(()) => {
+                state.register_transaction(&transaction);
+                // Commit after every transaction, so that a later failure doesn't lose track of
+                // the payments already made.
+                state.commit().await?;
+                for status in &mut statuses[first..first + batch.len()] {
+                    *status = format!("sent in transaction {}", hex::encode(transaction.id()));
+                }
+                submitted.push((first, batch.len(), transaction.id()));
+            }
+            Err(err) => {
+                for status in &mut statuses[first..first + batch.len()] {
+                    *status = format!("failed: {}", err);
+                }
+                state.cancel_transaction(&transaction);
+                failed = true;
+                break;
+            }
+        }
+    }
+    for (_, (transaction, _)) in transactions {
+        state.cancel_transaction(&transaction);
+    }
+    state.commit().await?;
+
+    if opt.wait {
+        for (first, len, transaction_id) in submitted {
+            let status = match wait_for_transaction(opt, state, transaction_id).await {
+                Ok(()) => format!("confirmed in transaction {}", hex::encode(transaction_id)),
+                Err(err) => {
+                    failed = true;
+                    format!("failed: {}", err)
+                }
+            };
+            for payment_status in &mut statuses[first..first + len] {
+                *payment_status = status.clone();
+            }
+        }
+    }
+
+    let mut table = Table::new();
+    table.load_preset(presets::NOTHING);
+    table.set_header(vec!["Line", "Address", "Value", "Status"]);
+    for (payment, status) in payments.iter().zip(statuses) {
+        table.add_row(vec![
+            payment.line.to_string(),
+            payment.payment.address.to_string(),
+            payment.payment.values[0]
+                .try_format(state.asset_cache())
+                .unwrap(),
+            status,
+        ]);
+    }
+    println!("{}", table);
+
+    if failed {
+        return Err(anyhow!(
+            "not every payment was sent; the payments which weren't can be sent again"
+        ));
+    }
+    Ok(())
+}
+
+/// Returns the notes to spend given the `--source` and `--spend` options.
+fn note_source(source: Option<u64>, spend: &[note::Commitment]) -> NoteSource {
+    match source {
+        Some(index) => NoteSource::Address(index),
+        None if !spend.is_empty() => NoteSource::Notes(spend.to_vec()),
+        None => NoteSource::Any,
+    }
+}
+
+/// Signs the transaction plan at `plan_path` with the spend key of the given account of the wallet
+/// at `wallet_path`, writing the signed transaction to `output_path`.
+///
+/// This is run without loading the rest of the client state, so that it can be used on a machine
+/// which has never synced.
+pub async fn sign(
+    plan_path: &Path,
+    output_path: &Path,
+    wallet_path: &Path,
+    account: u64,
+) -> Result<()> {
+    let plan = TransactionPlan::decode(std::fs::read(plan_path)?.as_slice())
+        .context("could not parse transaction plan")?;
+    let wallet = state::load_wallet(wallet_path, account).await?;
+
+    // Show what is being signed, since the plan may have come from an untrusted machine.
+    println!("Signing a transaction on chain {}", plan.chain_id);
+    for output in plan.output_plans() {
+        println!(
+            "  sending {}{} to {}",
+            output.value.amount, output.value.asset_id, output.dest_address
+        );
+    }
+    for action in &plan.actions {
+        match action {
+            ActionPlan::Delegate(delegate) => println!(
+                "  delegating {} upenumbra to {}",
+                delegate.unbonded_amount, delegate.validator_identity
+            ),
+            ActionPlan::Undelegate(undelegate) => println!(
+                "  undelegating {} upenumbra from {}",
+                undelegate.unbonded_amount, undelegate.validator_identity
+            ),
+            _ => {}
+        }
+    }
+    println!("  paying a fee of {} upenumbra", plan.fee.0);
+
+    let spend_key = wallet.spend_key()?;
+    let auth_data = plan.authorize(&mut OsRng, spend_key);
+    let transaction =
+        Builder::finalize_plan(&mut OsRng, &plan, spend_key.full_viewing_key(), &auth_data)
+            .map_err(|err| anyhow!("error during transaction finalization: {}", err))?;
+
+    std::fs::write(output_path, transaction.encode_to_vec()).with_context(|| {
+        format!(
+            "could not write signed transaction to {}",
+            output_path.display()
+        )
+    })?;
+    println!("Wrote signed transaction to {}", output_path.display());
+    Ok(())
+}
+
 // This code is done outside of the client state as a test case for whether it's
 // possible to use that interface to implement bespoke note handling.
 //
-// For each (account, denom) pair, we do a parallel, SWEEP_COUNT-to-1 sweep of
+// For each (address, denom) pair of the selected account, we do a parallel, SWEEP_COUNT-to-1 sweep of
 // as many of that denom's notes as possible.  This command can be run multiple
 // times, and tells the user about the results of sweeping.
 //
@@ -99,8 +758,9 @@ async fn sweep(opt: &Opt, state: &mut ClientStateFile) -> Result<()> {
     // changes to be applied later.
     let mut spent_notes = Vec::new();
     let mut change_notes = Vec::new();
-    let unspent = state.unspent_notes_by_address_and_denom();
-    for (id, label, addr) in state.wallet().addresses() {
+    let unspent = state.unspent_notes_by_address_and_denom(opt.account);
+    let wallet = state.account(opt.account)?;
+    for (id, label, addr) in wallet.addresses() {
         if unspent.get(&(id as u64)).is_none() {
             continue;
         }
@@ -110,6 +770,7 @@ async fn sweep(opt: &Opt, state: &mut ClientStateFile) -> Result<()> {
             let mut notes = notes
                 .iter()
                 .filter_map(|n| n.as_ready())
+                .filter(|n| !state.is_frozen(&n.commit()))
                 .collect::<Vec<_>>();
             // Sort notes by amount, ascending, so the biggest notes are at the end...
             notes.sort_by(|a, b| a.value().amount.cmp(&b.value().amount));
@@ -119,17 +780,19 @@ async fn sweep(opt: &Opt, state: &mut ClientStateFile) -> Result<()> {
                 tracing::info!(?denom, "building sweep transaction");
                 let mut tx_builder =
                     Transaction::build_with_root(state.note_commitment_tree().root2());
-                tx_builder.set_fee(0).set_chain_id(
-                    state
-                        .chain_id()
-                        .ok_or_else(|| anyhow!("missing chain_id"))?,
-                );
+                tx_builder
+                    .set_fee(0)
+                    .set_chain_id(
+                        state
+                            .chain_id()
+                            .ok_or_else(|| anyhow!("missing chain_id"))?,
+                    )
+                    .set_expiry_height(state.transaction_expiry_height());
 
                 for note in group {
                     tx_builder.add_spend(
                         &mut OsRng,
                         state.note_commitment_tree(),
-                        state.wallet().spend_key(),
                         (*note).clone(),
                     )?;
                     spent_notes.push((*note).clone());
@@ -142,13 +805,17 @@ async fn sweep(opt: &Opt, state: &mut ClientStateFile) -> Result<()> {
                         asset_id: denom.id(),
                     },
                     memo::MemoPlaintext([0u8; 512]),
-                    state.wallet().outgoing_viewing_key(),
                 );
                 change_notes.push(change);
+                state.pad_transaction(&mut OsRng, opt.account, &mut tx_builder)?;
 
-                transactions.push(tx_builder.finalize(&mut OsRng).map_err(|err| {
-                    anyhow::anyhow!("error during transaction finalization: {}", err)
-                })?);
+                transactions.push(
+                    tx_builder
+                        .finalize(&mut OsRng, wallet.spend_key()?)
+                        .map_err(|err| {
+                            anyhow::anyhow!("error during transaction finalization: {}", err)
+                        })?,
+                );
             }
         }
     }
@@ -158,15 +825,20 @@ async fn sweep(opt: &Opt, state: &mut ClientStateFile) -> Result<()> {
 
     let num_sweeps = transactions.len();
     tracing::info!(num_sweeps, "submitting sweeps");
-    for transaction in transactions {
-        opt.submit_transaction_unconfirmed(&transaction).await?;
+    for transaction in &transactions {
+        opt.submit_transaction_unconfirmed(transaction).await?;
     }
     for spend in spent_notes {
         state.register_spend(&spend);
     }
     for change in change_notes {
-        state.register_change(change);
+        state.register_change(opt.account, change);
+    }
+    // Register the transactions once their change is registered, so that they are tracked by it.
+    for transaction in &transactions {
+        state.register_transaction(transaction);
     }
+    state.commit().await?;
 
     // Print a message to the user, so they can find out what we did.
     if num_sweeps > 0 {
@@ -175,6 +847,11 @@ async fn sweep(opt: &Opt, state: &mut ClientStateFile) -> Result<()> {
             num_sweeps * SWEEP_COUNT,
             num_sweeps,
         );
+        if opt.wait {
+            for transaction in &transactions {
+                wait_for_transaction(opt, state, transaction.id()).await?;
+            }
+        }
     } else {
         println!("finished sweeping");
         // Terminate with a non-zero exit code so it's easy to script
@@ -184,3 +861,318 @@ async fn sweep(opt: &Opt, state: &mut ClientStateFile) -> Result<()> {
 
     Ok(())
 }
+
+/// Returns true if the entry involves a note received by or spent from the address with the given
+/// index of the given account.
+fn involves_address(
+    state: &ClientState,
+    account: u64,
+    entry: &TransactionHistoryEntry,
+    index: u64,
+) -> bool {
+    entry
+        .received
+        .iter()
+        .chain(entry.spent.iter())
+        .any(|(_, note)| address_index(state, account, note) == Some(index))
+}
+
+/// Returns true if the entry involves a note of the given denomination.
+fn involves_denom(entry: &TransactionHistoryEntry, denom: &Denom) -> bool {
+    entry
+        .received
+        .iter()
+        .chain(entry.spent.iter())
+        .map(|(_, note)| *note)
+        .chain(entry.sent.iter().map(|(_, sent)| &sent.note))
+        .any(|note| note.asset_id() == denom.id())
+}
+
+/// Returns the index of the address of the given account to which the note was sent, if it was
+/// sent to that account.
+fn address_index(state: &ClientState, account: u64, note: &Note) -> Option<u64> {
+    let ivk = state.account(account).ok()?.incoming_viewing_key();
+    let index = ivk.index_for_diversifier(&note.diversifier());
+    let (address, _dtk) = ivk.payment_address(index);
+    if *address.transmission_key() != note.transmission_key() {
+        return None;
+    }
+    index.try_into().ok()
+}
+
+fn format_value(state: &ClientState, value: Value) -> String {
+    value
+        .try_format(state.asset_cache())
+        .unwrap_or_else(|| format!("{}{}", value.amount, value.asset_id))
+}
+
+/// Formats an address or validator as its name in the address book, if it has one.
+fn format_contact(state: &ClientState, contact: Contact) -> String {
+    state
+        .contact_name(&contact)
+        .map_or_else(|| contact.to_string(), ToString::to_string)
+}
+
+/// A transaction history entry, formatted for JSON output.
+#[derive(Serialize)]
+struct HistoryJson {
+    height: u64,
+    transaction_id: String,
+    received: Vec<String>,
+    spent: Vec<String>,
+    sent: Vec<SentJson>,
+    fee: Option<String>,
+    memo: Option<String>,
+    delegations: Vec<StakeJson>,
+    undelegations: Vec<StakeJson>,
+}
+
+#[derive(Serialize)]
+struct SentJson {
+    value: String,
+    recipient: String,
+    recipient_name: Option<String>,
+}
+
+#[derive(Serialize)]
+struct StakeJson {
+    validator: String,
+    validator_name: Option<String>,
+    epoch_index: u64,
+    unbonded_amount: String,
+    delegation_amount: u64,
+}
+
+impl HistoryJson {
+    fn new(state: &ClientState, entry: &TransactionHistoryEntry) -> Self {
+        let fee = entry
+            .details
+            .map(|details| format_value(state, STAKING_TOKEN_DENOM.value(details.fee)));
+        let stake =
+            |validator: &IdentityKey, epoch_index, unbonded_amount, delegation_amount| StakeJson {
+                validator: validator.to_string(),
+                validator_name: state
+                    .contact_name(&Contact::Validator(validator.clone()))
+                    .map(ToString::to_string),
+                epoch_index,
+                unbonded_amount: format_value(state, STAKING_TOKEN_DENOM.value(unbonded_amount)),
+                delegation_amount,
+            };
+
+        HistoryJson {
+            height: entry.height,
+            transaction_id: hex::encode(entry.transaction_id),
+            received: entry
+                .received
+                .iter()
+                .map(|(_, note)| format_value(state, note.value()))
+                .collect(),
+            spent: entry
+                .spent
+                .iter()
+                .map(|(_, note)| format_value(state, note.value()))
+                .collect(),
+            sent: entry
+                .sent
+                .iter()
+                .map(|(_, sent)| SentJson {
+                    value: format_value(state, sent.note.value()),
+                    recipient: sent.recipient.to_string(),
+                    recipient_name: state
+                        .contact_name(&Contact::Address(sent.recipient))
+                        .map(ToString::to_string),
+                })
+                .collect(),
+            fee,
+            memo: entry.memo.map(ToString::to_string),
+            delegations: entry
+                .details
+                .iter()
+                .flat_map(|details| details.delegations.iter())
+                .map(|d| {
+                    stake(
+                        &d.validator_identity,
+                        d.epoch_index,
+                        d.unbonded_amount,
+                        d.delegation_amount,
+                    )
+                })
+                .collect(),
+            undelegations: entry
+                .details
+                .iter()
+                .flat_map(|details| details.undelegations.iter())
+                .map(|u| {
+                    stake(
+                        &u.validator_identity,
+                        u.epoch_index,
+                        u.unbonded_amount,
+                        u.delegation_amount,
+                    )
+                })
+                .collect(),
+        }
+    }
+}
+
+fn print_history(state: &ClientState, entries: &[TransactionHistoryEntry]) {
+    let mut table = Table::new();
+    table.load_preset(presets::NOTHING);
+    table.set_header(vec![
+        "Height",
+        "Transaction ID",
+        "Received",
+        "Spent",
+        "Sent",
+        "Fee",
+        "Staking",
+        "Memo",
+    ]);
+
+    for entry in entries {
+        let received = entry
+            .received
+            .iter()
+            .map(|(_, note)| format_value(state, note.value()))
+            .collect::<Vec<_>>();
+        let spent = entry
+            .spent
+            .iter()
+            .map(|(_, note)| format_value(state, note.value()))
+            .collect::<Vec<_>>();
+        // Sends to our own addresses are already listed as received notes.
+        let sent = entry
+            .sent
+            .iter()
+            .filter(|(commitment, _)| !entry.received.iter().any(|(c, _)| c == commitment))
+            .map(|(_, sent)| {
+                format!(
+                    "{} to {}",
+                    format_value(state, sent.note.value()),
+                    format_contact(state, Contact::Address(sent.recipient))
+                )
+            })
+            .collect::<Vec<_>>();
+        let fee = entry
+            .details
+            .map(|details| format_value(state, STAKING_TOKEN_DENOM.value(details.fee)))
+            .unwrap_or_default();
+        let staking = entry
+            .details
+            .iter()
+            .flat_map(|details| {
+                details
+                    .delegations
+                    .iter()
+                    .map(|d| {
+                        format!(
+                            "delegated {} to {}",
+                            format_value(state, STAKING_TOKEN_DENOM.value(d.unbonded_amount)),
+                            format_contact(state, Contact::Validator(d.validator_identity.clone()))
+                        )
+                    })
+                    .chain(details.undelegations.iter().map(|u| {
+                        format!(
+                            "undelegated {} from {}",
+                            format_value(state, STAKING_TOKEN_DENOM.value(u.unbonded_amount)),
+                            format_contact(state, Contact::Validator(u.validator_identity.clone()))
+                        )
+                    }))
+            })
+            .collect::<Vec<_>>();
+
+        table.add_row(vec![
+            entry.height.to_string(),
+            hex::encode(entry.transaction_id),
+            received.join("\n"),
+            spent.join("\n"),
+            sent.join("\n"),
+            fee,
+            staking.join("\n"),
+            entry.memo.map(ToString::to_string).unwrap_or_default(),
+        ]);
+    }
+
+    println!("{}", table);
+}
+
+#[cfg(test)]
+mod tests {
+    use penumbra_crypto::keys::SeedPhrase;
+
+    use super::*;
+
+    fn client_state() -> ClientState {
+        let mut state = ClientState::from_seed_phrase(SeedPhrase::generate(&mut OsRng));
+        state
+            .asset_cache_mut()
+            .extend([STAKING_TOKEN_DENOM.clone()]);
+        state
+    }
+
+    fn address(state: &ClientState, index: usize) -> Address {
+        state.account(0).unwrap().address_by_index(index).unwrap().1
+    }
+
+    #[test]
+    fn parse_batch_reads_payments() {
+        let mut state = client_state();
+        let alice = address(&state, 1);
+        let bob = address(&state, 2);
+        state
+            .add_contact("bob".to_string(), Contact::Address(bob))
+            .unwrap();
+
+        let contents = format!(
+            "address,value,memo\n\
+            {}, 10penumbra\n\
+            \n\
+            # Bob is paid in two denominations\n\
+            bob,1500mpenumbra,thanks, bob\n\
+            bob,3upenumbra,\n",
+            alice
+        );
+        let payments = parse_batch(&state, &contents).unwrap();
+
+        let lines = payments
+            .iter()
+            .map(|payment| payment.line)
+            .collect::<Vec<_>>();
+        assert_eq!(lines, vec![2, 5, 6]);
+        assert_eq!(payments[0].payment.address, alice);
+        assert_eq!(
+            payments[0].payment.values,
+            vec![STAKING_TOKEN_DENOM.value(10_000_000)]
+        );
+        assert_eq!(payments[0].payment.memo, None);
+        assert_eq!(payments[1].payment.address, bob);
+        assert_eq!(
+            payments[1].payment.values,
+            vec![STAKING_TOKEN_DENOM.value(1_500_000)]
+        );
+        assert_eq!(payments[1].payment.memo.as_deref(), Some("thanks, bob"));
+        assert_eq!(payments[2].payment.memo, None);
+    }
+
+    #[test]
+    fn parse_batch_reports_every_invalid_line() {
+        let state = client_state();
+        let alice = address(&state, 1);
+
+        let contents = format!(
+            "carol,1penumbra\n\
+            {alice},0penumbra\n\
+            {alice},5cubes\n\
+            {alice},notavalue\n\
+            {alice},1penumbra\n",
+            alice = alice
+        );
+        let err = parse_batch(&state, &contents).unwrap_err().to_string();
+        for line in 1..=4 {
+            assert!(err.contains(&format!("line {}:", line)), "{}", err);
+        }
+        assert!(!err.contains("line 5:"), "{}", err);
+
+        assert!(parse_batch(&state, "address,value\n# nothing to pay\n").is_err());
+    }
+}
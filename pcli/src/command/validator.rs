@@ -18,12 +18,12 @@ impl ValidatorCmd {
         }
     }
 
-    pub async fn exec(&self, _opt: &Opt, state: &ClientState) -> Result<()> {
+    pub async fn exec(&self, opt: &Opt, state: &ClientState) -> Result<()> {
         match self {
             ValidatorCmd::Identity => {
                 let ik = IdentityKey(
                     state
-                        .wallet()
+                        .account(opt.account)?
                         .full_viewing_key()
                         .spend_verification_key()
                         .clone(),
@@ -0,0 +1,97 @@
+use std::{net::SocketAddr, path::PathBuf, time::Duration};
+
+use anyhow::{Context, Result};
+use penumbra_proto::view::{
+    view_protocol_client::ViewProtocolClient, view_protocol_server::ViewProtocolServer,
+    StatusRequest,
+};
+use structopt::StructOpt;
+
+use crate::{fetch, view::ViewService, ClientStateFile, Opt};
+
+#[derive(Debug, StructOpt)]
+pub enum ViewCmd {
+    /// Runs the view service, keeping the wallet synced and serving its state to local clients.
+    ///
+    /// The view service holds the wallet lock while it runs, so other `pcli` commands using the
+    /// same wallet will wait until it is stopped, rather than using the view service.
+    Serve {
+        /// The address to bind the view service to.
+        #[structopt(long, default_value = "127.0.0.1")]
+        host: String,
+        /// The port to bind the view service to.
+        #[structopt(long, default_value = "26668")]
+        port: u16,
+        /// How often to sync with the node, in seconds.
+        #[structopt(long, default_value = "5")]
+        sync_interval: u64,
+    },
+    /// Queries the sync status of a running view service.
+    Status {
+        /// The port of the view service.
+        #[structopt(long, default_value = "26668")]
+        port: u16,
+    },
+}
+
+impl ViewCmd {
+    /// Determine if this command requires a network sync before it executes.
+    pub fn needs_sync(&self) -> bool {
+        match self {
+            // The view service syncs by itself, in the background.
+            ViewCmd::Serve { .. } => false,
+            ViewCmd::Status { .. } => false,
+        }
+    }
+
+    pub async fn exec(&self, opt: &Opt, wallet_path: PathBuf) -> Result<()> {
+        match self {
+            ViewCmd::Serve {
+                host,
+                port,
+                sync_interval,
+            } => {
+                let addr: SocketAddr = format!("{}:{}", host, port)
+                    .parse()
+                    .with_context(|| format!("invalid view service address {}:{}", host, port))?;
+
+                // The view service only needs viewing keys, so that it can run unattended.
+                let mut state = ClientStateFile::load(wallet_path, false).await?;
+                if state.chain_params().is_none() {
+                    fetch::chain_params(opt, &mut state).await?;
+                }
+
+                let service = ViewService::new(state);
+                println!("Serving the view protocol on {}", addr);
+                tokio::try_join!(
+                    async {
+                        tonic::transport::Server::builder()
+                            .add_service(ViewProtocolServer::new(service.clone()))
+                            .serve(addr)
+                            .await
+                            .context("view service failed")
+                    },
+                    service.sync_forever(opt, Duration::from_secs(*sync_interval)),
+                )?;
+            }
+            ViewCmd::Status { port } => {
+                let mut client = ViewProtocolClient::connect(format!("http://127.0.0.1:{}", port))
+                    .await
+                    .context(
+                        "could not connect to the view service, is `pcli view serve` running?",
+                    )?;
+                let status = client
+                    .status(tonic::Request::new(StatusRequest {}))
+                    .await?
+                    .into_inner();
+                if status.catching_up {
+                    println!("Catching up, synced to height {}", status.sync_height);
+                } else {
+                    println!("Synced to height {}", status.sync_height);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -1,15 +1,20 @@
-use std::{fs::File, io::BufReader, path::PathBuf, str::FromStr};
+use std::{ffi::OsString, path::PathBuf, str::FromStr};
 
 use anyhow::{anyhow, Context as _, Result};
 use directories::ProjectDirs;
-use penumbra_crypto::keys::{SeedPhrase, SpendSeed};
-use penumbra_wallet::{ClientState, Wallet};
+use penumbra_crypto::keys::{FullViewingKey, SeedPhrase, SpendSeed};
+use penumbra_wallet::{ClientState, KeyProtection, Storage, Wallet};
 use rand_core::OsRng;
-use serde::Deserialize;
 use sha2::{Digest, Sha256};
 use structopt::StructOpt;
 
-use crate::ClientStateFile;
+use crate::{
+    state::{
+        imported_legacy_wallet_path, legacy_wallet_path, prompt_new_password, prompt_password,
+        StorageFile,
+    },
+    ClientStateFile,
+};
 
 #[derive(Debug, StructOpt)]
 pub enum WalletCmd {
@@ -22,15 +27,66 @@ pub enum WalletCmd {
     ImportFromPhrase {
         /// A 24 word phrase in quotes.
         seed_phrase: String,
+        /// Optional. The height of the block in which the wallet was created: earlier blocks are
+        /// not searched for its notes, which makes the first sync faster.
+        #[structopt(long)]
+        birthday: Option<u64>,
+    },
+    /// Import a full viewing key, creating a watch-only wallet.
+    ///
+    /// A watch-only wallet syncs, shows balances and history, and plans transactions with `pcli tx
+    /// plan`, but can't authorize them.
+    ImportFvk {
+        /// The full viewing key, as exported by `pcli wallet export-fvk`.
+        full_viewing_key: String,
     },
-    /// Export the spend seed for the wallet.
+    /// Export the spend seed for the selected account.
     Export,
+    /// Export the full viewing key for the selected account.
+    ///
+    /// The full viewing key reveals all of the account's transactions, but can't spend its funds.
+    ExportFvk,
     /// Generate a new seed phrase.
     Generate,
-    /// Keep the spend seed, but reset all other client state.
+    /// Keep the spend seed, accounts and birthday height, but reset all other client state.
     Reset,
+    /// Discard the scanned notes and transaction history, so that they are rebuilt by the next
+    /// sync, keeping the keys, addresses, note labels, and contacts.
+    Rescan {
+        /// Optional. Only search blocks from this height onwards for the wallet's notes, making it
+        /// the wallet's birthday height [default: the wallet's birthday height, or 0]. This can't
+        /// be after the earliest note the wallet has found.
+        #[structopt(long)]
+        from: Option<u64>,
+    },
+    /// Trust a block header, from which `--verified` syncs verify the chain's later headers.
+    ///
+    /// The header's hash must come from a source trusted not to collude with the node, such as
+    /// another node or the chain's operators, and be recent enough to be within the trusting
+    /// period of the chain's validators. To verify the chain from genesis, trust the header at
+    /// height 1.
+    Trust {
+        /// The height of the block.
+        height: u64,
+        /// The hash of the block header, as a 32-byte hex string.
+        hash: String,
+    },
     /// Delete the entire wallet permanently.
     Delete,
+    /// Encrypt the wallet's secret key material with a password.
+    ///
+    /// The password is then needed to authorize transactions, but not to sync the wallet or view
+    /// its balance, unless `--seal-viewing-keys` is given.
+    Encrypt {
+        /// Also seal the full viewing keys, so that the wallet can't be synced without the
+        /// password.
+        #[structopt(long)]
+        seal_viewing_keys: bool,
+    },
+    /// Decrypt the wallet's secret key material, storing it in plaintext.
+    Decrypt,
+    /// Change the password of an encrypted wallet.
+    ChangePassword,
 }
 
 impl WalletCmd {
@@ -39,18 +95,25 @@ impl WalletCmd {
         match self {
             WalletCmd::Import { .. } => false,
             WalletCmd::ImportFromPhrase { .. } => false,
+            WalletCmd::ImportFvk { .. } => false,
             WalletCmd::Export => false,
+            WalletCmd::ExportFvk => false,
             WalletCmd::Generate => false,
             WalletCmd::Reset => false,
+            WalletCmd::Rescan { .. } => false,
+            WalletCmd::Trust { .. } => false,
             WalletCmd::Delete => false,
+            WalletCmd::Encrypt { .. } => false,
+            WalletCmd::Decrypt => false,
+            WalletCmd::ChangePassword => false,
         }
     }
 
-    pub fn exec(&self, wallet_path: PathBuf) -> Result<()> {
+    pub async fn exec(&self, wallet_path: PathBuf, account: u64) -> Result<()> {
         // Dispatch on the wallet command and return a new state if the command required a
         // wallet state to be saved to disk
         let state = match self {
-            // These two commands return new wallets to be saved to disk:
+            // These commands return new wallets to be saved to disk:
             WalletCmd::Generate => {
                 let seed_phrase = SeedPhrase::generate(&mut OsRng);
 
@@ -61,27 +124,57 @@ impl WalletCmd {
                     seed_phrase
                 );
 
-                Some(ClientState::new(Wallet::from_seed_phrase(seed_phrase)))
+                Some(ClientState::from_seed_phrase(seed_phrase))
             }
             WalletCmd::Import { spend_seed } => {
                 let seed = hex::decode(spend_seed)?;
                 let seed = SpendSeed::try_from(seed.as_slice())?;
                 Some(ClientState::new(Wallet::import(seed)))
             }
-            WalletCmd::ImportFromPhrase { seed_phrase } => Some(ClientState::new(
-                Wallet::from_seed_phrase(SeedPhrase::from_str(seed_phrase)?),
+            WalletCmd::ImportFromPhrase {
+                seed_phrase,
+                birthday,
+            } => {
+                let mut state = ClientState::from_seed_phrase(SeedPhrase::from_str(seed_phrase)?);
+                if let Some(height) = birthday {
+                    state.set_birthday_height(*height);
+                }
+                Some(state)
+            }
+            WalletCmd::ImportFvk { full_viewing_key } => Some(ClientState::from_full_viewing_key(
+                FullViewingKey::from_str(full_viewing_key)?,
             )),
             // The rest of these commands don't require a wallet state to be saved to disk:
             WalletCmd::Export => {
-                let state = ClientStateFile::load(wallet_path.clone())?;
-                let seed = state.wallet().spend_key().seed().clone();
+                let state = ClientStateFile::load(wallet_path.clone(), true).await?;
+                let seed = state.account(account)?.spend_key()?.seed().clone();
                 println!("{}", hex::encode(&seed.0));
                 None
             }
+            WalletCmd::ExportFvk => {
+                let state = ClientStateFile::load(wallet_path.clone(), false).await?;
+                println!("{}", state.account(account)?.full_viewing_key());
+                None
+            }
             WalletCmd::Delete => {
                 if wallet_path.is_file() {
                     std::fs::remove_file(&wallet_path)?;
                     println!("Deleted wallet file at {}", wallet_path.display());
+
+                    // Also remove the database's write-ahead log, if it was left behind, and the
+                    // backup of the JSON wallet file it was imported from, if any
+                    let mut leftover_paths = vec![imported_legacy_wallet_path(&wallet_path)];
+                    for suffix in ["-wal", "-shm"] {
+                        let mut path = OsString::from(&wallet_path);
+                        path.push(suffix);
+                        leftover_paths.push(path.into());
+                    }
+                    for path in leftover_paths {
+                        if path.is_file() {
+                            std::fs::remove_file(&path)?;
+                            println!("Deleted {}", path.display());
+                        }
+                    }
                 } else if wallet_path.exists() {
                     return Err(anyhow!(
                             "Expected wallet file at {} but found something that is not a file; refusing to delete it",
@@ -100,62 +193,165 @@ impl WalletCmd {
 
                 tracing::debug!("reading existing client state from disk");
 
-                #[derive(Deserialize)]
-                struct MinimalState {
-                    wallet: Wallet,
-                }
-
-                // Read the wallet field out of the state file, without fully deserializing the rest
-                let wallet = serde_json::from_reader::<_, MinimalState>(BufReader::new(
-                    File::open(&wallet_path)?,
-                ))?
-                .wallet;
+                // Read the keys out of the database, without loading the rest of the state
+                let mut storage = Storage::open(&wallet_path).await?;
+                let protection = storage.key_protection().await?;
+                let password = match protection {
+                    KeyProtection::Plaintext => None,
+                    KeyProtection::Encrypted { .. } => Some(prompt_password()?),
+                };
+                let mut state = storage.load_keys(password.as_deref()).await?;
+                storage.close().await;
 
                 tracing::debug!("writing fresh client state");
 
-                // Write the new wallet JSON to disk as a temporary file in the wallet directory
+                // Write the new wallet database to disk as a temporary file in the wallet directory
                 let tmp_path = wallet_path.with_extension("tmp");
-                let mut tmp_file = std::fs::OpenOptions::new()
-                    .create(true)
-                    .write(true)
-                    .truncate(true)
-                    .open(&tmp_path)?;
-
-                serde_json::to_writer_pretty(&mut tmp_file, &ClientState::new(wallet))?;
+                if tmp_path.exists() {
+                    std::fs::remove_file(&tmp_path)?;
+                }
+                // Keep the keys encrypted with the same password, if they were
+                let storage = match (protection, &password) {
+                    (
+                        KeyProtection::Encrypted {
+                            viewing_keys_sealed,
+                        },
+                        Some(password),
+                    ) => {
+                        Storage::create_encrypted(
+                            &tmp_path,
+                            &mut state,
+                            password,
+                            viewing_keys_sealed,
+                        )
+                        .await?
+                    }
+                    _ => Storage::create(&tmp_path, &mut state).await?,
+                };
+                storage.close().await;
 
-                tracing::debug!("checking that we can deserialize fresh client state");
+                tracing::debug!("checking that we can load fresh client state");
 
-                // Check that we can successfully parse the result from disk
-                ClientStateFile::load(tmp_path.clone()).context("can't parse wallet after attempting to reset: refusing to overwrite existing wallet file")?;
+                // Check that we can successfully load the result from disk
+                let mut storage = Storage::open(&tmp_path).await?;
+                storage.load(password.as_deref()).await.context("can't load wallet after attempting to reset: refusing to overwrite existing wallet file")?;
+                storage.close().await;
 
                 tracing::debug!("overwriting previous client state");
 
-                // Overwrite the existing wallet state file, *atomically*
+                // Overwrite the existing wallet database, *atomically*
                 std::fs::rename(&tmp_path, &wallet_path)?;
 
                 None
             }
+            WalletCmd::Rescan { from } => {
+                let mut state = ClientStateFile::load(wallet_path.clone(), false).await?;
+                state.rescan(*from)?;
+                state.commit().await?;
+                println!(
+                    "Discarded the scanned notes of the wallet at {}; they will be rebuilt from \
+                    height {} by the next sync",
+                    wallet_path.display(),
+                    state.birthday_height().unwrap_or_default()
+                );
+                None
+            }
+            WalletCmd::Trust { height, hash } => {
+                let hash: [u8; 32] = hex::decode(hash)?
+                    .try_into()
+                    .map_err(|_| anyhow!("header hashes must be 32 bytes"))?;
+                let mut state = ClientStateFile::load(wallet_path.clone(), false).await?;
+                state.set_trusted_header(*height, hash);
+                state.commit().await?;
+                println!(
+                    "Trusted the header at height {}; `--verified` syncs will verify later headers \
+                    from it",
+                    height
+                );
+                None
+            }
+            WalletCmd::Encrypt { seal_viewing_keys } => {
+                let mut storage = StorageFile::open(&wallet_path).await?;
+                if storage.key_protection().await? != KeyProtection::Plaintext {
+                    return Err(anyhow!(
+                        "the wallet is already encrypted, use `pcli wallet change-password` to change its password"
+                    ));
+                }
+                let password = prompt_new_password()?;
+                storage.encrypt(&password, *seal_viewing_keys).await?;
+                println!("Encrypted the wallet at {}", wallet_path.display());
+
+                // Copies of the wallet made before it was encrypted are left as they were
+                let mut backup_paths = vec![imported_legacy_wallet_path(&wallet_path)];
+                if let Some(archive_dir) =
+                    ProjectDirs::from("zone", "penumbra", "penumbra-testnet-archive")
+                {
+                    backup_paths.push(archive_dir.data_dir().to_path_buf());
+                }
+                for path in backup_paths.into_iter().filter(|path| path.exists()) {
+                    println!(
+                        "Warning: {} may still hold unencrypted copies of the wallet's keys",
+                        path.display()
+                    );
+                }
+                None
+            }
+            WalletCmd::Decrypt => {
+                let mut storage = StorageFile::open(&wallet_path).await?;
+                if storage.key_protection().await? == KeyProtection::Plaintext {
+                    return Err(anyhow!("the wallet is not encrypted"));
+                }
+                let password = prompt_password()?;
+                storage.decrypt(&password).await?;
+                println!("Decrypted the wallet at {}", wallet_path.display());
+                None
+            }
+            WalletCmd::ChangePassword => {
+                let mut storage = StorageFile::open(&wallet_path).await?;
+                if storage.key_protection().await? == KeyProtection::Plaintext {
+                    return Err(anyhow!(
+                        "the wallet is not encrypted, use `pcli wallet encrypt` to encrypt it"
+                    ));
+                }
+                let old_password = prompt_password()?;
+                let new_password = prompt_new_password()?;
+                storage
+                    .change_password(&old_password, &new_password)
+                    .await?;
+                println!(
+                    "Changed the password of the wallet at {}",
+                    wallet_path.display()
+                );
+                None
+            }
         };
 
         // If a new wallet should be saved to disk, save it and also archive it in the archive directory
         if let Some(state) = state {
-            // Never overwrite a wallet that already exists
-            if wallet_path.exists() {
-                return Err(anyhow::anyhow!(
-                    "Wallet path {} already exists, refusing to overwrite it",
-                    wallet_path.display()
-                ));
+            // Never overwrite a wallet that already exists, or one which has yet to be imported
+            for path in [&wallet_path, &legacy_wallet_path(&wallet_path)] {
+                if path.exists() {
+                    return Err(anyhow::anyhow!(
+                        "Wallet path {} already exists, refusing to overwrite it",
+                        path.display()
+                    ));
+                }
             }
 
             println!("Saving wallet to {}", wallet_path.display());
-            ClientStateFile::save(state.clone(), wallet_path)?;
+            ClientStateFile::save(state.clone(), wallet_path).await?;
 
             // Archive the newly generated state
             let archive_dir = ProjectDirs::from("zone", "penumbra", "penumbra-testnet-archive")
                 .expect("can access penumbra-testnet-archive dir");
 
             // Create the directory <data dir>/penumbra-testnet-archive/<chain id>/<spend key hash prefix>/
-            let spend_key_hash = Sha256::digest(&state.wallet().spend_key().seed().0);
+            // (watch-only wallets use the hash of their full viewing key in its place)
+            let wallet = state.account(0)?;
+            let spend_key_hash = match wallet.spend_key() {
+                Ok(spend_key) => Sha256::digest(&spend_key.seed().0),
+                Err(_) => Sha256::digest(&wallet.full_viewing_key().to_bytes()),
+            };
             let wallet_archive_dir = archive_dir
                 .data_dir()
                 // TODO the chain ID should be synced from the server if
@@ -172,7 +368,7 @@ impl WalletCmd {
             // Save the wallet file in the archive directory
             let archive_path = wallet_archive_dir.join("penumbra_wallet.json");
             println!("Saving backup wallet to {}", archive_path.display());
-            ClientStateFile::save(state, archive_path)?;
+            serde_json::to_writer_pretty(std::fs::File::create(&archive_path)?, &state)?;
         }
 
         Ok(())
@@ -1,32 +1,42 @@
 use anyhow::Result;
-use penumbra_crypto::asset;
-use penumbra_proto::{light_wallet::ChainParamsRequest, thin_wallet::AssetListRequest};
+use penumbra_crypto::asset::{self, Denom};
+use penumbra_proto::{
+    light_wallet::ChainParamsRequest,
+    thin_wallet::{AssetListRequest, ValidatorRateRequest},
+};
+use penumbra_stake::{IdentityKey, RateData};
 use tracing::instrument;
 
 use crate::{ClientStateFile, Opt};
 
 #[instrument(skip(opt, state))]
 pub async fn assets(opt: &Opt, state: &mut ClientStateFile) -> Result<()> {
-    let mut client = opt.thin_wallet_client().await?;
+    let denoms = asset_list(opt, state.chain_id().unwrap_or_default()).await?;
 
     // Update asset registry.
-    let request = tonic::Request::new(AssetListRequest {
-        chain_id: state.chain_id().unwrap_or_default(),
-    });
+    state.asset_cache_mut().extend(denoms);
+    state.commit().await?;
+    tracing::info!("updated asset registry");
+    Ok(())
+}
+
+/// Fetches the denominations of all assets on the chain.
+pub async fn asset_list(opt: &Opt, chain_id: String) -> Result<Vec<Denom>> {
+    let mut client = opt.thin_wallet_client().await?;
+
+    let request = tonic::Request::new(AssetListRequest { chain_id });
     let mut stream = client.asset_list(request).await?.into_inner();
+    let mut denoms = Vec::new();
     while let Some(asset) = stream.message().await? {
-        state.asset_cache_mut().extend(std::iter::once(
+        denoms.push(
             asset::REGISTRY
                 .parse_denom(&asset.asset_denom)
                 .ok_or_else(|| {
                     anyhow::anyhow!("invalid asset denomination: {}", asset.asset_denom)
                 })?,
-        ));
+        );
     }
-
-    state.commit()?;
-    tracing::info!("updated asset registry");
-    Ok(())
+    Ok(denoms)
 }
 
 /// Fetches the global chain parameters and stores them on `ClientState`.
@@ -45,6 +55,69 @@ pub async fn chain_params(opt: &Opt, state: &mut ClientStateFile) -> Result<()>
     tracing::info!(?params, "saving chain params");
 
     *state.chain_params_mut() = Some(params);
-    state.commit()?;
+    state.commit().await?;
+    Ok(())
+}
+
+/// Fetches the exchange rates at which our delegation notes were received and spent, and records
+/// them on `ClientState`.
+///
+/// This must be called after [`assets`], so that delegation notes can be recognized by their
+/// denomination.
+#[instrument(skip(opt, state))]
+pub async fn delegation_rates(opt: &Opt, state: &mut ClientStateFile) -> Result<()> {
+    let missing = state.missing_delegation_rates();
+    if missing.is_empty() {
+        return Ok(());
+    }
+
+    let rates = validator_rates(opt, state.chain_id().unwrap_or_default(), missing).await?;
+    for (identity_key, epoch_index, rate_data) in rates {
+        match rate_data {
+            Some(rate_data) => state.record_delegation_rate(&rate_data),
+            None => state.record_unavailable_delegation_rate(identity_key, epoch_index),
+        }
+    }
+
+    state.commit().await?;
+    tracing::info!("updated delegation exchange rates");
     Ok(())
 }
+
+/// Fetches the exchange rates of the given validators in the given epochs.
+///
+/// Rates which the node doesn't have are returned as `None`, while those which couldn't be fetched
+/// for any other reason are skipped.
+pub async fn validator_rates(
+    opt: &Opt,
+    chain_id: String,
+    rates: impl IntoIterator<Item = (IdentityKey, u64)>,
+) -> Result<Vec<(IdentityKey, u64, Option<RateData>)>> {
+    let mut client = opt.thin_wallet_client().await?;
+
+    let mut fetched = Vec::new();
+    for (identity_key, epoch_index) in rates {
+        let response = client
+            .validator_rate(tonic::Request::new(ValidatorRateRequest {
+                identity_key: Some(identity_key.clone().into()),
+                epoch_index,
+                chain_id: chain_id.clone(),
+            }))
+            .await;
+
+        // Don't fail the whole command if the rate can't be fetched; we'll try again next sync.
+        match response {
+            Ok(response) => {
+                let rate_data: RateData = response.into_inner().try_into()?;
+                fetched.push((identity_key, epoch_index, Some(rate_data)));
+            }
+            Err(e) if e.code() == tonic::Code::NotFound => {
+                fetched.push((identity_key, epoch_index, None));
+            }
+            Err(e) => {
+                tracing::warn!(%identity_key, epoch_index, error = %e, "could not fetch rate data");
+            }
+        }
+    }
+    Ok(fetched)
+}
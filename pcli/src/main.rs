@@ -3,6 +3,7 @@ use std::path::PathBuf;
 
 use anyhow::Result;
 use directories::ProjectDirs;
+use penumbra_wallet::{NoteSelection, TransactionShape};
 use structopt::StructOpt;
 
 mod command;
@@ -10,11 +11,14 @@ mod fetch;
 mod network;
 mod state;
 mod sync;
+mod verify;
+mod view;
 mod warning;
 
 use command::*;
 use state::ClientStateFile;
-use sync::sync;
+use sync::{sync, wait_for_transaction};
+use verify::verify;
 
 #[derive(Debug, StructOpt)]
 #[structopt(
@@ -40,6 +44,37 @@ pub struct Opt {
     /// The location of the wallet file [default: platform appdata directory]
     #[structopt(short, long)]
     pub wallet_location: Option<String>,
+    /// The index of the wallet account to use.
+    #[structopt(short, long, default_value = "0")]
+    pub account: u64,
+    /// The minimum number of spends in each transaction, padded with dummy spends.
+    ///
+    /// Padding is cosmetic under the current transparent proofs, which reveal that dummy spends
+    /// have zero value.
+    #[structopt(long, default_value = "2")]
+    pub min_spends: usize,
+    /// The minimum number of outputs in each transaction, padded with dummy outputs.
+    ///
+    /// Padding is cosmetic under the current transparent proofs, which reveal that dummy outputs
+    /// have zero value.
+    #[structopt(long, default_value = "2")]
+    pub min_outputs: usize,
+    /// How to choose the notes to spend: privacy, random, fewest-notes, largest-first or
+    /// exact-match.
+    #[structopt(long, default_value = "privacy")]
+    pub note_selection: NoteSelection,
+    /// Allow spending notes received by different addresses in the same transaction, which
+    /// reveals that those addresses belong to the same account.
+    #[structopt(long)]
+    pub link_addresses: bool,
+    /// After submitting a transaction, keep syncing until it is confirmed, failing if it expires
+    /// instead, or if it never expires and isn't confirmed within 100 blocks.
+    #[structopt(long)]
+    pub wait: bool,
+    /// After syncing, verify the wallet's note commitment tree against the app hash signed by the
+    /// chain's validators, starting from the header trusted with `pcli wallet trust`.
+    #[structopt(long)]
+    pub verified: bool,
 }
 
 #[tokio::main]
@@ -57,22 +92,49 @@ async fn main() -> Result<()> {
     // Currently we use just the data directory. Create it if it is missing.
     std::fs::create_dir_all(project_dir.data_dir()).expect("can create penumbra data directory");
 
-    // We store wallet data in `penumbra_wallet.dat` in the state directory, unless
+    // We store wallet data in the `penumbra_wallet.db` database in the state directory, unless
     // the user provides another location.
     let wallet_path = opt.wallet_location.as_ref().map_or_else(
-        || project_dir.data_dir().join("penumbra_wallet.json"),
+        || project_dir.data_dir().join("penumbra_wallet.db"),
         PathBuf::from,
     );
+    // Wallets used to be stored as JSON files: if given the location of one, use the database
+    // next to it instead, and import the JSON wallet into it if that hasn't been done yet.
+    let wallet_path = if wallet_path.extension() == Some("json".as_ref()) {
+        wallet_path.with_extension("db")
+    } else {
+        wallet_path
+    };
+    state::import_legacy_wallet(&wallet_path).await?;
 
     // The wallet command takes the wallet_path directly, since it may need to create the client state,
     // so handle it specially here so that we can have common code for the other subcommands.
     if let Command::Wallet(wallet_cmd) = &opt.cmd {
-        wallet_cmd.exec(wallet_path)?;
+        wallet_cmd.exec(wallet_path, opt.account).await?;
+        return Ok(());
+    }
+    // The view command manages its own client state, since `pcli view status` must not take the
+    // wallet lock held by a running view service.
+    if let Command::View(view_cmd) = &opt.cmd {
+        view_cmd.exec(&opt, wallet_path).await?;
+        return Ok(());
+    }
+
+    // Signing a transaction plan needs only the spend key, and must work on a machine which has
+    // no network access, so handle it before loading the client state.
+    if let Command::Tx(TxCmd::Sign { plan, output }) = &opt.cmd {
+        command::sign(plan, output, &wallet_path, opt.account).await?;
         return Ok(());
     }
 
     // Synchronize the wallet if the command requires it to be synchronized before it is run.
-    let mut state = ClientStateFile::load(wallet_path.clone())?;
+    let mut state = ClientStateFile::load(wallet_path.clone(), opt.cmd.needs_spend_key()).await?;
+    state.set_transaction_shape(TransactionShape {
+        min_spends: opt.min_spends,
+        min_outputs: opt.min_outputs,
+    });
+    state.set_note_selector(opt.note_selection);
+    state.set_link_addresses(opt.link_addresses);
 
     // Chain params may not have been fetched yet, do so if necessary.
     if state.chain_params().is_none() {
@@ -82,17 +144,25 @@ async fn main() -> Result<()> {
 
     if opt.cmd.needs_sync() {
         sync(&opt, &mut state).await?;
+        if opt.verified {
+            verify(&opt, &mut state).await?;
+        }
         fetch::assets(&opt, &mut state).await?;
+        fetch::delegation_rates(&opt, &mut state).await?;
     };
 
     match &opt.cmd {
         Command::Wallet(_) => unreachable!("wallet command already executed"),
+        Command::View(_) => unreachable!("view command already executed"),
         Command::Sync => {
             // We have already synchronized the wallet above, so we can just return.
         }
         Command::Tx(tx_cmd) => tx_cmd.exec(&opt, &mut state).await?,
-        Command::Addr(addr_cmd) => addr_cmd.exec(&mut state)?,
-        Command::Balance(balance_cmd) => balance_cmd.exec(&state)?,
+        Command::Addr(addr_cmd) => addr_cmd.exec(opt.account, &mut state).await?,
+        Command::Account(account_cmd) => account_cmd.exec(&opt, &mut state).await?,
+        Command::Balance(balance_cmd) => balance_cmd.exec(opt.account, &state)?,
+        Command::Note(note_cmd) => note_cmd.exec(&mut state).await?,
+        Command::Contact(contact_cmd) => contact_cmd.exec(&mut state).await?,
         Command::Validator(cmd) => cmd.exec(&opt, &state).await?,
         Command::Stake(cmd) => cmd.exec(&opt, &mut state).await?,
         Command::Tmp(cmd) => cmd.exec().await?,
@@ -3,12 +3,12 @@ use std::{
     path::{Path, PathBuf},
 };
 
-use anyhow::{Context, Result};
-use penumbra_wallet::ClientState;
+use anyhow::{anyhow, Context, Result};
+use penumbra_wallet::{ClientState, KeyProtection, Storage, Wallet};
 
 pub struct ClientStateFile {
-    path: PathBuf,
     state: ClientState,
+    storage: Storage,
     lock: fslock::LockFile,
 }
 
@@ -32,57 +32,209 @@ impl Drop for ClientStateFile {
 }
 
 impl ClientStateFile {
-    /// Create a new wrapper by saving to the provided `path`.
+    /// Create a new wrapper by saving to a new wallet database at the provided `path`.
     ///
     /// If you already have a wrapper, use [`Self::commit`].
-    pub fn save(state: ClientState, path: PathBuf) -> Result<Self> {
+    pub async fn save(mut state: ClientState, path: PathBuf) -> Result<Self> {
         let lock = lock_wallet(&path)?;
 
-        let wrapper = Self { state, path, lock };
-        wrapper.commit()?;
-        Ok(wrapper)
+        let storage = Storage::create(&path, &mut state).await?;
+        Ok(Self {
+            state,
+            storage,
+            lock,
+        })
     }
 
-    /// Create a new wrapper by loading from the provided `path`.
-    pub fn load(path: PathBuf) -> Result<Self> {
+    /// Create a new wrapper by loading from the wallet database at the provided `path`.
+    ///
+    /// If the wallet is encrypted, the password is prompted for if `unlock` is set, so that the
+    /// spend key is available, or if the wallet can't be loaded without it at all.
+    pub async fn load(path: PathBuf, unlock: bool) -> Result<Self> {
         let lock = lock_wallet(&path)?;
 
-        let mut state: ClientState = match std::fs::read(&path) {
-            Ok(data) => serde_json::from_slice(&data).context("Could not parse wallet data")?,
-            Err(err) => match err.kind() {
-                std::io::ErrorKind::NotFound => return Err(err).context(
-                    "Wallet data not found, run `pcli wallet generate` to generate Penumbra keys",
-                ),
-                _ => return Err(err.into()),
-            },
+        if !path.exists() {
+            return Err(anyhow::anyhow!("{} does not exist", path.display())).context(
+                "Wallet data not found, run `pcli wallet generate` to generate Penumbra keys",
+            );
+        }
+        let mut storage = Storage::open(&path).await?;
+        let password = match storage.key_protection().await? {
+            KeyProtection::Plaintext => None,
+            KeyProtection::Encrypted {
+                viewing_keys_sealed,
+            } => {
+                if unlock || viewing_keys_sealed {
+                    Some(prompt_password()?)
+                } else {
+                    None
+                }
+            }
         };
+        let mut state = storage
+            .load(password.as_deref())
+            .await
+            .context("Could not load wallet data")?;
 
         // Pruning timeouts on load means every freshly loaded wallet will be up to date on timeouts
         // as of when it is taken off disk
         state.prune_timeouts();
 
-        Ok(Self { state, path, lock })
+        Ok(Self {
+            state,
+            storage,
+            lock,
+        })
     }
 
-    /// Commit the client state to disk.
-    pub fn commit(&self) -> Result<()> {
-        tracing::debug!("committing state");
+    /// Commit the changes to the client state since it was loaded or last committed to disk.
+    pub async fn commit(&mut self) -> Result<()> {
+        self.storage.commit(&mut self.state).await
+    }
+}
 
-        let tmp_path = self.path.with_extension("tmp");
+/// The wallet database at a given path, opened without loading the client state, for commands
+/// which manage the database itself.
+///
+/// The wallet lock is held until this is dropped.
+pub struct StorageFile {
+    storage: Storage,
+    lock: fslock::LockFile,
+}
 
-        // Write the state to the temp file
-        let mut tmp_file = std::fs::OpenOptions::new()
-            .create(true)
-            .write(true)
-            .truncate(true)
-            .open(&tmp_path)?;
-        serde_json::to_writer_pretty(&mut tmp_file, &self.state)?;
+impl Deref for StorageFile {
+    type Target = Storage;
+    fn deref(&self) -> &Self::Target {
+        &self.storage
+    }
+}
 
-        // Overwrite the existing wallet state file, *atomically*
-        std::fs::rename(&tmp_path, &self.path)?;
+impl DerefMut for StorageFile {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.storage
+    }
+}
+
+impl Drop for StorageFile {
+    fn drop(&mut self) {
+        self.lock.unlock().unwrap();
+    }
+}
+
+impl StorageFile {
+    /// Open the existing wallet database at the provided `path`.
+    pub async fn open(path: &Path) -> Result<Self> {
+        let lock = lock_wallet(path)?;
+
+        if !path.exists() {
+            return Err(anyhow!("{} does not exist", path.display())).context(
+                "Wallet data not found, run `pcli wallet generate` to generate Penumbra keys",
+            );
+        }
+        let storage = Storage::open(path).await?;
+        Ok(Self { storage, lock })
+    }
+}
+
+/// Load only the keys of the given account from the wallet database at the provided `path`,
+/// without the rest of the client state.
+pub async fn load_wallet(path: &Path, account: u64) -> Result<Wallet> {
+    let mut lock = lock_wallet(path)?;
+
+    if !path.exists() {
+        return Err(anyhow::anyhow!("{} does not exist", path.display())).context(
+            "Wallet data not found, run `pcli wallet generate` to generate Penumbra keys",
+        );
+    }
+    let mut storage = Storage::open(path).await?;
+    let password = match storage.key_protection().await? {
+        KeyProtection::Plaintext => None,
+        KeyProtection::Encrypted { .. } => Some(prompt_password()?),
+    };
+    let wallet = storage
+        .load_wallet(account, password.as_deref())
+        .await
+        .context("Could not load wallet data")?;
+    storage.close().await;
+
+    lock.unlock()?;
+    Ok(wallet)
+}
+
+/// Prompt for the password of an encrypted wallet.
+pub fn prompt_password() -> Result<String> {
+    rpassword::read_password_from_tty(Some("Wallet password: "))
+        .context("could not read wallet password")
+}
+
+/// Prompt for a new wallet password, twice to guard against typos.
+pub fn prompt_new_password() -> Result<String> {
+    let password = rpassword::read_password_from_tty(Some("New wallet password: "))
+        .context("could not read wallet password")?;
+    if password.is_empty() {
+        return Err(anyhow!("the wallet password must not be empty"));
+    }
+    let confirmation = rpassword::read_password_from_tty(Some("Confirm new wallet password: "))
+        .context("could not read wallet password")?;
+    if password != confirmation {
+        return Err(anyhow!("the passwords do not match"));
+    }
+    Ok(password)
+}
+
+/// Returns the location of the JSON wallet file which older versions of pcli kept in place of the
+/// wallet database at `path`.
+pub fn legacy_wallet_path(path: &Path) -> PathBuf {
+    path.with_extension("json")
+}
+
+/// Returns the location to which a JSON wallet file is moved once it has been imported.
+pub fn imported_legacy_wallet_path(path: &Path) -> PathBuf {
+    path.with_extension("json.bak")
+}
+
+/// If there is no wallet database at `path` but there is a JSON wallet file from an older version
+/// of pcli next to it, import the JSON wallet into a new database at `path`.
+///
+/// Once imported, the JSON wallet file is kept as a backup with a `.json.bak` extension.
+pub async fn import_legacy_wallet(path: &Path) -> Result<()> {
+    let legacy_path = legacy_wallet_path(path);
+    if path.exists() || !legacy_path.is_file() {
+        return Ok(());
+    }
+
+    let mut lock = lock_wallet(path)?;
+    if path.exists() {
+        // Another process imported the wallet while we were waiting for the lock
+        lock.unlock()?;
+        return Ok(());
+    }
 
-        Ok(())
+    println!(
+        "Importing wallet from {} into {}",
+        legacy_path.display(),
+        path.display()
+    );
+    let mut state: ClientState = serde_json::from_slice(&std::fs::read(&legacy_path)?)
+        .context("Could not parse wallet data")?;
+
+    // Write the database to a temporary file first, so that an interrupted import can be retried
+    let tmp_path = path.with_extension("tmp");
+    if tmp_path.exists() {
+        std::fs::remove_file(&tmp_path)?;
     }
+    Storage::create(&tmp_path, &mut state).await?.close().await;
+    std::fs::rename(&tmp_path, path)?;
+
+    let backup_path = imported_legacy_wallet_path(path);
+    std::fs::rename(&legacy_path, &backup_path)?;
+    println!(
+        "Imported wallet; the old wallet file has been kept at {}",
+        backup_path.display()
+    );
+
+    lock.unlock()?;
+    Ok(())
 }
 
 fn lock_wallet(path: &Path) -> Result<fslock::LockFile> {
@@ -1,39 +1,201 @@
-use anyhow::Result;
-use penumbra_proto::light_wallet::CompactBlockRangeRequest;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use penumbra_proto::light_wallet::{CompactBlock, CompactBlockRangeRequest};
+use penumbra_wallet::{ClientState, DecryptedBlock, SubmittedTransactionStatus, Wallet};
+use rayon::prelude::*;
+use tokio::task::JoinHandle;
+use tonic::Streaming;
 use tracing::instrument;
 
 use crate::{ClientStateFile, Opt};
 
+/// The number of blocks to prefetch from the stream and trial-decrypt in parallel, while the
+/// previous window of blocks is being scanned.
+const DECRYPTION_WINDOW: usize = 64;
+
+/// The number of blocks scanned between commits of the client state, so that an interrupted sync
+/// picks up near where it left off, without rewriting the note commitment tree after every block.
+pub const COMMIT_INTERVAL: usize = 1000;
+
+/// How long to wait between syncs while waiting for a submitted transaction to be confirmed.
+const WAIT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How many blocks to wait for a submitted transaction which never expires to be confirmed before
+/// giving up.
+const WAIT_BLOCKS_WITHOUT_EXPIRY: u64 = 100;
+
 #[instrument(skip(opt, state), fields(start_height = state.last_block_height()))]
 pub async fn sync(opt: &Opt, state: &mut ClientStateFile) -> Result<()> {
     tracing::info!("starting client sync");
-    let mut client = opt.light_wallet_client().await?;
-
-    let start_height = state.last_block_height().map(|h| h + 1).unwrap_or(0);
-    let mut stream = client
-        .compact_block_range(tonic::Request::new(CompactBlockRangeRequest {
-            start_height,
-            end_height: 0,
-            chain_id: state
-                .chain_id()
-                .ok_or_else(|| anyhow::anyhow!("missing chain_id"))?,
-        }))
-        .await?
-        .into_inner();
-
+    let mut blocks = DecryptedBlocks::new(opt, state).await?;
     let mut count = 0;
-    while let Some(block) = stream.message().await? {
-        state.scan_block(block)?;
-        // very basic form of intermediate checkpointing
-        count += 1;
-        if count % 1000 == 1 {
-            state.commit()?;
-            tracing::info!(height = ?state.last_block_height().unwrap(), "syncing...");
+    while let Some(window) = blocks.next_window().await? {
+        for block in window {
+            state.scan_decrypted_block(block)?;
+            count += 1;
+            if count % COMMIT_INTERVAL == 0 {
+                state.commit().await?;
+                tracing::info!(height = ?state.last_block_height().unwrap(), "syncing...");
+            }
         }
     }
 
     state.prune_timeouts();
-    state.commit()?;
+    state.commit().await?;
     tracing::info!(end_height = ?state.last_block_height().unwrap(), "finished sync");
     Ok(())
 }
+
+/// The blocks following those a client state has scanned, fetched from the node and
+/// trial-decrypted with its accounts' keys a window at a time, to be scanned in order.
+///
+/// Trial decryption of each window of blocks runs on the rayon thread pool while the next window is
+/// fetched, so the client state need not be borrowed while blocks are fetched and decrypted.
+pub struct DecryptedBlocks {
+    stream: Streaming<CompactBlock>,
+    decrypting: Option<JoinHandle<Result<Vec<DecryptedBlock>>>>,
+    accounts: Vec<Wallet>,
+    birthday_height: Option<u64>,
+}
+
+impl DecryptedBlocks {
+    /// Starts fetching the blocks following those `state` has scanned.
+    pub async fn new(opt: &Opt, state: &ClientState) -> Result<Self> {
+        let mut client = opt.light_wallet_client().await?;
+
+        let start_height = state.last_block_height().map(|h| h + 1).unwrap_or(0);
+        let stream = client
+            .compact_block_range(tonic::Request::new(CompactBlockRangeRequest {
+                start_height,
+                end_height: 0,
+                chain_id: state
+                    .chain_id()
+                    .ok_or_else(|| anyhow::anyhow!("missing chain_id"))?,
+            }))
+            .await?
+            .into_inner();
+
+        Ok(Self {
+            stream,
+            decrypting: None,
+            accounts: state.account_wallets(),
+            birthday_height: state.birthday_height(),
+        })
+    }
+
+    /// Returns the next window of decrypted blocks, or `None` once the node has sent every block.
+    pub async fn next_window(&mut self) -> Result<Option<Vec<DecryptedBlock>>> {
+        loop {
+            let mut window = Vec::with_capacity(DECRYPTION_WINDOW);
+            while window.len() < DECRYPTION_WINDOW {
+                match self.stream.message().await? {
+                    Some(block) => window.push(block),
+                    None => break,
+                }
+            }
+
+            let decrypted = match self.decrypting.take() {
+                Some(decrypting) => Some(decrypting.await??),
+                None => None,
+            };
+            if !window.is_empty() {
+                let accounts = self.accounts.clone();
+                let birthday_height = self.birthday_height;
+                self.decrypting = Some(tokio::task::spawn_blocking(move || {
+                    decrypt_blocks(window, &accounts, birthday_height)
+                }));
+            }
+
+            // The first window is only being decrypted, so fetch the next before returning it.
+            if decrypted.is_some() || self.decrypting.is_none() {
+                return Ok(decrypted);
+            }
+        }
+    }
+}
+
+/// Syncs the wallet until the submitted transaction with the given ID is confirmed, returning an
+/// error if it expires instead.
+///
+/// A transaction without an expiry height never expires, so this gives up with an error if it
+/// isn't confirmed within [`WAIT_BLOCKS_WITHOUT_EXPIRY`] blocks.
+pub async fn wait_for_transaction(
+    opt: &Opt,
+    state: &mut ClientStateFile,
+    transaction_id: [u8; 32],
+) -> Result<()> {
+    println!(
+        "Waiting for transaction {} to be confirmed...",
+        hex::encode(transaction_id)
+    );
+    let mut give_up_height = None;
+    loop {
+        sync(opt, state).await?;
+        let submitted = state
+            .submitted_transaction(&transaction_id)
+            .ok_or_else(|| anyhow!("transaction {} is not tracked", hex::encode(transaction_id)))?;
+        let (status, expiry_height) = (submitted.status, submitted.expiry_height);
+        match status {
+            SubmittedTransactionStatus::Planned | SubmittedTransactionStatus::Pending => {
+                if expiry_height.is_none() {
+                    let height = state.last_block_height().unwrap_or_default();
+                    let give_up_height =
+                        *give_up_height.get_or_insert(height + WAIT_BLOCKS_WITHOUT_EXPIRY);
+                    if height >= give_up_height {
+                        return Err(anyhow!(
+                            "transaction {} was not confirmed within {} blocks; it has no expiry \
+                            height, so it may still be included later, and stays pending until \
+                            it is",
+                            hex::encode(transaction_id),
+                            WAIT_BLOCKS_WITHOUT_EXPIRY
+                        ));
+                    }
+                }
+                tokio::time::sleep(WAIT_INTERVAL).await
+            }
+            SubmittedTransactionStatus::Confirmed { height } => {
+                println!(
+                    "Transaction {} was confirmed at height {}",
+                    hex::encode(transaction_id),
+                    height
+                );
+                return Ok(());
+            }
+            SubmittedTransactionStatus::Expired => {
+                return Err(anyhow!(
+                    "transaction {} expired without being included in a block; the notes it spent \
+                    can be spent again",
+                    hex::encode(transaction_id)
+                ));
+            }
+            SubmittedTransactionStatus::Conflicted { height } => {
+                return Err(anyhow!(
+                    "transaction {} can't be included, since another transaction spending the same \
+                    notes was included at height {}",
+                    hex::encode(transaction_id),
+                    height
+                ));
+            }
+        }
+    }
+}
+
+/// Trial-decrypts the given blocks in parallel with the keys of all our accounts, preserving their
+/// order, except for those before the wallet's birthday height.
+fn decrypt_blocks(
+    blocks: Vec<CompactBlock>,
+    accounts: &[Wallet],
+    birthday_height: Option<u64>,
+) -> Result<Vec<DecryptedBlock>> {
+    blocks
+        .into_par_iter()
+        .map(|block| {
+            if birthday_height.map_or(false, |birthday_height| block.height < birthday_height) {
+                DecryptedBlock::without_decryption(block)
+            } else {
+                DecryptedBlock::new(block, accounts)
+            }
+        })
+        .collect()
+}
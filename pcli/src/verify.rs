@@ -0,0 +1,206 @@
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+use penumbra_chain::app_hash::verify_anchor_proof;
+use penumbra_crypto::merkle::{self, TreeExt};
+use penumbra_proto::light_wallet::AnchorProofRequest;
+use penumbra_wallet::ClientState;
+use tendermint::{block::Height, node, validator::Set as ValidatorSet, Hash, Time};
+use tendermint_light_client_verifier::{
+    options::Options,
+    types::{LightBlock, TrustThreshold},
+    ProdVerifier, Verdict, Verifier,
+};
+use tendermint_rpc::{Client, HttpClient, Paging};
+use tracing::instrument;
+
+use crate::{ClientStateFile, Opt};
+
+/// How long after a trusted header its validators can still be trusted not to sign a conflicting
+/// chain. This must be shorter than the chain's unbonding period.
+const TRUSTING_PERIOD: Duration = Duration::from_secs(14 * 24 * 60 * 60);
+
+/// The maximum amount by which a header's time may be ahead of the local clock.
+const CLOCK_DRIFT: Duration = Duration::from_secs(10);
+
+/// How long to wait between polls while waiting for the chain to sign the latest app hash.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Verifies the wallet's note commitment tree against the app hash signed by the chain's
+/// validators, and advances the trusted header to the header which signs it.
+///
+/// The app hash after block `h` is signed in the header of block `h + 1`, which is verified from
+/// the trusted header by skipping verification: each header is accepted once more than a third of
+/// the voting power of an already trusted validator set has signed it, bisecting the range of
+/// heights when too few of those validators signed the target header.
+#[instrument(skip(opt, state))]
+pub async fn verify(opt: &Opt, state: &mut ClientStateFile) -> Result<()> {
+    let (height, hash) = verify_anchor(opt, SyncedAnchor::new(state)?).await?;
+    state.set_trusted_header(height, hash);
+    state.commit().await?;
+    Ok(())
+}
+
+/// The note commitment tree anchor of a synced client state, with the header it trusts, taken
+/// from it so that it needn't be borrowed while the anchor is verified.
+pub struct SyncedAnchor {
+    trusted_header: (u64, [u8; 32]),
+    synced_height: u64,
+    chain_id: String,
+    anchor: merkle::Root,
+}
+
+impl SyncedAnchor {
+    pub fn new(state: &ClientState) -> Result<Self> {
+        Ok(Self {
+            trusted_header: state.trusted_header().ok_or_else(|| {
+                anyhow!("the wallet has no trusted header, set one with `pcli wallet trust`")
+            })?,
+            synced_height: state
+                .last_block_height()
+                .ok_or_else(|| anyhow!("the wallet has not been synced"))?,
+            chain_id: state
+                .chain_id()
+                .ok_or_else(|| anyhow!("missing chain_id"))?,
+            anchor: state.note_commitment_tree().root2(),
+        })
+    }
+}
+
+/// Verifies a synced note commitment tree anchor as [`verify`] does, returning the height and hash
+/// of the header to trust from now on.
+#[instrument(skip(opt, synced), fields(height = synced.synced_height))]
+pub async fn verify_anchor(opt: &Opt, synced: SyncedAnchor) -> Result<(u64, [u8; 32])> {
+    let SyncedAnchor {
+        trusted_header: (trusted_height, trusted_hash),
+        synced_height,
+        chain_id,
+        anchor: synced_anchor,
+    } = synced;
+    let target_height = synced_height + 1;
+    if target_height < trusted_height {
+        return Err(anyhow!(
+            "the wallet is synced to height {}, before its trusted header at height {}",
+            synced_height,
+            trusted_height
+        ));
+    }
+
+    let client = HttpClient::new(format!("http://{}:{}", opt.node, opt.rpc_port).as_str())?;
+
+    // The header signing the app hash of the latest synced block may not have been produced yet.
+    while client.status().await?.sync_info.latest_block_height.value() < target_height {
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+
+    let mut trusted = light_block(&client, trusted_height).await?;
+    if trusted.signed_header.header.hash() != Hash::Sha256(trusted_hash) {
+        return Err(anyhow!(
+            "the node's header at height {} does not match the trusted header",
+            trusted_height
+        ));
+    }
+    if trusted.signed_header.header.chain_id.as_str() != chain_id {
+        return Err(anyhow!(
+            "the trusted header is from chain {}, not {}",
+            trusted.signed_header.header.chain_id,
+            chain_id
+        ));
+    }
+
+    let verifier = ProdVerifier::default();
+    let options = Options {
+        trust_threshold: TrustThreshold::ONE_THIRD,
+        trusting_period: TRUSTING_PERIOD,
+        clock_drift: CLOCK_DRIFT,
+    };
+    while trusted.height().value() < target_height {
+        let mut candidate_height = target_height;
+        loop {
+            let candidate = light_block(&client, candidate_height).await?;
+            match verifier.verify(
+                candidate.as_untrusted_state(),
+                trusted.as_trusted_state(),
+                &options,
+                Time::now(),
+            ) {
+                Verdict::Success => {
+                    tracing::debug!(height = candidate_height, "verified header");
+                    trusted = candidate;
+                    break;
+                }
+                // Adjacent headers are always verified or rejected outright, so this never
+                // bisects down to the trusted height itself.
+                Verdict::NotEnoughTrust(_) => {
+                    candidate_height = trusted.height().value()
+                        + (candidate_height - trusted.height().value()) / 2;
+                }
+                Verdict::Invalid(e) => {
+                    return Err(anyhow!(
+                        "could not verify the header at height {}: {}",
+                        candidate_height,
+                        e
+                    ));
+                }
+            }
+        }
+    }
+
+    let app_hash = trusted.signed_header.header.app_hash.value();
+    let anchor_proof = opt
+        .light_wallet_client()
+        .await?
+        .anchor_proof(tonic::Request::new(AnchorProofRequest {
+            chain_id,
+            height: synced_height,
+        }))
+        .await?
+        .into_inner();
+    let anchor = merkle::Root::try_from(&anchor_proof.nct_anchor[..])?;
+    verify_anchor_proof(&app_hash, &anchor, &anchor_proof.proof).with_context(|| {
+        format!(
+            "the node's note commitment tree anchor at height {} is not committed in the signed \
+            app hash",
+            synced_height
+        )
+    })?;
+    if synced_anchor != anchor {
+        return Err(anyhow!(
+            "the wallet's note commitment tree at height {} does not match the verified anchor; \
+            run `pcli wallet rescan` and sync again",
+            synced_height
+        ));
+    }
+
+    let trusted_hash = match trusted.signed_header.header.hash() {
+        Hash::Sha256(hash) => hash,
+        Hash::None => return Err(anyhow!("verified header has no hash")),
+    };
+    tracing::info!(height = synced_height, "verified note commitment tree");
+    Ok((target_height, trusted_hash))
+}
+
+/// Fetches the signed header at the given height, with the validator sets which signed it and
+/// will sign the next header.
+async fn light_block(client: &HttpClient, height: u64) -> Result<LightBlock> {
+    let signed_header = client
+        .commit(Height::try_from(height)?)
+        .await?
+        .signed_header;
+    let validators = validator_set(client, height).await?;
+    let next_validators = validator_set(client, height + 1).await?;
+
+    Ok(LightBlock::new(
+        signed_header,
+        validators,
+        next_validators,
+        node::Id::new([0; 20]),
+    ))
+}
+
+async fn validator_set(client: &HttpClient, height: u64) -> Result<ValidatorSet> {
+    let response = client
+        .validators(Height::try_from(height)?, Paging::All)
+        .await?;
+    Ok(ValidatorSet::without_proposer(response.validators))
+}
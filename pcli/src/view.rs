@@ -0,0 +1,341 @@
+use std::{
+    collections::BTreeMap,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use anyhow::Result;
+use penumbra_crypto::{
+    merkle::{Tree, TreeExt},
+    note,
+};
+use penumbra_proto::{
+    chain,
+    view::{
+        note_record::NoteStatus, view_protocol_server::ViewProtocol, AddressRecord,
+        AddressesRequest, BalanceRecord, BalancesRequest, ChainParamsRequest, NoteRecord,
+        NoteWitness, NotesRequest, StatusRequest, StatusResponse, TransactionHistoryRecord,
+        TransactionHistoryRequest, WitnessRequest, WitnessResponse,
+    },
+};
+use penumbra_wallet::UnspentNote;
+use tokio::sync::RwLock;
+use tonic::Status;
+use tracing::instrument;
+
+use crate::{
+    fetch,
+    sync::{DecryptedBlocks, COMMIT_INTERVAL},
+    verify::{self, SyncedAnchor},
+    ClientStateFile, Opt,
+};
+
+type ViewStream<T> = Pin<Box<dyn futures::Stream<Item = Result<T, Status>> + Send>>;
+
+/// Serves the view protocol from a client state which it keeps synced in the background.
+#[derive(Clone)]
+pub struct ViewService {
+    state: Arc<RwLock<ClientStateFile>>,
+    /// The height the client state has been synced to, readable without waiting for a sync.
+    sync_height: Arc<AtomicU64>,
+    catching_up: Arc<AtomicBool>,
+}
+
+impl ViewService {
+    pub fn new(state: ClientStateFile) -> Self {
+        Self {
+            sync_height: Arc::new(AtomicU64::new(
+                state.last_block_height().unwrap_or_default(),
+            )),
+            state: Arc::new(RwLock::new(state)),
+            catching_up: Arc::new(AtomicBool::new(true)),
+        }
+    }
+
+    /// Syncs the client state every `interval`, forever.
+    ///
+    /// The client state is only locked for writing while each window of fetched blocks is scanned
+    /// and while fetched data is recorded, never during network requests, so requests are answered
+    /// while a sync is in progress, though never from a partially scanned block.
+    pub async fn sync_forever(&self, opt: &Opt, interval: Duration) -> Result<()> {
+        loop {
+            match self.sync_all(opt).await {
+                Ok(()) => self.catching_up.store(false, Ordering::SeqCst),
+                // Don't stop serving if the node is temporarily unavailable; we'll try again.
+                Err(e) => tracing::warn!(error = %e, "could not sync"),
+            }
+            tokio::time::sleep(interval).await;
+        }
+    }
+
+    async fn sync_all(&self, opt: &Opt) -> Result<()> {
+        self.sync(opt).await?;
+
+        if opt.verified {
+            let synced = SyncedAnchor::new(&*self.state.read().await)?;
+            let (height, hash) = verify::verify_anchor(opt, synced).await?;
+            let mut state = self.state.write().await;
+            state.set_trusted_header(height, hash);
+            state.commit().await?;
+        }
+
+        let chain_id = self.state.read().await.chain_id().unwrap_or_default();
+        let denoms = fetch::asset_list(opt, chain_id.clone()).await?;
+        {
+            let mut state = self.state.write().await;
+            state.asset_cache_mut().extend(denoms);
+            state.commit().await?;
+        }
+
+        let missing = self.state.read().await.missing_delegation_rates();
+        if !missing.is_empty() {
+            let rates = fetch::validator_rates(opt, chain_id, missing).await?;
+            let mut state = self.state.write().await;
+            for (identity_key, epoch_index, rate_data) in rates {
+                match rate_data {
+                    Some(rate_data) => state.record_delegation_rate(&rate_data),
+                    None => state.record_unavailable_delegation_rate(identity_key, epoch_index),
+                }
+            }
+            state.commit().await?;
+        }
+        Ok(())
+    }
+
+    /// Syncs the client state as [`sync::sync`](crate::sync::sync) does, but holding the write
+    /// lock only while scanning each window of blocks.
+    async fn sync(&self, opt: &Opt) -> Result<()> {
+        let mut blocks = DecryptedBlocks::new(opt, &*self.state.read().await).await?;
+        let mut count = 0;
+        while let Some(window) = blocks.next_window().await? {
+            let mut state = self.state.write().await;
+            for block in window {
+                state.scan_decrypted_block(block)?;
+                count += 1;
+                if count % COMMIT_INTERVAL == 0 {
+                    state.commit().await?;
+                }
+            }
+            self.sync_height.store(
+                state.last_block_height().unwrap_or_default(),
+                Ordering::SeqCst,
+            );
+        }
+
+        let mut state = self.state.write().await;
+        state.prune_timeouts();
+        state.commit().await?;
+        Ok(())
+    }
+}
+
+fn stream<T: Send + 'static>(items: Vec<T>) -> ViewStream<T> {
+    Box::pin(tokio_stream::iter(items.into_iter().map(Ok)))
+}
+
+#[tonic::async_trait]
+impl ViewProtocol for ViewService {
+    type AddressesStream = ViewStream<AddressRecord>;
+    type BalancesStream = ViewStream<BalanceRecord>;
+    type NotesStream = ViewStream<NoteRecord>;
+    type TransactionHistoryStream = ViewStream<TransactionHistoryRecord>;
+
+    #[instrument(skip(self, _request))]
+    async fn status(
+        &self,
+        _request: tonic::Request<StatusRequest>,
+    ) -> Result<tonic::Response<StatusResponse>, Status> {
+        Ok(tonic::Response::new(StatusResponse {
+            sync_height: self.sync_height.load(Ordering::SeqCst),
+            catching_up: self.catching_up.load(Ordering::SeqCst),
+        }))
+    }
+
+    #[instrument(skip(self, _request))]
+    async fn addresses(
+        &self,
+        _request: tonic::Request<AddressesRequest>,
+    ) -> Result<tonic::Response<Self::AddressesStream>, Status> {
+        let state = self.state.read().await;
+        let addresses = state
+            .accounts()
+            .flat_map(|(account, _, wallet)| {
+                wallet
+                    .addresses()
+                    .map(move |(index, label, address)| AddressRecord {
+                        index: index as u64,
+                        label,
+                        address: Some(address.into()),
+                        account,
+                    })
+            })
+            .collect();
+        Ok(tonic::Response::new(stream(addresses)))
+    }
+
+    #[instrument(skip(self, _request))]
+    async fn balances(
+        &self,
+        _request: tonic::Request<BalancesRequest>,
+    ) -> Result<tonic::Response<Self::BalancesStream>, Status> {
+        let state = self.state.read().await;
+
+        let mut balances = BTreeMap::<_, (u64, u64)>::new();
+        for (account, index, denom, note) in state.unspent_notes() {
+            let (amount, pending_amount) = balances.entry((account, index, denom)).or_default();
+            match note {
+                UnspentNote::Ready(note) => *amount += note.amount(),
+                UnspentNote::SubmittedChange(note) => *pending_amount += note.amount(),
+                UnspentNote::Quarantined(quarantined) => {
+                    *pending_amount += quarantined.note.amount()
+                }
+                UnspentNote::SubmittedSpend(_) => {}
+            }
+        }
+
+        let balances = balances
+            .into_iter()
+            .map(
+                |((account, address_index, denom), (amount, pending_amount))| BalanceRecord {
+                    address_index,
+                    denom: Some(denom.into()),
+                    amount,
+                    pending_amount,
+                    account,
+                },
+            )
+            .collect();
+        Ok(tonic::Response::new(stream(balances)))
+    }
+
+    #[instrument(skip(self, _request))]
+    async fn notes(
+        &self,
+        _request: tonic::Request<NotesRequest>,
+    ) -> Result<tonic::Response<Self::NotesStream>, Status> {
+        let state = self.state.read().await;
+        let notes = state
+            .unspent_notes()
+            .map(|(account, address_index, denom, unspent)| {
+                let status = match unspent {
+                    UnspentNote::Ready(_) => NoteStatus::Ready,
+                    UnspentNote::SubmittedSpend(_) => NoteStatus::SubmittedSpend,
+                    UnspentNote::SubmittedChange(_) => NoteStatus::SubmittedChange,
+                    UnspentNote::Quarantined(_) => NoteStatus::Quarantined,
+                };
+                let note = unspent.as_ref();
+                let note_commitment = note.commit();
+                NoteRecord {
+                    note_commitment: Some(note_commitment.into()),
+                    note: note.to_bytes().to_vec(),
+                    address_index,
+                    denom: Some(denom.into()),
+                    amount: note.amount(),
+                    status: status as i32,
+                    memo: state
+                        .memo(&note_commitment)
+                        .map(ToString::to_string)
+                        .unwrap_or_default(),
+                    account,
+                }
+            })
+            .collect();
+        Ok(tonic::Response::new(stream(notes)))
+    }
+
+    #[instrument(skip(self, request))]
+    async fn transaction_history(
+        &self,
+        request: tonic::Request<TransactionHistoryRequest>,
+    ) -> Result<tonic::Response<Self::TransactionHistoryStream>, Status> {
+        let TransactionHistoryRequest {
+            start_height,
+            end_height,
+        } = request.into_inner();
+
+        let state = self.state.read().await;
+        let history = state
+            .transaction_history()
+            .filter(|entry| {
+                entry.height >= start_height && (end_height == 0 || entry.height <= end_height)
+            })
+            .map(|entry| TransactionHistoryRecord {
+                height: entry.height,
+                transaction_id: entry.transaction_id.to_vec(),
+                received: entry
+                    .received
+                    .iter()
+                    .map(|(commitment, _)| (*commitment).into())
+                    .collect(),
+                spent: entry
+                    .spent
+                    .iter()
+                    .map(|(commitment, _)| (*commitment).into())
+                    .collect(),
+                sent: entry
+                    .sent
+                    .iter()
+                    .map(|(commitment, _)| (*commitment).into())
+                    .collect(),
+                fee: entry.details.map(|details| details.fee),
+                memo: entry.memo.map(ToString::to_string).unwrap_or_default(),
+            })
+            .collect();
+        Ok(tonic::Response::new(stream(history)))
+    }
+
+    #[instrument(skip(self, request))]
+    async fn witness(
+        &self,
+        request: tonic::Request<WitnessRequest>,
+    ) -> Result<tonic::Response<WitnessResponse>, Status> {
+        let note_commitments = request
+            .into_inner()
+            .note_commitments
+            .into_iter()
+            .map(note::Commitment::try_from)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| Status::invalid_argument("invalid note commitment"))?;
+
+        let state = self.state.read().await;
+        let tree = state.note_commitment_tree();
+        let witnesses = note_commitments
+            .iter()
+            .map(|note_commitment| {
+                let (position, auth_path) =
+                    tree.authentication_path(note_commitment).ok_or_else(|| {
+                        Status::not_found(format!(
+                            "no witness for note commitment {:?}",
+                            note_commitment
+                        ))
+                    })?;
+                Ok(NoteWitness {
+                    position: u64::from(position),
+                    auth_path: auth_path.into_iter().map(Into::into).collect(),
+                })
+            })
+            .collect::<Result<Vec<_>, Status>>()?;
+
+        Ok(tonic::Response::new(WitnessResponse {
+            anchor: Some(tree.root2().into()),
+            witnesses,
+        }))
+    }
+
+    #[instrument(skip(self, _request))]
+    async fn chain_params(
+        &self,
+        _request: tonic::Request<ChainParamsRequest>,
+    ) -> Result<tonic::Response<chain::ChainParams>, Status> {
+        let state = self.state.read().await;
+        let params = state
+            .chain_params()
+            .cloned()
+            .ok_or_else(|| Status::unavailable("chain parameters have not been fetched yet"))?;
+        Ok(tonic::Response::new(params.into()))
+    }
+}
@@ -0,0 +1,20 @@
+-- Record the height at which each note and nullifier entered quarantine, so that compact blocks
+-- can tell light clients about quarantined undelegation outputs as they happen.
+--
+-- The heights of notes and nullifiers already in quarantine weren't recorded, and can't be
+-- recovered, so this migration needs a fresh chain: the columns have no default, so adding them
+-- fails if anything is already quarantined, rather than placing it in the wrong compact block.
+ALTER TABLE quarantined_notes ADD COLUMN IF NOT EXISTS height bigint NOT NULL;
+CREATE INDEX ON quarantined_notes (height);
+
+ALTER TABLE quarantined_nullifiers ADD COLUMN IF NOT EXISTS height bigint NOT NULL;
+CREATE INDEX ON quarantined_nullifiers (height);
+
+-- Validators slashed in each block: all notes and nullifiers quarantined relative to a slashed
+-- validator are reverted in the block in which it is slashed
+CREATE TABLE IF NOT EXISTS slashings (
+    identity_key bytea NOT NULL REFERENCES validators (identity_key),
+    height bigint NOT NULL REFERENCES blocks (height),
+    PRIMARY KEY(identity_key, height)
+);
+CREATE INDEX ON slashings (height);
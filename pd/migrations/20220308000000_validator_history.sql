@@ -0,0 +1,7 @@
+-- Record each validator's voting power, state and delegation token supply alongside its rates for
+-- each epoch, so that light clients can query the history of a validator's performance
+ALTER TABLE validator_rates
+    ADD COLUMN IF NOT EXISTS voting_power bigint,
+    ADD COLUMN IF NOT EXISTS validator_state varchar,
+    ADD COLUMN IF NOT EXISTS unbonding_epoch bigint,
+    ADD COLUMN IF NOT EXISTS delegation_token_supply bigint;
@@ -0,0 +1,9 @@
+-- Keep the value commitment and OVK-wrapped key of each output, so that light clients can recover
+-- the notes they sent using their outgoing viewing key
+ALTER TABLE notes
+    ADD COLUMN IF NOT EXISTS value_commitment bytea,
+    ADD COLUMN IF NOT EXISTS ovk_wrapped_key bytea;
+
+ALTER TABLE quarantined_notes
+    ADD COLUMN IF NOT EXISTS value_commitment bytea,
+    ADD COLUMN IF NOT EXISTS ovk_wrapped_key bytea;
@@ -0,0 +1,7 @@
+-- Keep the encrypted memo of each output, so that light clients can read the memos of the notes
+-- they receive
+ALTER TABLE notes
+    ADD COLUMN IF NOT EXISTS encrypted_memo bytea;
+
+ALTER TABLE quarantined_notes
+    ADD COLUMN IF NOT EXISTS encrypted_memo bytea;
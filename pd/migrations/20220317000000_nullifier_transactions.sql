@@ -0,0 +1,3 @@
+-- Record the transaction which revealed each nullifier, so that light clients can tell which of
+-- their transactions spent their notes
+ALTER TABLE nullifiers ADD COLUMN IF NOT EXISTS transaction_id bytea;
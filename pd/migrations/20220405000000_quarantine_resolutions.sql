@@ -0,0 +1,29 @@
+-- Quarantined notes and nullifiers are kept once they unbond or are reverted, so that compact
+-- blocks can still be built for the heights at which they were quarantined. How and when each left
+-- quarantine is recorded separately, and only those without a resolution are still quarantined.
+
+-- A reverted nullifier leaves the nullifier set, and may be quarantined again if the note it
+-- spends is spent by another undelegation
+ALTER TABLE quarantined_nullifiers DROP CONSTRAINT IF EXISTS quarantined_nullifiers_nullifier_fkey;
+ALTER TABLE quarantined_nullifiers DROP CONSTRAINT IF EXISTS quarantined_nullifiers_pkey;
+ALTER TABLE quarantined_nullifiers ADD PRIMARY KEY (nullifier, height);
+
+CREATE TABLE IF NOT EXISTS quarantined_note_resolutions (
+    note_commitment bytea PRIMARY KEY REFERENCES quarantined_notes (note_commitment),
+    -- The height at which the note unbonded or was reverted
+    height bigint NOT NULL REFERENCES blocks (height),
+    -- Whether the note was reverted because its validator was slashed, rather than unbonding
+    reverted boolean NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS quarantined_nullifier_resolutions (
+    nullifier bytea NOT NULL,
+    -- The height at which the nullifier was quarantined
+    quarantine_height bigint NOT NULL,
+    -- The height at which the spend became permanent or was reverted
+    height bigint NOT NULL REFERENCES blocks (height),
+    -- Whether the spend was reverted because its validator was slashed, rather than unbonding
+    reverted boolean NOT NULL,
+    PRIMARY KEY (nullifier, quarantine_height),
+    FOREIGN KEY (nullifier, quarantine_height) REFERENCES quarantined_nullifiers (nullifier, height)
+);
@@ -16,9 +16,9 @@ use tendermint::{abci::types::ValidatorUpdate, PublicKey};
 
 use crate::state::Reader;
 use penumbra_stake::{
-    BaseRateData, Epoch, FundingStream, IdentityKey, RateData, Validator, ValidatorInfo,
-    ValidatorState, ValidatorStateName, ValidatorStatus, VerifiedValidatorDefinition,
-    STAKING_TOKEN_ASSET_ID, STAKING_TOKEN_DENOM,
+    ArithmeticError, BaseRateData, Epoch, FundingStream, IdentityKey, RateData, Validator,
+    ValidatorInfo, ValidatorState, ValidatorStateName, ValidatorStatus,
+    VerifiedValidatorDefinition, STAKING_TOKEN_ASSET_ID, STAKING_TOKEN_DENOM,
 };
 
 #[derive(Debug, Clone)]
@@ -67,6 +67,8 @@ pub struct EpochChanges {
     pub next_base_rate: Option<BaseRateData>,
     /// Validator rates for the next epoch go here.
     pub next_rates: Vec<RateData>,
+    /// Delegation token supplies for each validator, as used to compute the next epoch's voting power.
+    pub delegation_token_supplies: BTreeMap<IdentityKey, u64>,
     /// Set in `end_epoch` and reset to `None` when `reward_notes` is called.
     // TODO: produce in end_epoch and return to caller, make this private
     pub reward_notes: Vec<(u64, Address)>,
@@ -321,15 +323,60 @@ impl ValidatorSet {
             .await?;
 
             for rate in rate_data {
+                // Record the validator's status and delegation token supply alongside its rates,
+                // so that the history of the validator can be queried later.
+                let status = self
+                    .cache
+                    .validator_set
+                    .get(&rate.identity_key)
+                    .map(|info| info.status.clone());
+                let delegation_token_supply = self
+                    .epoch_changes
+                    .as_ref()
+                    .expect("expected epoch_changes to be set before commit_epoch")
+                    .delegation_token_supplies
+                    .get(&rate.identity_key)
+                    .copied();
+                let (voting_power, state_name, unbonding_epoch) = match status {
+                    Some(status) => {
+                        let (state_name, unbonding_epoch) = status.state.into();
+                        (
+                            Some(status.voting_power as i64),
+                            Some(state_name.to_str().to_string()),
+                            unbonding_epoch.map(|i| i as i64),
+                        )
+                    }
+                    None => (None, None, None),
+                };
+
                 query!(
                     // This query needs to be ON CONFLICT UPDATE because this rate will have already been set
                     // in the case of a new validator.
-                    "INSERT INTO validator_rates VALUES ($1, $2, $3, $4) ON CONFLICT ON CONSTRAINT validator_rates_pkey
-                    DO UPDATE SET validator_reward_rate=$3, validator_exchange_rate=$4",
+                    "INSERT INTO validator_rates (
+                        identity_key,
+                        epoch,
+                        validator_reward_rate,
+                        validator_exchange_rate,
+                        voting_power,
+                        validator_state,
+                        unbonding_epoch,
+                        delegation_token_supply
+                    ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8) ON CONFLICT ON CONSTRAINT validator_rates_pkey
+                    DO UPDATE SET
+                        validator_reward_rate=$3,
+                        validator_exchange_rate=$4,
+                        voting_power=$5,
+                        validator_state=$6,
+                        unbonding_epoch=$7,
+                        delegation_token_supply=$8",
                     rate.identity_key.encode_to_vec(),
                     rate.epoch_index as i64,
                     rate.validator_reward_rate as i64,
                     rate.validator_exchange_rate as i64,
+                    voting_power,
+                    state_name,
+                    unbonding_epoch,
+                    delegation_token_supply.map(|supply| supply as i64),
                 )
                 .execute(&mut *dbtx)
                 .await?;
@@ -625,7 +672,7 @@ impl ValidatorSet {
             /// 3bps -> 11% return over 365 epochs, why not
             const BASE_REWARD_RATE: u64 = 3_0000;
 
-            let next_base_rate = current_base_rate.next(BASE_REWARD_RATE);
+            let next_base_rate = current_base_rate.next(BASE_REWARD_RATE)?;
 
             // rename to curr_rate so it lines up with next_rate (same # chars)
             tracing::debug!(curr_base_rate = ?current_base_rate);
@@ -641,6 +688,7 @@ impl ValidatorSet {
             let mut next_rates = Vec::new();
             let mut reward_notes = Vec::new();
             let mut supply_updates = Vec::new();
+            let mut delegation_token_supplies = BTreeMap::new();
 
             // this is a bit complicated: because we're in the EndBlock phase, and the
             // delegations in this block have not yet been committed, we have to combine
@@ -684,13 +732,13 @@ impl ValidatorSet {
                     &next_base_rate,
                     funding_streams.as_ref(),
                     &validator.status.state,
-                );
+                )?;
                 let identity_key = validator.validator.identity_key.clone();
 
                 let delegation_delta = delegation_changes.get(&identity_key).unwrap_or(&0i64);
 
                 let delegation_amount = delegation_delta.abs() as u64;
-                let unbonded_amount = current_rate.unbonded_amount(delegation_amount);
+                let unbonded_amount = current_rate.unbonded_amount(delegation_amount)?;
 
                 let mut delegation_token_supply = self
                     .reader
@@ -701,27 +749,31 @@ impl ValidatorSet {
 
                 if *delegation_delta > 0 {
                     // net delegation: subtract the unbonded amount from the staking token supply
-                    staking_token_supply =
-                        staking_token_supply.checked_sub(unbonded_amount).unwrap();
+                    staking_token_supply = staking_token_supply
+                        .checked_sub(unbonded_amount)
+                        .ok_or(ArithmeticError::Underflow)?;
                     delegation_token_supply = delegation_token_supply
                         .checked_add(delegation_amount)
-                        .unwrap();
+                        .ok_or(ArithmeticError::Overflow)?;
                 } else {
                     // net undelegation: add the unbonded amount to the staking token supply
-                    staking_token_supply =
-                        staking_token_supply.checked_add(unbonded_amount).unwrap();
+                    staking_token_supply = staking_token_supply
+                        .checked_add(unbonded_amount)
+                        .ok_or(ArithmeticError::Overflow)?;
                     delegation_token_supply = delegation_token_supply
                         .checked_sub(delegation_amount)
-                        .unwrap();
+                        .ok_or(ArithmeticError::Underflow)?;
                 }
 
                 // update the delegation token supply
+                delegation_token_supplies.insert(identity_key.clone(), delegation_token_supply);
                 supply_updates.push((
                     identity_key.delegation_token().id(),
                     identity_key.delegation_token().denom(),
                     delegation_token_supply,
                 ));
-                let voting_power = next_rate.voting_power(delegation_token_supply, &next_base_rate);
+                let voting_power =
+                    next_rate.voting_power(delegation_token_supply, &next_base_rate)?;
                 tracing::debug!(?voting_power);
 
                 // Update the status of the validator within the validator set
@@ -737,7 +789,7 @@ impl ValidatorSet {
                             delegation_token_supply,
                             &next_base_rate,
                             &current_base_rate,
-                        );
+                        )?;
 
                         reward_notes.push((commission_reward_amount, stream.address));
                     }
@@ -766,6 +818,10 @@ impl ValidatorSet {
                 .as_mut()
                 .expect("epoch_changes should be set")
                 .next_rates = next_rates;
+            self.epoch_changes
+                .as_mut()
+                .expect("epoch_changes should be set")
+                .delegation_token_supplies = delegation_token_supplies;
             self.epoch_changes
                 .as_mut()
                 .expect("epoch_changes should be set")
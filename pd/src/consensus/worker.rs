@@ -241,7 +241,8 @@ impl Worker {
             .as_ref()
             .unwrap()
             .spent_nullifiers
-            .intersection(&transaction.spent_nullifiers);
+            .keys()
+            .filter(|nullifier| transaction.spent_nullifiers.contains(nullifier));
 
         if let Some(conflict) = conflicts.next() {
             return Err(anyhow!(
@@ -325,6 +326,7 @@ impl Worker {
         }
         drop(slashed_notes);
         drop(slashed_nullifiers);
+        pending_block.slashed_validators = slashed_validators.clone();
 
         // If we are at the end of an epoch, process changes for it
         if epoch.end_height().value() == height {
@@ -92,6 +92,17 @@ enum Command {
         #[structopt(long, default_value = "192.167.10.2")]
         starting_ip: Ipv4Addr,
     },
+
+    /// Simulates the staking economics of a scenario, writing the supplies, exchange rates and
+    /// commission payouts of each validator in each epoch as CSV.
+    SimulateStaking {
+        /// Path to JSON file describing the scenario to simulate.
+        #[structopt(parse(from_os_str))]
+        scenario_file: PathBuf,
+        /// Path to CSV file to write the results to [default: stdout].
+        #[structopt(long, parse(from_os_str))]
+        output_file: Option<PathBuf>,
+    },
 }
 
 // Extracted from tonic's remote_addr implementation; we'd like to instrument
@@ -564,6 +575,35 @@ async fn main() -> anyhow::Result<()> {
                 println!("-------------------------------------");
             }
         }
+        Command::SimulateStaking {
+            scenario_file,
+            output_file,
+        } => {
+            use std::{fs::File, io::Write};
+
+            use penumbra_stake::simulation::Scenario;
+
+            let scenario: Scenario = serde_json::from_reader(
+                File::open(&scenario_file)
+                    .with_context(|| format!("cannot open file {:?}", scenario_file))?,
+            )
+            .with_context(|| format!("could not parse scenario file {:?}", scenario_file))?;
+
+            let records = scenario.simulate()?;
+
+            let output: Box<dyn Write> = match output_file {
+                Some(output_file) => Box::new(
+                    File::create(&output_file)
+                        .with_context(|| format!("cannot create file {:?}", output_file))?,
+                ),
+                None => Box::new(std::io::stdout()),
+            };
+            let mut writer = csv::Writer::from_writer(output);
+            for record in records {
+                writer.serialize(record)?;
+            }
+            writer.flush()?;
+        }
     }
 
     Ok(())
@@ -19,8 +19,8 @@ pub struct PendingBlock {
     pub note_commitment_tree: NoteCommitmentTree,
     /// Stores note commitments for convienience when updating the NCT.
     pub notes: BTreeMap<note::Commitment, PositionedNoteData>,
-    /// Nullifiers that were spent in this block.
-    pub spent_nullifiers: BTreeSet<Nullifier>,
+    /// Nullifiers that were spent in this block, mapped to the ID of the transaction that spent them.
+    pub spent_nullifiers: BTreeMap<Nullifier, [u8; 32]>,
     /// The counter containing the number of rewards notes in the epoch. we need this to keep the
     /// blinding factor of the reward notes unique.
     reward_counter: u64,
@@ -33,6 +33,9 @@ pub struct PendingBlock {
     pub reverting_notes: BTreeSet<note::Commitment>,
     /// Nullifiers to remove from the nullifier set when this block is committed, reverting their spend.
     pub reverting_nullifiers: BTreeSet<Nullifier>,
+    /// Validators slashed in this block, recorded so that light clients can learn which of their
+    /// quarantined notes and nullifiers were reverted.
+    pub slashed_validators: Vec<IdentityKey>,
     /// Indicates the epoch the block belongs to.
     pub epoch: Option<Epoch>,
 }
@@ -55,12 +58,13 @@ impl PendingBlock {
             height: None,
             note_commitment_tree,
             notes: BTreeMap::new(),
-            spent_nullifiers: BTreeSet::new(),
+            spent_nullifiers: BTreeMap::new(),
             reward_counter: 0,
             quarantine: Vec::new(),
             reverting_notes: BTreeSet::new(),
             unbonding_nullifiers: BTreeSet::new(),
             reverting_nullifiers: BTreeSet::new(),
+            slashed_validators: Vec::new(),
             epoch: None,
         }
     }
@@ -110,6 +114,9 @@ impl PendingBlock {
         let note_data = NoteData {
             ephemeral_key: esk.diversified_public(&note.diversified_generator()),
             encrypted_note,
+            encrypted_memo: None,
+            value_commitment: None,
+            ovk_wrapped_key: None,
             transaction_id: [0; 32],
         };
 
@@ -156,7 +163,7 @@ impl PendingBlock {
         // Unconditionally, insert all nullifiers spent in this transaction into the spent set to
         // prevent double-spends, regardless of quarantine status.
         for nullifier in transaction.spent_nullifiers {
-            self.spent_nullifiers.insert(nullifier);
+            self.spent_nullifiers.insert(nullifier, transaction.id);
         }
     }
 }
@@ -1,42 +1,15 @@
 use anyhow::Result;
 use futures::future::BoxFuture;
 use jmt::{
-    define_hasher,
-    hash::{CryptoHasher, DefaultHasher, HashValue},
     node_type::{LeafNode, Node, NodeKey},
     NodeBatch, TreeReaderAsync, TreeWriterAsync, Value,
 };
-use once_cell::sync::{Lazy, OnceCell};
+pub use penumbra_chain::app_hash::Key;
 use sqlx::{query, Postgres};
 use tracing::instrument;
 
 use crate::state;
 
-pub enum Key {
-    NoteCommitmentAnchor,
-}
-
-impl Key {
-    pub fn hash(self) -> HashValue {
-        match self {
-            Key::NoteCommitmentAnchor => {
-                let mut state = NoteCommitmentAnchorHasher::default();
-                state.update(b"");
-                state.finish()
-            }
-        }
-    }
-}
-
-define_hasher! {
-    (
-        NoteCommitmentAnchorHasher,
-        NOTE_COMMITMENT_ANCHOR_HASHER,
-        NOTE_COMMITMENT_ANCHOR_SEED,
-        b"nct"
-    )
-}
-
 /// Wrapper struct used to implement [`jmt::TreeWriterAsync`] for a Postgres
 /// transaction, without violating the orphan rules.
 pub struct DbTx<'conn, 'tx>(pub &'tx mut sqlx::Transaction<'conn, Postgres>);
@@ -5,30 +5,31 @@ use std::{
     str::FromStr,
 };
 
-use anyhow::{Context, Result};
+use anyhow::{anyhow, Context, Result};
 use async_stream::try_stream;
 use futures::stream::{Stream, StreamExt, TryStreamExt};
 use penumbra_chain::params::ChainParams;
 use penumbra_crypto::{
-    asset,
+    asset, memo,
     merkle::{self, NoteCommitmentTree},
     note, Address, FieldExt, Fq, Nullifier,
 };
 use penumbra_proto::{
     chain,
-    light_wallet::{CompactBlock, StateFragment},
+    light_wallet::{CompactBlock, QuarantinedNullifier, QuarantinedStateFragment, StateFragment},
     thin_wallet::{Asset, TransactionDetail},
     Protobuf,
 };
 use penumbra_stake::{
     BaseRateData, FundingStream, FundingStreams, IdentityKey, RateData, RateDataById, Validator,
-    ValidatorInfo, ValidatorState, ValidatorStateName, ValidatorStatus,
+    ValidatorEpochInfo, ValidatorInfo, ValidatorState, ValidatorStateName, ValidatorStatus,
 };
 use sqlx::{query, query_as, Pool, Postgres};
 use tendermint::block;
 use tokio::sync::watch;
 use tracing::instrument;
 
+use super::jellyfish;
 use crate::{db::schema, genesis, pd_metrics::MetricsData, verify::NoteData};
 
 #[derive(Debug, Clone)]
@@ -238,6 +239,21 @@ impl Reader {
             .unwrap_or_else(|| vec![0; 32]))
     }
 
+    /// Retrieve the note commitment tree anchor committed in the app hash at the given height,
+    /// with the bincode encoding of its Jellyfish Merkle Tree proof.
+    pub async fn anchor_proof(&self, height: u64) -> Result<(merkle::Root, Vec<u8>)> {
+        if height > self.height().await?.value() {
+            return Err(anyhow!("height {} has not been reached", height));
+        }
+
+        let (anchor, proof) = jmt::JellyfishMerkleTree::<_, merkle::Root>::new(self)
+            .get_with_proof(jellyfish::Key::NoteCommitmentAnchor.hash(), height)
+            .await?;
+        let anchor = anchor.ok_or_else(|| anyhow!("no anchor committed at height {}", height))?;
+
+        Ok((anchor, bincode::serialize(&proof)?))
+    }
+
     pub async fn base_rate_data(&self, epoch_index: u64) -> Result<BaseRateData> {
         let mut conn = self.pool.acquire().await?;
         let row = query!(
@@ -319,6 +335,76 @@ impl Reader {
             .collect())
     }
 
+    /// Retrieve a stream of the rates, status and delegation token supply of a validator in each
+    /// epoch of the given (inclusive) range.
+    ///
+    /// If `end_epoch_index` is `None`, the stream runs from `start_epoch_index` up to the latest
+    /// epoch.
+    pub fn validator_history(
+        &self,
+        identity_key: IdentityKey,
+        start_epoch_index: u64,
+        end_epoch_index: Option<u64>,
+    ) -> impl Stream<Item = Result<ValidatorEpochInfo>> + Send + Unpin {
+        let pool = self.pool.clone();
+        Box::pin(try_stream! {
+            let mut rows = query!(
+                "SELECT
+                validator_rates.epoch,
+                validator_rates.validator_reward_rate,
+                validator_rates.validator_exchange_rate,
+                validator_rates.voting_power,
+                validator_rates.validator_state,
+                validator_rates.unbonding_epoch,
+                validator_rates.delegation_token_supply,
+                base_rates.base_reward_rate,
+                base_rates.base_exchange_rate
+            FROM (
+                validator_rates INNER JOIN base_rates ON validator_rates.epoch = base_rates.epoch
+            )
+            WHERE validator_rates.identity_key = $1 AND validator_rates.epoch BETWEEN $2 AND $3
+            ORDER BY validator_rates.epoch ASC",
+                identity_key.encode_to_vec(),
+                start_epoch_index as i64,
+                end_epoch_index.map(|i| i as i64).unwrap_or(i64::MAX),
+            )
+            .fetch(&pool);
+
+            // this does conversions manually rather than using query_as because of i64/u64 casting
+            while let Some(row) = rows.try_next().await? {
+                // The status is only recorded for epochs whose rates were computed at an epoch
+                // transition, not for the initial rates of a new validator.
+                let status = match (row.voting_power, row.validator_state) {
+                    (Some(voting_power), Some(state_name)) => Some(ValidatorStatus {
+                        identity_key: identity_key.clone(),
+                        voting_power: voting_power as u64,
+                        state: ValidatorState::try_from((
+                            ValidatorStateName::from_str(&state_name)?,
+                            row.unbonding_epoch.map(|i| i as u64),
+                        ))?,
+                    }),
+                    _ => None,
+                };
+
+                yield ValidatorEpochInfo {
+                    rate_data: RateData {
+                        identity_key: identity_key.clone(),
+                        epoch_index: row.epoch as u64,
+                        validator_exchange_rate: row.validator_exchange_rate as u64,
+                        validator_reward_rate: row.validator_reward_rate as u64,
+                    },
+                    base_rate_data: BaseRateData {
+                        epoch_index: row.epoch as u64,
+                        base_exchange_rate: row.base_exchange_rate as u64,
+                        base_reward_rate: row.base_reward_rate as u64,
+                    },
+                    status,
+                    delegation_token_supply: row.delegation_token_supply.map(|s| s as u64),
+                };
+            }
+        })
+    }
+
     pub async fn funding_streams(
         &self,
         validator_identity_key: IdentityKey,
@@ -425,7 +511,7 @@ impl Reader {
         let pool = self.pool.clone();
         Box::pin(try_stream! {
             let mut nullifiers = query!(
-                "SELECT height, nullifier
+                "SELECT height, nullifier, transaction_id
                     FROM nullifiers
                     WHERE height BETWEEN $1 AND $2
                     ORDER BY height ASC",
@@ -436,7 +522,7 @@ impl Reader {
             .peekable();
 
             let mut fragments = query!(
-                "SELECT height, note_commitment, ephemeral_key, encrypted_note
+                "SELECT height, note_commitment, ephemeral_key, encrypted_note, encrypted_memo, value_commitment, ovk_wrapped_key, transaction_id
                     FROM notes
                     WHERE height BETWEEN $1 AND $2
                     ORDER BY position ASC",
@@ -446,11 +532,60 @@ impl Reader {
             .fetch(&pool)
             .peekable();
 
+            let mut quarantined_fragments = query!(
+                "SELECT height, note_commitment, ephemeral_key, encrypted_note, encrypted_memo, value_commitment, ovk_wrapped_key, transaction_id, unbonding_height, validator_identity_key
+                    FROM quarantined_notes
+                    WHERE height BETWEEN $1 AND $2
+                    ORDER BY height ASC",
+                start_height,
+                end_height
+            )
+            .fetch(&pool)
+            .peekable();
+
+            let mut quarantined_nullifiers = query!(
+                "SELECT height, nullifier, unbonding_height, validator_identity_key
+                    FROM quarantined_nullifiers
+                    WHERE height BETWEEN $1 AND $2
+                    ORDER BY height ASC",
+                start_height,
+                end_height
+            )
+            .fetch(&pool)
+            .peekable();
+
+            let mut anchors = query!(
+                "SELECT height, nct_anchor
+                    FROM blocks
+                    WHERE height BETWEEN $1 AND $2
+                    ORDER BY height ASC",
+                start_height,
+                end_height
+            )
+            .fetch(&pool)
+            .peekable();
+
+            let mut slashings = query!(
+                "SELECT height, identity_key
+                    FROM slashings
+                    WHERE height BETWEEN $1 AND $2
+                    ORDER BY height ASC",
+                start_height,
+                end_height
+            )
+            .fetch(&pool)
+            .peekable();
+
             for height in start_height..=end_height {
                 let mut compact_block = CompactBlock {
                     height: height as u64,
                     fragments: vec![],
                     nullifiers: vec![],
+                    quarantined_fragments: vec![],
+                    quarantined_nullifiers: vec![],
+                    slashed: vec![],
+                    nullifier_transaction_ids: vec![],
+                    nct_anchor: Default::default(),
                 };
 
                 while let Some(row) = Pin::new(&mut nullifiers).peek().await {
@@ -466,6 +601,9 @@ impl Reader {
                         .await
                         .expect("we already peeked, so there is a next row")?;
                     compact_block.nullifiers.push(row.nullifier.into());
+                    compact_block
+                        .nullifier_transaction_ids
+                        .push(row.transaction_id.unwrap_or_default().into());
                 }
 
                 while let Some(row) = Pin::new(&mut fragments).peek().await {
@@ -484,13 +622,102 @@ impl Reader {
                         note_commitment: row.note_commitment.into(),
                         ephemeral_key: row.ephemeral_key.into(),
                         encrypted_note: row.encrypted_note.into(),
+                        encrypted_memo: row.encrypted_memo.unwrap_or_default().into(),
+                        value_commitment: row.value_commitment.unwrap_or_default().into(),
+                        ovk_wrapped_key: row.ovk_wrapped_key.unwrap_or_default().into(),
+                        transaction_id: row.transaction_id.into(),
+                    });
+                }
+
+                while let Some(row) = Pin::new(&mut quarantined_fragments).peek().await {
+                    // Bail out of the loop if the next iteration would be a different height
+                    if let Ok(row) = row {
+                        if row.height != height {
+                            break;
+                        }
+                    }
+
+                    let row = Pin::new(&mut quarantined_fragments)
+                        .next()
+                        .await
+                        .expect("we already peeked, so there is a next row")?;
+                    compact_block.quarantined_fragments.push(QuarantinedStateFragment {
+                        fragment: Some(StateFragment {
+                            note_commitment: row.note_commitment.into(),
+                            ephemeral_key: row.ephemeral_key.into(),
+                            encrypted_note: row.encrypted_note.into(),
+                            encrypted_memo: row.encrypted_memo.unwrap_or_default().into(),
+                            value_commitment: row.value_commitment.unwrap_or_default().into(),
+                            ovk_wrapped_key: row.ovk_wrapped_key.unwrap_or_default().into(),
+                            transaction_id: row.transaction_id.into(),
+                        }),
+                        unbonding_height: row.unbonding_height as u64,
+                        validator_identity_key: Some(
+                            IdentityKey::decode(row.validator_identity_key.as_slice())?.into(),
+                        ),
+                    });
+                }
+
+                while let Some(row) = Pin::new(&mut quarantined_nullifiers).peek().await {
+                    // Bail out of the loop if the next iteration would be a different height
+                    if let Ok(row) = row {
+                        if row.height != height {
+                            break;
+                        }
+                    }
+
+                    let row = Pin::new(&mut quarantined_nullifiers)
+                        .next()
+                        .await
+                        .expect("we already peeked, so there is a next row")?;
+                    compact_block.quarantined_nullifiers.push(QuarantinedNullifier {
+                        nullifier: row.nullifier.into(),
+                        unbonding_height: row.unbonding_height as u64,
+                        validator_identity_key: Some(
+                            IdentityKey::decode(row.validator_identity_key.as_slice())?.into(),
+                        ),
                     });
                 }
 
+                while let Some(row) = Pin::new(&mut slashings).peek().await {
+                    // Bail out of the loop if the next iteration would be a different height
+                    if let Ok(row) = row {
+                        if row.height != height {
+                            break;
+                        }
+                    }
+
+                    let row = Pin::new(&mut slashings)
+                        .next()
+                        .await
+                        .expect("we already peeked, so there is a next row")?;
+                    compact_block
+                        .slashed
+                        .push(IdentityKey::decode(row.identity_key.as_slice())?.into());
+                }
+
+                while let Some(row) = Pin::new(&mut anchors).peek().await {
+                    // Bail out of the loop if the next iteration would be a different height
+                    if let Ok(row) = row {
+                        if row.height != height {
+                            break;
+                        }
+                    }
+
+                    let row = Pin::new(&mut anchors)
+                        .next()
+                        .await
+                        .expect("we already peeked, so there is a next row")?;
+                    compact_block.nct_anchor = row.nct_anchor.into();
+                }
+
                 tracing::debug!(
                     ?height,
                     nullifiers_size = compact_block.nullifiers.len(),
                     fragments_size = compact_block.fragments.len(),
+                    quarantined_fragments_size = compact_block.quarantined_fragments.len(),
+                    quarantined_nullifiers_size = compact_block.quarantined_nullifiers.len(),
+                    slashed_size = compact_block.slashed.len(),
                     "yielding compact block"
                 );
 
@@ -525,11 +752,15 @@ impl Reader {
             .collect::<Vec<_>>();
 
         query!(
-            "SELECT validator_identity_key, note_commitment, ephemeral_key, encrypted_note, transaction_id
+            "SELECT validator_identity_key, note_commitment, ephemeral_key, encrypted_note, encrypted_memo, value_commitment, ovk_wrapped_key, transaction_id
             FROM quarantined_notes
             WHERE
                 unbonding_height <= $1 AND
-                ($2 OR validator_identity_key = ANY($3))",
+                ($2 OR validator_identity_key = ANY($3)) AND
+                NOT EXISTS (
+                    SELECT 1 FROM quarantined_note_resolutions
+                    WHERE quarantined_note_resolutions.note_commitment = quarantined_notes.note_commitment
+                )",
             maximum_unbonding_height.unwrap_or(u64::MAX) as i64,
             all_validators,
             &validator_list,
@@ -545,6 +776,18 @@ impl Reader {
                         NoteData {
                             ephemeral_key: row.ephemeral_key[..].try_into()?,
                             encrypted_note: row.encrypted_note[..].try_into()?,
+                            encrypted_memo: row
+                                .encrypted_memo
+                                .map(|bytes| bytes[..].try_into().map(memo::MemoCiphertext))
+                                .transpose()?,
+                            value_commitment: row
+                                .value_commitment
+                                .map(|bytes| bytes[..].try_into())
+                                .transpose()?,
+                            ovk_wrapped_key: row
+                                .ovk_wrapped_key
+                                .map(|bytes| bytes[..].try_into())
+                                .transpose()?,
                             transaction_id: row.transaction_id[..].try_into()?,
                         },
                     ))
@@ -582,7 +825,13 @@ impl Reader {
             FROM quarantined_nullifiers
             WHERE
                 unbonding_height <= $1 AND
-                ($2 OR validator_identity_key = ANY($3))",
+                ($2 OR validator_identity_key = ANY($3)) AND
+                NOT EXISTS (
+                    SELECT 1 FROM quarantined_nullifier_resolutions
+                    WHERE
+                        quarantined_nullifier_resolutions.nullifier = quarantined_nullifiers.nullifier AND
+                        quarantined_nullifier_resolutions.quarantine_height = quarantined_nullifiers.height
+                )",
             maximum_unbonding_height.unwrap_or(u64::MAX) as i64,
             all_validators,
             &validator_list,
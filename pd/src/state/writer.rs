@@ -237,6 +237,9 @@ impl Writer {
             let note_data = NoteData {
                 ephemeral_key: esk.diversified_public(&note.diversified_generator()),
                 encrypted_note,
+                encrypted_memo: None,
+                value_commitment: None,
+                ovk_wrapped_key: None,
                 // A transaction ID is either a hash of a transaction, or special data.
                 // Special data is encoded with 23 leading 0 bytes, followed by a nonzero code byte,
                 // followed by 8 data bytes.
@@ -331,13 +334,26 @@ impl Writer {
                     note_commitment,
                     ephemeral_key,
                     encrypted_note,
+                    encrypted_memo,
+                    value_commitment,
+                    ovk_wrapped_key,
                     transaction_id,
                     position,
                     height
-                ) VALUES ($1, $2, $3, $4, $5, $6)"#,
+                ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)"#,
                 &<[u8; 32]>::from(commitment)[..],
                 &positioned_note.data.ephemeral_key.0[..],
                 &positioned_note.data.encrypted_note[..],
+                positioned_note
+                    .data
+                    .encrypted_memo
+                    .as_ref()
+                    .map(|memo| memo.0.to_vec()),
+                positioned_note
+                    .data
+                    .value_commitment
+                    .map(|cv| <[u8; 32]>::from(cv).to_vec()),
+                positioned_note.data.ovk_wrapped_key.map(|key| key.to_vec()),
                 &positioned_note.data.transaction_id[..],
                 i64::try_from(positioned_note.position)?,
                 // height 0 for genesis
@@ -413,11 +429,29 @@ impl Writer {
         .execute(&mut dbtx)
         .await?;
 
-        // Drop quarantined notes associated with a validator slashed in this block
+        // Record the validators slashed in this block, so that light clients can revert their
+        // quarantined notes and nullifiers
+        for identity_key in block.slashed_validators.iter() {
+            query!(
+                "INSERT INTO slashings (identity_key, height) VALUES ($1, $2)",
+                identity_key.encode_to_vec(),
+                i64::try_from(height)?,
+            )
+            .execute(&mut dbtx)
+            .await?;
+        }
+
+        // Revert quarantined notes associated with a validator slashed in this block. Quarantined
+        // notes and nullifiers are never deleted, so that compact blocks for the heights at which
+        // they were quarantined stay the same; their resolution is recorded instead.
         for note_commitment in block.reverting_notes {
             query!(
-                "DELETE FROM quarantined_notes WHERE note_commitment = $1",
-                &<[u8; 32]>::from(note_commitment)[..]
+                r#"
+                INSERT INTO quarantined_note_resolutions (note_commitment, height, reverted)
+                SELECT note_commitment, $2, $3 FROM quarantined_notes WHERE note_commitment = $1"#,
+                &<[u8; 32]>::from(note_commitment)[..],
+                i64::try_from(height)?,
+                true,
             )
             .execute(&mut dbtx)
             .await?;
@@ -434,10 +468,41 @@ impl Writer {
             .execute(&mut dbtx)
             .await?;
 
-            // We have reverted this nullifier, so we can remove it from quarantine
+            // We have reverted this nullifier, so it has left quarantine
             query!(
-                "DELETE FROM quarantined_nullifiers WHERE nullifier = $1",
-                &nullifier.to_bytes()[..]
+                r#"
+                INSERT INTO quarantined_nullifier_resolutions (nullifier, quarantine_height, height, reverted)
+                SELECT nullifier, height, $2, $3 FROM quarantined_nullifiers
+                WHERE nullifier = $1 AND NOT EXISTS (
+                    SELECT 1 FROM quarantined_nullifier_resolutions
+                    WHERE
+                        quarantined_nullifier_resolutions.nullifier = quarantined_nullifiers.nullifier AND
+                        quarantined_nullifier_resolutions.quarantine_height = quarantined_nullifiers.height
+                )"#,
+                &nullifier.to_bytes()[..],
+                i64::try_from(height)?,
+                true,
+            )
+            .execute(&mut dbtx)
+            .await?;
+        }
+
+        // Nullifiers which unbonded in this block have left quarantine, making their spend
+        // permanent
+        for nullifier in block.unbonding_nullifiers {
+            query!(
+                r#"
+                INSERT INTO quarantined_nullifier_resolutions (nullifier, quarantine_height, height, reverted)
+                SELECT nullifier, height, $2, $3 FROM quarantined_nullifiers
+                WHERE nullifier = $1 AND NOT EXISTS (
+                    SELECT 1 FROM quarantined_nullifier_resolutions
+                    WHERE
+                        quarantined_nullifier_resolutions.nullifier = quarantined_nullifiers.nullifier AND
+                        quarantined_nullifier_resolutions.quarantine_height = quarantined_nullifiers.height
+                )"#,
+                &nullifier.to_bytes()[..],
+                i64::try_from(height)?,
+                false,
             )
             .execute(&mut dbtx)
             .await?;
@@ -451,13 +516,26 @@ impl Writer {
                     note_commitment,
                     ephemeral_key,
                     encrypted_note,
+                    encrypted_memo,
+                    value_commitment,
+                    ovk_wrapped_key,
                     transaction_id,
                     position,
                     height
-                ) VALUES ($1, $2, $3, $4, $5, $6)"#,
+                ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)"#,
                 &<[u8; 32]>::from(note_commitment)[..],
                 &positioned_note.data.ephemeral_key.0[..],
                 &positioned_note.data.encrypted_note[..],
+                positioned_note
+                    .data
+                    .encrypted_memo
+                    .as_ref()
+                    .map(|memo| memo.0.to_vec()),
+                positioned_note
+                    .data
+                    .value_commitment
+                    .map(|cv| <[u8; 32]>::from(cv).to_vec()),
+                positioned_note.data.ovk_wrapped_key.map(|key| key.to_vec()),
                 &positioned_note.data.transaction_id[..],
                 i64::try_from(positioned_note.position)?,
                 i64::try_from(height)?,
@@ -465,10 +543,14 @@ impl Writer {
             .execute(&mut dbtx)
             .await?;
 
-            // If the note was previously quarantined, drop it from quarantine
+            // If the note was previously quarantined, it has now unbonded
             query!(
-                "DELETE FROM quarantined_notes WHERE note_commitment = $1",
-                &<[u8; 32]>::from(note_commitment)[..]
+                r#"
+                INSERT INTO quarantined_note_resolutions (note_commitment, height, reverted)
+                SELECT note_commitment, $2, $3 FROM quarantined_notes WHERE note_commitment = $1"#,
+                &<[u8; 32]>::from(note_commitment)[..],
+                i64::try_from(height)?,
+                false,
             )
             .execute(&mut dbtx)
             .await?;
@@ -502,16 +584,25 @@ impl Writer {
                         note_commitment,
                         ephemeral_key,
                         encrypted_note,
+                        encrypted_memo,
+                        value_commitment,
+                        ovk_wrapped_key,
                         transaction_id,
                         unbonding_height,
-                        validator_identity_key
-                    ) VALUES ($1, $2, $3, $4, $5, $6)"#,
+                        validator_identity_key,
+                        height
+                    ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)"#,
                     &<[u8; 32]>::from(note_commitment)[..],
                     &data.ephemeral_key.0[..],
                     &data.encrypted_note[..],
+                    data.encrypted_memo.as_ref().map(|memo| memo.0.to_vec()),
+                    data.value_commitment
+                        .map(|cv| <[u8; 32]>::from(cv).to_vec()),
+                    data.ovk_wrapped_key.map(|key| key.to_vec()),
                     &data.transaction_id[..],
                     i64::try_from(unbonding_height)?,
                     &validator_identity_key.0.to_bytes()[..],
+                    i64::try_from(height)?,
                 )
                 .execute(&mut dbtx)
                 .await?;
@@ -524,11 +615,12 @@ impl Writer {
                 // Keep track of the nullifier associated with the block height
                 query!(
                     r#"
-                    INSERT INTO quarantined_nullifiers (nullifier, unbonding_height, validator_identity_key)
-                    VALUES ($1, $2, $3)"#,
+                    INSERT INTO quarantined_nullifiers (nullifier, unbonding_height, validator_identity_key, height)
+                    VALUES ($1, $2, $3, $4)"#,
                     nullifier_bytes,
                     i64::try_from(unbonding_height)?,
                     &validator_identity_key.0.to_bytes()[..],
+                    i64::try_from(height)?,
                 )
                 .execute(&mut dbtx)
                 .await?;
@@ -536,11 +628,12 @@ impl Writer {
         }
 
         // Mark spent notes as spent.
-        for nullifier in block.spent_nullifiers.into_iter() {
+        for (nullifier, transaction_id) in block.spent_nullifiers.into_iter() {
             query!(
-                "INSERT INTO nullifiers VALUES ($1, $2)",
+                "INSERT INTO nullifiers (nullifier, height, transaction_id) VALUES ($1, $2, $3)",
                 &<[u8; 32]>::from(nullifier)[..],
                 i64::try_from(height)?,
+                &transaction_id[..],
             )
             .execute(&mut dbtx)
             .await?;
@@ -1,6 +1,6 @@
 use std::collections::{BTreeMap, BTreeSet};
 
-use penumbra_crypto::{ka, merkle, note, Nullifier};
+use penumbra_crypto::{ka, memo, merkle, note, value, Nullifier};
 use penumbra_stake::{
     Delegate, IdentityKey, Undelegate, ValidatorDefinition, VerifiedValidatorDefinition,
 };
@@ -18,6 +18,12 @@ mod tests;
 pub struct NoteData {
     pub ephemeral_key: ka::Public,
     pub encrypted_note: [u8; note::NOTE_CIPHERTEXT_BYTES],
+    /// The memo of the output that created the note, encrypted to its recipient, if any.
+    pub encrypted_memo: Option<memo::MemoCiphertext>,
+    /// The value commitment of the output that created the note, if any.
+    pub value_commitment: Option<value::Commitment>,
+    /// The note's key wrapped to the sender's outgoing viewing key, if any.
+    pub ovk_wrapped_key: Option<[u8; note::OVK_WRAPPED_LEN_BYTES]>,
     pub transaction_id: [u8; 32],
 }
 
@@ -34,6 +40,9 @@ pub struct PendingTransaction {
     pub id: [u8; 32],
     /// Root of the note commitment tree.
     pub root: merkle::Root,
+    /// The last height at which the transaction can be included in a block, or 0 if it never
+    /// expires.
+    pub expiry_height: u32,
     /// Note data to add from outputs in this transaction.
     pub new_notes: BTreeMap<note::Commitment, NoteData>,
     /// List of spent nullifiers from spends in this transaction.
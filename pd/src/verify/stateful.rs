@@ -20,6 +20,9 @@ impl state::Reader {
             return Err(anyhow::anyhow!("invalid note commitment tree root"));
         }
 
+        // The transaction would be included in the next block.
+        transaction.check_expiry(self.height_rx().borrow().value() + 1)?;
+
         let existing_nullifiers = self.check_nullifiers(&transaction.spent_nullifiers).await?;
         if !existing_nullifiers.is_empty() {
             return Err(anyhow::anyhow!(
@@ -79,7 +82,7 @@ impl state::Reader {
             //
             // should give approximately the same results, they may not give
             // exactly the same results.
-            let expected_delegation_amount = rate_data.delegation_amount(d.unbonded_amount);
+            let expected_delegation_amount = rate_data.delegation_amount(d.unbonded_amount)?;
 
             if expected_delegation_amount == d.delegation_amount {
                 // The delegation amount is added to the delegation token supply.
@@ -129,7 +132,7 @@ impl state::Reader {
             //
             // should give approximately the same results, they may not give
             // exactly the same results.
-            let expected_unbonded_amount = rate_data.unbonded_amount(u.delegation_amount);
+            let expected_unbonded_amount = rate_data.unbonded_amount(u.delegation_amount)?;
 
             if expected_unbonded_amount == u.unbonded_amount {
                 // TODO: in order to have exact tracking of the token supply, we probably
@@ -192,3 +195,18 @@ impl state::Reader {
         })
     }
 }
+
+impl PendingTransaction {
+    /// Checks that the transaction can be included in the block at `height`, which must not be
+    /// past its expiry height.
+    pub fn check_expiry(&self, height: u64) -> Result<(), Error> {
+        if self.expiry_height != 0 && u64::from(self.expiry_height) < height {
+            return Err(anyhow::anyhow!(
+                "transaction expired at height {}, before height {}",
+                self.expiry_height,
+                height
+            ));
+        }
+        Ok(())
+    }
+}
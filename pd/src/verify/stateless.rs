@@ -60,6 +60,9 @@ impl StatelessTransactionExt for Transaction {
                         NoteData {
                             ephemeral_key: output.body.ephemeral_key,
                             encrypted_note: output.body.encrypted_note,
+                            encrypted_memo: Some(output.encrypted_memo.clone()),
+                            value_commitment: Some(output.body.value_commitment),
+                            ovk_wrapped_key: Some(output.ovk_wrapped_key),
                             transaction_id: id,
                         },
                     );
@@ -158,6 +161,7 @@ impl StatelessTransactionExt for Transaction {
         Ok(PendingTransaction {
             id,
             root: self.transaction_body().merkle_root,
+            expiry_height: self.transaction_body().expiry_height,
             new_notes,
             spent_nullifiers,
             delegations,
@@ -18,7 +18,6 @@ fn test_transaction_succeeds_if_values_balance() {
     let spend_seed = SpendSeed::from_seed_phrase(seed_phrase, 0);
     let sk_sender = SpendKey::new(spend_seed);
     let fvk_sender = sk_sender.full_viewing_key();
-    let ovk_sender = fvk_sender.outgoing();
     let (send_addr, _) = fvk_sender.incoming().payment_address(0u64.into());
 
     let seed_phrase = SeedPhrase::generate(&mut rng);
@@ -54,19 +53,69 @@ fn test_transaction_succeeds_if_values_balance() {
     let transaction = Transaction::build_with_root(anchor)
         .set_fee(10)
         .set_chain_id("penumbra".to_string())
-        .add_output(
-            &mut rng,
-            &dest,
-            output_value,
-            MemoPlaintext::default(),
-            ovk_sender,
-        )
-        .add_spend(&mut rng, &nct, &sk_sender, note)
+        .add_output(&mut rng, &dest, output_value, MemoPlaintext::default())
+        .add_spend(&mut rng, &nct, note)
         .expect("note is in nct")
-        .finalize(&mut rng)
+        .finalize(&mut rng, &sk_sender)
         .expect("transaction created ok");
 
     let _pending_tx = transaction
         .verify_stateless()
         .expect("stateless verification should pass");
 }
+
+#[test]
+fn test_transaction_rejected_after_expiry_height() {
+    let mut rng = OsRng;
+    let seed_phrase = SeedPhrase::generate(&mut rng);
+    let spend_seed = SpendSeed::from_seed_phrase(seed_phrase, 0);
+    let sk_sender = SpendKey::new(spend_seed);
+    let fvk_sender = sk_sender.full_viewing_key();
+    let (send_addr, _) = fvk_sender.incoming().payment_address(0u64.into());
+
+    let value = Value {
+        amount: 20,
+        asset_id: asset::REGISTRY.parse_denom("upenumbra").unwrap().id(),
+    };
+    let note = Note::from_parts(
+        *send_addr.diversifier(),
+        *send_addr.transmission_key(),
+        value,
+        Fq::zero(),
+    )
+    .expect("transmission key is valid");
+
+    let mut nct = NoteCommitmentTree::new(1);
+    nct.append(&note.commit());
+    nct.witness();
+    let anchor = nct.root2();
+
+    let build = |expiry_height| {
+        Transaction::build_with_root(anchor)
+            .set_fee(0)
+            .set_chain_id("penumbra".to_string())
+            .set_expiry_height(expiry_height)
+            .add_output(&mut OsRng, &send_addr, value, MemoPlaintext::default())
+            .add_spend(&mut OsRng, &nct, note.clone())
+            .expect("note is in nct")
+            .finalize(&mut OsRng, &sk_sender)
+            .expect("transaction created ok")
+            .verify_stateless()
+            .expect("stateless verification should pass")
+    };
+
+    // The transaction can be included up to and including the block at its expiry height.
+    let pending_tx = build(10);
+    pending_tx
+        .check_expiry(9)
+        .expect("transaction has not expired");
+    pending_tx
+        .check_expiry(10)
+        .expect("transaction can be included at its expiry height");
+    assert!(pending_tx.check_expiry(11).is_err());
+
+    // An expiry height of 0 means the transaction never expires.
+    build(0)
+        .check_expiry(u64::MAX)
+        .expect("transaction never expires");
+}
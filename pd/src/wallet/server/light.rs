@@ -4,8 +4,8 @@ use futures::stream::{StreamExt, TryStreamExt};
 use penumbra_proto::{
     chain::ChainParams,
     light_wallet::{
-        light_wallet_server::LightWallet, ChainParamsRequest, CompactBlock,
-        CompactBlockRangeRequest, ValidatorInfoRequest,
+        light_wallet_server::LightWallet, AnchorProof, AnchorProofRequest, ChainParamsRequest,
+        CompactBlock, CompactBlockRangeRequest, ValidatorInfoRequest,
     },
     stake::ValidatorInfo,
 };
@@ -108,4 +108,24 @@ impl LightWallet for state::Reader {
 
         Ok(tonic::Response::new(stream.boxed()))
     }
+
+    #[instrument(skip(self, request), fields(height = request.get_ref().height))]
+    async fn anchor_proof(
+        &self,
+        request: tonic::Request<AnchorProofRequest>,
+    ) -> Result<tonic::Response<AnchorProof>, Status> {
+        self.check_chain_id(&request.get_ref().chain_id)?;
+
+        let height = request.get_ref().height;
+        let (nct_anchor, proof) = self
+            .anchor_proof(height)
+            .await
+            .map_err(|e| tonic::Status::unavailable(e.to_string()))?;
+
+        Ok(tonic::Response::new(AnchorProof {
+            height,
+            nct_anchor: nct_anchor.to_bytes().to_vec(),
+            proof,
+        }))
+    }
 }
@@ -1,9 +1,14 @@
+use std::pin::Pin;
+
+use futures::stream::{StreamExt, TryStreamExt};
 use penumbra_proto::{
     self as proto,
     chain::AssetInfo,
+    stake::ValidatorEpochInfo,
     thin_wallet::{
         thin_wallet_server::ThinWallet, Asset, AssetListRequest, AssetLookupRequest,
-        TransactionByNoteRequest, TransactionDetail, ValidatorRateRequest, ValidatorStatusRequest,
+        TransactionByNoteRequest, TransactionDetail, ValidatorHistoryRequest, ValidatorRateRequest,
+        ValidatorStatusRequest,
     },
 };
 use penumbra_stake::IdentityKey;
@@ -18,6 +23,9 @@ use crate::state;
 impl ThinWallet for state::Reader {
     type AssetListStream = ReceiverStream<Result<Asset, Status>>;
 
+    type ValidatorHistoryStream =
+        Pin<Box<dyn futures::Stream<Item = Result<ValidatorEpochInfo, tonic::Status>> + Send>>;
+
     #[instrument(skip(self, request))]
     async fn transaction_by_note(
         &self,
@@ -126,4 +134,37 @@ tracing::debug!(asset_id = ?hex::encode(&asset.asset_id), asset_denom = ?asset.a
 
         Ok(tonic::Response::new(rate.into()))
     }
+
+    #[instrument(
+        skip(self, request),
+        fields(
+            start_epoch_index = request.get_ref().start_epoch_index,
+            end_epoch_index = ?request.get_ref().end_epoch_index,
+        ),
+    )]
+    async fn validator_history(
+        &self,
+        request: tonic::Request<ValidatorHistoryRequest>,
+    ) -> Result<tonic::Response<Self::ValidatorHistoryStream>, Status> {
+        self.check_chain_id(&request.get_ref().chain_id)?;
+
+        let request = request.into_inner();
+        let identity_key = IdentityKey::try_from(
+            request
+                .identity_key
+                .ok_or_else(|| tonic::Status::invalid_argument("missing identity key"))?,
+        )
+        .map_err(|_| tonic::Status::invalid_argument("invalid identity key"))?;
+
+        let stream = self
+            .validator_history(
+                identity_key,
+                request.start_epoch_index,
+                request.end_epoch_index,
+            )
+            .map_ok(Into::into)
+            .map_err(|e| tonic::Status::internal(e.to_string()));
+
+        Ok(tonic::Response::new(stream.boxed()))
+    }
 }
@@ -25,6 +25,7 @@ fn main() -> Result<()> {
         // byte arrays and then discarded.
         ".penumbra.light_wallet.StateFragment",
         ".penumbra.light_wallet.CompactBlock",
+        ".penumbra.light_wallet.QuarantinedNullifier",
     ]);
 
     for (path, attribute) in TYPE_ATTRIBUTES.iter() {
@@ -47,7 +48,11 @@ fn main() -> Result<()> {
     // For the client code, we also want to generate RPC instances, so compile via tonic:
     tonic_build::configure().compile_with_config(
         config,
-        &["proto/light_wallet.proto", "proto/thin_wallet.proto"],
+        &[
+            "proto/light_wallet.proto",
+            "proto/thin_wallet.proto",
+            "proto/view.proto",
+        ],
         &["proto/"],
     )?;
 
@@ -73,6 +78,7 @@ static TYPE_ATTRIBUTES: &[(&str, &str)] = &[
     (".penumbra.stake.ValidatorState", SERIALIZE),
     (".penumbra.stake.ValidatorStateName", SERIALIZE),
     (".penumbra.stake.ValidatorInfo", SERIALIZE),
+    (".penumbra.stake.ValidatorEpochInfo", SERIALIZE),
     (".penumbra.stake.RateData", SERIALIZE),
     (".penumbra.stake.BaseRateData", SERIALIZE),
     (".penumbra.stake.IdentityKey", SERIALIZE),
@@ -58,6 +58,11 @@ pub mod thin_wallet {
     tonic::include_proto!("penumbra.thin_wallet");
 }
 
+/// View protocol structures.
+pub mod view {
+    tonic::include_proto!("penumbra.view");
+}
+
 pub mod sighash {
     include!(concat!(env!("OUT_DIR"), "/penumbra.sighash.rs"));
 
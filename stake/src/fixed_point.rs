@@ -0,0 +1,238 @@
+//! Fixed-point arithmetic for rate computations, as described in the [spec].
+//!
+//! [spec]: https://protocol.penumbra.zone/main/stake/arithmetic.html
+
+use std::fmt;
+
+use serde::{Serialize, Serializer};
+
+/// The implicit denominator of a [`FixedPoint`] representation: 8 decimal digits of precision.
+pub const FIXED_POINT_DENOMINATOR: u64 = 1_0000_0000;
+
+/// The implicit denominator of a rate specified in basis points.
+const BPS_DENOMINATOR: u64 = 1_0000;
+
+/// An error in fixed-point arithmetic.
+#[derive(thiserror::Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArithmeticError {
+    #[error("Arithmetic overflow")]
+    Overflow,
+    #[error("Arithmetic underflow")]
+    Underflow,
+    #[error("Division by zero")]
+    DivisionByZero,
+}
+
+/// The direction in which to round the result of an inexact fixed-point operation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rounding {
+    /// Round towards zero.
+    Down,
+    /// Round away from zero.
+    Up,
+}
+
+/// A non-negative fixed-point number with 8 decimal digits of precision.
+///
+/// A value `x` is represented by the `u64` `x * 10^8`, so that rates like the exchange rate and
+/// reward rate fit in a `u64` while all intermediate products fit in a `u128`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct FixedPoint(u64);
+
+impl FixedPoint {
+    /// The fixed-point representation of `0`.
+    pub const ZERO: FixedPoint = FixedPoint(0);
+
+    /// The fixed-point representation of `1`.
+    pub const ONE: FixedPoint = FixedPoint(FIXED_POINT_DENOMINATOR);
+
+    /// Constructs a fixed-point number from its representation, i.e. the value times `10^8`.
+    pub const fn from_raw(raw: u64) -> Self {
+        FixedPoint(raw)
+    }
+
+    /// Returns the representation of this fixed-point number, i.e. the value times `10^8`.
+    pub const fn raw(&self) -> u64 {
+        self.0
+    }
+
+    /// Constructs a fixed-point number from a rate in basis points.
+    pub fn from_bps(bps: u16) -> Self {
+        // This can't overflow, since u16::MAX * 10^4 < u64::MAX.
+        FixedPoint(bps as u64 * (FIXED_POINT_DENOMINATOR / BPS_DENOMINATOR))
+    }
+
+    /// Adds two fixed-point numbers, returning an error on overflow.
+    pub fn checked_add(&self, other: &FixedPoint) -> Result<FixedPoint, ArithmeticError> {
+        self.0
+            .checked_add(other.0)
+            .map(FixedPoint)
+            .ok_or(ArithmeticError::Overflow)
+    }
+
+    /// Subtracts `other` from this fixed-point number, returning an error on underflow.
+    pub fn checked_sub(&self, other: &FixedPoint) -> Result<FixedPoint, ArithmeticError> {
+        self.0
+            .checked_sub(other.0)
+            .map(FixedPoint)
+            .ok_or(ArithmeticError::Underflow)
+    }
+
+    /// Multiplies two fixed-point numbers, rounding the result in the given direction.
+    pub fn checked_mul(
+        &self,
+        other: &FixedPoint,
+        rounding: Rounding,
+    ) -> Result<FixedPoint, ArithmeticError> {
+        mul_div(self.0, other.0, FIXED_POINT_DENOMINATOR, rounding).map(FixedPoint)
+    }
+
+    /// Divides this fixed-point number by `other`, rounding the result in the given direction.
+    pub fn checked_div(
+        &self,
+        other: &FixedPoint,
+        rounding: Rounding,
+    ) -> Result<FixedPoint, ArithmeticError> {
+        mul_div(self.0, FIXED_POINT_DENOMINATOR, other.0, rounding).map(FixedPoint)
+    }
+
+    /// Multiplies an integer amount by this fixed-point number, rounding the result in the given
+    /// direction.
+    pub fn apply_to_amount(&self, amount: u64, rounding: Rounding) -> Result<u64, ArithmeticError> {
+        mul_div(amount, self.0, FIXED_POINT_DENOMINATOR, rounding)
+    }
+
+    /// Divides an integer amount by this fixed-point number, rounding the result in the given
+    /// direction.
+    pub fn divide_amount(&self, amount: u64, rounding: Rounding) -> Result<u64, ArithmeticError> {
+        mul_div(amount, FIXED_POINT_DENOMINATOR, self.0, rounding)
+    }
+
+    /// Converts an integer amount valued at this rate into one valued at the `other` rate, i.e.
+    /// computes `amount * self / other` without rounding the intermediate ratio.
+    pub fn rescale_amount(
+        &self,
+        amount: u64,
+        other: &FixedPoint,
+        rounding: Rounding,
+    ) -> Result<u64, ArithmeticError> {
+        mul_div(amount, self.0, other.0, rounding)
+    }
+}
+
+impl fmt::Display for FixedPoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}.{:08}",
+            self.0 / FIXED_POINT_DENOMINATOR,
+            self.0 % FIXED_POINT_DENOMINATOR
+        )
+    }
+}
+
+impl Serialize for FixedPoint {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// Computes `a * b / c` with a `u128` intermediate, rounding in the given direction and checking
+/// that the result fits in a `u64`.
+fn mul_div(a: u64, b: u64, c: u64, rounding: Rounding) -> Result<u64, ArithmeticError> {
+    if c == 0 {
+        return Err(ArithmeticError::DivisionByZero);
+    }
+
+    // This can't overflow, since (2^64 - 1)^2 < 2^128.
+    let product = a as u128 * b as u128;
+    let quotient = product / c as u128;
+    let quotient = match rounding {
+        Rounding::Down => quotient,
+        Rounding::Up if product % c as u128 != 0 => quotient + 1,
+        Rounding::Up => quotient,
+    };
+
+    quotient.try_into().map_err(|_| ArithmeticError::Overflow)
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    #[test]
+    fn rounding_modes() {
+        let third = FixedPoint::ONE
+            .checked_div(
+                &FixedPoint::from_raw(3 * FIXED_POINT_DENOMINATOR),
+                Rounding::Down,
+            )
+            .unwrap();
+        assert_eq!(third, FixedPoint::from_raw(3333_3333));
+
+        let third = FixedPoint::ONE
+            .checked_div(
+                &FixedPoint::from_raw(3 * FIXED_POINT_DENOMINATOR),
+                Rounding::Up,
+            )
+            .unwrap();
+        assert_eq!(third, FixedPoint::from_raw(3333_3334));
+
+        assert_eq!(third.apply_to_amount(3, Rounding::Down), Ok(1));
+        assert_eq!(third.apply_to_amount(3, Rounding::Up), Ok(2));
+    }
+
+    #[test]
+    fn checked_operations_return_errors() {
+        let max = FixedPoint::from_raw(u64::MAX);
+
+        assert_eq!(
+            max.checked_add(&FixedPoint::from_raw(1)),
+            Err(ArithmeticError::Overflow)
+        );
+        assert_eq!(
+            FixedPoint::ZERO.checked_sub(&FixedPoint::from_raw(1)),
+            Err(ArithmeticError::Underflow)
+        );
+        assert_eq!(
+            max.checked_mul(&FixedPoint::from_bps(2_0000), Rounding::Down),
+            Err(ArithmeticError::Overflow)
+        );
+        assert_eq!(
+            FixedPoint::ONE.checked_div(&FixedPoint::ZERO, Rounding::Down),
+            Err(ArithmeticError::DivisionByZero)
+        );
+    }
+
+    #[test]
+    fn display() {
+        assert_eq!(FixedPoint::ONE.to_string(), "1.00000000");
+        assert_eq!(FixedPoint::from_bps(3).to_string(), "0.00030000");
+    }
+
+    proptest! {
+        #[test]
+        fn multiplying_by_one_is_identity(raw: u64, amount: u64) {
+            let x = FixedPoint::from_raw(raw);
+            assert_eq!(x.checked_mul(&FixedPoint::ONE, Rounding::Down), Ok(x));
+            assert_eq!(FixedPoint::ONE.apply_to_amount(amount, Rounding::Down), Ok(amount));
+            assert_eq!(FixedPoint::ONE.divide_amount(amount, Rounding::Up), Ok(amount));
+        }
+
+        #[test]
+        fn rounding_up_is_at_most_one_more_than_rounding_down(
+            amount: u64,
+            raw in 1..=(100 * FIXED_POINT_DENOMINATOR),
+        ) {
+            let x = FixedPoint::from_raw(raw);
+            if let (Ok(down), Ok(up)) = (
+                x.divide_amount(amount, Rounding::Down),
+                x.divide_amount(amount, Rounding::Up),
+            ) {
+                assert!(up == down || up == down + 1);
+            }
+        }
+    }
+}
@@ -2,6 +2,17 @@ use penumbra_crypto::Address;
 use penumbra_proto::{stake as pb, Protobuf};
 use serde::{Deserialize, Serialize};
 
+use crate::{ArithmeticError, FixedPoint, Rounding};
+
+/// An error computing a funding stream's reward.
+#[derive(thiserror::Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RewardError {
+    #[error("base rate data for epoch {previous} does not precede epoch {current}")]
+    NonConsecutiveEpochs { previous: u64, current: u64 },
+    #[error(transparent)]
+    Arithmetic(#[from] ArithmeticError),
+}
+
 /// A destination for a portion of a validator's commission of staking rewards.
 #[derive(Debug, Deserialize, Serialize, PartialEq, Eq, Clone, Copy)]
 #[serde(try_from = "pb::FundingStream", into = "pb::FundingStream")]
@@ -16,22 +27,29 @@ pub struct FundingStream {
 
 impl FundingStream {
     /// Computes the amount of reward at the epoch specified by base_rate_data
+    ///
+    /// Returns an error if `prev_epoch_rate_data` is not for the epoch immediately preceding it.
     pub fn reward_amount(
         &self,
         total_delegation_tokens: u64,
         base_rate_data: &crate::BaseRateData,
         prev_epoch_rate_data: &crate::BaseRateData,
-    ) -> u64 {
-        if prev_epoch_rate_data.epoch_index != base_rate_data.epoch_index - 1 {
-            panic!("wrong base rate data for previous epoch")
+    ) -> Result<u64, RewardError> {
+        if prev_epoch_rate_data.epoch_index.checked_add(1) != Some(base_rate_data.epoch_index) {
+            return Err(RewardError::NonConsecutiveEpochs {
+                previous: prev_epoch_rate_data.epoch_index,
+                current: base_rate_data.epoch_index,
+            });
         }
         // take yv*cve*re*psi(e-1)
-        let mut r =
-            (total_delegation_tokens as u128 * (self.rate_bps as u128 * 1_0000)) / 1_0000_0000;
-        r = (r * base_rate_data.base_reward_rate as u128) / 1_0000_0000;
-        r = (r * prev_epoch_rate_data.base_exchange_rate as u128) / 1_0000_0000;
-
-        r as u64
+        let r = FixedPoint::from_bps(self.rate_bps)
+            .apply_to_amount(total_delegation_tokens, Rounding::Down)?;
+        let r = base_rate_data
+            .reward_rate()
+            .apply_to_amount(r, Rounding::Down)?;
+        Ok(prev_epoch_rate_data
+            .exchange_rate()
+            .apply_to_amount(r, Rounding::Down)?)
     }
 }
 
@@ -65,3 +83,53 @@ impl TryFrom<pb::FundingStream> for FundingStream {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use penumbra_crypto::keys::{SpendKey, SpendSeed};
+
+    use super::*;
+    use crate::{BaseRateData, FIXED_POINT_DENOMINATOR};
+
+    fn funding_stream(rate_bps: u16) -> FundingStream {
+        FundingStream {
+            address: SpendKey::new(SpendSeed([0; 32]))
+                .incoming_viewing_key()
+                .payment_address(0u64.into())
+                .0,
+            rate_bps,
+        }
+    }
+
+    fn base_rate_data(epoch_index: u64) -> BaseRateData {
+        BaseRateData {
+            epoch_index,
+            base_reward_rate: 3 * FIXED_POINT_DENOMINATOR / 100,
+            base_exchange_rate: FIXED_POINT_DENOMINATOR,
+        }
+    }
+
+    #[test]
+    fn reward_amount_of_consecutive_epochs() {
+        let stream = funding_stream(500);
+        assert_eq!(
+            stream.reward_amount(1_000_000, &base_rate_data(1), &base_rate_data(0)),
+            Ok(1500)
+        );
+    }
+
+    #[test]
+    fn reward_amount_rejects_non_consecutive_epochs() {
+        let stream = funding_stream(500);
+        for (current, previous) in [(2, 0), (1, 1), (0, 0), (0, u64::MAX)] {
+            assert_eq!(
+                stream.reward_amount(
+                    1_000_000,
+                    &base_rate_data(current),
+                    &base_rate_data(previous)
+                ),
+                Err(RewardError::NonConsecutiveEpochs { previous, current })
+            );
+        }
+    }
+}
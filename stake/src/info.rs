@@ -1,7 +1,7 @@
 use penumbra_proto::{stake as pb, Protobuf};
 use serde::{Deserialize, Serialize};
 
-use crate::{RateData, Validator, ValidatorStatus};
+use crate::{BaseRateData, RateData, Validator, ValidatorStatus};
 
 #[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
 #[serde(try_from = "pb::ValidatorInfo", into = "pb::ValidatorInfo")]
@@ -42,3 +42,46 @@ impl TryFrom<pb::ValidatorInfo> for ValidatorInfo {
         })
     }
 }
+
+/// Describes a validator's rates, status and delegation token supply in some epoch.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+#[serde(try_from = "pb::ValidatorEpochInfo", into = "pb::ValidatorEpochInfo")]
+pub struct ValidatorEpochInfo {
+    pub rate_data: RateData,
+    pub base_rate_data: BaseRateData,
+    /// The validator's status in this epoch, if it was recorded.
+    pub status: Option<ValidatorStatus>,
+    /// The total supply of the validator's delegation tokens in this epoch, if it was recorded.
+    pub delegation_token_supply: Option<u64>,
+}
+
+impl Protobuf<pb::ValidatorEpochInfo> for ValidatorEpochInfo {}
+
+impl From<ValidatorEpochInfo> for pb::ValidatorEpochInfo {
+    fn from(v: ValidatorEpochInfo) -> Self {
+        pb::ValidatorEpochInfo {
+            rate_data: Some(v.rate_data.into()),
+            base_rate_data: Some(v.base_rate_data.into()),
+            status: v.status.map(Into::into),
+            delegation_token_supply: v.delegation_token_supply,
+        }
+    }
+}
+
+impl TryFrom<pb::ValidatorEpochInfo> for ValidatorEpochInfo {
+    type Error = anyhow::Error;
+    fn try_from(v: pb::ValidatorEpochInfo) -> Result<Self, Self::Error> {
+        Ok(ValidatorEpochInfo {
+            rate_data: v
+                .rate_data
+                .ok_or_else(|| anyhow::anyhow!("missing rate_data field in proto"))?
+                .try_into()?,
+            base_rate_data: v
+                .base_rate_data
+                .ok_or_else(|| anyhow::anyhow!("missing base_rate_data field in proto"))?
+                .try_into()?,
+            status: v.status.map(TryInto::try_into).transpose()?,
+            delegation_token_supply: v.delegation_token_supply,
+        })
+    }
+}
@@ -4,10 +4,12 @@ use penumbra_crypto::asset;
 
 mod delegate;
 mod epoch;
+mod fixed_point;
 mod funding_stream;
 mod identity_key;
 mod info;
 mod rate;
+pub mod simulation;
 mod status;
 mod token;
 mod undelegate;
@@ -16,9 +18,10 @@ mod validator_state;
 
 pub use delegate::Delegate;
 pub use epoch::Epoch;
-pub use funding_stream::FundingStream;
+pub use fixed_point::{ArithmeticError, FixedPoint, Rounding, FIXED_POINT_DENOMINATOR};
+pub use funding_stream::{FundingStream, RewardError};
 pub use identity_key::IdentityKey;
-pub use info::ValidatorInfo;
+pub use info::{ValidatorEpochInfo, ValidatorInfo};
 pub use rate::{BaseRateData, RateData, RateDataById};
 pub use status::ValidatorStatus;
 pub use token::DelegationToken;
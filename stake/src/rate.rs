@@ -6,7 +6,7 @@ use penumbra_proto::{
 };
 use serde::{Deserialize, Serialize};
 
-use crate::{FundingStream, IdentityKey, ValidatorState};
+use crate::{ArithmeticError, FixedPoint, FundingStream, IdentityKey, Rounding, ValidatorState};
 
 pub type RateDataById = BTreeMap<IdentityKey, RateData>;
 
@@ -25,13 +25,23 @@ pub struct RateData {
 }
 
 impl RateData {
+    /// Returns the validator-specific reward rate as a fixed-point number.
+    pub fn reward_rate(&self) -> FixedPoint {
+        FixedPoint::from_raw(self.validator_reward_rate)
+    }
+
+    /// Returns the validator-specific exchange rate as a fixed-point number.
+    pub fn exchange_rate(&self) -> FixedPoint {
+        FixedPoint::from_raw(self.validator_exchange_rate)
+    }
+
     /// Compute the validator rate data for the epoch following the current one.
     pub fn next(
         &self,
         base_rate_data: &BaseRateData,
         funding_streams: &[FundingStream],
         validator_state: &ValidatorState,
-    ) -> RateData {
+    ) -> Result<RateData, ArithmeticError> {
         let constant_rate =
             // Non-Active validator states result in a constant rate. This means
             // the next epoch's rate is set to the current rate.
@@ -49,47 +59,46 @@ impl RateData {
             // if a validator is slashed during the epoch transition the current epoch's rate is set
             // to the slashed value (during end_block) and in here, the next epoch's rate is held constant.
             ValidatorState::Slashed => {
-                return constant_rate;
+                return Ok(constant_rate);
             }
             // if a validator isn't part of the consensus set, we do not update their rates
             ValidatorState::Inactive => {
-                return constant_rate;
+                return Ok(constant_rate);
             }
             ValidatorState::Unbonding { unbonding_epoch: _ } => {
-                return constant_rate;
+                return Ok(constant_rate);
             }
             ValidatorState::Active => {}
         };
 
         // compute the validator's total commission
-        let commission_rate_bps = funding_streams
+        let commission_rate = funding_streams
             .iter()
-            .fold(0u64, |total, stream| total + stream.rate_bps as u64);
-
-        if commission_rate_bps > 1_0000 {
-            // we should never hit this branch: validator funding streams should be verified not to
-            // sum past 100% in the state machine's validation of registration of new funding
-            // streams
-            panic!("commission rate sums to > 100%")
-        }
+            .try_fold(FixedPoint::ZERO, |total, stream| {
+                total.checked_add(&FixedPoint::from_bps(stream.rate_bps))
+            })?;
 
         // compute next validator reward rate
-        // 1 bps = 1e-4, so here we group digits by 4s rather than 3s as is usual
-        let validator_reward_rate = ((1_0000_0000u64 - (commission_rate_bps * 1_0000))
-            * base_rate_data.base_reward_rate)
-            / 1_0000_0000;
+        //
+        // validator funding streams should be verified not to sum past 100% in the state
+        // machine's validation of registration of new funding streams, so the subtraction should
+        // never underflow
+        let validator_reward_rate = FixedPoint::ONE
+            .checked_sub(&commission_rate)?
+            .checked_mul(&base_rate_data.reward_rate(), Rounding::Down)?;
 
         // compute validator exchange rate
-        let validator_exchange_rate = (self.validator_exchange_rate
-            * (self.validator_reward_rate + 1_0000_0000))
-            / 1_0000_0000;
+        let validator_exchange_rate = self.exchange_rate().checked_mul(
+            &FixedPoint::ONE.checked_add(&self.reward_rate())?,
+            Rounding::Down,
+        )?;
 
-        RateData {
+        Ok(RateData {
             identity_key: self.identity_key.clone(),
             epoch_index: self.epoch_index + 1,
-            validator_reward_rate,
-            validator_exchange_rate,
-        }
+            validator_reward_rate: validator_reward_rate.raw(),
+            validator_exchange_rate: validator_exchange_rate.raw(),
+        })
     }
 
     /// Computes the amount of delegation tokens corresponding to the given amount of unbonded stake.
@@ -105,19 +114,24 @@ impl RateData {
     /// unbonded_amount == rate_data.unbonded_amount(delegation_amount)
     /// ```
     /// but in general *not both*, because the computation involves rounding.
-    pub fn delegation_amount(&self, unbonded_amount: u64) -> u64 {
-        // validator_exchange_rate fits in 32 bits, but unbonded_amount is 64-bit;
-        // upconvert to u128 intermediates and panic if the result is too large (unlikely)
-        ((unbonded_amount as u128 * 1_0000_0000) / self.validator_exchange_rate as u128)
-            .try_into()
-            .unwrap()
+    ///
+    /// The result is rounded down, so that delegating never creates value.
+    pub fn delegation_amount(&self, unbonded_amount: u64) -> Result<u64, ArithmeticError> {
+        self.exchange_rate()
+            .divide_amount(unbonded_amount, Rounding::Down)
     }
 
+    /// Applies the given slashing penalty (a fixed-point fraction) to the validator's reward rate.
     pub fn slash(&mut self, slashing_penalty: u64) {
-        // Slashing penalty is in base points
+        // A penalty of more than 100% reduces the reward rate to zero
+        let remaining = FixedPoint::ONE
+            .checked_sub(&FixedPoint::from_raw(slashing_penalty))
+            .unwrap_or(FixedPoint::ZERO);
         self.validator_reward_rate = self
-            .validator_reward_rate
-            .saturating_sub(self.validator_reward_rate * slashing_penalty / 1_0000_0000);
+            .reward_rate()
+            .checked_mul(&remaining, Rounding::Down)
+            .expect("multiplying by a factor of at most one cannot overflow")
+            .raw();
     }
 
     /// Computes the amount of unbonded stake corresponding to the given amount of delegation tokens.
@@ -133,21 +147,25 @@ impl RateData {
     /// unbonded_amount == rate_data.unbonded_amount(delegation_amount)
     /// ```
     /// but in general *not both*, because the computation involves rounding.
-    pub fn unbonded_amount(&self, delegation_amount: u64) -> u64 {
-        // validator_exchange_rate fits in 32 bits, but unbonded_amount is 64-bit;
-        // upconvert to u128 intermediates and panic if the result is too large (unlikely)
-        ((delegation_amount as u128 * self.validator_exchange_rate as u128) / 1_0000_0000)
-            .try_into()
-            .unwrap()
+    ///
+    /// The result is rounded down, so that undelegating never creates value.
+    pub fn unbonded_amount(&self, delegation_amount: u64) -> Result<u64, ArithmeticError> {
+        self.exchange_rate()
+            .apply_to_amount(delegation_amount, Rounding::Down)
     }
 
     /// Computes the validator's voting power at this epoch given the total supply of the
     /// validator's delegation tokens.
-    pub fn voting_power(&self, total_delegation_tokens: u64, base_rate_data: &BaseRateData) -> u64 {
-        ((total_delegation_tokens as u128 * self.validator_exchange_rate as u128)
-            / base_rate_data.base_exchange_rate as u128)
-            .try_into()
-            .unwrap()
+    pub fn voting_power(
+        &self,
+        total_delegation_tokens: u64,
+        base_rate_data: &BaseRateData,
+    ) -> Result<u64, ArithmeticError> {
+        self.exchange_rate().rescale_amount(
+            total_delegation_tokens,
+            &base_rate_data.exchange_rate(),
+            Rounding::Down,
+        )
     }
 }
 
@@ -164,16 +182,28 @@ pub struct BaseRateData {
 }
 
 impl BaseRateData {
+    /// Returns the base reward rate as a fixed-point number.
+    pub fn reward_rate(&self) -> FixedPoint {
+        FixedPoint::from_raw(self.base_reward_rate)
+    }
+
+    /// Returns the base exchange rate as a fixed-point number.
+    pub fn exchange_rate(&self) -> FixedPoint {
+        FixedPoint::from_raw(self.base_exchange_rate)
+    }
+
     /// Compute the base rate data for the epoch following the current one,
     /// given the next epoch's base reward rate.
-    pub fn next(&self, base_reward_rate: u64) -> BaseRateData {
-        let base_exchange_rate =
-            (self.base_exchange_rate * (base_reward_rate + 1_0000_0000)) / 1_0000_0000;
-        BaseRateData {
-            base_exchange_rate,
+    pub fn next(&self, base_reward_rate: u64) -> Result<BaseRateData, ArithmeticError> {
+        let base_exchange_rate = self.exchange_rate().checked_mul(
+            &FixedPoint::ONE.checked_add(&FixedPoint::from_raw(base_reward_rate))?,
+            Rounding::Down,
+        )?;
+        Ok(BaseRateData {
+            base_exchange_rate: base_exchange_rate.raw(),
             base_reward_rate,
             epoch_index: self.epoch_index + 1,
-        }
+        })
     }
 }
 
@@ -227,3 +257,82 @@ impl TryFrom<pb::BaseRateData> for BaseRateData {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use penumbra_crypto::{
+        keys::{SeedPhrase, SpendKey, SpendSeed},
+        rdsa::{SigningKey, SpendAuth},
+    };
+    use proptest::prelude::*;
+    use rand_core::OsRng;
+
+    use super::*;
+
+    fn rate_data(validator_exchange_rate: u64) -> RateData {
+        RateData {
+            identity_key: IdentityKey(SigningKey::<SpendAuth>::new(OsRng).into()),
+            epoch_index: 0,
+            validator_reward_rate: 0,
+            validator_exchange_rate,
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn delegation_round_trip_never_creates_value(
+            unbonded_amount in 0..u64::MAX / 1000,
+            validator_exchange_rate in 1_0000_0000..100_0000_0000u64,
+        ) {
+            let rate_data = rate_data(validator_exchange_rate);
+            let delegation_amount = rate_data.delegation_amount(unbonded_amount).unwrap();
+            let round_trip = rate_data.unbonded_amount(delegation_amount).unwrap();
+            assert!(round_trip <= unbonded_amount);
+        }
+
+        #[test]
+        fn undelegation_round_trip_never_creates_value(
+            delegation_amount in 0..u64::MAX / 1000,
+            validator_exchange_rate in 1_0000_0000..100_0000_0000u64,
+        ) {
+            let rate_data = rate_data(validator_exchange_rate);
+            let unbonded_amount = rate_data.unbonded_amount(delegation_amount).unwrap();
+            let round_trip = rate_data.delegation_amount(unbonded_amount).unwrap();
+            assert!(round_trip <= delegation_amount);
+        }
+
+        #[test]
+        fn exchange_rates_never_decrease(
+            base_reward_rate in 0..1_0000_0000u64,
+            validator_reward_rate in 0..1_0000_0000u64,
+            validator_exchange_rate in 1_0000_0000..100_0000_0000u64,
+            commission_bps in 0..=1_0000u16,
+        ) {
+            let base_rate_data = BaseRateData {
+                epoch_index: 0,
+                base_reward_rate: 0,
+                base_exchange_rate: 1_0000_0000,
+            };
+            let next_base_rate_data = base_rate_data.next(base_reward_rate).unwrap();
+            assert!(next_base_rate_data.base_exchange_rate >= base_rate_data.base_exchange_rate);
+
+            let rate_data = RateData {
+                validator_reward_rate,
+                ..rate_data(validator_exchange_rate)
+            };
+            let spend_seed = SpendSeed::from_seed_phrase(SeedPhrase::generate(OsRng), 0);
+            let funding_streams = [FundingStream {
+                address: SpendKey::new(spend_seed)
+                    .incoming_viewing_key()
+                    .payment_address(0u64.into())
+                    .0,
+                rate_bps: commission_bps,
+            }];
+            let next_rate_data = rate_data
+                .next(&next_base_rate_data, &funding_streams, &ValidatorState::Active)
+                .unwrap();
+            assert!(next_rate_data.validator_exchange_rate >= rate_data.validator_exchange_rate);
+            assert!(next_rate_data.validator_reward_rate <= base_reward_rate);
+        }
+    }
+}
@@ -0,0 +1,444 @@
+//! A simulator for the staking economics of a chain, for choosing chain parameters.
+//!
+//! The simulator runs a [`Scenario`] through the same rate, voting power and commission
+//! computations the chain performs at each epoch transition, and reports the resulting supplies,
+//! exchange rates and commission payouts for each validator in each epoch.
+
+use std::collections::BTreeMap;
+
+use anyhow::{anyhow, Context};
+use penumbra_crypto::keys::{SpendKey, SpendSeed};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    BaseRateData, FixedPoint, FundingStream, FundingStreams, IdentityKey, RateData, ValidatorState,
+    FIXED_POINT_DENOMINATOR,
+};
+
+/// A description of the validators, delegations and events to simulate.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Scenario {
+    /// The number of epochs to simulate.
+    pub epochs: u64,
+    /// Number of epochs before unbonding stake is released.
+    pub unbonding_epochs: u64,
+    /// Maximum number of validators in the consensus set.
+    pub active_validator_limit: u64,
+    /// Penalty to be applied to slashed validators' rates, in the same units as the chain
+    /// parameter.
+    pub slashing_penalty: u64,
+    /// The base reward rate, as a fixed-point number with 8 digits of precision.
+    pub base_reward_rate: u64,
+    /// Changes to the base reward rate, keyed by the epoch from which they apply.
+    #[serde(default)]
+    pub base_reward_rate_changes: BTreeMap<u64, u64>,
+    /// The initial supply of unbonded staking tokens.
+    pub staking_token_supply: u64,
+    /// The validators participating in the chain at genesis.
+    pub validators: Vec<ScenarioValidator>,
+    /// The delegations, undelegations, slashings and funding stream changes to simulate.
+    #[serde(default)]
+    pub events: Vec<ScenarioEvent>,
+}
+
+/// A validator participating in a [`Scenario`].
+#[derive(Debug, Clone, Deserialize)]
+pub struct ScenarioValidator {
+    /// The validator's name, used to refer to it in events and in the output.
+    pub name: String,
+    /// The rates (in basis points) of the validator's funding streams.
+    #[serde(default)]
+    pub funding_streams: Vec<u16>,
+    /// The amount of delegation tokens allocated to the validator at genesis.
+    pub initial_delegation: u64,
+}
+
+/// An event occurring during some epoch of a [`Scenario`].
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ScenarioEvent {
+    /// Delegate an amount of unbonded stake to a validator.
+    Delegate {
+        epoch: u64,
+        validator: String,
+        amount: u64,
+    },
+    /// Undelegate an amount of delegation tokens from a validator.
+    Undelegate {
+        epoch: u64,
+        validator: String,
+        amount: u64,
+    },
+    /// Slash a validator for misbehavior.
+    Slash { epoch: u64, validator: String },
+    /// Replace a validator's funding streams with ones of the given rates (in basis points).
+    SetFundingStreams {
+        epoch: u64,
+        validator: String,
+        funding_streams: Vec<u16>,
+    },
+}
+
+impl ScenarioEvent {
+    fn epoch(&self) -> u64 {
+        match self {
+            ScenarioEvent::Delegate { epoch, .. } => *epoch,
+            ScenarioEvent::Undelegate { epoch, .. } => *epoch,
+            ScenarioEvent::Slash { epoch, .. } => *epoch,
+            ScenarioEvent::SetFundingStreams { epoch, .. } => *epoch,
+        }
+    }
+
+    fn validator(&self) -> &str {
+        match self {
+            ScenarioEvent::Delegate { validator, .. } => validator,
+            ScenarioEvent::Undelegate { validator, .. } => validator,
+            ScenarioEvent::Slash { validator, .. } => validator,
+            ScenarioEvent::SetFundingStreams { validator, .. } => validator,
+        }
+    }
+}
+
+/// The state of a single validator at the start of a single epoch of a simulation.
+#[derive(Debug, Clone, Serialize)]
+pub struct EpochRecord {
+    /// The index of the epoch.
+    pub epoch: u64,
+    /// The validator's name.
+    pub validator: String,
+    /// The name of the validator's state.
+    pub state: &'static str,
+    /// The validator's voting power.
+    pub voting_power: u64,
+    /// The total supply of the validator's delegation tokens.
+    pub delegation_token_supply: u64,
+    /// The validator-specific exchange rate.
+    pub validator_exchange_rate: FixedPoint,
+    /// The validator-specific reward rate.
+    pub validator_reward_rate: FixedPoint,
+    /// The base exchange rate.
+    pub base_exchange_rate: FixedPoint,
+    /// The base reward rate.
+    pub base_reward_rate: FixedPoint,
+    /// The commission paid to the validator's funding streams at the start of this epoch.
+    pub commission: u64,
+    /// The total supply of unbonded staking tokens, including all commission paid so far.
+    pub staking_token_supply: u64,
+}
+
+/// A validator's state during a simulation.
+struct SimulatedValidator {
+    name: String,
+    rate_data: RateData,
+    funding_streams: FundingStreams,
+    state: ValidatorState,
+    voting_power: u64,
+    delegation_token_supply: u64,
+}
+
+impl Scenario {
+    /// Runs the scenario, returning a record for each validator in each epoch.
+    pub fn simulate(&self) -> anyhow::Result<Vec<EpochRecord>> {
+        // Validator identities and funding stream addresses only need to be distinct, so derive
+        // them deterministically rather than generating fresh keys.
+        let spend_key = SpendKey::new(SpendSeed([0; 32]));
+        let mut address_index = 0u64;
+        let mut funding_streams = |rates: &[u16]| -> anyhow::Result<FundingStreams> {
+            rates
+                .iter()
+                .map(|&rate_bps| {
+                    address_index += 1;
+                    FundingStream {
+                        address: spend_key
+                            .incoming_viewing_key()
+                            .payment_address(address_index.into())
+                            .0,
+                        rate_bps,
+                    }
+                })
+                .collect::<Vec<_>>()
+                .try_into()
+        };
+
+        let mut base_rate_data = BaseRateData {
+            epoch_index: 0,
+            base_reward_rate: 0,
+            base_exchange_rate: FIXED_POINT_DENOMINATOR,
+        };
+
+        let mut validators = Vec::new();
+        for (i, validator) in self.validators.iter().enumerate() {
+            let mut seed = [0; 32];
+            seed[..8].copy_from_slice(&(i as u64 + 1).to_le_bytes());
+            let identity_key = IdentityKey(
+                SpendKey::new(SpendSeed(seed))
+                    .full_viewing_key()
+                    .spend_verification_key()
+                    .clone(),
+            );
+            let rate_data = RateData {
+                identity_key,
+                epoch_index: 0,
+                validator_reward_rate: 0,
+                validator_exchange_rate: FIXED_POINT_DENOMINATOR,
+            };
+            validators.push(SimulatedValidator {
+                name: validator.name.clone(),
+                voting_power: rate_data
+                    .voting_power(validator.initial_delegation, &base_rate_data)?,
+                rate_data,
+                funding_streams: funding_streams(&validator.funding_streams)
+                    .with_context(|| format!("invalid funding streams for {}", validator.name))?,
+                state: ValidatorState::Inactive,
+                delegation_token_supply: validator.initial_delegation,
+            });
+        }
+
+        for event in &self.events {
+            if !validators.iter().any(|v| v.name == event.validator()) {
+                return Err(anyhow!(
+                    "event refers to unknown validator {}",
+                    event.validator()
+                ));
+            }
+        }
+
+        let mut staking_token_supply = self.staking_token_supply;
+        let mut commissions = BTreeMap::<String, u64>::new();
+        let mut records = Vec::new();
+
+        self.process_epoch_transitions(&mut validators, 0);
+
+        for epoch in 0..=self.epochs {
+            for v in &validators {
+                records.push(EpochRecord {
+                    epoch,
+                    validator: v.name.clone(),
+                    state: v.state.name().to_str(),
+                    voting_power: v.voting_power,
+                    delegation_token_supply: v.delegation_token_supply,
+                    validator_exchange_rate: v.rate_data.exchange_rate(),
+                    validator_reward_rate: v.rate_data.reward_rate(),
+                    base_exchange_rate: base_rate_data.exchange_rate(),
+                    base_reward_rate: base_rate_data.reward_rate(),
+                    commission: commissions.get(&v.name).copied().unwrap_or(0),
+                    staking_token_supply,
+                });
+            }
+            if epoch == self.epochs {
+                break;
+            }
+
+            // Process the events occurring during this epoch, netting delegations and
+            // undelegations as the chain does.
+            let mut delegation_changes = BTreeMap::<String, i64>::new();
+            for event in self.events.iter().filter(|e| e.epoch() == epoch) {
+                let v = validators
+                    .iter_mut()
+                    .find(|v| v.name == event.validator())
+                    .expect("validators were checked above");
+                match event {
+                    ScenarioEvent::Delegate { amount, .. } => {
+                        let delegation_amount = v.rate_data.delegation_amount(*amount)?;
+                        *delegation_changes.entry(v.name.clone()).or_default() +=
+                            i64::try_from(delegation_amount)?;
+                    }
+                    ScenarioEvent::Undelegate { amount, .. } => {
+                        *delegation_changes.entry(v.name.clone()).or_default() -=
+                            i64::try_from(*amount)?;
+                    }
+                    ScenarioEvent::Slash { .. } => {
+                        if matches!(
+                            v.state,
+                            ValidatorState::Active | ValidatorState::Unbonding { .. }
+                        ) {
+                            v.rate_data.slash(self.slashing_penalty);
+                            v.state = ValidatorState::Slashed;
+                        }
+                    }
+                    ScenarioEvent::SetFundingStreams {
+                        funding_streams: rates,
+                        ..
+                    } => {
+                        v.funding_streams = funding_streams(rates)
+                            .with_context(|| format!("invalid funding streams for {}", v.name))?;
+                    }
+                }
+            }
+
+            // Compute the rates for the next epoch, as in `end_epoch`.
+            let base_reward_rate = self
+                .base_reward_rate_changes
+                .range(..=epoch + 1)
+                .next_back()
+                .map(|(_, rate)| *rate)
+                .unwrap_or(self.base_reward_rate);
+            let next_base_rate = base_rate_data.next(base_reward_rate)?;
+
+            commissions.clear();
+            for v in validators.iter_mut() {
+                let current_rate = v.rate_data.clone();
+                let next_rate =
+                    current_rate.next(&next_base_rate, v.funding_streams.as_ref(), &v.state)?;
+
+                let delegation_delta = delegation_changes.get(&v.name).copied().unwrap_or(0);
+                let delegation_amount = delegation_delta.unsigned_abs();
+                let unbonded_amount = current_rate.unbonded_amount(delegation_amount)?;
+
+                if delegation_delta > 0 {
+                    staking_token_supply = staking_token_supply
+                        .checked_sub(unbonded_amount)
+                        .ok_or_else(|| anyhow!("insufficient staking tokens to delegate"))?;
+                    v.delegation_token_supply = v
+                        .delegation_token_supply
+                        .checked_add(delegation_amount)
+                        .ok_or_else(|| anyhow!("delegation token supply overflow"))?;
+                } else {
+                    staking_token_supply = staking_token_supply
+                        .checked_add(unbonded_amount)
+                        .ok_or_else(|| anyhow!("staking token supply overflow"))?;
+                    v.delegation_token_supply = v
+                        .delegation_token_supply
+                        .checked_sub(delegation_amount)
+                        .ok_or_else(|| anyhow!("insufficient delegation tokens to undelegate"))?;
+                }
+
+                v.voting_power =
+                    next_rate.voting_power(v.delegation_token_supply, &next_base_rate)?;
+                v.rate_data = next_rate;
+
+                // Only Active validators produce commission rewards
+                if v.state == ValidatorState::Active {
+                    for stream in v.funding_streams.as_ref() {
+                        let commission = stream.reward_amount(
+                            v.delegation_token_supply,
+                            &next_base_rate,
+                            &base_rate_data,
+                        )?;
+                        *commissions.entry(v.name.clone()).or_default() += commission;
+                        staking_token_supply = staking_token_supply
+                            .checked_add(commission)
+                            .ok_or_else(|| anyhow!("staking token supply overflow"))?;
+                    }
+                }
+            }
+            base_rate_data = next_base_rate;
+
+            self.process_epoch_transitions(&mut validators, epoch + 1);
+        }
+
+        Ok(records)
+    }
+
+    /// Moves validators between the active, unbonding and inactive states based on their voting
+    /// power, as in `process_epoch_transitions`.
+    fn process_epoch_transitions(&self, validators: &mut [SimulatedValidator], epoch: u64) {
+        let mut by_voting_power = validators
+            .iter()
+            .filter(|v| v.state != ValidatorState::Slashed)
+            .map(|v| (v.voting_power, v.name.clone()))
+            .collect::<Vec<_>>();
+        by_voting_power.sort_by(|a, b| b.0.cmp(&a.0));
+        let top_validators = by_voting_power
+            .into_iter()
+            .take(self.active_validator_limit as usize)
+            .map(|(_, name)| name)
+            .collect::<Vec<_>>();
+
+        for v in validators.iter_mut() {
+            let in_top = top_validators.contains(&v.name);
+            v.state = match v.state {
+                ValidatorState::Inactive | ValidatorState::Unbonding { .. } if in_top => {
+                    ValidatorState::Active
+                }
+                ValidatorState::Active if !in_top => ValidatorState::Unbonding {
+                    unbonding_epoch: epoch + self.unbonding_epochs,
+                },
+                ValidatorState::Unbonding { unbonding_epoch } if unbonding_epoch <= epoch => {
+                    ValidatorState::Inactive
+                }
+                state => state,
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scenario() -> Scenario {
+        serde_json::from_str(
+            r#"{
+                "epochs": 10,
+                "unbonding_epochs": 2,
+                "active_validator_limit": 1,
+                "slashing_penalty": 1000,
+                "base_reward_rate": 30000,
+                "staking_token_supply": 1000000000,
+                "validators": [
+                    { "name": "a", "funding_streams": [500], "initial_delegation": 1000000 },
+                    { "name": "b", "initial_delegation": 500000 }
+                ],
+                "events": [
+                    { "type": "delegate", "epoch": 2, "validator": "b", "amount": 1000000 },
+                    { "type": "undelegate", "epoch": 3, "validator": "a", "amount": 100000 },
+                    { "type": "slash", "epoch": 6, "validator": "b" }
+                ]
+            }"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn simulation_runs_scenario() {
+        let records = scenario().simulate().unwrap();
+        assert_eq!(records.len(), 2 * 11);
+
+        let record = |epoch, name: &str| {
+            records
+                .iter()
+                .find(|r| r.epoch == epoch && r.validator == name)
+                .unwrap()
+        };
+
+        // Only the validator with the most voting power is active, and only it pays commission.
+        assert_eq!(record(1, "a").state, "ACTIVE");
+        assert_eq!(record(1, "b").state, "INACTIVE");
+        assert!(record(1, "a").commission > 0);
+        assert_eq!(record(1, "b").commission, 0);
+
+        // After the delegation, b displaces a, which starts unbonding.
+        assert_eq!(record(3, "b").state, "ACTIVE");
+        assert_eq!(record(3, "a").state, "UNBONDING");
+        assert_eq!(record(6, "a").state, "INACTIVE");
+
+        // Slashed validators stay slashed, and their exchange rate no longer grows.
+        assert_eq!(record(7, "b").state, "SLASHED");
+        assert_eq!(
+            record(7, "b").validator_exchange_rate,
+            record(10, "b").validator_exchange_rate
+        );
+
+        // Exchange rates never decrease, and the base rate grows every epoch.
+        for epoch in 1..=10 {
+            assert!(
+                record(epoch, "a").validator_exchange_rate
+                    >= record(epoch - 1, "a").validator_exchange_rate
+            );
+            assert!(
+                record(epoch, "a").base_exchange_rate > record(epoch - 1, "a").base_exchange_rate
+            );
+        }
+    }
+
+    #[test]
+    fn simulation_rejects_unknown_validators() {
+        let mut scenario = scenario();
+        scenario.events.push(ScenarioEvent::Slash {
+            epoch: 1,
+            validator: "c".to_string(),
+        });
+        assert!(scenario.simulate().is_err());
+    }
+}
@@ -4,7 +4,7 @@ use bytes::Bytes;
 use penumbra_crypto::{
     keys, merkle,
     proofs::transparent::SpendProof,
-    rdsa::{Signature, SigningKey, SpendAuth, VerificationKey},
+    rdsa::{Signature, SpendAuth, VerificationKey},
     value, Fr, Note, Nullifier,
 };
 use penumbra_proto::{transaction, Message, Protobuf};
@@ -62,15 +62,16 @@ pub struct Body {
 impl Body {
     pub fn new(
         value_commitment: value::Commitment,
-        ask: SigningKey<SpendAuth>,
+        ak: VerificationKey<SpendAuth>,
         spend_auth_randomizer: Fr,
         merkle_path: merkle::Path,
         note: Note,
         v_blinding: Fr,
         nk: keys::NullifierKey,
     ) -> Body {
-        let rsk = ask.randomize(&spend_auth_randomizer);
-        let rk = rsk.into();
+        // The randomized verification key can be derived without the spend authorization key, so
+        // spends can be built before they are authorized.
+        let rk = ak.randomize(&spend_auth_randomizer);
         let note_commitment = note.commit();
         let position = merkle_path.0.clone();
         let proof = SpendProof {
@@ -85,7 +86,7 @@ impl Body {
             note_commitment,
             note_blinding: note.note_blinding(),
             spend_auth_randomizer,
-            ak: ask.into(),
+            ak,
             nk,
         };
         Body {
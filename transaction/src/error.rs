@@ -6,4 +6,10 @@ pub enum Error {
     FeeNotSet,
     #[error("Value balance of this transaction is not zero")]
     NonZeroValueBalance,
+    #[error("Authorization data does not match the transaction plan")]
+    AuthorizationMismatch,
+    #[error("Invalid spend authorization signature")]
+    InvalidSpendAuthSignature,
+    #[error("Arithmetic error in rate computation: {0}")]
+    Arithmetic(#[from] penumbra_stake::ArithmeticError),
 }
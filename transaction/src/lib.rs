@@ -5,5 +5,8 @@ pub use action::Action;
 mod error;
 pub use error::Error;
 
+mod plan;
+pub use plan::{ActionPlan, AuthorizationData, OutputPlan, SpendPlan, TransactionPlan};
+
 mod transaction;
-pub use transaction::{Fee, Transaction, TransactionBody};
+pub use transaction::{Builder, Fee, Transaction, TransactionBody};
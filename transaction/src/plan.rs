@@ -0,0 +1,572 @@
+use std::convert::{TryFrom, TryInto};
+
+use ark_ff::{UniformRand, Zero};
+use bytes::Bytes;
+use decaf377::FieldExt;
+use penumbra_crypto::{
+    ka,
+    keys::{FullViewingKey, OutgoingViewingKey, SpendKey},
+    memo::{MemoPlaintext, MEMO_LEN_BYTES},
+    merkle, note,
+    rdsa::{Signature, SpendAuth},
+    value, Address, Fq, Fr, Note, Value,
+};
+use penumbra_proto::{transaction as pb, Protobuf};
+use penumbra_stake::{Delegate, Undelegate, STAKING_TOKEN_ASSET_ID};
+use rand_core::{CryptoRng, RngCore};
+
+use crate::{
+    action::{output, spend, Output, Spend},
+    Action, Fee, TransactionBody,
+};
+
+/// A plan for a transaction: every action it will perform, along with the randomness used to build
+/// them, but none of the signatures which authorize it.
+///
+/// Building the planned transaction requires only the [`FullViewingKey`], and authorizing it
+/// requires only the [`SpendKey`], so a plan can be prepared on one machine and authorized on
+/// another which holds the keys.
+#[derive(Clone, Debug)]
+pub struct TransactionPlan {
+    pub actions: Vec<ActionPlan>,
+    pub merkle_root: merkle::Root,
+    pub expiry_height: u32,
+    pub chain_id: String,
+    pub fee: Fee,
+}
+
+/// A plan for an action performed by a transaction.
+#[derive(Clone, Debug)]
+pub enum ActionPlan {
+    Spend(SpendPlan),
+    Output(OutputPlan),
+    Delegate(Delegate),
+    Undelegate(Undelegate),
+}
+
+/// A plan for spending a note.
+#[derive(Clone, Debug)]
+pub struct SpendPlan {
+    /// The note to spend.
+    pub note: Note,
+    /// The position of the note and its authentication path, relative to the transaction anchor.
+    pub merkle_path: merkle::Path,
+    /// The blinding factor for the value commitment.
+    pub value_blinding: Fr,
+    /// The randomizer for the spend authorization key.
+    pub randomizer: Fr,
+}
+
+/// A plan for creating a new note.
+#[derive(Clone, Debug)]
+pub struct OutputPlan {
+    /// The address the note is sent to.
+    pub dest_address: Address,
+    /// The value of the note.
+    pub value: Value,
+    /// The blinding factor for the note commitment.
+    pub note_blinding: Fq,
+    /// The blinding factor for the value commitment.
+    pub value_blinding: Fr,
+    /// The ephemeral secret key used for note encryption.
+    pub esk: ka::Secret,
+    /// The memo sent with the note.
+    pub memo: MemoPlaintext,
+    /// The outgoing viewing key to wrap the note's key to instead of the sender's, if any.
+    pub ovk: Option<OutgoingViewingKey>,
+}
+
+/// The signatures authorizing a [`TransactionPlan`], in the order of its spends.
+#[derive(Clone, Debug)]
+pub struct AuthorizationData {
+    /// The sighash of the planned transaction which was signed.
+    pub sighash: [u8; 64],
+    /// The spend authorization signatures.
+    pub spend_auths: Vec<Signature<SpendAuth>>,
+}
+
+impl TransactionPlan {
+    /// Returns an iterator over the planned spends, in the order they appear in the transaction.
+    pub fn spend_plans(&self) -> impl Iterator<Item = &SpendPlan> {
+        self.actions.iter().filter_map(|action| match action {
+            ActionPlan::Spend(spend) => Some(spend),
+            _ => None,
+        })
+    }
+
+    /// Returns an iterator over the planned outputs, in the order they appear in the transaction.
+    pub fn output_plans(&self) -> impl Iterator<Item = &OutputPlan> {
+        self.actions.iter().filter_map(|action| match action {
+            ActionPlan::Output(output) => Some(output),
+            _ => None,
+        })
+    }
+
+    /// Returns the sum of the blinding factors of the value commitments of the planned actions,
+    /// which is the signing key for the binding signature.
+    pub fn synthetic_blinding_factor(&self) -> Fr {
+        let mut synthetic_blinding_factor = Fr::zero();
+        for action in &self.actions {
+            match action {
+                // Spends add to the transaction's value balance...
+                ActionPlan::Spend(spend) => synthetic_blinding_factor += spend.value_blinding,
+                // ... and outputs subtract from it.
+                ActionPlan::Output(output) => synthetic_blinding_factor -= output.value_blinding,
+                // Delegations and undelegations have a zero blinding factor.
+                ActionPlan::Delegate(_) | ActionPlan::Undelegate(_) => {}
+            }
+        }
+        synthetic_blinding_factor
+    }
+
+    /// Build the body of the planned transaction, with blank spend authorization signatures.
+    pub fn build_body(&self, fvk: &FullViewingKey) -> TransactionBody {
+        let actions = self
+            .actions
+            .iter()
+            .map(|action| match action {
+                ActionPlan::Spend(spend) => Action::Spend(Spend {
+                    body: spend.spend_body(fvk),
+                    auth_sig: Signature::from([0; 64]),
+                }),
+                ActionPlan::Output(output) => Action::Output(output.output(fvk.outgoing())),
+                ActionPlan::Delegate(delegate) => Action::Delegate(delegate.clone()),
+                ActionPlan::Undelegate(undelegate) => Action::Undelegate(undelegate.clone()),
+            })
+            .collect();
+
+        TransactionBody {
+            actions,
+            merkle_root: self.merkle_root.clone(),
+            expiry_height: self.expiry_height,
+            chain_id: self.chain_id.clone(),
+            fee: self.fee.clone(),
+        }
+    }
+
+    /// Sign the planned transaction with the given spend key.
+    pub fn authorize<R: RngCore + CryptoRng>(
+        &self,
+        rng: &mut R,
+        spend_key: &SpendKey,
+    ) -> AuthorizationData {
+        let sighash = self.build_body(spend_key.full_viewing_key()).sighash();
+        let spend_auths = self
+            .spend_plans()
+            .map(|spend| {
+                spend_key
+                    .spend_auth_key()
+                    .randomize(&spend.randomizer)
+                    .sign(&mut *rng, &sighash)
+            })
+            .collect();
+
+        AuthorizationData {
+            sighash,
+            spend_auths,
+        }
+    }
+}
+
+impl SpendPlan {
+    /// Plan a spend of the given note, with fresh blinding factors.
+    pub fn new<R: RngCore + CryptoRng>(rng: &mut R, note: Note, merkle_path: merkle::Path) -> Self {
+        Self {
+            note,
+            merkle_path,
+            value_blinding: Fr::rand(rng),
+            randomizer: Fr::rand(rng),
+        }
+    }
+
+    /// Plan a dummy spend of a fresh zero-value note sent to `address`, with a random position
+    /// and authentication path.
+    ///
+    /// Dummy spends pad transactions to hide their arity. Zero-value notes need not be in the note
+    /// commitment tree, but `address` must belong to the spend key which authorizes the
+    /// transaction.
+    pub fn dummy<R: RngCore + CryptoRng>(rng: &mut R, address: &Address) -> Self {
+        let note = Note::generate(
+            rng,
+            address,
+            Value {
+                amount: 0,
+                asset_id: *STAKING_TOKEN_ASSET_ID,
+            },
+        );
+        let position = (rng.next_u32() as usize).into();
+        let auth_path = (0..merkle::DEPTH)
+            .map(|_| note::Commitment(Fq::rand(rng)))
+            .collect();
+        Self::new(rng, note, (position, auth_path))
+    }
+
+    /// Returns the commitment to the value of the spent note.
+    pub fn value_commitment(&self) -> value::Commitment {
+        self.note.value().commit(self.value_blinding)
+    }
+
+    /// Build the body of the planned spend.
+    pub fn spend_body(&self, fvk: &FullViewingKey) -> spend::Body {
+        spend::Body::new(
+            self.value_commitment(),
+            *fvk.spend_verification_key(),
+            self.randomizer,
+            self.merkle_path.clone(),
+            self.note.clone(),
+            self.value_blinding,
+            *fvk.nullifier_key(),
+        )
+    }
+}
+
+impl OutputPlan {
+    /// Plan an output of the given value to `dest_address`, with fresh blinding factors and a
+    /// fresh ephemeral secret key.
+    pub fn new<R: RngCore + CryptoRng>(
+        rng: &mut R,
+        dest_address: Address,
+        value: Value,
+        memo: MemoPlaintext,
+    ) -> Self {
+        Self {
+            dest_address,
+            value,
+            note_blinding: Fq::rand(rng),
+            value_blinding: Fr::rand(rng),
+            esk: ka::Secret::new(rng),
+            memo,
+            ovk: None,
+        }
+    }
+
+    /// Plan a dummy output of a zero-value note to an address nobody controls.
+    ///
+    /// Dummy outputs pad transactions to hide their arity. Their keys are wrapped to a random
+    /// outgoing viewing key rather than the sender's, so that nobody can recover them.
+    pub fn dummy<R: RngCore + CryptoRng>(rng: &mut R) -> Self {
+        let dest_address = Address::dummy(rng);
+        let ovk = OutgoingViewingKey::dummy(rng);
+        Self {
+            ovk: Some(ovk),
+            ..Self::new(
+                rng,
+                dest_address,
+                Value {
+                    amount: 0,
+                    asset_id: *STAKING_TOKEN_ASSET_ID,
+                },
+                MemoPlaintext::default(),
+            )
+        }
+    }
+
+    /// Returns the note created by the planned output.
+    pub fn output_note(&self) -> Note {
+        Note::from_parts(
+            *self.dest_address.diversifier(),
+            *self.dest_address.transmission_key(),
+            self.value,
+            self.note_blinding,
+        )
+        .expect("transmission key in address is always valid")
+    }
+
+    /// Returns the commitment to the value of the output note.
+    pub fn value_commitment(&self) -> value::Commitment {
+        // Outputs subtract from the transaction value balance, so commit to -value.
+        -self.value.commit(self.value_blinding)
+    }
+
+    /// Build the planned output, wrapping its encryption key to the given outgoing viewing key,
+    /// unless the plan has its own.
+    pub fn output(&self, ovk: &OutgoingViewingKey) -> Output {
+        let ovk = self.ovk.as_ref().unwrap_or(ovk);
+        let note = self.output_note();
+        let encrypted_memo = self.memo.encrypt(&self.esk, &self.dest_address);
+
+        let body = output::Body::new(
+            note.clone(),
+            self.value_blinding,
+            note.diversified_generator(),
+            note.transmission_key(),
+            &self.esk,
+        );
+
+        let ovk_wrapped_key = note.encrypt_key(
+            &self.esk,
+            ovk,
+            body.value_commitment,
+            self.dest_address.clue_key(),
+        );
+
+        Output {
+            body,
+            encrypted_memo,
+            ovk_wrapped_key,
+        }
+    }
+}
+
+impl Protobuf<pb::TransactionPlan> for TransactionPlan {}
+
+impl From<TransactionPlan> for pb::TransactionPlan {
+    fn from(msg: TransactionPlan) -> Self {
+        pb::TransactionPlan {
+            actions: msg.actions.into_iter().map(Into::into).collect(),
+            anchor: Bytes::copy_from_slice(&msg.merkle_root.0.to_bytes()),
+            expiry_height: msg.expiry_height,
+            chain_id: msg.chain_id,
+            fee: Some(msg.fee.into()),
+        }
+    }
+}
+
+impl TryFrom<pb::TransactionPlan> for TransactionPlan {
+    type Error = anyhow::Error;
+
+    fn try_from(proto: pb::TransactionPlan) -> anyhow::Result<Self, Self::Error> {
+        Ok(TransactionPlan {
+            actions: proto
+                .actions
+                .into_iter()
+                .map(TryInto::try_into)
+                .collect::<Result<_, _>>()?,
+            merkle_root: proto.anchor[..]
+                .try_into()
+                .map_err(|_| anyhow::anyhow!("invalid anchor"))?,
+            expiry_height: proto.expiry_height,
+            chain_id: proto.chain_id,
+            fee: proto
+                .fee
+                .ok_or_else(|| anyhow::anyhow!("missing fee"))?
+                .into(),
+        })
+    }
+}
+
+impl Protobuf<pb::ActionPlan> for ActionPlan {}
+
+impl From<ActionPlan> for pb::ActionPlan {
+    fn from(msg: ActionPlan) -> Self {
+        let action = match msg {
+            ActionPlan::Spend(inner) => pb::action_plan::Action::Spend(inner.into()),
+            ActionPlan::Output(inner) => pb::action_plan::Action::Output(inner.into()),
+            ActionPlan::Delegate(inner) => pb::action_plan::Action::Delegate(inner.into()),
+            ActionPlan::Undelegate(inner) => pb::action_plan::Action::Undelegate(inner.into()),
+        };
+        pb::ActionPlan {
+            action: Some(action),
+        }
+    }
+}
+
+impl TryFrom<pb::ActionPlan> for ActionPlan {
+    type Error = anyhow::Error;
+
+    fn try_from(proto: pb::ActionPlan) -> anyhow::Result<Self, Self::Error> {
+        match proto
+            .action
+            .ok_or_else(|| anyhow::anyhow!("missing action plan content"))?
+        {
+            pb::action_plan::Action::Spend(inner) => Ok(ActionPlan::Spend(inner.try_into()?)),
+            pb::action_plan::Action::Output(inner) => Ok(ActionPlan::Output(inner.try_into()?)),
+            pb::action_plan::Action::Delegate(inner) => Ok(ActionPlan::Delegate(inner.try_into()?)),
+            pb::action_plan::Action::Undelegate(inner) => {
+                Ok(ActionPlan::Undelegate(inner.try_into()?))
+            }
+        }
+    }
+}
+
+impl Protobuf<pb::SpendPlan> for SpendPlan {}
+
+impl From<SpendPlan> for pb::SpendPlan {
+    fn from(msg: SpendPlan) -> Self {
+        let (position, auth_path) = msg.merkle_path;
+        pb::SpendPlan {
+            note: Bytes::copy_from_slice(&msg.note.to_bytes()),
+            position: u64::from(position),
+            auth_path: auth_path
+                .into_iter()
+                .map(|commitment| Bytes::copy_from_slice(&<[u8; 32]>::from(commitment)))
+                .collect(),
+            value_blinding: Bytes::copy_from_slice(&msg.value_blinding.to_bytes()),
+            randomizer: Bytes::copy_from_slice(&msg.randomizer.to_bytes()),
+        }
+    }
+}
+
+impl TryFrom<pb::SpendPlan> for SpendPlan {
+    type Error = anyhow::Error;
+
+    fn try_from(proto: pb::SpendPlan) -> anyhow::Result<Self, Self::Error> {
+        let auth_path = proto
+            .auth_path
+            .iter()
+            .map(|commitment| note::Commitment::try_from(&commitment[..]))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| anyhow::anyhow!("invalid authentication path"))?;
+
+        Ok(SpendPlan {
+            note: Note::try_from(&proto.note[..])?,
+            merkle_path: ((proto.position as usize).into(), auth_path),
+            value_blinding: fr_from_bytes(&proto.value_blinding)?,
+            randomizer: fr_from_bytes(&proto.randomizer)?,
+        })
+    }
+}
+
+impl Protobuf<pb::OutputPlan> for OutputPlan {}
+
+impl From<OutputPlan> for pb::OutputPlan {
+    fn from(msg: OutputPlan) -> Self {
+        pb::OutputPlan {
+            dest_address: Some(msg.dest_address.into()),
+            value: Some(msg.value.into()),
+            note_blinding: Bytes::copy_from_slice(&msg.note_blinding.to_bytes()),
+            value_blinding: Bytes::copy_from_slice(&msg.value_blinding.to_bytes()),
+            esk: Bytes::copy_from_slice(&msg.esk.to_bytes()),
+            memo: Bytes::copy_from_slice(&msg.memo.0),
+            ovk: msg
+                .ovk
+                .map(|ovk| Bytes::copy_from_slice(ovk.as_ref()))
+                .unwrap_or_default(),
+        }
+    }
+}
+
+impl TryFrom<pb::OutputPlan> for OutputPlan {
+    type Error = anyhow::Error;
+
+    fn try_from(proto: pb::OutputPlan) -> anyhow::Result<Self, Self::Error> {
+        let note_blinding = Fq::from_bytes(
+            proto.note_blinding[..]
+                .try_into()
+                .map_err(|_| anyhow::anyhow!("invalid note blinding factor"))?,
+        )
+        .map_err(|_| anyhow::anyhow!("invalid note blinding factor"))?;
+
+        let memo: [u8; MEMO_LEN_BYTES] = proto.memo[..]
+            .try_into()
+            .map_err(|_| anyhow::anyhow!("invalid memo"))?;
+
+        Ok(OutputPlan {
+            dest_address: proto
+                .dest_address
+                .ok_or_else(|| anyhow::anyhow!("missing destination address"))?
+                .try_into()?,
+            value: proto
+                .value
+                .ok_or_else(|| anyhow::anyhow!("missing value"))?
+                .try_into()?,
+            note_blinding,
+            value_blinding: fr_from_bytes(&proto.value_blinding)?,
+            esk: ka::Secret::try_from(&proto.esk[..])
+                .map_err(|_| anyhow::anyhow!("invalid ephemeral secret key"))?,
+            memo: MemoPlaintext(memo),
+            ovk: if proto.ovk.is_empty() {
+                None
+            } else {
+                Some(OutgoingViewingKey::try_from(&proto.ovk[..])?)
+            },
+        })
+    }
+}
+
+fn fr_from_bytes(bytes: &[u8]) -> anyhow::Result<Fr> {
+    Fr::from_bytes(
+        bytes
+            .try_into()
+            .map_err(|_| anyhow::anyhow!("invalid blinding factor"))?,
+    )
+    .map_err(|_| anyhow::anyhow!("invalid blinding factor"))
+}
+
+#[cfg(test)]
+mod tests {
+    use penumbra_crypto::{
+        keys::{SeedPhrase, SpendSeed},
+        merkle::{NoteCommitmentTree, Tree, TreeExt},
+    };
+    use penumbra_stake::STAKING_TOKEN_ASSET_ID;
+    use rand_core::OsRng;
+
+    use super::*;
+    use crate::{Builder, Error, Transaction};
+
+    #[test]
+    fn plan_can_be_authorized_after_round_trip() {
+        let mut rng = OsRng;
+        let sk = SpendKey::new(SpendSeed::from_seed_phrase(
+            SeedPhrase::generate(&mut rng),
+            0,
+        ));
+        let (address, _dtk) = sk.incoming_viewing_key().payment_address(0u64.into());
+
+        let note = Note::generate(
+            &mut rng,
+            &address,
+            Value {
+                amount: 20,
+                asset_id: *STAKING_TOKEN_ASSET_ID,
+            },
+        );
+        let mut nct = NoteCommitmentTree::new(1);
+        nct.append(&note.commit());
+        nct.witness();
+
+        let plan = Transaction::build_with_root(nct.root2())
+            .set_fee(10)
+            .set_chain_id("penumbra".to_string())
+            .add_output(
+                &mut rng,
+                &address,
+                Value {
+                    amount: 10,
+                    asset_id: *STAKING_TOKEN_ASSET_ID,
+                },
+                MemoPlaintext::default(),
+            )
+            .add_spend(&mut rng, &nct, note)
+            .expect("note is in nct")
+            .plan(&mut rng)
+            .expect("plan is valid");
+
+        let plan = TransactionPlan::decode(plan.encode_to_vec().as_slice()).expect("can decode");
+        let auth_data = plan.authorize(&mut rng, &sk);
+        let transaction =
+            Builder::finalize_plan(&mut rng, &plan, sk.full_viewing_key(), &auth_data)
+                .expect("authorized plan can be finalized");
+        assert_eq!(
+            transaction.transaction_body().sighash(),
+            plan.build_body(sk.full_viewing_key()).sighash()
+        );
+
+        // Dummy spends and outputs can be authorized and finalized like any other.
+        let padded_plan = Transaction::build_with_root(nct.root2())
+            .set_fee(0)
+            .set_chain_id("penumbra".to_string())
+            .pad(&mut rng, 2, 2, &address)
+            .plan(&mut rng)
+            .expect("plan is valid");
+        assert_eq!(padded_plan.spend_plans().count(), 2);
+        assert_eq!(padded_plan.output_plans().count(), 2);
+        let padded_auth_data = padded_plan.authorize(&mut rng, &sk);
+        Builder::finalize_plan(
+            &mut rng,
+            &padded_plan,
+            sk.full_viewing_key(),
+            &padded_auth_data,
+        )
+        .expect("padded plan can be finalized");
+
+        // Authorization data for one plan can't be used to finalize another.
+        let mut other_plan = plan.clone();
+        other_plan.expiry_height = 100;
+        assert_eq!(
+            Builder::finalize_plan(&mut rng, &other_plan, sk.full_viewing_key(), &auth_data).err(),
+            Some(Error::AuthorizationMismatch)
+        );
+    }
+}
@@ -63,9 +63,7 @@ impl Transaction {
             delegations: Vec::new(),
             undelegations: Vec::new(),
             fee: None,
-            synthetic_blinding_factor: Fr::zero(),
             value_balance: decaf377::Element::default(),
-            value_commitments: decaf377::Element::default(),
             merkle_root,
             expiry_height: None,
             chain_id: None,
@@ -276,8 +274,6 @@ mod tests {
         let seed_phrase = SeedPhrase::generate(&mut rng);
         let spend_seed = SpendSeed::from_seed_phrase(seed_phrase, 0);
         let sk_sender = SpendKey::new(spend_seed);
-        let fvk_sender = sk_sender.full_viewing_key();
-        let ovk_sender = fvk_sender.outgoing();
 
         let seed_phrase = SeedPhrase::generate(&mut rng);
         let spend_seed = SpendSeed::from_seed_phrase(seed_phrase, 0);
@@ -298,9 +294,8 @@ mod tests {
                     asset_id: *STAKING_TOKEN_ASSET_ID,
                 },
                 MemoPlaintext::default(),
-                ovk_sender,
             )
-            .finalize(&mut rng);
+            .finalize(&mut rng, &sk_sender);
 
         assert!(transaction.is_err());
         assert_eq!(transaction.err(), Some(Error::NonZeroValueBalance));
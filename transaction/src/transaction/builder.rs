@@ -1,41 +1,37 @@
-use std::ops::Deref;
-
-use ark_ff::{UniformRand, Zero};
+use ark_ff::Zero;
 use incrementalmerkletree::Tree;
 use penumbra_crypto::{
-    ka,
-    keys::{OutgoingViewingKey, SpendKey},
+    keys::{FullViewingKey, SpendKey},
     memo::MemoPlaintext,
     merkle::{self, NoteCommitmentTree},
-    rdsa::{Binding, Signature, SigningKey, SpendAuth},
-    value, Address, Fr, Note, Value,
+    rdsa::{Binding, SigningKey},
+    Address, Fr, Note, Value,
 };
 use penumbra_stake::{Delegate, RateData, Undelegate, STAKING_TOKEN_ASSET_ID};
 use rand::seq::SliceRandom;
 use rand_core::{CryptoRng, RngCore};
 
 use crate::{
-    action::{output, spend, Action, Output, Spend},
-    Error, Fee, Transaction, TransactionBody,
+    action::{Action, Spend},
+    plan::{ActionPlan, AuthorizationData, OutputPlan, SpendPlan, TransactionPlan},
+    Error, Fee, Transaction,
 };
 
 /// Used to construct a Penumbra transaction.
+///
+/// The builder first produces a [`TransactionPlan`], which can then be authorized and finalized
+/// separately, or it can do both in one step with [`Builder::finalize`].
 pub struct Builder {
-    /// List of spends. We store the spend key and body rather than a Spend
-    /// so we can defer signing until the complete transaction is ready.
-    pub spends: Vec<(SigningKey<SpendAuth>, spend::Body)>,
-    /// List of outputs in the transaction.
-    pub outputs: Vec<Output>,
+    /// List of planned spends.
+    pub spends: Vec<SpendPlan>,
+    /// List of planned outputs.
+    pub outputs: Vec<OutputPlan>,
     /// List of delegations in the transaction.
     pub delegations: Vec<Delegate>,
     /// List of undelegations in the transaction.
     pub undelegations: Vec<Undelegate>,
     /// Transaction fee. None if unset.
     pub fee: Option<Fee>,
-    /// Sum of blinding factors for each value commitment.
-    pub synthetic_blinding_factor: Fr,
-    /// Sum of value commitments.
-    pub value_commitments: decaf377::Element,
     /// Value balance.
     pub value_balance: decaf377::Element,
     /// The root of the note commitment merkle tree.
@@ -52,7 +48,6 @@ impl Builder {
         &mut self,
         rng: &mut R,
         note_commitment_tree: &NoteCommitmentTree,
-        spend_key: &SpendKey,
         note: Note,
     ) -> Result<&mut Self, anyhow::Error> {
         let merkle_path = note_commitment_tree
@@ -64,31 +59,57 @@ impl Builder {
                 )
             })?;
 
-        let v_blinding = Fr::rand(rng);
-        let value_commitment = note.value().commit(v_blinding);
-
         // Spends add to the transaction's value balance.
-        self.synthetic_blinding_factor += v_blinding;
         self.value_balance +=
             Fr::from(note.value().amount) * note.value().asset_id.value_generator();
-        self.value_commitments += value_commitment.0;
 
-        let spend_auth_randomizer = Fr::rand(rng);
-        let rsk = spend_key.spend_auth_key().randomize(&spend_auth_randomizer);
+        self.spends.push(SpendPlan::new(rng, note, merkle_path));
 
-        let body = spend::Body::new(
-            value_commitment,
-            *spend_key.spend_auth_key(),
-            spend_auth_randomizer,
-            merkle_path,
-            note,
-            v_blinding,
-            *spend_key.nullifier_key(),
-        );
+        Ok(self)
+    }
 
-        self.spends.push((rsk, body));
+    /// Create a dummy `Spend` of a zero-value note, which doesn't change the transaction's value
+    /// balance.
+    ///
+    /// The note is sent to `address`, which must belong to the spend key that authorizes the
+    /// transaction.
+    pub fn add_dummy_spend<R: RngCore + CryptoRng>(
+        &mut self,
+        rng: &mut R,
+        address: &Address,
+    ) -> &mut Self {
+        self.spends.push(SpendPlan::dummy(rng, address));
+        self
+    }
 
-        Ok(self)
+    /// Create a dummy `Output` of a zero-value note to an address nobody controls, which doesn't
+    /// change the transaction's value balance.
+    pub fn add_dummy_output<R: RngCore + CryptoRng>(&mut self, rng: &mut R) -> &mut Self {
+        self.outputs.push(OutputPlan::dummy(rng));
+        self
+    }
+
+    /// Add dummy spends and outputs until the transaction has at least `min_spends` spends and
+    /// `min_outputs` outputs, so that transactions padded to the same shape can't be told apart
+    /// by their arity once proofs are zero-knowledge. Transparent proofs reveal that dummies have
+    /// zero value, so for now padding is cosmetic.
+    ///
+    /// The notes of dummy spends are sent to `address`, which must belong to the spend key that
+    /// authorizes the transaction.
+    pub fn pad<R: RngCore + CryptoRng>(
+        &mut self,
+        rng: &mut R,
+        min_spends: usize,
+        min_outputs: usize,
+        address: &Address,
+    ) -> &mut Self {
+        while self.spends.len() < min_spends {
+            self.add_dummy_spend(rng, address);
+        }
+        while self.outputs.len() < min_outputs {
+            self.add_dummy_output(rng);
+        }
+        self
     }
 
     /// Create a new `Output`, implicitly creating a new note for it and encrypting the provided
@@ -101,9 +122,8 @@ impl Builder {
         dest: &Address,
         value_to_send: Value,
         memo: MemoPlaintext,
-        ovk: &OutgoingViewingKey,
     ) -> &mut Self {
-        self.add_output_producing_note(rng, dest, value_to_send, memo, ovk);
+        self.add_output_producing_note(rng, dest, value_to_send, memo);
         self
     }
 
@@ -116,81 +136,60 @@ impl Builder {
         dest: &Address,
         value_to_send: Value,
         memo: MemoPlaintext,
-        ovk: &OutgoingViewingKey,
     ) -> Note {
-        let note = Note::generate(rng, dest, value_to_send);
-        let diversified_generator = note.diversified_generator();
-        let transmission_key = note.transmission_key();
-        let value_to_send = note.value();
-
-        let v_blinding = Fr::rand(rng);
-
-        let esk = ka::Secret::new(rng);
-        let encrypted_memo = memo.encrypt(&esk, dest);
-
         // We subtract from the transaction's value balance.
-        self.synthetic_blinding_factor -= v_blinding;
         self.value_balance -=
             Fr::from(value_to_send.amount) * value_to_send.asset_id.value_generator();
 
-        let body = output::Body::new(
-            note.clone(),
-            v_blinding,
-            diversified_generator,
-            transmission_key,
-            &esk,
-        );
-        self.value_commitments += body.value_commitment.0;
-
-        let ovk_wrapped_key = note.encrypt_key(&esk, ovk, body.value_commitment);
-
-        self.outputs.push(Output {
-            body,
-            encrypted_memo,
-            ovk_wrapped_key,
-        });
+        let output = OutputPlan::new(rng, *dest, value_to_send, memo);
+        let note = output.output_note();
+        self.outputs.push(output);
 
         note
     }
 
     /// Create a new `Delegate` description for the transaction.
-    pub fn add_delegation(&mut self, rate_data: &RateData, unbonded_amount: u64) -> &mut Self {
+    pub fn add_delegation(
+        &mut self,
+        rate_data: &RateData,
+        unbonded_amount: u64,
+    ) -> Result<&mut Self, Error> {
         let delegate = Delegate {
-            delegation_amount: rate_data.delegation_amount(unbonded_amount),
+            delegation_amount: rate_data.delegation_amount(unbonded_amount)?,
             epoch_index: rate_data.epoch_index,
             unbonded_amount,
             validator_identity: rate_data.identity_key.clone(),
         };
 
-        let value_commitment = delegate.value_commitment();
-        // The value commitment has 0 blinding factor, so we skip
-        // accumulating a blinding term into the synthetic blinding factor.
-        self.value_balance += value_commitment.0;
-        self.value_commitments += value_commitment.0;
+        // The value commitment has 0 blinding factor, so it is its own contribution to the
+        // transaction's value balance.
+        self.value_balance += delegate.value_commitment().0;
 
         self.delegations.push(delegate);
 
-        self
+        Ok(self)
     }
 
     /// Create a new `Undelegate` description for the transaction.
-    pub fn add_undelegation(&mut self, rate_data: &RateData, delegation_amount: u64) -> &mut Self {
+    pub fn add_undelegation(
+        &mut self,
+        rate_data: &RateData,
+        delegation_amount: u64,
+    ) -> Result<&mut Self, Error> {
         let undelegate = Undelegate {
             epoch_index: rate_data.epoch_index,
             delegation_amount,
-            unbonded_amount: rate_data.unbonded_amount(delegation_amount),
+            unbonded_amount: rate_data.unbonded_amount(delegation_amount)?,
             validator_identity: rate_data.identity_key.clone(),
         };
 
-        let value_commitment = undelegate.value_commitment();
-        // The value commitment has 0 blinding factor, so we skip
-        // accumulating a blinding term into the synthetic blinding factor.
-        self.value_balance += value_commitment.0;
-        self.value_commitments += value_commitment.0;
+        // The value commitment has 0 blinding factor, so it is its own contribution to the
+        // transaction's value balance.
+        self.value_balance += undelegate.value_commitment().0;
 
         self.undelegations.push(undelegate);
 
-        self
+        Ok(self)
     }
 
     /// Set the transaction fee in PEN.
@@ -203,13 +202,9 @@ impl Builder {
             asset_id: asset_id.clone(),
         };
 
-        // The fee is effectively an additional output, so we
-        // add to the transaction's value balance.
-        let value_commitment = fee_value.commit(Fr::zero());
-        // The value commitment has 0 blinding factor, so we skip
-        // accumulating a blinding term into the synthetic blinding factor.
-        self.value_balance -= value_commitment.0;
-        self.value_commitments -= value_commitment.0;
+        // The fee is effectively an additional output, so we subtract it from the transaction's
+        // value balance. The value commitment has 0 blinding factor.
+        self.value_balance -= fee_value.commit(Fr::zero()).0;
 
         self.fee = Some(Fee(fee));
         self
@@ -227,31 +222,9 @@ impl Builder {
         self
     }
 
-    /// Add the binding signature based on the current sum of synthetic blinding factors.
-    #[allow(non_snake_case)]
-    pub fn compute_binding_sig<R: CryptoRng + RngCore>(
-        &self,
-        rng: &mut R,
-        sighash: &[u8; 64],
-    ) -> Signature<Binding> {
-        let binding_signing_key: SigningKey<Binding> = self.synthetic_blinding_factor.into();
-
-        // Check that the derived verification key corresponds to the signing key to be used.
-        let H = value::VALUE_BLINDING_GENERATOR.deref();
-        let binding_verification_key_raw = (self.synthetic_blinding_factor * H).compress().0;
-
-        // If value balance is non-zero, the verification key would be value_commitments - value_balance,
-        // but value_balance should always be zero.
-        let computed_verification_key = self.value_commitments.compress().0;
-        assert_eq!(binding_verification_key_raw, computed_verification_key);
-
-        binding_signing_key.sign(rng, sighash)
-    }
-
-    pub fn finalize<R: CryptoRng + RngCore>(
-        &mut self,
-        mut rng: &mut R,
-    ) -> Result<Transaction, Error> {
+    /// Produce a plan for the transaction, which can be authorized with
+    /// [`TransactionPlan::authorize`] and then built with [`Builder::finalize_plan`].
+    pub fn plan<R: CryptoRng + RngCore>(&mut self, rng: &mut R) -> Result<TransactionPlan, Error> {
         if self.chain_id.is_none() {
             return Err(Error::NoChainID);
         }
@@ -264,68 +237,86 @@ impl Builder {
             return Err(Error::NonZeroValueBalance);
         }
 
-        let mut actions = Vec::<Action>::new();
-
         // Randomize all actions to minimize info leakage.
         self.spends.shuffle(rng);
         self.outputs.shuffle(rng);
         self.delegations.shuffle(rng);
         self.undelegations.shuffle(rng);
 
-        // Fill in the spends using blank signatures, so we can build the sighash tx
-        for (_, body) in &self.spends {
-            actions.push(Action::Spend(Spend {
-                body: body.clone(),
-                auth_sig: Signature::from([0; 64]),
-            }));
-        }
-        for output in self.outputs.drain(..) {
-            actions.push(Action::Output(output));
-        }
-        for delegation in self.delegations.drain(..) {
-            actions.push(Action::Delegate(delegation));
-        }
-        for undelegation in self.undelegations.drain(..) {
-            actions.push(Action::Undelegate(undelegation));
-        }
+        let mut actions = Vec::<ActionPlan>::new();
+        actions.extend(self.spends.drain(..).map(ActionPlan::Spend));
+        actions.extend(self.outputs.drain(..).map(ActionPlan::Output));
+        actions.extend(self.delegations.drain(..).map(ActionPlan::Delegate));
+        actions.extend(self.undelegations.drain(..).map(ActionPlan::Undelegate));
 
-        let mut transaction_body = TransactionBody {
+        // Prevent accidental reuse by erasing the chain ID.
+        // It'd be cleaner to take ownership of self and consume it,
+        // but that's not possible to chain with &mut self methods, and those
+        // are useful when building complex transactions.
+        Ok(TransactionPlan {
             actions,
             merkle_root: self.merkle_root.clone(),
             expiry_height: self.expiry_height.unwrap_or(0),
             chain_id: self.chain_id.take().unwrap(),
             fee: self.fee.take().unwrap(),
-        };
+        })
+    }
+
+    /// Build the transaction described by `plan`, authorized by `auth_data`.
+    pub fn finalize_plan<R: CryptoRng + RngCore>(
+        rng: &mut R,
+        plan: &TransactionPlan,
+        fvk: &FullViewingKey,
+        auth_data: &AuthorizationData,
+    ) -> Result<Transaction, Error> {
+        let mut transaction_body = plan.build_body(fvk);
 
         // The transaction body is filled except for the signatures,
         // so we can compute the sighash value....
         let sighash = transaction_body.sighash();
+        if sighash != auth_data.sighash {
+            return Err(Error::AuthorizationMismatch);
+        }
 
         // and use it to fill in the spendauth sigs...
-        for i in 0..self.spends.len() {
-            let (rsk, _) = self.spends[i];
-            if let Action::Spend(Spend {
-                ref mut auth_sig, ..
-            }) = transaction_body.actions[i]
-            {
-                *auth_sig = rsk.sign(&mut rng, &sighash);
-            } else {
-                unreachable!("spends come first in actions list")
+        let mut spend_auths = auth_data.spend_auths.iter();
+        for action in transaction_body.actions.iter_mut() {
+            if let Action::Spend(Spend { body, auth_sig }) = action {
+                *auth_sig = spend_auths
+                    .next()
+                    .ok_or(Error::AuthorizationMismatch)?
+                    .clone();
+                body.rk
+                    .verify(&sighash, auth_sig)
+                    .map_err(|_| Error::InvalidSpendAuthSignature)?;
             }
         }
+        if spend_auths.next().is_some() {
+            return Err(Error::AuthorizationMismatch);
+        }
 
-        // ... and the binding sig
-        let binding_sig = self.compute_binding_sig(rng, &sighash);
+        // ... and the binding sig, which can only be verified if the value balance is zero.
+        let binding_signing_key: SigningKey<Binding> = plan.synthetic_blinding_factor().into();
+        let transaction = Transaction {
+            transaction_body,
+            binding_sig: binding_signing_key.sign(rng, &sighash),
+        };
+        transaction
+            .binding_verification_key()
+            .verify(&sighash, transaction.binding_sig())
+            .map_err(|_| Error::NonZeroValueBalance)?;
 
-        // Prevent accidental reuse by erasing the chain ID.
-        // It'd be cleaner to take ownership of self and consume it,
-        // but that's not possible to chain with &mut self methods, and those
-        // are useful when building complex transactions.
-        self.chain_id = None;
+        Ok(transaction)
+    }
 
-        Ok(Transaction {
-            transaction_body,
-            binding_sig,
-        })
+    /// Plan, authorize and build the transaction in one step, using the given spend key.
+    pub fn finalize<R: CryptoRng + RngCore>(
+        &mut self,
+        rng: &mut R,
+        spend_key: &SpendKey,
+    ) -> Result<Transaction, Error> {
+        let plan = self.plan(rng)?;
+        let auth_data = plan.authorize(rng, spend_key);
+        Self::finalize_plan(rng, &plan, spend_key.full_viewing_key(), &auth_data)
     }
 }
@@ -0,0 +1,151 @@
+//! Benchmarks for wallet scanning over synthetic compact blocks, comparing sequential and
+//! parallel trial decryption.
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use penumbra_crypto::{
+    asset, ka,
+    keys::{SeedPhrase, SpendKey, SpendSeed},
+    merkle::{NoteCommitmentTree, Tree, TreeExt},
+    note, Address, Note, Value,
+};
+use penumbra_proto::light_wallet::{CompactBlock, StateFragment};
+use penumbra_wallet::{ClientState, DecryptedBlock, Wallet};
+use rand_core::OsRng;
+use rayon::prelude::*;
+
+/// The number of blocks in each benchmark run.
+const BLOCKS: u64 = 32;
+/// The number of fragments in each block.
+const FRAGMENTS_PER_BLOCK: usize = 64;
+/// One in this many fragments is a note sent to the wallet being scanned.
+const OURS_EVERY: usize = 16;
+
+/// Returns a state fragment for a new note to `address`.
+fn fragment(address: &Address) -> StateFragment {
+    let value = Value {
+        amount: 10,
+        asset_id: asset::REGISTRY.parse_denom("upenumbra").unwrap().id(),
+    };
+    let note = Note::generate(&mut OsRng, address, value);
+    let esk = ka::Secret::new(&mut OsRng);
+
+    StateFragment {
+        note_commitment: note.commit().0.to_bytes().to_vec().into(),
+        ephemeral_key: esk
+            .diversified_public(&note.diversified_generator())
+            .0
+            .to_vec()
+            .into(),
+        encrypted_note: note.encrypt(&esk).to_vec().into(),
+        ..Default::default()
+    }
+}
+
+/// Returns a wallet and a sequence of synthetic compact blocks, starting at genesis, in which a
+/// fraction of the notes are sent to the wallet.
+fn synthetic_blocks() -> (Wallet, Vec<CompactBlock>) {
+    let wallet = Wallet::from_seed_phrase(SeedPhrase::generate(&mut OsRng), 0);
+    let (_label, ours) = wallet.address_by_index(0).unwrap();
+
+    let other = SpendKey::new(SpendSeed::from_seed_phrase(
+        SeedPhrase::generate(&mut OsRng),
+        0,
+    ));
+    let (theirs, _dtk) = other
+        .full_viewing_key()
+        .incoming()
+        .payment_address(0u64.into());
+
+    // Each block's anchor is the root of the note commitment tree after its notes are added.
+    let mut tree = NoteCommitmentTree::new(0);
+    let blocks = (0..BLOCKS)
+        .map(|height| {
+            let fragments = (0..FRAGMENTS_PER_BLOCK)
+                .map(|i| {
+                    if i % OURS_EVERY == 0 {
+                        fragment(&ours)
+                    } else {
+                        fragment(&theirs)
+                    }
+                })
+                .collect::<Vec<_>>();
+            for fragment in &fragments {
+                tree.append(&note::Commitment::try_from(&fragment.note_commitment[..]).unwrap());
+            }
+            CompactBlock {
+                height,
+                fragments,
+                nct_anchor: tree.root2().to_bytes().to_vec().into(),
+                ..Default::default()
+            }
+        })
+        .collect();
+
+    (wallet, blocks)
+}
+
+fn trial_decryption(c: &mut Criterion) {
+    let (wallet, blocks) = synthetic_blocks();
+
+    // Run the sequential benchmark on a single thread, so that trial decryption of the fragments
+    // within each block isn't parallelized either.
+    let single_thread = rayon::ThreadPoolBuilder::new()
+        .num_threads(1)
+        .build()
+        .unwrap();
+
+    let mut group = c.benchmark_group("trial_decryption");
+    group.bench_function("sequential", |b| {
+        b.iter_batched(
+            || blocks.clone(),
+            |blocks| {
+                single_thread.install(|| {
+                    blocks
+                        .into_iter()
+                        .map(|block| {
+                            DecryptedBlock::new(block, std::slice::from_ref(&wallet)).unwrap()
+                        })
+                        .collect::<Vec<_>>()
+                })
+            },
+            BatchSize::LargeInput,
+        )
+    });
+    group.bench_function("parallel", |b| {
+        b.iter_batched(
+            || blocks.clone(),
+            |blocks| {
+                blocks
+                    .into_par_iter()
+                    .map(|block| DecryptedBlock::new(block, std::slice::from_ref(&wallet)).unwrap())
+                    .collect::<Vec<_>>()
+            },
+            BatchSize::LargeInput,
+        )
+    });
+    group.finish();
+}
+
+fn scanning(c: &mut Criterion) {
+    let (wallet, blocks) = synthetic_blocks();
+    let decrypted = blocks
+        .into_par_iter()
+        .map(|block| DecryptedBlock::new(block, std::slice::from_ref(&wallet)).unwrap())
+        .collect::<Vec<_>>();
+
+    c.bench_function("scan_decrypted_blocks", |b| {
+        b.iter_batched(
+            || (ClientState::new(wallet.clone()), decrypted.clone()),
+            |(mut state, decrypted)| {
+                for block in decrypted {
+                    state.scan_decrypted_block(block).unwrap();
+                }
+                state
+            },
+            BatchSize::LargeInput,
+        )
+    });
+}
+
+criterion_group!(benches, trial_decryption, scanning);
+criterion_main!(benches);
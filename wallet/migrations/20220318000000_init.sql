@@ -0,0 +1,113 @@
+-- Key material: the spend seed of the wallet
+CREATE TABLE IF NOT EXISTS wallet (
+    id integer PRIMARY KEY CHECK (id = 0),
+    spend_seed blob NOT NULL
+);
+
+-- Human-readable labels for our addresses, indexed by diversifier index
+CREATE TABLE IF NOT EXISTS addresses (
+    address_index integer PRIMARY KEY,
+    label text NOT NULL
+);
+
+-- The height we have scanned to and the chain parameters, if they have been fetched
+CREATE TABLE IF NOT EXISTS sync_state (
+    id integer PRIMARY KEY CHECK (id = 0),
+    last_block_height integer,
+    -- JSON-encoded chain parameters
+    chain_params text
+);
+
+-- The (bincode-serialized) note commitment tree, rewritten after every scanned block
+CREATE TABLE IF NOT EXISTS note_commitment_tree (
+    id integer PRIMARY KEY CHECK (id = 0),
+    tree blob NOT NULL
+);
+
+-- All known assets
+CREATE TABLE IF NOT EXISTS assets (
+    asset_id text PRIMARY KEY NOT NULL,
+    denom text NOT NULL
+);
+
+-- Our nullifiers and the notes they correspond to
+CREATE TABLE IF NOT EXISTS nullifiers (
+    nullifier blob PRIMARY KEY NOT NULL,
+    note_commitment blob NOT NULL
+);
+
+-- Notes we have received, by the set they are in: one of 'unspent', 'submitted_spend',
+-- 'submitted_change', 'spent', 'quarantined' or 'reverted'
+CREATE TABLE IF NOT EXISTS notes (
+    note_commitment blob NOT NULL,
+    note_set text NOT NULL,
+    note blob NOT NULL,
+    -- Milliseconds since the Unix epoch at which a submitted spend or change note times out
+    timeout integer,
+    -- The unbonding height and validator of quarantined and reverted notes
+    unbonding_height integer,
+    validator_identity_key text,
+    PRIMARY KEY (note_commitment, note_set)
+);
+
+-- Spends of our notes which are quarantined until their unbonding height
+CREATE TABLE IF NOT EXISTS quarantined_spends (
+    note_commitment blob PRIMARY KEY NOT NULL,
+    unbonding_height integer NOT NULL,
+    validator_identity_key text NOT NULL
+);
+
+-- The heights at which our notes were received and spent
+CREATE TABLE IF NOT EXISTS note_heights (
+    note_commitment blob PRIMARY KEY NOT NULL,
+    received_height integer NOT NULL,
+    spent_height integer
+);
+
+-- The validator exchange rates at which our delegation notes were received and spent
+CREATE TABLE IF NOT EXISTS delegation_rates (
+    note_commitment blob PRIMARY KEY NOT NULL,
+    received_exchange_rate integer,
+    spent_exchange_rate integer
+);
+
+-- The memos attached to notes we have received
+CREATE TABLE IF NOT EXISTS memos (
+    note_commitment blob PRIMARY KEY NOT NULL,
+    memo blob NOT NULL
+);
+
+-- Notes we have sent, recovered using our outgoing viewing key
+CREATE TABLE IF NOT EXISTS sent_notes (
+    note_commitment blob PRIMARY KEY NOT NULL,
+    note blob NOT NULL,
+    recipient text NOT NULL,
+    height integer NOT NULL
+);
+
+-- The transactions which involved our notes
+CREATE TABLE IF NOT EXISTS transactions (
+    height integer NOT NULL,
+    transaction_id blob NOT NULL,
+    PRIMARY KEY (height, transaction_id)
+);
+
+-- The notes involved in each transaction, by their role: one of 'received', 'spent' or 'sent'
+CREATE TABLE IF NOT EXISTS transaction_notes (
+    height integer NOT NULL,
+    transaction_id blob NOT NULL,
+    role text NOT NULL,
+    note_commitment blob NOT NULL,
+    PRIMARY KEY (height, transaction_id, role, note_commitment),
+    FOREIGN KEY (height, transaction_id) REFERENCES transactions (height, transaction_id)
+);
+
+-- Details of the transactions we have built, which can't be learned by scanning
+CREATE TABLE IF NOT EXISTS transaction_details (
+    transaction_id blob PRIMARY KEY NOT NULL,
+    fee integer NOT NULL,
+    memo blob,
+    -- JSON-encoded delegate and undelegate actions
+    delegations text NOT NULL,
+    undelegations text NOT NULL
+);
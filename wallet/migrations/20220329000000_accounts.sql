@@ -0,0 +1,28 @@
+-- The seed phrase further accounts are derived from, for wallets created from one
+ALTER TABLE wallet ADD COLUMN seed_phrase text;
+
+-- Accounts derived from the seed phrase, other than account 0, whose spend seed is in the
+-- wallet table
+CREATE TABLE IF NOT EXISTS accounts (
+    account_index integer PRIMARY KEY CHECK (account_index > 0),
+    label text NOT NULL,
+    spend_seed blob NOT NULL
+);
+
+-- Address labels are now kept for each account
+CREATE TABLE IF NOT EXISTS account_addresses (
+    account_index integer NOT NULL,
+    address_index integer NOT NULL,
+    label text NOT NULL,
+    PRIMARY KEY (account_index, address_index)
+);
+INSERT INTO account_addresses (account_index, address_index, label)
+    SELECT 0, address_index, label FROM addresses;
+DROP TABLE addresses;
+ALTER TABLE account_addresses RENAME TO addresses;
+
+-- The accounts which received our notes; notes with no row here were received by account 0
+CREATE TABLE IF NOT EXISTS note_accounts (
+    note_commitment blob PRIMARY KEY NOT NULL,
+    account_index integer NOT NULL
+);
@@ -0,0 +1,38 @@
+-- Secret key material may now be sealed with a password, in which case the spend seeds and the
+-- seed phrase are null and the full viewing keys are kept in their place, unless they are sealed
+-- as well
+CREATE TABLE IF NOT EXISTS wallet_keys (
+    id integer PRIMARY KEY CHECK (id = 0),
+    spend_seed blob,
+    seed_phrase text,
+    full_viewing_key blob
+);
+INSERT INTO wallet_keys (id, spend_seed, seed_phrase)
+    SELECT id, spend_seed, seed_phrase FROM wallet;
+DROP TABLE wallet;
+ALTER TABLE wallet_keys RENAME TO wallet;
+
+CREATE TABLE IF NOT EXISTS account_keys (
+    account_index integer PRIMARY KEY CHECK (account_index > 0),
+    label text NOT NULL,
+    spend_seed blob,
+    full_viewing_key blob
+);
+INSERT INTO account_keys (account_index, label, spend_seed)
+    SELECT account_index, label, spend_seed FROM accounts;
+DROP TABLE accounts;
+ALTER TABLE account_keys RENAME TO accounts;
+
+-- The secret key material of an encrypted wallet, sealed with a key derived from the password
+CREATE TABLE IF NOT EXISTS sealed_keys (
+    id integer PRIMARY KEY CHECK (id = 0),
+    salt blob NOT NULL,
+    memory_cost integer NOT NULL,
+    time_cost integer NOT NULL,
+    parallelism integer NOT NULL,
+    nonce blob NOT NULL,
+    ciphertext blob NOT NULL,
+    -- Whether the full viewing keys are sealed too, so the wallet can't be synced without the
+    -- password
+    viewing_keys_sealed boolean NOT NULL
+);
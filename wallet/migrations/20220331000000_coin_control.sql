@@ -0,0 +1,10 @@
+-- Labels given to notes by the user
+CREATE TABLE IF NOT EXISTS note_labels (
+    note_commitment blob PRIMARY KEY NOT NULL,
+    label text NOT NULL
+);
+
+-- Notes frozen by the user, which are never chosen to be spent automatically
+CREATE TABLE IF NOT EXISTS frozen_notes (
+    note_commitment blob PRIMARY KEY NOT NULL
+);
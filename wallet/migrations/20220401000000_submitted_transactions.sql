@@ -0,0 +1,19 @@
+-- Transactions we have submitted, tracked until they are included in a block or expire
+CREATE TABLE IF NOT EXISTS submitted_transactions (
+    transaction_id blob PRIMARY KEY NOT NULL,
+    -- The last height at which the transaction can be included, if it expires
+    expiry_height integer,
+    -- One of 'pending', 'confirmed' or 'expired'
+    status text NOT NULL,
+    -- The height of the block a confirmed transaction was included in
+    confirmed_height integer
+);
+
+-- The notes involved in submitted transactions, by their role: 'spent' or 'change'
+CREATE TABLE IF NOT EXISTS submitted_transaction_notes (
+    transaction_id blob NOT NULL,
+    role text NOT NULL,
+    note_commitment blob NOT NULL,
+    PRIMARY KEY (transaction_id, role, note_commitment),
+    FOREIGN KEY (transaction_id) REFERENCES submitted_transactions (transaction_id)
+);
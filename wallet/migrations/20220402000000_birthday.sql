@@ -0,0 +1,2 @@
+-- The height before which no block can hold notes of the wallet, if known
+ALTER TABLE wallet ADD COLUMN birthday_height integer;
@@ -0,0 +1,5 @@
+-- The address book: addresses and validator identity keys named by the user
+CREATE TABLE IF NOT EXISTS contacts (
+    name text PRIMARY KEY NOT NULL,
+    contact text NOT NULL
+);
@@ -0,0 +1,6 @@
+-- The latest block header verified by the light client, from which the next verification starts
+CREATE TABLE IF NOT EXISTS trusted_header (
+    id integer PRIMARY KEY CHECK (id = 0),
+    height integer NOT NULL,
+    hash blob NOT NULL
+);
@@ -0,0 +1,14 @@
+-- The memos attached to notes we have sent, recovered using our outgoing viewing key
+ALTER TABLE sent_notes ADD COLUMN memo blob;
+
+-- Memos were recorded with the details of the transactions we built before they were recovered
+-- for the notes we sent, so carry them over to the notes those transactions sent. The memo column
+-- of transaction_details is no longer written.
+UPDATE sent_notes SET memo = (
+    SELECT transaction_details.memo
+    FROM transaction_details
+    JOIN transaction_notes
+        ON transaction_notes.transaction_id = transaction_details.transaction_id
+    WHERE transaction_notes.role = 'sent'
+        AND transaction_notes.note_commitment = sent_notes.note_commitment
+);
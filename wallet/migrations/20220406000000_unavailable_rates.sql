@@ -0,0 +1,8 @@
+-- Validator rates the node didn't have when they were last requested, with the index of the epoch
+-- the wallet had synced to then, so that they are only requested again in a later epoch
+CREATE TABLE IF NOT EXISTS unavailable_rates (
+    identity_key text NOT NULL,
+    epoch_index integer NOT NULL,
+    checked_epoch_index integer NOT NULL,
+    PRIMARY KEY (identity_key, epoch_index)
+);
@@ -1,5 +1,12 @@
+mod note_selector;
 mod state;
 mod wallet;
 
-pub use state::{ClientState, UnspentNote};
+pub use note_selector::{NoteSelection, NoteSelector};
+pub use state::{
+    storage::{KeyProtection, Storage},
+    ClientState, Contact, DecryptedBlock, DelegationRecord, NoteSource, Payment, QuarantinedNote,
+    SentNote, SubmittedTransaction, SubmittedTransactionStatus, TransactionDetails,
+    TransactionHistoryEntry, TransactionRecord, TransactionShape, UnspentNote,
+};
 pub use wallet::Wallet;
@@ -0,0 +1,389 @@
+use std::{collections::BTreeMap, fmt, str::FromStr};
+
+use anyhow::anyhow;
+use penumbra_crypto::Note;
+use rand::seq::SliceRandom;
+use rand_core::RngCore;
+
+/// The number of subsets [`NoteSelection::ExactMatch`] considers before giving up on finding
+/// notes which add up to exactly the amount to spend.
+const EXACT_MATCH_MAX_TRIES: usize = 100_000;
+
+/// A strategy for choosing which notes to spend to cover an amount.
+///
+/// Candidate notes are all of the same denomination and ready to spend, and each is paired with the
+/// index of the address which received it.
+pub trait NoteSelector: fmt::Debug + Send + Sync {
+    /// Selects notes from `candidates` whose amounts add up to at least `amount`, or returns
+    /// `None` if that isn't possible.
+    fn select(
+        &self,
+        rng: &mut dyn RngCore,
+        candidates: &[(u64, Note)],
+        amount: u64,
+    ) -> Option<Vec<Note>>;
+}
+
+/// The built-in note selection strategies.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NoteSelection {
+    /// Prefer notes received by a single address, and combine as few addresses as possible
+    /// otherwise, choosing notes at random.
+    PrivacyPreferring,
+    /// Choose notes in a random order until the amount is covered.
+    Random,
+    /// Spend as few notes as possible, and among those, leave as little change as possible.
+    FewestNotes,
+    /// Spend the largest notes first.
+    LargestFirst,
+    /// Look for notes which add up to exactly the amount, so that there is no change, falling
+    /// back to [`NoteSelection::FewestNotes`] if there are none.
+    ExactMatch,
+}
+
+impl Default for NoteSelection {
+    fn default() -> Self {
+        NoteSelection::PrivacyPreferring
+    }
+}
+
+impl NoteSelector for NoteSelection {
+    fn select(
+        &self,
+        rng: &mut dyn RngCore,
+        candidates: &[(u64, Note)],
+        amount: u64,
+    ) -> Option<Vec<Note>> {
+        let notes = candidates.iter().map(|(_, note)| note.clone()).collect();
+        match self {
+            NoteSelection::PrivacyPreferring => privacy_preferring(rng, candidates, amount),
+            NoteSelection::Random => random(rng, notes, amount),
+            NoteSelection::FewestNotes => fewest_notes(notes, amount),
+            NoteSelection::LargestFirst => take_until(largest_first(notes), amount),
+            NoteSelection::ExactMatch => exact_match(&largest_first(notes.clone()), amount)
+                .or_else(|| fewest_notes(notes, amount)),
+        }
+    }
+}
+
+impl fmt::Display for NoteSelection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            NoteSelection::PrivacyPreferring => "privacy",
+            NoteSelection::Random => "random",
+            NoteSelection::FewestNotes => "fewest-notes",
+            NoteSelection::LargestFirst => "largest-first",
+            NoteSelection::ExactMatch => "exact-match",
+        })
+    }
+}
+
+impl FromStr for NoteSelection {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "privacy" => Ok(NoteSelection::PrivacyPreferring),
+            "random" => Ok(NoteSelection::Random),
+            "fewest-notes" => Ok(NoteSelection::FewestNotes),
+            "largest-first" => Ok(NoteSelection::LargestFirst),
+            "exact-match" => Ok(NoteSelection::ExactMatch),
+            _ => Err(anyhow!(
+                "unknown note selection strategy {}, expected one of privacy, random, \
+                fewest-notes, largest-first or exact-match",
+                s
+            )),
+        }
+    }
+}
+
+/// Takes notes in order until their amounts add up to at least `amount`.
+fn take_until(notes: impl IntoIterator<Item = Note>, amount: u64) -> Option<Vec<Note>> {
+    let mut selected = Vec::new();
+    let mut total = 0u64;
+    for note in notes {
+        if total >= amount {
+            break;
+        }
+        total += note.amount();
+        selected.push(note);
+    }
+    (total >= amount).then(|| selected)
+}
+
+/// Sorts notes by amount, largest first.
+fn largest_first(mut notes: Vec<Note>) -> Vec<Note> {
+    notes.sort_by(|a, b| b.amount().cmp(&a.amount()));
+    notes
+}
+
+fn random(rng: &mut dyn RngCore, mut notes: Vec<Note>, amount: u64) -> Option<Vec<Note>> {
+    notes.shuffle(rng);
+    take_until(notes, amount)
+}
+
+fn fewest_notes(notes: Vec<Note>, amount: u64) -> Option<Vec<Note>> {
+    // Taking the largest notes first spends the fewest notes...
+    let notes = largest_first(notes);
+    let mut selected = take_until(notes.iter().cloned(), amount)?;
+
+    // ... and the last of them can be swapped for the smallest note which still covers the amount,
+    // to leave as little change as possible.
+    if let Some(last) = selected.pop() {
+        let needed = amount - selected.iter().map(|note| note.amount()).sum::<u64>();
+        let smallest_sufficient = notes[selected.len()..]
+            .iter()
+            .rev()
+            .find(|note| note.amount() >= needed)
+            .cloned()
+            .unwrap_or(last);
+        selected.push(smallest_sufficient);
+    }
+    Some(selected)
+}
+
+/// Searches for notes whose amounts add up to exactly `amount`, given notes sorted largest first.
+fn exact_match(notes: &[Note], amount: u64) -> Option<Vec<Note>> {
+    // The total amount of each suffix of the notes, to prune branches which can't reach the amount
+    let mut remaining = vec![0u64; notes.len() + 1];
+    for i in (0..notes.len()).rev() {
+        remaining[i] = remaining[i + 1] + notes[i].amount();
+    }
+
+    fn search(
+        notes: &[Note],
+        remaining: &[u64],
+        start: usize,
+        amount: u64,
+        selected: &mut Vec<usize>,
+        tries: &mut usize,
+    ) -> bool {
+        if amount == 0 {
+            return true;
+        }
+        for i in start..notes.len() {
+            *tries += 1;
+            if *tries > EXACT_MATCH_MAX_TRIES || remaining[i] < amount {
+                return false;
+            }
+            if notes[i].amount() <= amount {
+                selected.push(i);
+                if search(
+                    notes,
+                    remaining,
+                    i + 1,
+                    amount - notes[i].amount(),
+                    selected,
+                    tries,
+                ) {
+                    return true;
+                }
+                selected.pop();
+            }
+        }
+        false
+    }
+
+    let mut selected = Vec::new();
+    search(notes, &remaining, 0, amount, &mut selected, &mut 0)
+        .then(|| selected.into_iter().map(|i| notes[i].clone()).collect())
+}
+
+fn privacy_preferring(
+    rng: &mut dyn RngCore,
+    candidates: &[(u64, Note)],
+    amount: u64,
+) -> Option<Vec<Note>> {
+    let mut notes_by_address = BTreeMap::<u64, Vec<Note>>::new();
+    for (address, note) in candidates {
+        notes_by_address
+            .entry(*address)
+            .or_default()
+            .push(note.clone());
+    }
+    let mut addresses = notes_by_address.into_values().collect::<Vec<_>>();
+    addresses.shuffle(rng);
+
+    // Spend the notes of a single address, if any address holds enough...
+    let total = |notes: &Vec<Note>| notes.iter().map(|note| note.amount()).sum::<u64>();
+    if let Some(notes) = addresses.iter().find(|notes| total(notes) >= amount) {
+        return random(rng, notes.clone(), amount);
+    }
+
+    // ... and otherwise link as few addresses as possible, starting with those holding the most.
+    addresses.sort_by_key(|notes| std::cmp::Reverse(total(notes)));
+    let mut linked = Vec::new();
+    for notes in addresses {
+        if total(&linked) >= amount {
+            break;
+        }
+        linked.extend(notes);
+    }
+    take_until(largest_first(linked), amount)
+}
+
+#[cfg(test)]
+mod tests {
+    use penumbra_crypto::{
+        asset,
+        keys::{SpendKey, SpendSeed},
+        Value,
+    };
+    use rand_core::OsRng;
+
+    use super::*;
+
+    const STRATEGIES: [NoteSelection; 5] = [
+        NoteSelection::PrivacyPreferring,
+        NoteSelection::Random,
+        NoteSelection::FewestNotes,
+        NoteSelection::LargestFirst,
+        NoteSelection::ExactMatch,
+    ];
+
+    /// Returns a note of the given amount for each pair of address index and amount.
+    fn candidates(amounts: &[(u64, u64)]) -> Vec<(u64, Note)> {
+        let address = SpendKey::new(SpendSeed([0; 32]))
+            .incoming_viewing_key()
+            .payment_address(0u64.into())
+            .0;
+        let asset_id = asset::REGISTRY.parse_denom("upenumbra").unwrap().id();
+        amounts
+            .iter()
+            .map(|&(index, amount)| {
+                let note = Note::generate(&mut OsRng, &address, Value { amount, asset_id });
+                (index, note)
+            })
+            .collect()
+    }
+
+    fn notes(amounts: &[u64]) -> Vec<Note> {
+        let amounts = amounts
+            .iter()
+            .map(|&amount| (0, amount))
+            .collect::<Vec<_>>();
+        candidates(&amounts)
+            .into_iter()
+            .map(|(_, note)| note)
+            .collect()
+    }
+
+    fn amounts(notes: &[Note]) -> Vec<u64> {
+        notes.iter().map(|note| note.amount()).collect()
+    }
+
+    #[test]
+    fn note_selection_covers_amount() {
+        let candidates = candidates(&[(0, 30), (0, 20), (1, 40), (2, 5)]);
+        for strategy in STRATEGIES {
+            for amount in [1, 30, 45, 95] {
+                let selected = strategy.select(&mut OsRng, &candidates, amount).unwrap();
+                assert!(
+                    amounts(&selected).iter().sum::<u64>() >= amount,
+                    "{}",
+                    strategy
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn note_selection_insufficient_funds() {
+        let candidates = candidates(&[(0, 30), (1, 20)]);
+        for strategy in STRATEGIES {
+            assert_eq!(strategy.select(&mut OsRng, &candidates, 51), None);
+            assert_eq!(strategy.select(&mut OsRng, &[], 1), None);
+        }
+    }
+
+    #[test]
+    fn note_selection_of_zero_amount_spends_nothing() {
+        let candidates = candidates(&[(0, 30), (1, 20)]);
+        for strategy in STRATEGIES {
+            assert_eq!(
+                strategy.select(&mut OsRng, &candidates, 0),
+                Some(Vec::new())
+            );
+            assert_eq!(strategy.select(&mut OsRng, &[], 0), Some(Vec::new()));
+        }
+    }
+
+    #[test]
+    fn fewest_notes_swaps_in_smallest_sufficient_note() {
+        // The two largest notes cover the amount, but the 30 can replace the 50 to leave less
+        // change.
+        let selected = fewest_notes(notes(&[10, 100, 30, 50]), 120).unwrap();
+        assert_eq!(amounts(&selected), vec![100, 30]);
+
+        // A single note is swapped for the smallest note covering the whole amount.
+        let selected = fewest_notes(notes(&[10, 100, 30, 50]), 25).unwrap();
+        assert_eq!(amounts(&selected), vec![30]);
+    }
+
+    #[test]
+    fn largest_first_takes_largest_notes() {
+        let selected = take_until(largest_first(notes(&[10, 100, 30, 50])), 120).unwrap();
+        assert_eq!(amounts(&selected), vec![100, 50]);
+    }
+
+    #[test]
+    fn exact_match_leaves_no_change() {
+        let notes = largest_first(notes(&[50, 5, 40, 30, 25]));
+        let selected = exact_match(&notes, 55).unwrap();
+        assert_eq!(amounts(&selected), vec![50, 5]);
+
+        let selected = exact_match(&notes, 95).unwrap();
+        assert_eq!(amounts(&selected), vec![50, 40, 5]);
+
+        assert_eq!(exact_match(&notes, 151), None);
+    }
+
+    #[test]
+    fn exact_match_prunes_insufficient_branches() {
+        // Without pruning, the search for the rest of the amount after taking the 20 would try
+        // every subset of the notes of amount 0, running out of tries before finding 15 + 15.
+        let mut amounts_with_zeros = vec![20, 15, 15];
+        amounts_with_zeros.extend([0; 30]);
+        let notes = largest_first(notes(&amounts_with_zeros));
+        let selected = exact_match(&notes, 30).unwrap();
+        assert_eq!(amounts(&selected), vec![15, 15]);
+    }
+
+    #[test]
+    fn exact_match_gives_up_after_max_tries() {
+        // No subset of even amounts adds up to an odd amount, but there are too many subsets to
+        // try them all.
+        let candidates = candidates(&[(0, 2); 40]);
+        let notes = candidates
+            .iter()
+            .map(|(_, note)| note.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(exact_match(&notes, 41), None);
+
+        // The strategy falls back to spending the fewest notes.
+        let selected = NoteSelection::ExactMatch
+            .select(&mut OsRng, &candidates, 41)
+            .unwrap();
+        assert_eq!(amounts(&selected), vec![2; 21]);
+    }
+
+    #[test]
+    fn privacy_preferring_spends_from_one_address() {
+        // Only the address with index 1 holds enough by itself.
+        let candidates = candidates(&[(0, 10), (0, 10), (1, 50), (2, 30)]);
+        for _ in 0..10 {
+            let selected = privacy_preferring(&mut OsRng, &candidates, 40).unwrap();
+            assert_eq!(amounts(&selected), vec![50]);
+        }
+    }
+
+    #[test]
+    fn privacy_preferring_links_fewest_addresses() {
+        // No address holds enough, and linking the two addresses holding the most covers it.
+        let candidates = candidates(&[(0, 30), (1, 20), (1, 20), (2, 5)]);
+        for _ in 0..10 {
+            let selected = privacy_preferring(&mut OsRng, &candidates, 45).unwrap();
+            assert_eq!(amounts(&selected), vec![30, 20]);
+        }
+    }
+}
@@ -1,6 +1,8 @@
 use std::{
-    collections::{BTreeMap, HashMap},
-    mem,
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fmt, mem,
+    str::FromStr,
+    sync::Arc,
     time::{Duration, SystemTime},
 };
 
@@ -8,25 +10,42 @@ use anyhow::{anyhow, Context};
 use penumbra_chain::params::ChainParams;
 use penumbra_crypto::{
     asset::{self, Denom},
+    ka,
+    keys::{FullViewingKey, SeedPhrase},
     memo,
-    merkle::{Frontier, NoteCommitmentTree, Tree, TreeExt},
-    note, Address, FieldExt, Note, Nullifier, Value,
+    merkle::{self, Frontier, NoteCommitmentTree, Tree, TreeExt},
+    note, value, Address, FieldExt, Note, Nullifier, Value,
 };
-use penumbra_proto::light_wallet::{CompactBlock, StateFragment};
-use penumbra_stake::{RateData, STAKING_TOKEN_ASSET_ID, STAKING_TOKEN_DENOM};
-use penumbra_transaction::Transaction;
+use penumbra_proto::light_wallet::{
+    CompactBlock, QuarantinedNullifier, QuarantinedStateFragment, StateFragment,
+};
+use penumbra_stake::{
+    Delegate, DelegationToken, Epoch, IdentityKey, RateData, Undelegate, STAKING_TOKEN_ASSET_ID,
+    STAKING_TOKEN_DENOM,
+};
+use penumbra_transaction::{Action, Builder, Transaction, TransactionPlan};
 use rand::seq::SliceRandom;
 use rand_core::{CryptoRng, RngCore};
+use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
 use tracing::instrument;
 
-use crate::Wallet;
+use crate::{NoteSelection, NoteSelector, Wallet};
+
+pub(crate) mod storage;
+#[cfg(test)]
+mod tests;
 
 const MAX_MERKLE_CHECKPOINTS_CLIENT: usize = 10;
 
-/// The time after which a locally cached submitted transaction is considered to have failed.
+/// The time after which a locally cached submitted transaction is considered to have failed, if
+/// it isn't tracked until its expiry height.
 const SUBMITTED_TRANSACTION_TIMEOUT: Duration = Duration::from_secs(60);
 
+/// The number of blocks after the last synced height during which a transaction we build can be
+/// included in a block, after which it expires.
+const TRANSACTION_EXPIRY_BLOCKS: u64 = 100;
+
 /// State about the chain and our transactions.
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(
@@ -36,6 +55,9 @@ const SUBMITTED_TRANSACTION_TIMEOUT: Duration = Duration::from_secs(60);
 pub struct ClientState {
     /// The last block height we've scanned to, if any.
     last_block_height: Option<u64>,
+    /// The height before which no block can hold notes of ours, if known: earlier blocks are only
+    /// added to the note commitment tree, without trial-decrypting them.
+    birthday_height: Option<u64>,
     /// Note commitment tree.
     note_commitment_tree: NoteCommitmentTree,
     /// Our nullifiers and the notes they correspond to.
@@ -48,14 +70,206 @@ pub struct ClientState {
     submitted_change_set: BTreeMap<note::Commitment, (SystemTime, Note)>,
     /// Notes that we have spent.
     spent_set: BTreeMap<note::Commitment, Note>,
+    /// Notes that we have received from an undelegation, which are quarantined until their
+    /// unbonding height.
+    quarantined_set: BTreeMap<note::Commitment, QuarantinedNote>,
+    /// Notes in the spent set whose spend is quarantined until its unbonding height, and which
+    /// will be returned to the unspent set if the validator is slashed before then.
+    quarantined_spent_set: BTreeMap<note::Commitment, (u64, IdentityKey)>,
+    /// Quarantined notes that were reverted because their validator was slashed.
+    reverted_set: BTreeMap<note::Commitment, QuarantinedNote>,
+    /// The heights at which our notes were received and, if they have been, spent.
+    note_heights: BTreeMap<note::Commitment, (u64, Option<u64>)>,
+    /// The validator exchange rates at which our delegation notes were received and, if they have
+    /// been, spent.
+    delegation_rates: BTreeMap<note::Commitment, (Option<u64>, Option<u64>)>,
+    /// The memos attached to notes we have received, for those notes which have a memo.
+    memos: BTreeMap<note::Commitment, memo::MemoPlaintext>,
+    /// The accounts our notes were received by. Notes with no entry belong to account 0.
+    note_accounts: BTreeMap<note::Commitment, u64>,
+    /// Labels given to notes by the user, stored only locally.
+    note_labels: BTreeMap<note::Commitment, String>,
+    /// Notes frozen by the user, which can't be spent until they are unfrozen.
+    frozen_notes: BTreeSet<note::Commitment>,
+    /// Notes that we have sent, including change sent to ourselves, recovered using our outgoing
+    /// viewing key.
+    sent_set: BTreeMap<note::Commitment, SentNote>,
+    /// The transactions which involved our notes, keyed by the height at which they were included
+    /// and their transaction ID.
+    transaction_history: BTreeMap<(u64, [u8; 32]), TransactionRecord>,
+    /// Details of the transactions we have built which can't be learned by scanning, keyed by
+    /// transaction ID.
+    transaction_details: BTreeMap<[u8; 32], TransactionDetails>,
+    /// The transactions we have submitted, keyed by transaction ID, tracked until they are
+    /// confirmed or expire.
+    submitted_transactions: BTreeMap<[u8; 32], SubmittedTransaction>,
     /// Map of note commitment to full transaction data for transactions we have visibility into.
     transactions: BTreeMap<note::Commitment, Option<Vec<u8>>>,
     /// Map of asset IDs to (raw) asset denominations.
     asset_cache: asset::Cache,
-    /// Key material.
-    wallet: Wallet,
+    /// The seed phrase our accounts are derived from, if the wallet was created from one.
+    seed_phrase: Option<SeedPhrase>,
+    /// Key material for each of our accounts, in order of account index.
+    accounts: Vec<Account>,
     /// Global chain parameters. May not have been fetched yet.
     chain_params: Option<ChainParams>,
+    /// The address book: addresses and validators named by the user, stored only locally.
+    contacts: BTreeMap<String, Contact>,
+    /// The height and hash of the latest block header verified by the light client, or trusted by
+    /// the user, from which the next verification starts.
+    trusted_header: Option<(u64, [u8; 32])>,
+    /// Validator rates which the node didn't have when we last asked for them, with the index of
+    /// the epoch we had synced to then, so that they aren't asked for again until a later epoch.
+    unavailable_rates: BTreeMap<(IdentityKey, u64), u64>,
+    /// The shape every transaction we build is padded to with dummy spends and outputs. Not
+    /// persisted.
+    transaction_shape: TransactionShape,
+    /// The strategy used to choose which notes to spend. Not persisted.
+    note_selector: Arc<dyn NoteSelector>,
+    /// Whether notes received by different addresses may be spent together, linking those
+    /// addresses. Not persisted.
+    link_addresses: bool,
+    /// The parts of the state changed since it was last written to storage. Not persisted.
+    changes: Changes,
+}
+
+/// The minimum number of spends and outputs in every transaction the wallet builds.
+///
+/// Transactions with fewer are padded with dummy spends and outputs of zero-value notes, so that
+/// sends, delegations and sweeps of up to this size can't be told apart by their arity once proofs
+/// are zero-knowledge. Under the current transparent proofs, padding is cosmetic.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TransactionShape {
+    pub min_spends: usize,
+    pub min_outputs: usize,
+}
+
+impl Default for TransactionShape {
+    fn default() -> Self {
+        Self {
+            min_spends: 2,
+            min_outputs: 2,
+        }
+    }
+}
+
+/// A payment of some values to one recipient.
+#[derive(Clone, Debug)]
+pub struct Payment {
+    /// The address to send the values to.
+    pub address: Address,
+    /// The values to send.
+    pub values: Vec<Value>,
+    /// The text of the memo attached to the payment's outputs, if any.
+    pub memo: Option<String>,
+}
+
+/// The notes a transaction may spend.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum NoteSource {
+    /// Any notes of the account, chosen by the configured [`NoteSelector`].
+    Any,
+    /// Notes received by the address of the account with the given index, chosen by the
+    /// configured [`NoteSelector`].
+    Address(u64),
+    /// Exactly the notes with the given commitments.
+    Notes(Vec<note::Commitment>),
+}
+
+impl NoteSource {
+    /// Returns the index of the address to spend notes from, if restricted to one.
+    fn address(&self) -> Option<u64> {
+        match self {
+            NoteSource::Address(index) => Some(*index),
+            _ => None,
+        }
+    }
+}
+
+impl Default for NoteSource {
+    fn default() -> Self {
+        NoteSource::Any
+    }
+}
+
+/// An entry in the address book.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Contact {
+    /// Someone we send to, by their address.
+    Address(Address),
+    /// A validator we delegate to, by its identity key.
+    Validator(IdentityKey),
+}
+
+impl fmt::Display for Contact {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Contact::Address(address) => fmt::Display::fmt(address, f),
+            Contact::Validator(identity_key) => fmt::Display::fmt(identity_key, f),
+        }
+    }
+}
+
+impl FromStr for Contact {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(address) = s.parse() {
+            Ok(Contact::Address(address))
+        } else if let Ok(identity_key) = s.parse() {
+            Ok(Contact::Validator(identity_key))
+        } else {
+            Err(anyhow!(
+                "{} is neither an address nor a validator identity key",
+                s
+            ))
+        }
+    }
+}
+
+/// The parts of a [`ClientState`] which have changed since it was last written to storage, so
+/// that only those need to be rewritten.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Changes {
+    /// Notes whose entries in any of our note sets have changed.
+    notes: BTreeSet<note::Commitment>,
+    /// Nullifiers which have been added to the nullifier map.
+    nullifiers: BTreeSet<Nullifier>,
+    /// Transaction history entries which have changed.
+    transactions: BTreeSet<(u64, [u8; 32])>,
+    /// Transactions whose details have been recorded.
+    transaction_details: BTreeSet<[u8; 32]>,
+    /// Submitted transactions which have been registered or whose status has changed.
+    submitted_transactions: BTreeSet<[u8; 32]>,
+    /// Whether the asset cache may have changed.
+    assets: bool,
+    /// Whether our accounts, their address labels, or the birthday height may have changed.
+    accounts: bool,
+    /// Whether the chain parameters may have changed.
+    chain_params: bool,
+    /// Whether blocks have been scanned or discarded, changing the sync height and the note
+    /// commitment tree.
+    sync_state: bool,
+    /// Whether the address book may have changed.
+    contacts: bool,
+    /// Whether the trusted header may have changed.
+    trusted_header: bool,
+    /// Whether the validator rates known to be unavailable may have changed.
+    unavailable_rates: bool,
+}
+
+/// One of the accounts of the wallet, each of which has its own spend authority.
+#[derive(Clone, Debug)]
+pub(crate) struct Account {
+    /// A human-readable label for the account.
+    pub(crate) label: String,
+    /// The account's key material and address labels.
+    pub(crate) wallet: Wallet,
+}
+
+impl Account {
+    /// The label of account 0, which every wallet has.
+    pub(crate) const DEFAULT_LABEL: &'static str = "Default";
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -64,6 +278,144 @@ pub enum SubmittedNoteCommitment {
     Spend(note::Commitment),
 }
 
+/// A note produced by an undelegation, which is held in quarantine until its unbonding height.
+#[derive(Clone, Debug)]
+pub struct QuarantinedNote {
+    /// The quarantined note.
+    pub note: Note,
+    /// The height at which the note will be released from quarantine, unless the validator is
+    /// slashed first.
+    pub unbonding_height: u64,
+    /// The validator from which the undelegation was performed.
+    pub validator_identity_key: IdentityKey,
+}
+
+impl QuarantinedNote {
+    /// Returns the index of the epoch at whose end the note will be released from quarantine.
+    pub fn unbonding_epoch(&self, epoch_duration: u64) -> u64 {
+        Epoch::from_height(self.unbonding_height, epoch_duration).index
+    }
+}
+
+/// A note we have sent, recovered using our outgoing viewing key.
+#[derive(Clone, Debug)]
+pub struct SentNote {
+    /// The sent note.
+    pub note: Note,
+    /// The address the note was sent to.
+    pub recipient: Address,
+    /// The height at which the note was included in the chain.
+    pub height: u64,
+    /// The memo attached to the note, if it has one.
+    pub memo: Option<memo::MemoPlaintext>,
+}
+
+/// A transaction we have submitted, and the notes it involves.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SubmittedTransaction {
+    /// Our notes spent by the transaction.
+    pub spent: BTreeSet<note::Commitment>,
+    /// The change notes the transaction sends back to us.
+    pub change: BTreeSet<note::Commitment>,
+    /// The last height at which the transaction can be included in a block, if it expires.
+    pub expiry_height: Option<u64>,
+    /// Whether the transaction has been included in a block, or has expired.
+    pub status: SubmittedTransactionStatus,
+}
+
+impl SubmittedTransaction {
+    /// Whether the transaction is planned or pending, so that it may still be included in a block.
+    pub fn is_unresolved(&self) -> bool {
+        matches!(
+            self.status,
+            SubmittedTransactionStatus::Planned | SubmittedTransactionStatus::Pending
+        )
+    }
+}
+
+/// The status of a transaction we have submitted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SubmittedTransactionStatus {
+    /// The transaction was planned, and the notes it spends set aside, but it has not been
+    /// submitted from this wallet. Its ID is only known once it is authorized, so it is tracked
+    /// by the ID of its plan, until it is submitted, seen in a block, or expires.
+    Planned,
+    /// The transaction has not yet been seen in a block.
+    Pending,
+    /// The transaction was included in the block at the given height.
+    Confirmed { height: u64 },
+    /// The transaction expired without being included in a block, and the notes it spent are
+    /// spendable again.
+    Expired,
+    /// Another transaction spending some of the same notes was included in the block at the given
+    /// height, so this transaction can never be.
+    Conflicted { height: u64 },
+}
+
+/// The notes involved in a transaction, as recorded while scanning.
+#[derive(Clone, Debug, Default)]
+pub struct TransactionRecord {
+    /// Notes we received in the transaction.
+    pub received: BTreeSet<note::Commitment>,
+    /// Our notes spent by the transaction.
+    pub spent: BTreeSet<note::Commitment>,
+    /// Notes we sent in the transaction, including change sent to ourselves.
+    pub sent: BTreeSet<note::Commitment>,
+}
+
+/// Details of a transaction we built, which can't be recovered by scanning the chain.
+#[derive(Clone, Debug)]
+pub struct TransactionDetails {
+    /// The fee paid by the transaction.
+    pub fee: u64,
+    /// The delegations performed by the transaction.
+    pub delegations: Vec<Delegate>,
+    /// The undelegations performed by the transaction.
+    pub undelegations: Vec<Undelegate>,
+}
+
+/// An entry in our transaction history, with the notes involved resolved from our note sets.
+#[derive(Clone, Debug)]
+pub struct TransactionHistoryEntry<'a> {
+    /// The height at which the transaction was included.
+    pub height: u64,
+    /// The ID of the transaction.
+    pub transaction_id: [u8; 32],
+    /// Notes we received in the transaction.
+    pub received: Vec<(note::Commitment, &'a Note)>,
+    /// Our notes spent by the transaction.
+    pub spent: Vec<(note::Commitment, &'a Note)>,
+    /// Notes we sent in the transaction, including change sent to ourselves.
+    pub sent: Vec<(note::Commitment, &'a SentNote)>,
+    /// The memo of the transaction: either that of a note we received, or of a note we sent.
+    pub memo: Option<&'a memo::MemoPlaintext>,
+    /// Details of the transaction, if we built it.
+    pub details: Option<&'a TransactionDetails>,
+}
+
+/// A delegation note we have received, along with the validator exchange rates at which it was
+/// received and spent.
+///
+/// Delegations and undelegations are priced at the rate for the epoch following the one in which
+/// they are included, so the epochs recorded here are those whose rates apply.
+#[derive(Clone, Debug)]
+pub struct DelegationRecord<'a> {
+    /// The note commitment of the delegation note.
+    pub note_commitment: note::Commitment,
+    /// The delegation note.
+    pub note: &'a Note,
+    /// The delegation token of the note.
+    pub delegation_token: DelegationToken,
+    /// The index of the epoch whose rate applied when the note was received.
+    pub received_epoch: u64,
+    /// The validator exchange rate at which the note was received, if it has been recorded.
+    pub received_exchange_rate: Option<u64>,
+    /// The index of the epoch whose rate applied when the note was spent, if it has been spent.
+    pub spent_epoch: Option<u64>,
+    /// The validator exchange rate at which the note was spent, if it has been recorded.
+    pub spent_exchange_rate: Option<u64>,
+}
+
 #[derive(Clone, Debug)]
 /// A note which has not yet been confirmed on the chain as spent.
 pub enum UnspentNote<'a> {
@@ -76,6 +428,9 @@ pub enum UnspentNote<'a> {
     /// A note which resulted as predicted change from a spend transaction, but which has not
     /// yet been confirmed on the chain (so we cannot spend it yet).
     SubmittedChange(&'a Note),
+    /// A note which resulted from an undelegation and is quarantined until its unbonding height
+    /// (so we cannot spend it yet, and will lose it if the validator is slashed before then).
+    Quarantined(&'a QuarantinedNote),
 }
 
 impl<'a> UnspentNote<'a> {
@@ -94,27 +449,291 @@ impl AsRef<Note> for UnspentNote<'_> {
             UnspentNote::Ready(note) => note,
             UnspentNote::SubmittedSpend(note) => note,
             UnspentNote::SubmittedChange(note) => note,
+            UnspentNote::Quarantined(quarantined) => &quarantined.note,
         }
     }
 }
 
+/// A compact block which has been trial-decrypted with our viewing keys, ready to be scanned by
+/// [`ClientState::scan_decrypted_block`].
+///
+/// Trial decryption depends only on the wallet's viewing keys and not on the rest of the client
+/// state, so it can be done for many blocks in parallel ahead of the sequential scan which updates
+/// the note commitment tree and our note sets.
+#[derive(Clone, Debug)]
+pub struct DecryptedBlock {
+    /// The compact block.
+    block: CompactBlock,
+    /// The result of decrypting each of the block's fragments, in the same order.
+    fragments: Vec<DecryptedFragment>,
+    /// The note decrypted from each of the block's quarantined fragments and the account which
+    /// received it, if it was ours, in the same order.
+    quarantined_fragments: Vec<Option<(u64, Note)>>,
+}
+
+/// The result of trial-decrypting a single state fragment.
+#[derive(Clone, Debug)]
+struct DecryptedFragment {
+    /// The note commitment of the fragment.
+    note_commitment: note::Commitment,
+    /// The note, its recipient, and its memo, if it has one, if we sent it.
+    sent: Option<(Note, Address, Option<memo::MemoPlaintext>)>,
+    /// The account which received the note, the note, and its memo, if it has one, if we received
+    /// it.
+    received: Option<(u64, Note, Option<memo::MemoPlaintext>)>,
+}
+
+impl DecryptedBlock {
+    /// Trial-decrypts all the fragments of the given compact block with the viewing keys of each
+    /// of the given accounts, in parallel.
+    ///
+    /// The accounts must be given in order of account index, as returned by
+    /// [`ClientState::account_wallets`].
+    pub fn new(block: CompactBlock, accounts: &[Wallet]) -> Result<Self, anyhow::Error> {
+        let fragments = block
+            .fragments
+            .par_iter()
+            .map(|fragment| DecryptedFragment::new(fragment, accounts))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let quarantined_fragments = block
+            .quarantined_fragments
+            .par_iter()
+            .map(
+                |quarantined| -> Result<Option<(u64, Note)>, anyhow::Error> {
+                    let StateFragment {
+                        ephemeral_key,
+                        encrypted_note,
+                        ..
+                    } = quarantined
+                        .fragment
+                        .as_ref()
+                        .ok_or_else(|| anyhow!("missing quarantined state fragment"))?;
+                    let ephemeral_key = ephemeral_key
+                        .as_ref()
+                        .try_into()
+                        .context("invalid ephemeral key")?;
+                    Ok(accounts.iter().enumerate().find_map(|(account, wallet)| {
+                        Note::decrypt(
+                            encrypted_note.as_ref(),
+                            wallet.incoming_viewing_key(),
+                            &ephemeral_key,
+                        )
+                        .ok()
+                        .map(|note| (account as u64, note))
+                    }))
+                },
+            )
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self {
+            block,
+            fragments,
+            quarantined_fragments,
+        })
+    }
+
+    /// Wraps the given compact block without trial-decrypting it, for blocks before the wallet's
+    /// birthday height: scanning it only adds its note commitments to the note commitment tree.
+    pub fn without_decryption(block: CompactBlock) -> Result<Self, anyhow::Error> {
+        let fragments = block
+            .fragments
+            .iter()
+            .map(|fragment| {
+                Ok(DecryptedFragment {
+                    note_commitment: fragment
+                        .note_commitment
+                        .as_ref()
+                        .try_into()
+                        .context("invalid note commitment")?,
+                    sent: None,
+                    received: None,
+                })
+            })
+            .collect::<Result<Vec<_>, anyhow::Error>>()?;
+        let quarantined_fragments = vec![None; block.quarantined_fragments.len()];
+
+        Ok(Self {
+            block,
+            fragments,
+            quarantined_fragments,
+        })
+    }
+
+    /// Returns the height of the block.
+    pub fn height(&self) -> u64 {
+        self.block.height
+    }
+}
+
+impl DecryptedFragment {
+    fn new(
+        StateFragment {
+            note_commitment,
+            ephemeral_key,
+            encrypted_note,
+            encrypted_memo,
+            value_commitment,
+            ovk_wrapped_key,
+            ..
+        }: &StateFragment,
+        accounts: &[Wallet],
+    ) -> Result<Self, anyhow::Error> {
+        let note_commitment = note_commitment
+            .as_ref()
+            .try_into()
+            .context("invalid note commitment")?;
+        let ephemeral_key: ka::Public = ephemeral_key
+            .as_ref()
+            .try_into()
+            .context("invalid ephemeral key")?;
+
+        // Notes which were not created by an output (genesis and reward notes) have no memo.
+        let encrypted_memo = if encrypted_memo.is_empty() {
+            None
+        } else {
+            Some(memo::MemoCiphertext(
+                encrypted_memo[..]
+                    .try_into()
+                    .context("invalid encrypted memo")?,
+            ))
+        };
+        let nonempty_memo = |decrypted: Result<memo::MemoPlaintext, anyhow::Error>| match decrypted
+        {
+            Ok(memo) if !memo.is_empty() => Some(memo),
+            Ok(_) => None,
+            Err(e) => {
+                tracing::warn!(?note_commitment, error = ?e, "could not decrypt memo");
+                None
+            }
+        };
+
+        // Try to recover the note and its memo using the persistent outgoing viewing key of each
+        // account -- if it decrypts, we sent it. Notes which were not created by an output have no
+        // wrapped key, and can't have been sent by anyone.
+        let sent = if ovk_wrapped_key.is_empty() {
+            None
+        } else {
+            let value_commitment: value::Commitment = value_commitment
+                .as_ref()
+                .try_into()
+                .context("invalid value commitment")?;
+
+            accounts.iter().find_map(|wallet| {
+                let (note, recipient) = Note::decrypt_outgoing(
+                    encrypted_note.as_ref(),
+                    ovk_wrapped_key.as_ref(),
+                    note_commitment,
+                    value_commitment,
+                    wallet.outgoing_viewing_key(),
+                    &ephemeral_key,
+                )
+                .ok()?;
+                let memo = encrypted_memo.clone().and_then(|encrypted_memo| {
+                    nonempty_memo(memo::MemoPlaintext::decrypt_outgoing(
+                        encrypted_memo,
+                        ovk_wrapped_key.as_ref(),
+                        note_commitment,
+                        value_commitment,
+                        wallet.outgoing_viewing_key(),
+                        &ephemeral_key,
+                    ))
+                });
+                Some((note, recipient, memo))
+            })
+        };
+
+        // Try to decrypt the encrypted note and its memo using the ephemeral key and the
+        // persistent incoming viewing key of each account -- if it doesn't decrypt, it wasn't
+        // meant for us.
+        let received = accounts.iter().enumerate().find_map(|(account, wallet)| {
+            let note = Note::decrypt(
+                encrypted_note.as_ref(),
+                wallet.incoming_viewing_key(),
+                &ephemeral_key,
+            )
+            .ok()?;
+            let memo = encrypted_memo.clone().and_then(|encrypted_memo| {
+                nonempty_memo(memo::MemoPlaintext::decrypt(
+                    encrypted_memo,
+                    wallet.incoming_viewing_key(),
+                    &ephemeral_key,
+                ))
+            });
+            Some((account as u64, note, memo))
+        });
+
+        Ok(Self {
+            note_commitment,
+            sent,
+            received,
+        })
+    }
+}
+
 impl ClientState {
+    /// Creates a new client state with a single account, whose key material is the given wallet.
+    ///
+    /// No further accounts can be derived for a client state created this way; use
+    /// [`ClientState::from_seed_phrase`] to be able to create them.
     pub fn new(wallet: Wallet) -> Self {
         Self {
             last_block_height: None,
+            birthday_height: None,
             note_commitment_tree: NoteCommitmentTree::new(MAX_MERKLE_CHECKPOINTS_CLIENT),
             nullifier_map: BTreeMap::new(),
             unspent_set: BTreeMap::new(),
             submitted_spend_set: BTreeMap::new(),
             submitted_change_set: BTreeMap::new(),
             spent_set: BTreeMap::new(),
+            quarantined_set: BTreeMap::new(),
+            quarantined_spent_set: BTreeMap::new(),
+            reverted_set: BTreeMap::new(),
+            note_heights: BTreeMap::new(),
+            delegation_rates: BTreeMap::new(),
+            memos: BTreeMap::new(),
+            note_accounts: BTreeMap::new(),
+            note_labels: BTreeMap::new(),
+            frozen_notes: BTreeSet::new(),
+            sent_set: BTreeMap::new(),
+            transaction_history: BTreeMap::new(),
+            transaction_details: BTreeMap::new(),
+            submitted_transactions: BTreeMap::new(),
             transactions: BTreeMap::new(),
             asset_cache: Default::default(),
-            wallet,
+            seed_phrase: None,
+            accounts: vec![Account {
+                label: Account::DEFAULT_LABEL.to_string(),
+                wallet,
+            }],
             chain_params: None,
+            contacts: BTreeMap::new(),
+            trusted_header: None,
+            unavailable_rates: BTreeMap::new(),
+            transaction_shape: Default::default(),
+            note_selector: Arc::new(NoteSelection::default()),
+            link_addresses: false,
+            changes: Default::default(),
         }
     }
 
+    /// Creates a new client state whose accounts are derived from the given seed phrase, starting
+    /// with account 0.
+    pub fn from_seed_phrase(seed_phrase: SeedPhrase) -> Self {
+        let mut state = Self::new(Wallet::from_seed_phrase(seed_phrase.clone(), 0));
+        state.seed_phrase = Some(seed_phrase);
+        state
+    }
+
+    /// Creates a new watch-only client state for the given full viewing key.
+    ///
+    /// The client state scans the chain and tracks the balance and history of the key's single
+    /// account, and can plan transactions, but can't authorize them: transaction plans must be
+    /// signed by the holder of the spend key, see [`TransactionPlan`].
+    pub fn from_full_viewing_key(full_viewing_key: FullViewingKey) -> Self {
+        Self::new(Wallet::from_full_viewing_key(full_viewing_key))
+    }
+
     /// Returns a reference to the note commitment tree.
     pub fn note_commitment_tree(&self) -> &NoteCommitmentTree {
         &self.note_commitment_tree
@@ -127,12 +746,143 @@ impl ClientState {
 
     /// Returns a mutable reference to the client state's asset cache.
     pub fn asset_cache_mut(&mut self) -> &mut asset::Cache {
+        self.changes.assets = true;
         &mut self.asset_cache
     }
 
-    /// Returns the wallet the state is tracking.
-    pub fn wallet(&self) -> &Wallet {
-        &self.wallet
+    /// Returns the wallet of the account with the given index.
+    pub fn account(&self, account: u64) -> Result<&Wallet, anyhow::Error> {
+        self.accounts
+            .get(account as usize)
+            .map(|account| &account.wallet)
+            .ok_or_else(|| anyhow!("no account with index {}", account))
+    }
+
+    /// Returns an iterator over our accounts, as `(index, label, wallet)` triples.
+    pub fn accounts(&self) -> impl Iterator<Item = (u64, &str, &Wallet)> + '_ {
+        self.accounts
+            .iter()
+            .enumerate()
+            .map(|(index, account)| (index as u64, account.label.as_str(), &account.wallet))
+    }
+
+    /// Returns the height before which no block can hold notes of ours, if known.
+    pub fn birthday_height(&self) -> Option<u64> {
+        self.birthday_height
+    }
+
+    /// Sets the height before which no block can hold notes of ours.
+    ///
+    /// Blocks before the birthday height are not trial-decrypted, so notes received in them are
+    /// not found. It should be set before the wallet first syncs; to change it afterwards, use
+    /// [`ClientState::rescan`].
+    pub fn set_birthday_height(&mut self, height: u64) {
+        self.birthday_height = Some(height);
+        self.changes.accounts = true;
+    }
+
+    /// Returns true if the block at the given height is before the birthday height, so that it
+    /// need not be trial-decrypted.
+    pub fn is_before_birthday(&self, height: u64) -> bool {
+        self.birthday_height
+            .map_or(false, |birthday_height| height < birthday_height)
+    }
+
+    /// Discards everything learned by scanning the chain, so that the next sync scans it again
+    /// from the start, trial-decrypting the blocks from height `from` onwards, or from the
+    /// birthday height if it isn't given. A given height becomes the new birthday height.
+    ///
+    /// Keys, accounts and their address labels, the user's note labels and frozen notes, and the
+    /// details of the transactions we built are kept.
+    ///
+    /// Since the notes in blocks before the birthday height are never found, `from` can't be
+    /// after the earliest block we have found notes or transactions of ours in.
+    pub fn rescan(&mut self, from: Option<u64>) -> Result<(), anyhow::Error> {
+        if let Some(height) = from {
+            if let Some(earliest_height) = self.earliest_note_height() {
+                if height > earliest_height {
+                    return Err(anyhow!(
+                        "the wallet has notes from height {}, so it can't be rescanned from the \
+                        later height {} without losing them",
+                        earliest_height,
+                        height
+                    ));
+                }
+            }
+            self.set_birthday_height(height);
+        }
+
+        self.last_block_height = None;
+        self.note_commitment_tree = NoteCommitmentTree::new(MAX_MERKLE_CHECKPOINTS_CLIENT);
+        self.changes.sync_state = true;
+        self.transactions.clear();
+
+        // Record everything discarded as changed, so that it is removed from storage too.
+        let mut notes = BTreeSet::new();
+        notes.extend(mem::take(&mut self.unspent_set).into_keys());
+        notes.extend(mem::take(&mut self.submitted_spend_set).into_keys());
+        notes.extend(mem::take(&mut self.submitted_change_set).into_keys());
+        notes.extend(mem::take(&mut self.spent_set).into_keys());
+        notes.extend(mem::take(&mut self.quarantined_set).into_keys());
+        notes.extend(mem::take(&mut self.quarantined_spent_set).into_keys());
+        notes.extend(mem::take(&mut self.reverted_set).into_keys());
+        notes.extend(mem::take(&mut self.note_heights).into_keys());
+        notes.extend(mem::take(&mut self.delegation_rates).into_keys());
+        notes.extend(mem::take(&mut self.memos).into_keys());
+        notes.extend(mem::take(&mut self.note_accounts).into_keys());
+        notes.extend(mem::take(&mut self.sent_set).into_keys());
+        self.changes.notes.extend(notes);
+        self.changes
+            .nullifiers
+            .extend(mem::take(&mut self.nullifier_map).into_keys());
+        self.changes
+            .transactions
+            .extend(mem::take(&mut self.transaction_history).into_keys());
+
+        Ok(())
+    }
+
+    /// Returns the height of the earliest block in which we have found notes or transactions of
+    /// ours, if any.
+    fn earliest_note_height(&self) -> Option<u64> {
+        let received = self.note_heights.values().map(|(height, _)| *height);
+        let transactions = self.transaction_history.keys().map(|(height, _)| *height);
+        received.chain(transactions).min()
+    }
+
+    /// Returns the wallets of all our accounts, in order of account index, for trial decryption
+    /// with [`DecryptedBlock::new`].
+    pub fn account_wallets(&self) -> Vec<Wallet> {
+        self.accounts
+            .iter()
+            .map(|account| account.wallet.clone())
+            .collect()
+    }
+
+    /// Derives the next account from the wallet's seed phrase, returning its index.
+    ///
+    /// Notes sent to the new account before the wallet had synced up to the point it was created
+    /// are not found until the wallet is rescanned with [`ClientState::rescan`].
+    pub fn new_account(&mut self, label: String) -> Result<u64, anyhow::Error> {
+        let seed_phrase = self.seed_phrase.clone().ok_or_else(|| {
+            anyhow!("the wallet was not created from a seed phrase, so it has only one account")
+        })?;
+        let index = self.accounts.len() as u64;
+
+        self.changes.accounts = true;
+        self.accounts.push(Account {
+            label,
+            wallet: Wallet::from_seed_phrase(seed_phrase, index),
+        });
+        Ok(index)
+    }
+
+    /// Returns the account which received the note with the given commitment.
+    fn note_account(&self, note_commitment: &note::Commitment) -> u64 {
+        self.note_accounts
+            .get(note_commitment)
+            .copied()
+            .unwrap_or_default()
     }
 
     /// Returns the global chain parameters.
@@ -142,12 +892,17 @@ impl ClientState {
 
     /// Returns a mutable reference to the global chain parameters.
     pub fn chain_params_mut(&mut self) -> &mut Option<ChainParams> {
+        self.changes.chain_params = true;
         &mut self.chain_params
     }
 
-    /// Returns a mutable reference to the wallet the state is tracking.
-    pub fn wallet_mut(&mut self) -> &mut Wallet {
-        &mut self.wallet
+    /// Returns a mutable reference to the wallet of the account with the given index.
+    pub fn account_mut(&mut self, account: u64) -> Result<&mut Wallet, anyhow::Error> {
+        self.changes.accounts = true;
+        self.accounts
+            .get_mut(account as usize)
+            .map(|account| &mut account.wallet)
+            .ok_or_else(|| anyhow!("no account with index {}", account))
     }
 
     /// Register a change note.
@@ -160,11 +915,18 @@ impl ClientState {
     ///
     /// This registration is temporary; if the note is not observed on-chain
     /// before some timeout, it will be forgotten.
-    pub fn register_change(&mut self, note: Note) {
+    pub fn register_change(&mut self, account: u64, note: Note) {
         let timeout = SystemTime::now() + SUBMITTED_TRANSACTION_TIMEOUT;
         let commitment = note.commit();
 
-        tracing::debug!(?commitment, value = ?note.value(), "adding note to submitted change set");
+        tracing::debug!(
+            ?commitment,
+            value = ?note.value(),
+            account,
+            "adding note to submitted change set"
+        );
+        self.changes.notes.insert(commitment);
+        self.note_accounts.insert(commitment, account);
         self.submitted_change_set
             .insert(commitment, (timeout, note));
     }
@@ -183,6 +945,7 @@ impl ClientState {
         tracing::debug!(?commitment, value = ?note.value(), "moving note from unspent set to submitted spend set");
         let note = self.unspent_set.remove(&commitment).unwrap();
         let timeout = SystemTime::now() + SUBMITTED_TRANSACTION_TIMEOUT;
+        self.changes.notes.insert(commitment);
         self.submitted_spend_set.insert(commitment, (timeout, note));
     }
 
@@ -192,64 +955,357 @@ impl ClientState {
     /// The returned notes are removed from the unspent set and marked as having
     /// been spent (pending confirmation) by the chain.
     ///
-    /// If `source_address` is `Some`, restrict to only the notes sent to that
-    /// address.
+    /// Only notes received by the given account are spent. If `source_address` is `Some`,
+    /// restrict to only the notes sent to that address of the account. The notes are chosen by
+    /// the configured [`NoteSelector`], and unless linking addresses has been enabled with
+    /// [`ClientState::set_link_addresses`], they are all notes received by the same address.
     pub fn notes_to_spend<R: CryptoRng + RngCore>(
         &mut self,
         rng: &mut R,
+        account: u64,
         amount: u64,
         denom: &Denom,
         source_address: Option<u64>,
     ) -> Result<Vec<Note>, anyhow::Error> {
         let mut notes_by_address = self
-            .unspent_notes_by_denom_and_address()
+            .unspent_notes_by_denom_and_address(account)
             .remove(denom)
-            .ok_or_else(|| anyhow::anyhow!("no notes of denomination {} found", denom))?;
-
-        let mut notes = if let Some(source) = source_address {
-            notes_by_address.remove(&source).ok_or_else(|| {
+            .ok_or_else(|| {
                 anyhow::anyhow!(
+                    "no notes of denomination {} found in account {}",
+                    denom,
+                    account
+                )
+            })?;
+
+        if let Some(source) = source_address {
+            notes_by_address.retain(|address, _| *address == source);
+            if notes_by_address.is_empty() {
+                return Err(anyhow::anyhow!(
                     "no notes of denomination {} found in address {}",
                     denom,
                     source
-                )
-            })?
-        } else {
-            notes_by_address.values().flatten().cloned().collect()
-        };
+                ));
+            }
+        }
 
-        // Draw notes in a random order, to avoid leaking information via arity.
-        notes.shuffle(rng);
+        // A note is only spendable if it has been confirmed on chain to us (change outputs
+        // cannot be spent yet because they do not have a position), and frozen notes can't be
+        // spent until they are unfrozen:
+        let frozen_notes = &self.frozen_notes;
+        let candidates = notes_by_address
+            .into_iter()
+            .flat_map(|(address, notes)| {
+                notes.into_iter().filter_map(move |note| match note {
+                    UnspentNote::Ready(note) if !frozen_notes.contains(&note.commit()) => {
+                        Some((address, note.clone()))
+                    }
+                    _ => None,
+                })
+            })
+            .collect::<Vec<_>>();
 
-        let mut notes_to_spend = Vec::new();
-        let mut total_spend_value = 0u64;
-        for note in notes.into_iter() {
-            // A note is only spendable if it has been confirmed on chain to us (change outputs
-            // cannot be spent yet because they do not have a position):
-            if let UnspentNote::Ready(note) = note {
-                notes_to_spend.push(note.clone());
-                total_spend_value += note.amount();
+        let notes_to_spend = if self.link_addresses || source_address.is_some() {
+            self.note_selector.select(rng, &candidates, amount)
+        } else {
+            // Spending notes received by different addresses together would link those addresses,
+            // so unless the user opts in, select from the notes of one address at a time, visiting
+            // the addresses in a random order.
+            let mut addresses = candidates
+                .iter()
+                .map(|(address, _)| *address)
+                .collect::<Vec<_>>();
+            addresses.dedup();
+            addresses.shuffle(rng);
+            addresses
+                .into_iter()
+                .filter_map(|address| {
+                    let notes = candidates
+                        .iter()
+                        .filter(|(a, _)| *a == address)
+                        .cloned()
+                        .collect::<Vec<_>>();
+                    self.note_selector.select(rng, &notes, amount)
+                })
+                .min_by_key(|notes| notes.len())
+        };
 
-                if total_spend_value >= amount {
-                    break;
+        match notes_to_spend {
+            Some(notes_to_spend) => {
+                // Before returning the notes to the caller, mark them as having been
+                // spent.  (If the caller does not spend them, or the tx fails, etc.,
+                // this state will be erased after the timeout).
+                for note in &notes_to_spend {
+                    self.register_spend(note);
                 }
+
+                Ok(notes_to_spend)
+            }
+            None if !self.link_addresses
+                && candidates
+                    .iter()
+                    .map(|(_, note)| note.amount())
+                    .sum::<u64>()
+                    >= amount =>
+            {
+                Err(anyhow::anyhow!(
+                    "no single address of account {} holds enough {} for the requested spend, \
+                    and spending notes received by different addresses together would link them",
+                    account,
+                    denom
+                ))
             }
+            None => Err(anyhow::anyhow!(
+                "not enough available notes for requested spend"
+            )),
         }
+    }
 
-        if total_spend_value >= amount {
-            // Before returning the notes to the caller, mark them as having been
-            // spent.  (If the caller does not spend them, or the tx fails, etc.,
-            // this state will be erased after the timeout).
-            for note in &notes_to_spend {
-                self.register_spend(note);
+    /// Returns the notes of the given account with the given commitments, if they are all ready
+    /// to spend and not frozen.
+    fn chosen_notes(
+        &self,
+        account: u64,
+        note_commitments: &[note::Commitment],
+    ) -> Result<Vec<Note>, anyhow::Error> {
+        let mut notes = Vec::new();
+        for note_commitment in note_commitments.iter().collect::<BTreeSet<_>>() {
+            let note = self
+                .unspent_set
+                .get(note_commitment)
+                .filter(|_| self.note_account(note_commitment) == account)
+                .ok_or_else(|| {
+                    anyhow!(
+                        "note {} is not a note of account {} which is ready to spend",
+                        note_commitment,
+                        account
+                    )
+                })?;
+            if self.frozen_notes.contains(note_commitment) {
+                return Err(anyhow!(
+                    "note {} is frozen, and must be unfrozen before it can be spent",
+                    note_commitment
+                ));
             }
+            notes.push(note.clone());
+        }
+        Ok(notes)
+    }
 
-            Ok(notes_to_spend)
-        } else {
-            Err(anyhow::anyhow!(
-                "not enough available notes for requested spend"
-            ))
+    /// Returns whether we have a record of the note with the given commitment, in any state.
+    fn has_note(&self, note_commitment: &note::Commitment) -> bool {
+        self.unspent_set.contains_key(note_commitment)
+            || self.submitted_spend_set.contains_key(note_commitment)
+            || self.submitted_change_set.contains_key(note_commitment)
+            || self.spent_set.contains_key(note_commitment)
+            || self.quarantined_set.contains_key(note_commitment)
+            || self.reverted_set.contains_key(note_commitment)
+    }
+
+    /// Returns the label of the given note, if it has one.
+    pub fn note_label(&self, note_commitment: &note::Commitment) -> Option<&str> {
+        self.note_labels.get(note_commitment).map(String::as_str)
+    }
+
+    /// Sets the label of one of our notes, or removes it if `label` is `None`.
+    pub fn set_note_label(
+        &mut self,
+        note_commitment: note::Commitment,
+        label: Option<String>,
+    ) -> Result<(), anyhow::Error> {
+        if !self.has_note(&note_commitment) {
+            return Err(anyhow!("no note with commitment {}", note_commitment));
+        }
+        match label {
+            Some(label) => self.note_labels.insert(note_commitment, label),
+            None => self.note_labels.remove(&note_commitment),
+        };
+        self.changes.notes.insert(note_commitment);
+        Ok(())
+    }
+
+    /// Returns whether the given note is frozen.
+    pub fn is_frozen(&self, note_commitment: &note::Commitment) -> bool {
+        self.frozen_notes.contains(note_commitment)
+    }
+
+    /// Returns an iterator over the commitments of the frozen notes.
+    pub fn frozen_notes(&self) -> impl Iterator<Item = &note::Commitment> + '_ {
+        self.frozen_notes.iter()
+    }
+
+    /// Freezes one of our notes which hasn't been spent, so that it is never chosen to be spent
+    /// automatically, and can't be spent at all until it is unfrozen.
+    pub fn freeze_note(&mut self, note_commitment: note::Commitment) -> Result<(), anyhow::Error> {
+        if !(self.unspent_set.contains_key(&note_commitment)
+            || self.submitted_change_set.contains_key(&note_commitment)
+            || self.quarantined_set.contains_key(&note_commitment))
+        {
+            return Err(anyhow!(
+                "no unspent note with commitment {}",
+                note_commitment
+            ));
+        }
+        self.frozen_notes.insert(note_commitment);
+        self.changes.notes.insert(note_commitment);
+        Ok(())
+    }
+
+    /// Unfreezes a frozen note.
+    pub fn unfreeze_note(
+        &mut self,
+        note_commitment: note::Commitment,
+    ) -> Result<(), anyhow::Error> {
+        if !self.frozen_notes.remove(&note_commitment) {
+            return Err(anyhow!("note {} is not frozen", note_commitment));
+        }
+        self.changes.notes.insert(note_commitment);
+        Ok(())
+    }
+
+    /// Returns the height and hash of the block header from which light-client verification
+    /// starts, if there is one.
+    pub fn trusted_header(&self) -> Option<(u64, [u8; 32])> {
+        self.trusted_header
+    }
+
+    /// Sets the block header from which light-client verification starts, either because the
+    /// user trusts it or because it has been verified from the previous trusted header.
+    pub fn set_trusted_header(&mut self, height: u64, hash: [u8; 32]) {
+        self.trusted_header = Some((height, hash));
+        self.changes.trusted_header = true;
+    }
+
+    /// Returns an iterator over the address book, in order of name.
+    pub fn contacts(&self) -> impl Iterator<Item = (&str, &Contact)> + '_ {
+        self.contacts
+            .iter()
+            .map(|(name, contact)| (name.as_str(), contact))
+    }
+
+    /// Adds a named address or validator to the address book.
+    ///
+    /// Names must be unique, and can't themselves be addresses or identity keys, so that anywhere
+    /// either is accepted a name can be used instead without ambiguity.
+    pub fn add_contact(&mut self, name: String, contact: Contact) -> Result<(), anyhow::Error> {
+        if name.trim().is_empty() || name.trim() != name || name.contains(',') {
+            return Err(anyhow!(
+                "contact names must be non-empty, contain no commas, and not start or end with \
+                whitespace"
+            ));
+        }
+        if name.parse::<Contact>().is_ok() {
+            return Err(anyhow!(
+                "{} can't be used as a contact name, since it is an address or identity key",
+                name
+            ));
+        }
+        if self.contacts.contains_key(&name) {
+            return Err(anyhow!("there is already a contact named {}", name));
         }
+        self.contacts.insert(name, contact);
+        self.changes.contacts = true;
+        Ok(())
+    }
+
+    /// Removes a contact from the address book, returning its address or identity key.
+    pub fn remove_contact(&mut self, name: &str) -> Result<Contact, anyhow::Error> {
+        let contact = self
+            .contacts
+            .remove(name)
+            .ok_or_else(|| anyhow!("there is no contact named {}", name))?;
+        self.changes.contacts = true;
+        Ok(contact)
+    }
+
+    /// Returns the name of the given address or validator in the address book, if it has one.
+    pub fn contact_name(&self, contact: &Contact) -> Option<&str> {
+        self.contacts
+            .iter()
+            .find(|(_, entry)| *entry == contact)
+            .map(|(name, _)| name.as_str())
+    }
+
+    /// Parses an address, or looks it up in the address book by name.
+    pub fn resolve_address(&self, address_or_name: &str) -> Result<Address, anyhow::Error> {
+        match self.contacts.get(address_or_name) {
+            Some(Contact::Address(address)) => Ok(*address),
+            Some(Contact::Validator(_)) => Err(anyhow!(
+                "contact {} is a validator, not an address",
+                address_or_name
+            )),
+            None => address_or_name.parse().map_err(|_| {
+                anyhow!(
+                    "{} is neither a valid address nor the name of a contact",
+                    address_or_name
+                )
+            }),
+        }
+    }
+
+    /// Parses a validator identity key, or looks it up in the address book by name.
+    pub fn resolve_identity_key(
+        &self,
+        identity_key_or_name: &str,
+    ) -> Result<IdentityKey, anyhow::Error> {
+        match self.contacts.get(identity_key_or_name) {
+            Some(Contact::Validator(identity_key)) => Ok(identity_key.clone()),
+            Some(Contact::Address(_)) => Err(anyhow!(
+                "contact {} is an address, not a validator",
+                identity_key_or_name
+            )),
+            None => identity_key_or_name.parse().map_err(|_| {
+                anyhow!(
+                    "{} is neither a valid identity key nor the name of a contact",
+                    identity_key_or_name
+                )
+            }),
+        }
+    }
+
+    /// Sets the strategy used to choose which notes to spend.
+    pub fn set_note_selector<S: NoteSelector + 'static>(&mut self, note_selector: S) {
+        self.note_selector = Arc::new(note_selector);
+    }
+
+    /// Sets whether notes received by different addresses may be spent together, which reveals
+    /// to the recipient that those addresses belong to the same account.
+    pub fn set_link_addresses(&mut self, link_addresses: bool) {
+        self.link_addresses = link_addresses;
+    }
+
+    /// Returns the shape transactions are padded to.
+    pub fn transaction_shape(&self) -> TransactionShape {
+        self.transaction_shape
+    }
+
+    /// Sets the shape transactions are padded to.
+    pub fn set_transaction_shape(&mut self, transaction_shape: TransactionShape) {
+        self.transaction_shape = transaction_shape;
+    }
+
+    /// Pads the transaction being built for the given account with dummy spends and outputs, up
+    /// to the configured [`TransactionShape`].
+    pub fn pad_transaction<R: RngCore + CryptoRng>(
+        &self,
+        rng: &mut R,
+        account: u64,
+        tx_builder: &mut Builder,
+    ) -> Result<(), anyhow::Error> {
+        // Dummy spends must be of notes sent to one of the account's own addresses
+        let (_label, address) = self.account(account)?.address_by_index(0)?;
+        tx_builder.pad(
+            rng,
+            self.transaction_shape.min_spends,
+            self.transaction_shape.min_outputs,
+            &address,
+        );
+        Ok(())
+    }
+
+    /// Returns the last height at which a transaction built now can be included in a block.
+    pub fn transaction_expiry_height(&self) -> u32 {
+        (self.last_block_height.unwrap_or_default() + TRANSACTION_EXPIRY_BLOCKS) as u32
     }
 
     /// Returns the chain id, if the chain parameters are set.
@@ -258,19 +1314,46 @@ impl ClientState {
     }
 
     /// Generate a new transaction delegating stake
-    #[instrument(skip(self, rng, rate_data))]
+    ///
+    /// Once it has been submitted, record it with [`ClientState::register_transaction`].
     pub fn build_delegate<R: RngCore + CryptoRng>(
         &mut self,
         rng: &mut R,
+        account: u64,
         rate_data: RateData,
         unbonded_amount: u64,
         fee: u64,
         source_address: Option<u64>,
     ) -> Result<Transaction, anyhow::Error> {
+        let plan = self.plan_delegate(
+            rng,
+            account,
+            rate_data,
+            unbonded_amount,
+            fee,
+            source_address,
+        )?;
+        self.build_planned(rng, account, &plan)
+    }
+
+    /// Plan a new transaction delegating stake, without authorizing it.
+    ///
+    /// As with [`ClientState::build_delegate`], the notes it spends and creates are registered as
+    /// submitted.
+    #[instrument(skip(self, rng, rate_data))]
+    pub fn plan_delegate<R: RngCore + CryptoRng>(
+        &mut self,
+        rng: &mut R,
+        account: u64,
+        rate_data: RateData,
+        unbonded_amount: u64,
+        fee: u64,
+        source_address: Option<u64>,
+    ) -> Result<TransactionPlan, anyhow::Error> {
         // If the source address is set, send the delegation tokens to the same
         // address; otherwise, send them to the default address.
         let (_label, self_address) = self
-            .wallet()
+            .account(account)?
             .address_by_index(source_address.unwrap_or(0) as usize)?;
 
         let mut tx_builder = Transaction::build_with_root(self.note_commitment_tree.root2());
@@ -278,35 +1361,35 @@ impl ClientState {
         tx_builder
             .set_fee(fee)
             .set_chain_id(self.chain_id().ok_or_else(|| anyhow!("missing chain_id"))?)
-            .add_delegation(&rate_data, unbonded_amount);
+            .set_expiry_height(self.transaction_expiry_height())
+            .add_delegation(&rate_data, unbonded_amount)?;
 
         let spend_amount = unbonded_amount + fee;
         let mut spent_amount = 0;
 
-        for note in self.notes_to_spend(rng, spend_amount, &*STAKING_TOKEN_DENOM, source_address)? {
+        for note in self.notes_to_spend(
+            rng,
+            account,
+            spend_amount,
+            &*STAKING_TOKEN_DENOM,
+            source_address,
+        )? {
             spent_amount += note.amount();
-            tx_builder.add_spend(
-                rng,
-                &self.note_commitment_tree,
-                self.wallet.spend_key(),
-                note,
-            )?;
+            tx_builder.add_spend(rng, &self.note_commitment_tree, note)?;
         }
 
         let delegation_note = tx_builder.add_output_producing_note(
             rng,
             &self_address,
             Value {
-                amount: rate_data.delegation_amount(unbonded_amount),
+                amount: rate_data.delegation_amount(unbonded_amount)?,
                 asset_id: rate_data.identity_key.delegation_token().id(),
             },
             memo::MemoPlaintext([0u8; memo::MEMO_LEN_BYTES]),
-            self.wallet.outgoing_viewing_key(),
         );
 
+        // If there is no change, the transaction is padded with a dummy output in its place.
         let change_amount = spent_amount - spend_amount;
-        // TODO: support dummy notes, and produce a change output unconditionally.
-        // let change_note = if change_amount > 0 { ... } else { /* dummy note */}
         if change_amount > 0 {
             let change_note = tx_builder.add_output_producing_note(
                 rng,
@@ -316,30 +1399,57 @@ impl ClientState {
                     asset_id: *STAKING_TOKEN_ASSET_ID,
                 },
                 memo::MemoPlaintext([0u8; memo::MEMO_LEN_BYTES]),
-                self.wallet.outgoing_viewing_key(),
             );
-            self.register_change(change_note);
+            self.register_change(account, change_note);
         }
 
-        self.register_change(delegation_note);
+        self.register_change(account, delegation_note);
 
-        tx_builder.finalize(rng).map_err(Into::into)
+        self.pad_transaction(rng, account, &mut tx_builder)?;
+        Ok(tx_builder.plan(rng)?)
     }
 
-    /// Generate a new transaction delegating stake
-    #[instrument(skip(self, rng))]
+    /// Generate a new transaction undelegating stake
+    ///
+    /// Once it has been submitted, record it with [`ClientState::register_transaction`].
     pub fn build_undelegate<R: RngCore + CryptoRng>(
         &mut self,
         rng: &mut R,
+        account: u64,
         rate_data: RateData,
         delegation_amount: u64,
         fee: u64,
         source_address: Option<u64>,
     ) -> Result<Transaction, anyhow::Error> {
+        let plan = self.plan_undelegate(
+            rng,
+            account,
+            rate_data,
+            delegation_amount,
+            fee,
+            source_address,
+        )?;
+        self.build_planned(rng, account, &plan)
+    }
+
+    /// Plan a new transaction undelegating stake, without authorizing it.
+    ///
+    /// As with [`ClientState::build_undelegate`], the notes it spends and creates are registered
+    /// as submitted.
+    #[instrument(skip(self, rng))]
+    pub fn plan_undelegate<R: RngCore + CryptoRng>(
+        &mut self,
+        rng: &mut R,
+        account: u64,
+        rate_data: RateData,
+        delegation_amount: u64,
+        fee: u64,
+        source_address: Option<u64>,
+    ) -> Result<TransactionPlan, anyhow::Error> {
         // If the source address is set, send the delegation tokens to the same
         // address; otherwise, send them to the default address.
         let (_label, self_address) = self
-            .wallet()
+            .account(account)?
             .address_by_index(source_address.unwrap_or(0) as usize)?;
 
         let mut tx_builder = Transaction::build_with_root(self.note_commitment_tree.root2());
@@ -347,12 +1457,13 @@ impl ClientState {
         tx_builder
             .set_fee(fee)
             .set_chain_id(self.chain_id().ok_or_else(|| anyhow!("missing chain_id"))?)
-            .add_undelegation(&rate_data, delegation_amount);
+            .set_expiry_height(self.transaction_expiry_height())
+            .add_undelegation(&rate_data, delegation_amount)?;
 
         // Because the outputs of an undelegation are quarantined, we want to
         // avoid any unnecessary change outputs, so we pay fees out of the
         // unbonded amount.
-        let unbonded_amount = rate_data.unbonded_amount(delegation_amount);
+        let unbonded_amount = rate_data.unbonded_amount(delegation_amount)?;
         let output_amount = unbonded_amount.checked_sub(fee).ok_or_else(|| {
             anyhow::anyhow!(
                 "unbonded amount {} from delegation amount {} is insufficient to pay fees {}",
@@ -369,16 +1480,15 @@ impl ClientState {
         // this sucks lmao
         let mut spent_amount = 0;
 
-        for note in
-            self.notes_to_spend(rng, delegation_amount, &delegation_denom, source_address)?
-        {
+        for note in self.notes_to_spend(
+            rng,
+            account,
+            delegation_amount,
+            &delegation_denom,
+            source_address,
+        )? {
             spent_amount += note.amount();
-            tx_builder.add_spend(
-                rng,
-                &self.note_commitment_tree,
-                self.wallet.spend_key(),
-                note,
-            )?;
+            tx_builder.add_spend(rng, &self.note_commitment_tree, note)?;
         }
 
         let output_note = tx_builder.add_output_producing_note(
@@ -389,12 +1499,10 @@ impl ClientState {
                 asset_id: *STAKING_TOKEN_ASSET_ID,
             },
             memo::MemoPlaintext([0u8; memo::MEMO_LEN_BYTES]),
-            self.wallet.outgoing_viewing_key(),
         );
 
+        // If there is no change, the transaction is padded with a dummy output in its place.
         let change_amount = spent_amount - delegation_amount;
-        // TODO: support dummy notes, and produce a change output unconditionally.
-        // let change_note = if change_amount > 0 { ... } else { /* dummy note */}
         if change_amount > 0 {
             let change_note = tx_builder.add_output_producing_note(
                 rng,
@@ -404,88 +1512,144 @@ impl ClientState {
                     asset_id: delegation_denom.id(),
                 },
                 memo::MemoPlaintext([0u8; memo::MEMO_LEN_BYTES]),
-                self.wallet.outgoing_viewing_key(),
             );
-            self.register_change(change_note);
+            self.register_change(account, change_note);
         }
 
-        self.register_change(output_note);
+        self.register_change(account, output_note);
 
-        tx_builder.finalize(rng).map_err(Into::into)
+        self.pad_transaction(rng, account, &mut tx_builder)?;
+        Ok(tx_builder.plan(rng)?)
     }
 
-    /// Generate a new transaction sending value to `dest_address`.
-    #[instrument(skip(self, rng))]
+    /// Generate a new transaction making each of the given payments, spending notes from `source`.
+    ///
+    /// Once it has been submitted, record it with [`ClientState::register_transaction`].
     pub fn build_send<R: RngCore + CryptoRng>(
         &mut self,
         rng: &mut R,
-        values: &[Value],
+        account: u64,
+        payments: &[Payment],
         fee: u64,
-        dest_address: Address,
-        source_address: Option<u64>,
-        tx_memo: Option<String>,
+        source: NoteSource,
     ) -> Result<Transaction, anyhow::Error> {
+        let plan = self.plan_send(rng, account, payments, fee, source)?;
+        self.build_planned(rng, account, &plan)
+    }
+
+    /// Plan a new transaction making each of the given payments, without authorizing it.
+    ///
+    /// As with [`ClientState::build_send`], the notes it spends and creates are registered as
+    /// submitted.
+    #[instrument(skip(self, rng))]
+    pub fn plan_send<R: RngCore + CryptoRng>(
+        &mut self,
+        rng: &mut R,
+        account: u64,
+        payments: &[Payment],
+        fee: u64,
+        source: NoteSource,
+    ) -> Result<TransactionPlan, anyhow::Error> {
         let mut tx_builder = Transaction::build_with_root(self.note_commitment_tree.root2());
 
         tx_builder
             .set_fee(fee)
-            .set_chain_id(self.chain_id().ok_or_else(|| anyhow!("missing chain_id"))?);
-
-        let mut output_value = HashMap::<Denom, u64>::new();
-        for Value { amount, asset_id } in values {
-            let denom = self
-                .asset_cache()
-                .get(asset_id)
-                .ok_or_else(|| anyhow::anyhow!("unknown denomination for asset id {}", asset_id))?;
-            output_value.insert(denom.clone(), *amount);
-        }
+            .set_chain_id(self.chain_id().ok_or_else(|| anyhow!("missing chain_id"))?)
+            .set_expiry_height(self.transaction_expiry_height());
 
-        for (denom, amount) in &output_value {
-            let memo: memo::MemoPlaintext = match tx_memo {
+        // The value we need to spend is the output value, plus fees.
+        let mut value_to_spend = HashMap::<Denom, u64>::new();
+        for payment in payments {
+            let memo: memo::MemoPlaintext = match payment.memo {
                 Some(ref input_memo) => input_memo.clone().try_into()?,
                 None => memo::MemoPlaintext([0u8; memo::MEMO_LEN_BYTES]),
             };
-            tx_builder.add_output(
-                rng,
-                &dest_address,
-                Value {
-                    amount: *amount,
-                    asset_id: denom.id(),
-                },
-                memo,
-                self.wallet.outgoing_viewing_key(),
-            );
-        }
 
-        // The value we need to spend is the output value, plus fees.
-        let mut value_to_spend = output_value;
+            // Each payment has one output for each of its denominations.
+            let mut output_value = BTreeMap::<Denom, u64>::new();
+            for Value { amount, asset_id } in &payment.values {
+                let denom = self.asset_cache().get(asset_id).ok_or_else(|| {
+                    anyhow::anyhow!("unknown denomination for asset id {}", asset_id)
+                })?;
+                let output_amount = output_value.entry(denom.clone()).or_default();
+                *output_amount = output_amount
+                    .checked_add(*amount)
+                    .ok_or_else(|| anyhow!("amount of {} to send overflows", denom))?;
+            }
+
+            for (denom, amount) in output_value {
+                tx_builder.add_output(
+                    rng,
+                    &payment.address,
+                    Value {
+                        amount,
+                        asset_id: denom.id(),
+                    },
+                    memo.clone(),
+                );
+                let total = value_to_spend.entry(denom.clone()).or_default();
+                *total = total
+                    .checked_add(amount)
+                    .ok_or_else(|| anyhow!("total amount of {} to send overflows", denom))?;
+            }
+        }
         if fee > 0 {
             *value_to_spend
                 .entry(STAKING_TOKEN_DENOM.clone())
                 .or_default() += fee;
         }
 
-        for (denom, amount) in value_to_spend {
-            // Only produce an output if the amount is greater than zero
-            if amount == 0 {
-                continue;
+        // Select a list of notes of each denomination that provides at least the required amount.
+        let mut notes_by_denom = BTreeMap::<Denom, Vec<Note>>::new();
+        if let NoteSource::Notes(note_commitments) = &source {
+            for note in self.chosen_notes(account, note_commitments)? {
+                let denom = self
+                    .asset_cache()
+                    .get(&note.asset_id())
+                    .ok_or_else(|| {
+                        anyhow!("unknown denomination for asset id {}", note.asset_id())
+                    })?
+                    .clone();
+                notes_by_denom.entry(denom).or_default().push(note);
+            }
+            for (denom, amount) in &value_to_spend {
+                let spent: u64 = notes_by_denom
+                    .get(denom)
+                    .map_or(0, |notes| notes.iter().map(|note| note.amount()).sum());
+                if spent < *amount {
+                    return Err(anyhow!(
+                        "the notes to spend hold {}{}, but {}{} is needed",
+                        spent,
+                        denom,
+                        amount,
+                        denom
+                    ));
+                }
+            }
+            for note in notes_by_denom.values().flatten() {
+                self.register_spend(note);
+            }
+        } else {
+            for (denom, amount) in &value_to_spend {
+                // Only spend notes if the amount is greater than zero
+                if *amount == 0 {
+                    continue;
+                }
+                let notes = self.notes_to_spend(rng, account, *amount, denom, source.address())?;
+                notes_by_denom.insert(denom.clone(), notes);
             }
+        }
 
-            // Select a list of notes that provides at least the required amount.
-            let notes: Vec<Note> = self.notes_to_spend(rng, amount, &denom, source_address)?;
+        for (denom, notes) in notes_by_denom {
+            let amount = value_to_spend.get(&denom).copied().unwrap_or_default();
             let change_address = self
-                .wallet
+                .account(account)?
                 .change_address(notes.last().expect("spent at least one note"))?;
             let spent: u64 = notes.iter().map(|note| note.amount()).sum();
 
             // Spend each of the notes we selected.
             for note in notes {
-                tx_builder.add_spend(
-                    rng,
-                    &self.note_commitment_tree,
-                    self.wallet.spend_key(),
-                    note,
-                )?;
+                tx_builder.add_spend(rng, &self.note_commitment_tree, note)?;
             }
 
             // Find out how much change we have and whether to add a change output.
@@ -501,39 +1665,59 @@ impl ClientState {
                         asset_id: denom.id(),
                     },
                     memo,
-                    self.wallet.outgoing_viewing_key(),
                 );
 
-                self.register_change(note);
+                self.register_change(account, note);
             }
         }
 
-        let transaction = tx_builder
-            .finalize(rng)
-            .map_err(|err| anyhow::anyhow!("error during transaction finalization: {}", err))?;
+        self.pad_transaction(rng, account, &mut tx_builder)?;
+        tx_builder
+            .plan(rng)
+            .map_err(|err| anyhow::anyhow!("error during transaction planning: {}", err))
+    }
 
-        Ok(transaction)
+    /// Authorize and build a planned transaction with the spend key of the given account.
+    fn build_planned<R: RngCore + CryptoRng>(
+        &mut self,
+        rng: &mut R,
+        account: u64,
+        plan: &TransactionPlan,
+    ) -> Result<Transaction, anyhow::Error> {
+        let spend_key = self.account(account)?.spend_key()?;
+        let auth_data = plan.authorize(rng, spend_key);
+        Builder::finalize_plan(rng, plan, spend_key.full_viewing_key(), &auth_data)
+            .map_err(|err| anyhow::anyhow!("error during transaction finalization: {}", err))
     }
 
-    /// Returns an iterator over unspent `(address_id, denom, note)` triples.
+    /// Returns an iterator over unspent `(account, address_id, denom, note)` tuples.
     ///
     /// Notes are [`UnspentNote`]s, which describe whether the note is ready to spend, part of a
     /// submitted output, or part of submitted change expected to be received.
-    pub fn unspent_notes(&self) -> impl Iterator<Item = (u64, Denom, UnspentNote)> + '_ {
+    pub fn unspent_notes(&self) -> impl Iterator<Item = (u64, u64, Denom, UnspentNote)> + '_ {
         self.unspent_set
-            .values()
-            .map(UnspentNote::Ready)
+            .iter()
+            .map(|(commitment, note)| (commitment, UnspentNote::Ready(note)))
             .chain(
                 self.submitted_spend_set
-                    .values()
-                    .map(|(_, note)| UnspentNote::SubmittedSpend(note)),
+                    .iter()
+                    .map(|(commitment, (_, note))| (commitment, UnspentNote::SubmittedSpend(note))),
             )
             .chain(
                 self.submitted_change_set
-                    .values()
-                    .map(|(_, note)| UnspentNote::SubmittedChange(note)),
+                    .iter()
+                    .map(|(commitment, (_, note))| {
+                        (commitment, UnspentNote::SubmittedChange(note))
+                    }),
             )
-            .map(|note| {
+            .chain(
+                self.quarantined_set
+                    .iter()
+                    .map(|(commitment, quarantined)| {
+                        (commitment, UnspentNote::Quarantined(quarantined))
+                    }),
+            )
+            .map(|(commitment, note)| {
                 // Any notes we have in the unspent set we will have the corresponding denominations
                 // for since the notes and asset registry are both part of the sync.
                 let denom = self
@@ -542,24 +1726,31 @@ impl ClientState {
                     .expect("all asset IDs should have denominations stored locally")
                     .clone();
 
+                let account = self.note_account(commitment);
                 let index: u64 = self
-                    .wallet()
+                    .account(account)
+                    .expect("notes are only recorded for our own accounts")
                     .incoming_viewing_key()
                     .index_for_diversifier(&note.as_ref().diversifier())
                     .try_into()
                     .expect("diversifiers created by `pcli` are well-formed");
 
-                (index, denom, note)
+                (account, index, denom, note)
             })
     }
 
-    /// Returns unspent notes, grouped by address index and then by denomination.
+    /// Returns the unspent notes of the given account, grouped by address index and then by
+    /// denomination.
     pub fn unspent_notes_by_address_and_denom(
         &self,
+        account: u64,
     ) -> BTreeMap<u64, BTreeMap<Denom, Vec<UnspentNote>>> {
         let mut notemap = BTreeMap::default();
 
-        for (index, denom, note) in self.unspent_notes() {
+        for (_, index, denom, note) in self
+            .unspent_notes()
+            .filter(|(note_account, ..)| *note_account == account)
+        {
             notemap
                 .entry(index)
                 .or_insert_with(BTreeMap::default)
@@ -567,26 +1758,473 @@ impl ClientState {
                 .or_insert_with(Vec::default)
                 .push(note.clone());
         }
-
-        notemap
+
+        notemap
+    }
+
+    /// Returns the unspent notes of the given account, grouped by denomination and then by
+    /// address index.
+    pub fn unspent_notes_by_denom_and_address(
+        &self,
+        account: u64,
+    ) -> BTreeMap<Denom, BTreeMap<u64, Vec<UnspentNote>>> {
+        let mut notemap = BTreeMap::default();
+
+        for (_, index, denom, note) in self
+            .unspent_notes()
+            .filter(|(note_account, ..)| *note_account == account)
+        {
+            notemap
+                .entry(denom)
+                .or_insert_with(BTreeMap::default)
+                .entry(index)
+                .or_insert_with(Vec::default)
+                .push(note.clone());
+        }
+
+        notemap
+    }
+
+    /// Returns an iterator over notes received from undelegations which are still quarantined.
+    pub fn quarantined_notes(&self) -> impl Iterator<Item = &QuarantinedNote> + '_ {
+        self.quarantined_set.values()
+    }
+
+    /// Returns an iterator over quarantined notes which were reverted because the validator they
+    /// were undelegated from was slashed before their unbonding height.
+    pub fn reverted_notes(&self) -> impl Iterator<Item = &QuarantinedNote> + '_ {
+        self.reverted_set.values()
+    }
+
+    /// Returns the memo attached to the note we received with the given commitment, if it has one.
+    pub fn memo(&self, note_commitment: &note::Commitment) -> Option<&memo::MemoPlaintext> {
+        self.memos.get(note_commitment)
+    }
+
+    /// Returns an iterator over the notes we have sent, in order of note commitment.
+    ///
+    /// This includes change and other notes we have sent to ourselves.
+    pub fn sent_notes(&self) -> impl Iterator<Item = (&note::Commitment, &SentNote)> + '_ {
+        self.sent_set.iter()
+    }
+
+    /// Returns an iterator over the transactions which involved our notes, in order of height.
+    pub fn transaction_history(&self) -> impl Iterator<Item = TransactionHistoryEntry> + '_ {
+        self.transaction_history
+            .iter()
+            .map(move |(&(height, transaction_id), record)| {
+                let details = self.transaction_details.get(&transaction_id);
+                let received = record
+                    .received
+                    .iter()
+                    .filter_map(|commitment| Some((*commitment, self.received_note(commitment)?)))
+                    .collect::<Vec<_>>();
+                let memo = received
+                    .iter()
+                    .find_map(|(commitment, _)| self.memos.get(commitment))
+                    .or_else(|| {
+                        record
+                            .sent
+                            .iter()
+                            .find_map(|commitment| self.sent_set.get(commitment)?.memo.as_ref())
+                    });
+
+                TransactionHistoryEntry {
+                    height,
+                    transaction_id,
+                    received,
+                    spent: record
+                        .spent
+                        .iter()
+                        .filter_map(|commitment| {
+                            Some((*commitment, self.received_note(commitment)?))
+                        })
+                        .collect(),
+                    sent: record
+                        .sent
+                        .iter()
+                        .filter_map(|commitment| {
+                            Some((*commitment, self.sent_set.get(commitment)?))
+                        })
+                        .collect(),
+                    memo,
+                    details,
+                }
+            })
+    }
+
+    /// Returns the note we received with the given commitment, whether or not it has been spent.
+    fn received_note(&self, note_commitment: &note::Commitment) -> Option<&Note> {
+        self.unspent_set
+            .get(note_commitment)
+            .or_else(|| self.spent_set.get(note_commitment))
+            .or_else(|| {
+                self.submitted_spend_set
+                    .get(note_commitment)
+                    .map(|(_, note)| note)
+            })
+            .or_else(|| {
+                self.quarantined_set
+                    .get(note_commitment)
+                    .or_else(|| self.reverted_set.get(note_commitment))
+                    .map(|quarantined| &quarantined.note)
+            })
+    }
+
+    /// Record the details of a transaction we built and submitted, which we can't learn by
+    /// scanning the chain, and track it until it is included in a block or expires.
+    pub fn register_transaction(&mut self, transaction: &Transaction) {
+        let body = transaction.transaction_body();
+        let mut details = TransactionDetails {
+            fee: body.fee.0,
+            delegations: Vec::new(),
+            undelegations: Vec::new(),
+        };
+        // Track the transaction until its spends or change are seen in a block, or it expires.
+        let (spent, change) = self.built_notes(transaction);
+        let submitted = SubmittedTransaction {
+            spent,
+            change,
+            expiry_height: Some(body.expiry_height as u64).filter(|height| *height > 0),
+            status: SubmittedTransactionStatus::Pending,
+        };
+        for action in body.actions {
+            match action {
+                Action::Delegate(delegate) => details.delegations.push(delegate),
+                Action::Undelegate(undelegate) => details.undelegations.push(undelegate),
+                _ => {}
+            }
+        }
+        // If the transaction was built from a plan we are tracking, track it by its ID instead.
+        let plan_ids = self
+            .submitted_transactions
+            .iter()
+            .filter(|(_, planned)| {
+                planned.status == SubmittedTransactionStatus::Planned
+                    && (!planned.spent.is_disjoint(&submitted.spent)
+                        || !planned.change.is_disjoint(&submitted.change))
+            })
+            .map(|(plan_id, _)| *plan_id)
+            .collect::<Vec<_>>();
+        for plan_id in plan_ids {
+            self.submitted_transactions.remove(&plan_id);
+            self.changes.submitted_transactions.insert(plan_id);
+        }
+
+        self.changes.transaction_details.insert(transaction.id());
+        self.transaction_details.insert(transaction.id(), details);
+        self.changes.submitted_transactions.insert(transaction.id());
+        self.submitted_transactions
+            .insert(transaction.id(), submitted);
+    }
+
+    /// Returns the commitments of the notes of ours spent by a transaction we built, and of the
+    /// change it creates, which we registered as submitted while building it.
+    fn built_notes(
+        &self,
+        transaction: &Transaction,
+    ) -> (BTreeSet<note::Commitment>, BTreeSet<note::Commitment>) {
+        let mut spent = BTreeSet::new();
+        let mut change = BTreeSet::new();
+        for action in &transaction.transaction_body().actions {
+            match action {
+                Action::Spend(spend) => {
+                    if let Some(note_commitment) = self.nullifier_map.get(&spend.body.nullifier) {
+                        spent.insert(*note_commitment);
+                    }
+                }
+                Action::Output(output) => {
+                    let note_commitment = output.body.note_commitment;
+                    if self.submitted_change_set.contains_key(&note_commitment) {
+                        change.insert(note_commitment);
+                    }
+                }
+                _ => {}
+            }
+        }
+        (spent, change)
+    }
+
+    /// Track a transaction plan, which is signed and submitted elsewhere, until its expiry height,
+    /// so that the notes it spends aren't spent again in the meantime. Returns the ID of the plan.
+    ///
+    /// The plan must have been made by one of the `plan_` methods, which registered the notes it
+    /// spends and creates as submitted.
+    pub fn register_plan(&mut self, plan: &TransactionPlan) -> [u8; 32] {
+        use penumbra_proto::Protobuf;
+        use sha2::{Digest, Sha256};
+
+        let mut plan_id = [0; 32];
+        plan_id.copy_from_slice(&Sha256::digest(&plan.encode_to_vec()));
+
+        // Dummy spends and outputs are not in the submitted sets, and so aren't tracked.
+        let submitted = SubmittedTransaction {
+            spent: plan
+                .spend_plans()
+                .map(|spend| spend.note.commit())
+                .filter(|note_commitment| self.submitted_spend_set.contains_key(note_commitment))
+                .collect(),
+            change: plan
+                .output_plans()
+                .map(|output| output.output_note().commit())
+                .filter(|note_commitment| self.submitted_change_set.contains_key(note_commitment))
+                .collect(),
+            expiry_height: Some(plan.expiry_height as u64).filter(|height| *height > 0),
+            status: SubmittedTransactionStatus::Planned,
+        };
+        self.changes.submitted_transactions.insert(plan_id);
+        self.submitted_transactions.insert(plan_id, submitted);
+
+        plan_id
+    }
+
+    /// Returns the transaction we submitted with the given ID, if we are tracking it.
+    pub fn submitted_transaction(
+        &self,
+        transaction_id: &[u8; 32],
+    ) -> Option<&SubmittedTransaction> {
+        self.submitted_transactions.get(transaction_id)
+    }
+
+    /// Returns the height at which the transaction with the given ID was included, if it involved
+    /// our notes and has been scanned.
+    pub fn transaction_height(&self, transaction_id: &[u8; 32]) -> Option<u64> {
+        self.transaction_history
+            .keys()
+            .find(|(_, id)| id == transaction_id)
+            .map(|(height, _)| *height)
+    }
+
+    /// Confirms the pending or planned transactions whose spends or change were seen in the block
+    /// at `height`, and fails those which can no longer be included after it, returning the notes
+    /// they spent to the unspent set.
+    ///
+    /// A pending transaction whose notes were spent by a different transaction in the block is
+    /// marked as conflicted rather than confirmed, and its other notes returned. This is only
+    /// detected when the block records the IDs of the transactions revealing its nullifiers;
+    /// otherwise, and for planned transactions, whose IDs aren't known, any spend of their notes
+    /// confirms them.
+    fn update_submitted_transactions(&mut self, height: u64) {
+        let mut submitted_transactions = mem::take(&mut self.submitted_transactions);
+        for (transaction_id, submitted) in submitted_transactions.iter_mut() {
+            if !submitted.is_unresolved() {
+                continue;
+            }
+
+            let spends_seen = submitted
+                .spent
+                .iter()
+                .any(|note_commitment| self.spent_set.contains_key(note_commitment));
+            let change_seen = submitted.change.iter().any(|note_commitment| {
+                self.unspent_set.contains_key(note_commitment)
+                    || self.quarantined_set.contains_key(note_commitment)
+                    || self.spent_set.contains_key(note_commitment)
+            });
+            let conflicting = submitted.status == SubmittedTransactionStatus::Pending
+                && self
+                    .transaction_history
+                    .range((height, [0; 32])..=(height, [u8::MAX; 32]))
+                    .any(|((_, spender_id), record)| {
+                        spender_id != transaction_id && !record.spent.is_disjoint(&submitted.spent)
+                    });
+            if conflicting {
+                tracing::debug!(
+                    transaction_id = %hex::encode(transaction_id),
+                    "another transaction spent the notes of a submitted transaction, marking it as conflicted"
+                );
+                self.release_submitted_notes(&submitted.spent, &submitted.change);
+                submitted.status = SubmittedTransactionStatus::Conflicted { height };
+                self.changes.submitted_transactions.insert(*transaction_id);
+            } else if spends_seen || change_seen {
+                tracing::debug!(
+                    transaction_id = %hex::encode(transaction_id),
+                    "found submitted transaction while scanning, marking it as confirmed"
+                );
+                submitted.status = SubmittedTransactionStatus::Confirmed { height };
+                self.changes.submitted_transactions.insert(*transaction_id);
+            } else if submitted
+                .expiry_height
+                .map_or(false, |expiry_height| expiry_height <= height)
+            {
+                tracing::debug!(
+                    transaction_id = %hex::encode(transaction_id),
+                    "submitted transaction expired, putting its spent notes back into the unspent set"
+                );
+                self.release_submitted_notes(&submitted.spent, &submitted.change);
+                submitted.status = SubmittedTransactionStatus::Expired;
+                self.changes.submitted_transactions.insert(*transaction_id);
+            }
+        }
+        self.submitted_transactions = submitted_transactions;
+    }
+
+    /// Returns the given submitted spends which haven't been seen in a block to the unspent set,
+    /// and drops the given submitted change, for a transaction which will never be included.
+    fn release_submitted_notes(
+        &mut self,
+        spent: &BTreeSet<note::Commitment>,
+        change: &BTreeSet<note::Commitment>,
+    ) {
+        for note_commitment in spent {
+            if let Some((_, note)) = self.submitted_spend_set.remove(note_commitment) {
+                self.unspent_set.insert(*note_commitment, note);
+                self.changes.notes.insert(*note_commitment);
+            }
+        }
+        for note_commitment in change {
+            if self.submitted_change_set.remove(note_commitment).is_some() {
+                self.note_accounts.remove(note_commitment);
+                self.changes.notes.insert(*note_commitment);
+            }
+        }
+    }
+
+    /// Forgets a transaction we built but didn't submit, returning the notes it spends to the
+    /// unspent set and dropping the change it would have created, so that they can be used by
+    /// another transaction.
+    pub fn cancel_transaction(&mut self, transaction: &Transaction) {
+        let (spent, change) = self.built_notes(transaction);
+        self.release_submitted_notes(&spent, &change);
+
+        let transaction_id = transaction.id();
+        if self
+            .submitted_transactions
+            .remove(&transaction_id)
+            .is_some()
+        {
+            self.changes.submitted_transactions.insert(transaction_id);
+        }
+        if self.transaction_details.remove(&transaction_id).is_some() {
+            self.changes.transaction_details.insert(transaction_id);
+        }
+    }
+
+    /// Returns the record of the transaction with the given ID included at the given height, if
+    /// the ID is known.
+    fn transaction_record(
+        &mut self,
+        height: u64,
+        transaction_id: Option<[u8; 32]>,
+    ) -> Option<&mut TransactionRecord> {
+        transaction_id.map(|transaction_id| {
+            self.changes.transactions.insert((height, transaction_id));
+            self.transaction_history
+                .entry((height, transaction_id))
+                .or_default()
+        })
+    }
+
+    /// Returns an iterator over the delegation notes we have received, whether or not they have
+    /// been spent.
+    ///
+    /// Notes whose denomination is not yet in the asset cache are not included.
+    pub fn delegation_records(&self) -> impl Iterator<Item = DelegationRecord> + '_ {
+        let epoch_duration = self
+            .chain_params
+            .as_ref()
+            .map(|params| params.epoch_duration);
+
+        self.unspent_set
+            .iter()
+            .chain(self.spent_set.iter())
+            .filter_map(move |(note_commitment, note)| {
+                let epoch_duration = epoch_duration?;
+                let delegation_token =
+                    DelegationToken::try_from(self.asset_cache.get(&note.asset_id())?.clone())
+                        .ok()?;
+                let &(received_height, spent_height) = self.note_heights.get(note_commitment)?;
+                let (received_exchange_rate, spent_exchange_rate) = self
+                    .delegation_rates
+                    .get(note_commitment)
+                    .copied()
+                    .unwrap_or_default();
+
+                Some(DelegationRecord {
+                    note_commitment: *note_commitment,
+                    note,
+                    delegation_token,
+                    received_epoch: Epoch::from_height(received_height, epoch_duration)
+                        .next()
+                        .index,
+                    received_exchange_rate,
+                    spent_epoch: spent_height
+                        .map(|height| Epoch::from_height(height, epoch_duration).next().index),
+                    spent_exchange_rate,
+                })
+            })
+    }
+
+    /// Returns the validators and epoch indices whose rate data is needed to fill in the exchange
+    /// rates of our delegation notes.
+    ///
+    /// Rates recorded as unavailable with [`ClientState::record_unavailable_delegation_rate`] are
+    /// left out until the client state has synced to a later epoch.
+    pub fn missing_delegation_rates(&self) -> BTreeSet<(IdentityKey, u64)> {
+        let mut missing = BTreeSet::new();
+        for record in self.delegation_records() {
+            if record.received_exchange_rate.is_none() {
+                missing.insert((record.delegation_token.validator(), record.received_epoch));
+            }
+            if let (Some(spent_epoch), None) = (record.spent_epoch, record.spent_exchange_rate) {
+                missing.insert((record.delegation_token.validator(), spent_epoch));
+            }
+        }
+        let synced_epoch = self.synced_epoch().map(|epoch| epoch.index);
+        missing.retain(
+            |rate| match (self.unavailable_rates.get(rate), synced_epoch) {
+                (Some(checked_epoch), Some(synced_epoch)) => *checked_epoch < synced_epoch,
+                _ => true,
+            },
+        );
+        missing
     }
 
-    /// Returns unspent notes, grouped by denomination and then by address index.
-    pub fn unspent_notes_by_denom_and_address(
-        &self,
-    ) -> BTreeMap<Denom, BTreeMap<u64, Vec<UnspentNote>>> {
-        let mut notemap = BTreeMap::default();
+    /// Record that the node doesn't have the rate data of the given validator in the given epoch,
+    /// so that it isn't asked for again until the client state has synced to a later epoch.
+    pub fn record_unavailable_delegation_rate(
+        &mut self,
+        identity_key: IdentityKey,
+        epoch_index: u64,
+    ) {
+        if let Some(synced_epoch) = self.synced_epoch() {
+            self.unavailable_rates
+                .insert((identity_key, epoch_index), synced_epoch.index);
+            self.changes.unavailable_rates = true;
+        }
+    }
 
-        for (index, denom, note) in self.unspent_notes() {
-            notemap
-                .entry(denom)
-                .or_insert_with(BTreeMap::default)
-                .entry(index)
-                .or_insert_with(Vec::default)
-                .push(note.clone());
+    /// Record the exchange rate in the given rate data for all our delegation notes which were
+    /// received or spent at that rate.
+    pub fn record_delegation_rate(&mut self, rate_data: &RateData) {
+        if self
+            .unavailable_rates
+            .remove(&(rate_data.identity_key.clone(), rate_data.epoch_index))
+            .is_some()
+        {
+            self.changes.unavailable_rates = true;
         }
+        let updates = self
+            .delegation_records()
+            .filter(|record| record.delegation_token.validator() == rate_data.identity_key)
+            .map(|record| {
+                let received = if record.received_epoch == rate_data.epoch_index {
+                    Some(rate_data.validator_exchange_rate)
+                } else {
+                    record.received_exchange_rate
+                };
+                let spent = if record.spent_epoch == Some(rate_data.epoch_index) {
+                    Some(rate_data.validator_exchange_rate)
+                } else {
+                    record.spent_exchange_rate
+                };
+                (record.note_commitment, (received, spent))
+            })
+            .collect::<Vec<_>>();
 
-        notemap
+        self.changes
+            .notes
+            .extend(updates.iter().map(|(note_commitment, _)| *note_commitment));
+        self.delegation_rates.extend(updates);
     }
 
     /// Returns the last block height the client state has synced up to, if any.
@@ -594,8 +2232,20 @@ impl ClientState {
         self.last_block_height
     }
 
+    /// Returns the epoch of the last block the client state has synced up to, if it has synced
+    /// any and the chain parameters are set.
+    pub fn synced_epoch(&self) -> Option<Epoch> {
+        Some(Epoch::from_height(
+            self.last_block_height?,
+            self.chain_params.as_ref()?.epoch_duration,
+        ))
+    }
+
     /// Remove all submitted spends and change whose timeouts have expired, dropping submitted change
     /// and returning submitted spends to the unspent set.
+    ///
+    /// Notes involved in planned or pending transactions which expire are left alone, since those
+    /// are returned or dropped once the transaction's expiry height has passed.
     #[instrument(
         skip(self),
         fields(
@@ -606,6 +2256,14 @@ impl ClientState {
     pub fn prune_timeouts(&mut self) {
         let now = SystemTime::now();
 
+        let tracked = self
+            .submitted_transactions
+            .values()
+            .filter(|submitted| submitted.is_unresolved() && submitted.expiry_height.is_some())
+            .flat_map(|submitted| submitted.spent.iter().chain(&submitted.change))
+            .copied()
+            .collect::<BTreeSet<_>>();
+
         // Pull out the submitted sets and set them in `self` to the empty map
         let submitted_spend_set = mem::take(&mut self.submitted_spend_set);
         let submitted_change_set = mem::take(&mut self.submitted_change_set);
@@ -613,7 +2271,8 @@ impl ClientState {
         // Iterate over submitted spends and put back into the unspent set any whose timeouts have
         // already expired
         for (note_commitment, (timeout, note)) in submitted_spend_set {
-            if now > timeout {
+            if now > timeout && !tracked.contains(&note_commitment) {
+                self.changes.notes.insert(note_commitment);
                 // IMPORTANT: we must recover the submitted spend note or else we can't ever spend
                 // it without resetting and resyncing the wallet entirely
                 if self.spent_set.contains_key(&note_commitment) {
@@ -636,7 +2295,8 @@ impl ClientState {
 
         // Iterate over submitted change and **DROP** any whose timeouts have already expired
         for (note_commitment, (timeout, note)) in submitted_change_set {
-            if now > timeout {
+            if now > timeout && !tracked.contains(&note_commitment) {
+                self.changes.notes.insert(note_commitment);
                 // We can drop submitted change notes, because they are outputs of the transaction
                 // and therefore we can expect that either the transaction will fail, or we will
                 // receive them again later
@@ -644,6 +2304,7 @@ impl ClientState {
                     value = ?note.value(),
                     "timeout expired without confirmation for submitted change, dropping it"
                 );
+                self.note_accounts.remove(&note_commitment);
             } else {
                 self.submitted_change_set
                     .insert(note_commitment, (timeout, note));
@@ -654,14 +2315,57 @@ impl ClientState {
     /// Scan the provided block and update the client state.
     ///
     /// The provided block must be the one immediately following [`Self::last_block_height`].
-    #[instrument(skip(self, fragments, nullifiers))]
-    pub fn scan_block(
+    ///
+    /// This trial-decrypts the block and scans it in one go; to trial-decrypt many blocks in
+    /// parallel ahead of scanning them, use [`DecryptedBlock::new`] and
+    /// [`Self::scan_decrypted_block`].
+    #[instrument(skip(self, block), fields(height = block.height))]
+    pub fn scan_block(&mut self, block: CompactBlock) -> Result<(), anyhow::Error> {
+        let block = if self.is_before_birthday(block.height) {
+            DecryptedBlock::without_decryption(block)?
+        } else {
+            DecryptedBlock::new(block, &self.account_wallets())?
+        };
+        self.scan_decrypted_block(block)
+    }
+
+    /// Scan the provided trial-decrypted block and update the client state.
+    ///
+    /// The provided block must be the one immediately following [`Self::last_block_height`], and
+    /// must have been decrypted with the keys of this client state's accounts.
+    ///
+    /// Fails if the note commitment tree doesn't match the block's anchor after the block's notes
+    /// are added to it, leaving the client state partially updated: it must then be discarded
+    /// rather than committed.
+    #[instrument(skip(
+        self,
+        fragments,
+        nullifiers,
+        quarantined_fragments,
+        quarantined_nullifiers,
+        slashed,
+        nullifier_transaction_ids,
+        nct_anchor,
+        decrypted_fragments,
+        decrypted_quarantined_fragments
+    ))]
+    pub fn scan_decrypted_block(
         &mut self,
-        CompactBlock {
-            height,
-            fragments,
-            nullifiers,
-        }: CompactBlock,
+        DecryptedBlock {
+            block:
+                CompactBlock {
+                    height,
+                    fragments,
+                    nullifiers,
+                    quarantined_fragments,
+                    quarantined_nullifiers,
+                    slashed,
+                    nullifier_transaction_ids,
+                    nct_anchor,
+                },
+            fragments: decrypted_fragments,
+            quarantined_fragments: decrypted_quarantined_fragments,
+        }: DecryptedBlock,
     ) -> Result<(), anyhow::Error> {
         // We have to do a bit of a dance to use None as "-1" and handle genesis notes.
         match (height, self.last_block_height()) {
@@ -677,31 +2381,87 @@ impl ClientState {
         }
         tracing::debug!(fragments_len = fragments.len(), "starting block scan");
 
-        for StateFragment {
-            note_commitment,
-            ephemeral_key,
-            encrypted_note,
-        } in fragments.into_iter()
+        // Before changing anything, check that adding the block's note commitments to the note
+        // commitment tree gives the tree committed by the chain, so that a faulty node can't lead
+        // us to build transactions against the wrong tree, and a rejected block leaves the state
+        // as it was. The commitments are added to a checkpoint which is then rewound, and added
+        // again below as the notes they commit to are scanned.
+        let nct_anchor = merkle::Root::try_from(&nct_anchor[..]).map_err(|_| {
+            anyhow!(
+                "block {} has no valid note commitment tree anchor; the node may be running an \
+                older version",
+                height
+            )
+        })?;
+        self.note_commitment_tree.checkpoint();
+        for DecryptedFragment {
+            note_commitment, ..
+        } in &decrypted_fragments
+        {
+            self.note_commitment_tree.append(note_commitment);
+        }
+        let root = self.note_commitment_tree.root2();
+        self.note_commitment_tree.rewind();
+        if root != nct_anchor {
+            return Err(anyhow!(
+                "the note commitment tree root {} after block {} doesn't match the anchor {} \
+                committed by the chain; the node may be faulty, so syncing has stopped",
+                hex::encode(root.to_bytes()),
+                height,
+                hex::encode(nct_anchor.to_bytes())
+            ));
+        }
+
+        for (
+            StateFragment { transaction_id, .. },
+            DecryptedFragment {
+                note_commitment,
+                sent,
+                received,
+            },
+        ) in fragments.into_iter().zip(decrypted_fragments)
         {
             // Unconditionally insert the note commitment into the merkle tree
-            let note_commitment = note_commitment
-                .as_ref()
-                .try_into()
-                .context("invalid note commitment")?;
             tracing::debug!(?note_commitment, "appending to note commitment tree");
             self.note_commitment_tree.append(&note_commitment);
 
-            // Try to decrypt the encrypted note using the ephemeral key and persistent incoming
-            // viewing key -- if it doesn't decrypt, it wasn't meant for us.
-            if let Ok(note) = Note::decrypt(
-                encrypted_note.as_ref(),
-                self.wallet.incoming_viewing_key(),
-                &ephemeral_key
-                    .as_ref()
-                    .try_into()
-                    .context("invalid ephemeral key")?,
-            ) {
-                tracing::debug!(?note_commitment, ?note, "found note while scanning");
+            let transaction_id: Option<[u8; 32]> = transaction_id[..].try_into().ok();
+
+            // If the note was quarantined, it is one of our undelegation outputs which has now
+            // unbonded, and it is already recorded in our transaction history.
+            let unbonded = self.quarantined_set.contains_key(&note_commitment);
+
+            // If the note decrypted with our outgoing viewing key, we sent it.
+            if let Some((note, recipient, memo)) = sent.filter(|_| !unbonded) {
+                tracing::debug!(
+                    ?note_commitment,
+                    ?note,
+                    %recipient,
+                    "found sent note while scanning"
+                );
+                self.changes.notes.insert(note_commitment);
+                self.sent_set.insert(
+                    note_commitment,
+                    SentNote {
+                        note,
+                        recipient,
+                        height,
+                        memo,
+                    },
+                );
+                if let Some(record) = self.transaction_record(height, transaction_id) {
+                    record.sent.insert(note_commitment);
+                }
+            }
+
+            // If the note decrypted with our incoming viewing key, it was meant for us.
+            if let Some((account, note, memo)) = received {
+                tracing::debug!(
+                    ?note_commitment,
+                    ?note,
+                    account,
+                    "found note while scanning"
+                );
                 // Mark the most-recently-inserted note commitment (the one corresponding to this
                 // note) as worth keeping track of, because it's ours
                 self.note_commitment_tree.witness();
@@ -711,31 +2471,169 @@ impl ClientState {
                     .note_commitment_tree
                     .authentication_path(&note_commitment)
                     .expect("we just witnessed this commitment");
-                self.nullifier_map.insert(
-                    self.wallet
-                        .full_viewing_key()
-                        .derive_nullifier(pos, &note_commitment),
-                    note_commitment,
-                );
+                let nullifier = self
+                    .account(account)?
+                    .full_viewing_key()
+                    .derive_nullifier(pos, &note_commitment);
+                self.nullifier_map.insert(nullifier, note_commitment);
+                self.note_accounts.insert(note_commitment, account);
+                self.changes.nullifiers.insert(nullifier);
+                self.changes.notes.insert(note_commitment);
 
                 // If the note was a submitted change note, remove it from the submitted change set
                 if self.submitted_change_set.remove(&note_commitment).is_some() {
                     tracing::debug!(value = ?note.value(), "found submitted change note while scanning, removing it from the submitted change set");
                 }
 
+                // If the note was quarantined, it has now unbonded
+                if self.quarantined_set.remove(&note_commitment).is_some() {
+                    tracing::debug!(value = ?note.value(), "found quarantined note while scanning, removing it from the quarantined set");
+                }
+
+                if let Some(memo) = memo {
+                    self.memos.insert(note_commitment, memo);
+                }
+
                 // Insert the note into the received set
                 self.unspent_set.insert(note_commitment, note.clone());
+                self.note_heights.insert(note_commitment, (height, None));
+
+                if !unbonded {
+                    if let Some(record) = self.transaction_record(height, transaction_id) {
+                        record.received.insert(note_commitment);
+                    }
+                }
+            }
+        }
+
+        for (
+            QuarantinedStateFragment {
+                fragment,
+                unbonding_height,
+                validator_identity_key,
+            },
+            note,
+        ) in quarantined_fragments
+            .into_iter()
+            .zip(decrypted_quarantined_fragments)
+        {
+            // Quarantined notes are not part of the note commitment tree until they unbond, so
+            // we only try to decrypt them to find out about our own undelegation outputs.
+            if let Some((account, note)) = note {
+                let StateFragment {
+                    note_commitment,
+                    transaction_id,
+                    ..
+                } = fragment.ok_or_else(|| anyhow!("missing quarantined state fragment"))?;
+                let note_commitment = note_commitment
+                    .as_ref()
+                    .try_into()
+                    .context("invalid note commitment")?;
+                let validator_identity_key: IdentityKey = validator_identity_key
+                    .ok_or_else(|| anyhow!("missing validator identity key"))?
+                    .try_into()?;
+                tracing::debug!(
+                    ?note_commitment,
+                    ?note,
+                    unbonding_height,
+                    %validator_identity_key,
+                    "found quarantined note while scanning"
+                );
+
+                self.changes.notes.insert(note_commitment);
+                self.note_accounts.insert(note_commitment, account);
+
+                // Undelegation outputs are registered as change when they are built
+                if self.submitted_change_set.remove(&note_commitment).is_some() {
+                    tracing::debug!(value = ?note.value(), "found submitted change note in quarantine, removing it from the submitted change set");
+                }
+
+                self.quarantined_set.insert(
+                    note_commitment,
+                    QuarantinedNote {
+                        note,
+                        unbonding_height,
+                        validator_identity_key,
+                    },
+                );
+                if let Some(record) =
+                    self.transaction_record(height, transaction_id[..].try_into().ok())
+                {
+                    record.received.insert(note_commitment);
+                }
             }
         }
 
+        // Collect the nullifiers in this block whose spends are quarantined, so that we keep track
+        // of the notes they spend in case the spend is reverted.
+        let mut quarantined_nullifier_map = BTreeMap::new();
+        for QuarantinedNullifier {
+            nullifier,
+            unbonding_height,
+            validator_identity_key,
+        } in quarantined_nullifiers
+        {
+            let nullifier: Nullifier = nullifier.as_ref().try_into()?;
+            let validator_identity_key: IdentityKey = validator_identity_key
+                .ok_or_else(|| anyhow!("missing validator identity key"))?
+                .try_into()?;
+            quarantined_nullifier_map.insert(nullifier, (unbonding_height, validator_identity_key));
+        }
+
         // Scan through the list of nullifiers to find those which refer to notes in our unspent
         // set, submitted change set, or submitted spend set and move them into the spent set.
-        for nullifier in nullifiers {
+        for (i, nullifier) in nullifiers.into_iter().enumerate() {
             // Try to decode the nullifier
             let nullifier = nullifier.as_ref().try_into()?;
+            let transaction_id: Option<[u8; 32]> = nullifier_transaction_ids
+                .get(i)
+                .and_then(|transaction_id| transaction_id[..].try_into().ok());
+
+            // If the spend is quarantined, remember that it may yet be reverted, and keep the
+            // witness for the note so that we can spend it again if it is.
+            if let (Some(&note_commitment), Some(quarantine)) = (
+                self.nullifier_map.get(&nullifier),
+                quarantined_nullifier_map.remove(&nullifier),
+            ) {
+                if let Some(note) = self.unspent_set.remove(&note_commitment).or_else(|| {
+                    self.submitted_spend_set
+                        .remove(&note_commitment)
+                        .map(|(_, note)| note)
+                }) {
+                    tracing::debug!(
+                        value = ?note.value(),
+                        ?nullifier,
+                        unbonding_height = quarantine.0,
+                        "found quarantined nullifier for our note, marking it as spent"
+                    );
+                    self.changes.notes.insert(note_commitment);
+                    self.spent_set.insert(note_commitment, note);
+                    self.quarantined_spent_set
+                        .insert(note_commitment, quarantine);
+                    if let Some((_, spent_height)) = self.note_heights.get_mut(&note_commitment) {
+                        *spent_height = Some(height);
+                    }
+                    if let Some(record) = self.transaction_record(height, transaction_id) {
+                        record.spent.insert(note_commitment);
+                    }
+                    continue;
+                }
+            }
 
             // Try to find the corresponding note commitment in the nullifier map
             if let Some(&note_commitment) = self.nullifier_map.get(&nullifier) {
+                self.changes.notes.insert(note_commitment);
+
+                // Record the height of the spend, if this is the first time we've seen it
+                if !self.spent_set.contains_key(&note_commitment) {
+                    if let Some((_, spent_height)) = self.note_heights.get_mut(&note_commitment) {
+                        *spent_height = Some(height);
+                    }
+                    if let Some(record) = self.transaction_record(height, transaction_id) {
+                        record.spent.insert(note_commitment);
+                    }
+                }
+
                 // Try to remove the nullifier from the unspent set
                 if let Some(note) = self.unspent_set.remove(&note_commitment) {
                     // Insert the note into the spent set
@@ -779,8 +2677,96 @@ impl ClientState {
             }
         }
 
+        // If any validators were slashed in this block, all notes and spends still quarantined
+        // relative to them are reverted.
+        for validator_identity_key in slashed {
+            let validator_identity_key: IdentityKey = validator_identity_key.try_into()?;
+
+            for (note_commitment, quarantined) in mem::take(&mut self.quarantined_set) {
+                if quarantined.validator_identity_key == validator_identity_key {
+                    tracing::warn!(
+                        value = ?quarantined.note.value(),
+                        %validator_identity_key,
+                        "validator was slashed, reverting quarantined note"
+                    );
+                    self.changes.notes.insert(note_commitment);
+                    self.reverted_set.insert(note_commitment, quarantined);
+                } else {
+                    self.quarantined_set.insert(note_commitment, quarantined);
+                }
+            }
+
+            for (note_commitment, (unbonding_height, identity_key)) in
+                mem::take(&mut self.quarantined_spent_set)
+            {
+                if identity_key == validator_identity_key {
+                    if let Some(note) = self.spent_set.remove(&note_commitment) {
+                        tracing::warn!(
+                            value = ?note.value(),
+                            %validator_identity_key,
+                            "validator was slashed, returning quarantined spent note to the unspent set"
+                        );
+                        self.unspent_set.insert(note_commitment, note);
+                        if let Some((_, spent_height)) = self.note_heights.get_mut(&note_commitment)
+                        {
+                            *spent_height = None;
+                        }
+                        if let Some((_, spent_rate)) =
+                            self.delegation_rates.get_mut(&note_commitment)
+                        {
+                            *spent_rate = None;
+                        }
+                        self.changes.notes.insert(note_commitment);
+                        for (key, record) in self.transaction_history.iter_mut() {
+                            if record.spent.remove(&note_commitment) {
+                                self.changes.transactions.insert(*key);
+                            }
+                        }
+                    }
+                } else {
+                    self.quarantined_spent_set
+                        .insert(note_commitment, (unbonding_height, identity_key));
+                }
+            }
+        }
+
+        // Quarantined spends become permanent at the end of the first epoch following their
+        // unbonding height, at which point we no longer need to witness the spent notes.
+        let is_epoch_end = self
+            .chain_params
+            .as_ref()
+            .map(|params| {
+                Epoch::from_height(height, params.epoch_duration)
+                    .end_height()
+                    .value()
+                    == height
+            })
+            .unwrap_or(true);
+        if is_epoch_end {
+            for (note_commitment, (unbonding_height, identity_key)) in
+                mem::take(&mut self.quarantined_spent_set)
+            {
+                if unbonding_height <= height {
+                    tracing::debug!(
+                        ?note_commitment,
+                        "quarantined spend has unbonded, marking it as permanent"
+                    );
+                    self.changes.notes.insert(note_commitment);
+                    self.note_commitment_tree.remove_witness(&note_commitment);
+                } else {
+                    self.quarantined_spent_set
+                        .insert(note_commitment, (unbonding_height, identity_key));
+                }
+            }
+        }
+
+        // Now that the block's spends and outputs are recorded, update the status of the
+        // transactions we submitted.
+        self.update_submitted_transactions(height);
+
         // Remember that we've scanned this block & we're ready for the next one.
         self.last_block_height = Some(height);
+        self.changes.sync_state = true;
         tracing::debug!(self.last_block_height, "finished scanning block");
 
         Ok(())
@@ -796,7 +2782,14 @@ mod serde_helpers {
     #[derive(Serialize, Deserialize)]
     pub struct ClientStateHelper {
         wallet: Wallet, // this should be at the top to make `wallet reset` faster
+        #[serde(default)]
+        seed_phrase: Option<String>,
+        /// The labels and wallets of the accounts after account 0, whose wallet is `wallet`.
+        #[serde(default)]
+        accounts: Vec<(String, Wallet)>,
         last_block_height: Option<u64>,
+        #[serde(default)]
+        birthday_height: Option<u64>,
         #[serde_as(as = "serde_with::hex::Hex")]
         note_commitment_tree: Vec<u8>,
         nullifier_map: Vec<(String, String)>,
@@ -806,9 +2799,43 @@ mod serde_helpers {
         #[serde(default, alias = "pending_change_set")]
         submitted_change_set: Vec<(String, SystemTime, String)>,
         spent_set: Vec<(String, String)>,
+        #[serde(default)]
+        quarantined_set: Vec<(String, String, u64, IdentityKey)>,
+        #[serde(default)]
+        quarantined_spent_set: Vec<(String, u64, IdentityKey)>,
+        #[serde(default)]
+        reverted_set: Vec<(String, String, u64, IdentityKey)>,
+        #[serde(default)]
+        note_heights: Vec<(String, u64, Option<u64>)>,
+        #[serde(default)]
+        delegation_rates: Vec<(String, Option<u64>, Option<u64>)>,
+        #[serde(default)]
+        memos: Vec<(String, String)>,
+        #[serde(default)]
+        note_accounts: Vec<(String, u64)>,
+        #[serde(default)]
+        note_labels: Vec<(String, String)>,
+        #[serde(default)]
+        frozen_notes: Vec<String>,
+        #[serde(default)]
+        sent_set: Vec<(String, String, Address, u64)>,
+        #[serde(default)]
+        sent_memos: Vec<(String, String)>,
+        #[serde(default)]
+        transaction_history: Vec<(u64, String, Vec<String>, Vec<String>, Vec<String>)>,
+        // Transaction memos were recorded with their details before sent notes recorded their
+        // own, and are still read from here for older client states.
+        #[serde(default)]
+        transaction_details: Vec<(String, u64, Option<String>, Vec<Delegate>, Vec<Undelegate>)>,
+        #[serde(default)]
+        submitted_transactions: Vec<(String, SubmittedTransaction)>,
         transactions: Vec<(String, String)>,
         asset_registry: Vec<(asset::Id, String)>,
         chain_params: Option<ChainParams>,
+        #[serde(default)]
+        contacts: Vec<(String, String)>,
+        #[serde(default)]
+        trusted_header: Option<(u64, String)>,
     }
 
     #[serde_as]
@@ -854,9 +2881,18 @@ mod serde_helpers {
 
     impl From<ClientState> for ClientStateHelper {
         fn from(state: ClientState) -> Self {
+            let mut accounts = state
+                .accounts
+                .into_iter()
+                .map(|account| (account.label, account.wallet));
+            let (_, wallet) = accounts.next().expect("every wallet has account 0");
+
             Self {
-                wallet: state.wallet,
+                wallet,
+                seed_phrase: state.seed_phrase.map(|phrase| phrase.to_string()),
+                accounts: accounts.collect(),
                 last_block_height: state.last_block_height,
+                birthday_height: state.birthday_height,
                 note_commitment_tree: bincode::serialize(&state.note_commitment_tree).unwrap(),
                 nullifier_map: state
                     .nullifier_map
@@ -910,6 +2946,148 @@ mod serde_helpers {
                         )
                     })
                     .collect(),
+                quarantined_set: state
+                    .quarantined_set
+                    .iter()
+                    .map(|(commitment, quarantined)| {
+                        (
+                            hex::encode(commitment.0.to_bytes()),
+                            hex::encode(quarantined.note.to_bytes()),
+                            quarantined.unbonding_height,
+                            quarantined.validator_identity_key.clone(),
+                        )
+                    })
+                    .collect(),
+                quarantined_spent_set: state
+                    .quarantined_spent_set
+                    .iter()
+                    .map(|(commitment, (unbonding_height, identity_key))| {
+                        (
+                            hex::encode(commitment.0.to_bytes()),
+                            *unbonding_height,
+                            identity_key.clone(),
+                        )
+                    })
+                    .collect(),
+                reverted_set: state
+                    .reverted_set
+                    .iter()
+                    .map(|(commitment, quarantined)| {
+                        (
+                            hex::encode(commitment.0.to_bytes()),
+                            hex::encode(quarantined.note.to_bytes()),
+                            quarantined.unbonding_height,
+                            quarantined.validator_identity_key.clone(),
+                        )
+                    })
+                    .collect(),
+                note_heights: state
+                    .note_heights
+                    .iter()
+                    .map(|(commitment, (received_height, spent_height))| {
+                        (
+                            hex::encode(commitment.0.to_bytes()),
+                            *received_height,
+                            *spent_height,
+                        )
+                    })
+                    .collect(),
+                delegation_rates: state
+                    .delegation_rates
+                    .iter()
+                    .map(|(commitment, (received_rate, spent_rate))| {
+                        (
+                            hex::encode(commitment.0.to_bytes()),
+                            *received_rate,
+                            *spent_rate,
+                        )
+                    })
+                    .collect(),
+                memos: state
+                    .memos
+                    .iter()
+                    .map(|(commitment, memo)| {
+                        (hex::encode(commitment.0.to_bytes()), hex::encode(memo.0))
+                    })
+                    .collect(),
+                note_accounts: state
+                    .note_accounts
+                    .iter()
+                    .map(|(commitment, account)| (hex::encode(commitment.0.to_bytes()), *account))
+                    .collect(),
+                note_labels: state
+                    .note_labels
+                    .iter()
+                    .map(|(commitment, label)| {
+                        (hex::encode(commitment.0.to_bytes()), label.clone())
+                    })
+                    .collect(),
+                frozen_notes: state
+                    .frozen_notes
+                    .iter()
+                    .map(|commitment| hex::encode(commitment.0.to_bytes()))
+                    .collect(),
+                sent_set: state
+                    .sent_set
+                    .iter()
+                    .map(|(commitment, sent)| {
+                        (
+                            hex::encode(commitment.0.to_bytes()),
+                            hex::encode(sent.note.to_bytes()),
+                            sent.recipient,
+                            sent.height,
+                        )
+                    })
+                    .collect(),
+                sent_memos: state
+                    .sent_set
+                    .iter()
+                    .filter_map(|(commitment, sent)| {
+                        Some((
+                            hex::encode(commitment.0.to_bytes()),
+                            hex::encode(sent.memo.as_ref()?.0),
+                        ))
+                    })
+                    .collect(),
+                transaction_history: state
+                    .transaction_history
+                    .iter()
+                    .map(|((height, transaction_id), record)| {
+                        let encode = |commitments: &BTreeSet<note::Commitment>| {
+                            commitments
+                                .iter()
+                                .map(|commitment| hex::encode(commitment.0.to_bytes()))
+                                .collect()
+                        };
+                        (
+                            *height,
+                            hex::encode(transaction_id),
+                            encode(&record.received),
+                            encode(&record.spent),
+                            encode(&record.sent),
+                        )
+                    })
+                    .collect(),
+                transaction_details: state
+                    .transaction_details
+                    .iter()
+                    .map(|(transaction_id, details)| {
+                        (
+                            hex::encode(transaction_id),
+                            details.fee,
+                            None,
+                            details.delegations.clone(),
+                            details.undelegations.clone(),
+                        )
+                    })
+                    .collect(),
+                submitted_transactions: state
+                    .submitted_transactions
+                    .iter()
+                    .map(|(transaction_id, submitted)| {
+                        (hex::encode(transaction_id), submitted.clone())
+                    })
+                    .collect(),
                 asset_registry: state
                     .asset_cache
                     .iter()
@@ -918,6 +3096,14 @@ mod serde_helpers {
                 // TODO: serialize full transactions
                 transactions: vec![],
                 chain_params: state.chain_params,
+                contacts: state
+                    .contacts
+                    .iter()
+                    .map(|(name, contact)| (name.clone(), contact.to_string()))
+                    .collect(),
+                trusted_header: state
+                    .trusted_header
+                    .map(|(height, hash)| (height, hex::encode(hash))),
             }
         }
     }
@@ -967,24 +3153,242 @@ mod serde_helpers {
                 );
             }
 
+            let mut quarantined_set = BTreeMap::new();
+            for (commitment, note, unbonding_height, validator_identity_key) in
+                state.quarantined_set.into_iter()
+            {
+                quarantined_set.insert(
+                    hex::decode(commitment)?.as_slice().try_into()?,
+                    QuarantinedNote {
+                        note: hex::decode(note)?.as_slice().try_into()?,
+                        unbonding_height,
+                        validator_identity_key,
+                    },
+                );
+            }
+
+            let mut quarantined_spent_set = BTreeMap::new();
+            for (commitment, unbonding_height, validator_identity_key) in
+                state.quarantined_spent_set.into_iter()
+            {
+                quarantined_spent_set.insert(
+                    hex::decode(commitment)?.as_slice().try_into()?,
+                    (unbonding_height, validator_identity_key),
+                );
+            }
+
+            let mut reverted_set = BTreeMap::new();
+            for (commitment, note, unbonding_height, validator_identity_key) in
+                state.reverted_set.into_iter()
+            {
+                reverted_set.insert(
+                    hex::decode(commitment)?.as_slice().try_into()?,
+                    QuarantinedNote {
+                        note: hex::decode(note)?.as_slice().try_into()?,
+                        unbonding_height,
+                        validator_identity_key,
+                    },
+                );
+            }
+
+            let mut note_heights = BTreeMap::new();
+            for (commitment, received_height, spent_height) in state.note_heights.into_iter() {
+                note_heights.insert(
+                    hex::decode(commitment)?.as_slice().try_into()?,
+                    (received_height, spent_height),
+                );
+            }
+
+            let mut delegation_rates = BTreeMap::new();
+            for (commitment, received_rate, spent_rate) in state.delegation_rates.into_iter() {
+                delegation_rates.insert(
+                    hex::decode(commitment)?.as_slice().try_into()?,
+                    (received_rate, spent_rate),
+                );
+            }
+
+            let decode_memo = |memo: String| {
+                Ok::<_, anyhow::Error>(memo::MemoPlaintext(
+                    hex::decode(memo)?
+                        .try_into()
+                        .map_err(|_| anyhow!("invalid memo length"))?,
+                ))
+            };
+            let mut memos = BTreeMap::new();
+            for (commitment, memo) in state.memos.into_iter() {
+                memos.insert(
+                    hex::decode(commitment)?.as_slice().try_into()?,
+                    decode_memo(memo)?,
+                );
+            }
+
+            let mut note_accounts = BTreeMap::new();
+            for (commitment, account) in state.note_accounts.into_iter() {
+                note_accounts.insert(hex::decode(commitment)?.as_slice().try_into()?, account);
+            }
+
+            let mut note_labels = BTreeMap::new();
+            for (commitment, label) in state.note_labels.into_iter() {
+                note_labels.insert(hex::decode(commitment)?.as_slice().try_into()?, label);
+            }
+
+            let mut frozen_notes = BTreeSet::new();
+            for commitment in state.frozen_notes.into_iter() {
+                frozen_notes.insert(hex::decode(commitment)?.as_slice().try_into()?);
+            }
+
+            let mut sent_set = BTreeMap::new();
+            for (commitment, note, recipient, height) in state.sent_set.into_iter() {
+                sent_set.insert(
+                    hex::decode(commitment)?.as_slice().try_into()?,
+                    SentNote {
+                        note: hex::decode(note)?.as_slice().try_into()?,
+                        recipient,
+                        height,
+                        memo: None,
+                    },
+                );
+            }
+            for (commitment, memo) in state.sent_memos.into_iter() {
+                let commitment: note::Commitment =
+                    hex::decode(commitment)?.as_slice().try_into()?;
+                if let Some(sent) = sent_set.get_mut(&commitment) {
+                    sent.memo = Some(decode_memo(memo)?);
+                }
+            }
+
+            let decode = |commitments: Vec<String>| {
+                commitments
+                    .into_iter()
+                    .map(|commitment| Ok(hex::decode(commitment)?.as_slice().try_into()?))
+                    .collect::<Result<BTreeSet<note::Commitment>, anyhow::Error>>()
+            };
+            let mut transaction_history = BTreeMap::new();
+            for (height, transaction_id, received, spent, sent) in
+                state.transaction_history.into_iter()
+            {
+                transaction_history.insert(
+                    (
+                        height,
+                        hex::decode(transaction_id)?
+                            .try_into()
+                            .map_err(|_| anyhow!("invalid transaction id"))?,
+                    ),
+                    TransactionRecord {
+                        received: decode(received)?,
+                        spent: decode(spent)?,
+                        sent: decode(sent)?,
+                    },
+                );
+            }
+
+            let mut transaction_details = BTreeMap::new();
+            for (transaction_id, fee, memo, delegations, undelegations) in
+                state.transaction_details.into_iter()
+            {
+                let transaction_id: [u8; 32] = hex::decode(transaction_id)?
+                    .try_into()
+                    .map_err(|_| anyhow!("invalid transaction id"))?;
+                if let Some(memo) = memo {
+                    let memo = decode_memo(memo)?;
+                    for ((_, id), record) in &transaction_history {
+                        if *id != transaction_id {
+                            continue;
+                        }
+                        for commitment in &record.sent {
+                            if let Some(sent) = sent_set.get_mut(commitment) {
+                                sent.memo.get_or_insert_with(|| memo.clone());
+                            }
+                        }
+                    }
+                }
+                transaction_details.insert(
+                    transaction_id,
+                    TransactionDetails {
+                        fee,
+                        delegations,
+                        undelegations,
+                    },
+                );
+            }
+
+            let mut submitted_transactions = BTreeMap::new();
+            for (transaction_id, submitted) in state.submitted_transactions.into_iter() {
+                submitted_transactions.insert(
+                    hex::decode(transaction_id)?
+                        .try_into()
+                        .map_err(|_| anyhow!("invalid transaction id"))?,
+                    submitted,
+                );
+            }
+
             let mut asset_registry = BTreeMap::new();
             for (id, denom) in state.asset_registry.into_iter() {
                 asset_registry.insert(id, denom);
             }
 
-            Ok(Self {
+            let accounts = std::iter::once(Account {
+                label: Account::DEFAULT_LABEL.to_string(),
                 wallet: state.wallet,
+            })
+            .chain(
+                state
+                    .accounts
+                    .into_iter()
+                    .map(|(label, wallet)| Account { label, wallet }),
+            )
+            .collect();
+
+            Ok(Self {
+                seed_phrase: state.seed_phrase.map(|phrase| phrase.parse()).transpose()?,
+                accounts,
                 last_block_height: state.last_block_height,
+                birthday_height: state.birthday_height,
                 note_commitment_tree: bincode::deserialize(&state.note_commitment_tree)?,
                 nullifier_map,
                 unspent_set,
                 submitted_spend_set,
                 submitted_change_set,
                 spent_set,
+                quarantined_set,
+                quarantined_spent_set,
+                reverted_set,
+                note_heights,
+                delegation_rates,
+                memos,
+                note_accounts,
+                note_labels,
+                frozen_notes,
+                sent_set,
+                transaction_history,
+                transaction_details,
+                submitted_transactions,
                 asset_cache: asset_registry.try_into()?,
                 // TODO: serialize full transactions
                 transactions: Default::default(),
                 chain_params: state.chain_params,
+                contacts: state
+                    .contacts
+                    .into_iter()
+                    .map(|(name, contact)| Ok((name, contact.parse()?)))
+                    .collect::<Result<_, anyhow::Error>>()?,
+                trusted_header: state
+                    .trusted_header
+                    .map(|(height, hash)| {
+                        Ok::<_, anyhow::Error>((
+                            height,
+                            hex::decode(hash)?
+                                .try_into()
+                                .map_err(|_| anyhow!("trusted header hash must be 32 bytes"))?,
+                        ))
+                    })
+                    .transpose()?,
+                // Unavailable rates are only a cache of the node's answers, and aren't archived.
+                unavailable_rates: BTreeMap::new(),
+                transaction_shape: Default::default(),
+                note_selector: Arc::new(NoteSelection::default()),
+                link_addresses: false,
+                changes: Default::default(),
             })
         }
     }
@@ -0,0 +1,1358 @@
+use std::{
+    collections::BTreeSet,
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, Context};
+use penumbra_crypto::{keys::SpendSeed, memo, note, Note};
+use sqlx::{
+    sqlite::{
+        SqliteConnectOptions, SqliteConnection, SqliteJournalMode, SqlitePoolOptions,
+        SqliteSynchronous,
+    },
+    Row, Sqlite, SqlitePool, Transaction,
+};
+
+use super::{
+    Account, Changes, ClientState, Contact, QuarantinedNote, SentNote, SubmittedTransaction,
+    SubmittedTransactionStatus, TransactionDetails,
+};
+use crate::Wallet;
+
+mod sealed;
+
+use sealed::{KdfParams, SealedKeys, SealingKey, SecretKeys};
+
+/// How the secret key material in a wallet database is protected.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeyProtection {
+    /// The spend seeds and seed phrase are stored in plaintext.
+    Plaintext,
+    /// The spend seeds and seed phrase are sealed with a password.
+    Encrypted {
+        /// Whether the full viewing keys are sealed too, in which case the wallet can't be synced
+        /// without the password.
+        viewing_keys_sealed: bool,
+    },
+}
+
+/// Persistent storage for a [`ClientState`] in an embedded SQLite database.
+///
+/// The whole state is written when the database is created; after that, [`Storage::commit`]
+/// writes only the parts of the state which have changed, in a single database transaction, so
+/// that it is cheap enough to call after every scanned block.
+///
+/// The secret key material may be sealed with a password, see [`Storage::encrypt`]. Once the
+/// database has been loaded with the password, the key derived from it is kept so that the keys of
+/// new accounts can be sealed as they are committed.
+pub struct Storage {
+    pool: SqlitePool,
+    sealing_key: Option<SealingKey>,
+}
+
+impl Storage {
+    /// Creates a new database at `path` containing the given client state.
+    ///
+    /// Fails if something already exists at `path`.
+    pub async fn create(path: &Path, state: &mut ClientState) -> anyhow::Result<Self> {
+        if path.exists() {
+            return Err(anyhow!(
+                "wallet database {} already exists, refusing to overwrite it",
+                path.display()
+            ));
+        }
+        let storage = Self::connect(path, true).await?;
+
+        let mut dbtx = storage.pool.begin().await?;
+        write_changes(&mut dbtx, state, &all_changes(state), None).await?;
+        dbtx.commit().await?;
+
+        state.changes = Default::default();
+        Ok(storage)
+    }
+
+    /// Creates a new database at `path` containing the given client state, with its secret key
+    /// material sealed with `password`.
+    ///
+    /// The client state must be unlocked. Fails if something already exists at `path`.
+    pub async fn create_encrypted(
+        path: &Path,
+        state: &mut ClientState,
+        password: &str,
+        seal_viewing_keys: bool,
+    ) -> anyhow::Result<Self> {
+        if path.exists() {
+            return Err(anyhow!(
+                "wallet database {} already exists, refusing to overwrite it",
+                path.display()
+            ));
+        }
+        let mut storage = Self::connect(path, true).await?;
+        let sealing_key = SealingKey::new(password)?;
+
+        let mut dbtx = storage.pool.begin().await?;
+        write_keys(&mut dbtx, state, Some((&sealing_key, seal_viewing_keys))).await?;
+        write_changes(&mut dbtx, state, &all_changes(state), Some(&sealing_key)).await?;
+        dbtx.commit().await?;
+
+        storage.sealing_key = Some(sealing_key);
+        state.changes = Default::default();
+        Ok(storage)
+    }
+
+    /// Opens an existing database at `path`.
+    pub async fn open(path: &Path) -> anyhow::Result<Self> {
+        Self::connect(path, false).await
+    }
+
+    async fn connect(path: &Path, create: bool) -> anyhow::Result<Self> {
+        let options = SqliteConnectOptions::new()
+            .filename(path)
+            .create_if_missing(create)
+            .foreign_keys(true)
+            // Write-ahead logging lets us commit after every block without syncing the database
+            // file each time, while keeping every commit atomic.
+            .journal_mode(SqliteJournalMode::Wal)
+            .synchronous(SqliteSynchronous::Normal);
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect_with(options)
+            .await
+            .with_context(|| format!("could not open wallet database {}", path.display()))?;
+
+        sqlx::migrate!("./migrations").run(&pool).await?;
+
+        Ok(Self {
+            pool,
+            sealing_key: None,
+        })
+    }
+
+    /// Closes the database, waiting for all pending writes to finish.
+    pub async fn close(self) {
+        self.pool.close().await
+    }
+
+    /// Returns how the secret key material in the database is protected.
+    pub async fn key_protection(&self) -> anyhow::Result<KeyProtection> {
+        key_protection(&mut *self.pool.acquire().await?).await
+    }
+
+    /// Loads only the wallet (key material and address labels) of the given account from the
+    /// database.
+    ///
+    /// See [`Storage::load_keys`] for how the `password` is used.
+    pub async fn load_wallet(
+        &mut self,
+        account: u64,
+        password: Option<&str>,
+    ) -> anyhow::Result<Wallet> {
+        let state = self.load_keys(password).await?;
+        Ok(state.account(account)?.clone())
+    }
+
+    /// Loads only the key material of the client state -- its seed phrase and the wallets of all
+    /// its accounts -- from the database, as a client state which has never been synced.
+    ///
+    /// If the secret key material is encrypted, it is unlocked with `password` if one is given.
+    /// Without a password, the wallets are loaded from their full viewing keys, and can scan the
+    /// chain but not authorize transactions; if the full viewing keys are sealed as well, loading
+    /// fails.
+    pub async fn load_keys(&mut self, password: Option<&str>) -> anyhow::Result<ClientState> {
+        let secrets = match (
+            sealed_keys(&mut *self.pool.acquire().await?).await?,
+            password,
+        ) {
+            (Some((sealed, _)), Some(password)) => {
+                let (sealing_key, secrets) = sealed.open(password)?;
+                self.sealing_key = Some(sealing_key);
+                Some(secrets)
+            }
+            (Some((_, true)), None) => {
+                return Err(anyhow!(
+                    "the wallet's keys are encrypted, and it can't be loaded without its password"
+                ));
+            }
+            _ => None,
+        };
+
+        let row = sqlx::query(
+            "SELECT spend_seed, seed_phrase, full_viewing_key, birthday_height FROM wallet
+            WHERE id = 0",
+        )
+        .fetch_one(&self.pool)
+        .await?;
+        let mut state = ClientState::new(restore_wallet(
+            0,
+            row.try_get("spend_seed")?,
+            row.try_get("full_viewing_key")?,
+            secrets.as_ref(),
+            self.address_labels(0).await?,
+        )?);
+
+        state.seed_phrase = match row.try_get::<Option<String>, _>("seed_phrase")? {
+            Some(phrase) => Some(phrase),
+            None => secrets
+                .as_ref()
+                .and_then(|secrets| secrets.seed_phrase.clone()),
+        }
+        .map(|phrase| phrase.parse())
+        .transpose()?;
+        state.birthday_height = row
+            .try_get::<Option<i64>, _>("birthday_height")?
+            .map(|height| height as u64);
+
+        for row in sqlx::query(
+            "SELECT account_index, label, spend_seed, full_viewing_key FROM accounts
+            ORDER BY account_index",
+        )
+        .fetch_all(&self.pool)
+        .await?
+        {
+            let index = row.try_get::<i64, _>("account_index")? as u64;
+            if index != state.accounts.len() as u64 {
+                return Err(anyhow!("missing account before account {}", index));
+            }
+            state.accounts.push(Account {
+                label: row.try_get("label")?,
+                wallet: restore_wallet(
+                    index,
+                    row.try_get("spend_seed")?,
+                    row.try_get("full_viewing_key")?,
+                    secrets.as_ref(),
+                    self.address_labels(index).await?,
+                )?,
+            });
+        }
+
+        Ok(state)
+    }
+
+    async fn address_labels(&self, account: u64) -> anyhow::Result<Vec<String>> {
+        Ok(sqlx::query(
+            "SELECT label FROM addresses WHERE account_index = ? ORDER BY address_index",
+        )
+        .bind(account as i64)
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(|row| row.try_get("label"))
+        .collect::<Result<Vec<String>, _>>()?)
+    }
+
+    /// Loads the client state from the database.
+    ///
+    /// See [`Storage::load_keys`] for how the `password` is used.
+    pub async fn load(&mut self, password: Option<&str>) -> anyhow::Result<ClientState> {
+        let mut state = self.load_keys(password).await?;
+
+        if let Some(row) =
+            sqlx::query("SELECT last_block_height, chain_params FROM sync_state WHERE id = 0")
+                .fetch_optional(&self.pool)
+                .await?
+        {
+            state.last_block_height = row
+                .try_get::<Option<i64>, _>("last_block_height")?
+                .map(|height| height as u64);
+            state.chain_params = row
+                .try_get::<Option<String>, _>("chain_params")?
+                .map(|params| serde_json::from_str(&params))
+                .transpose()?;
+        }
+
+        if let Some(row) = sqlx::query("SELECT tree FROM note_commitment_tree WHERE id = 0")
+            .fetch_optional(&self.pool)
+            .await?
+        {
+            state.note_commitment_tree = bincode::deserialize(&row.try_get::<Vec<u8>, _>("tree")?)?;
+        }
+
+        let mut asset_registry = std::collections::BTreeMap::new();
+        for row in sqlx::query("SELECT asset_id, denom FROM assets")
+            .fetch_all(&self.pool)
+            .await?
+        {
+            let asset_id: String = row.try_get("asset_id")?;
+            asset_registry.insert(asset_id.parse()?, row.try_get::<String, _>("denom")?);
+        }
+        state.asset_cache = asset_registry.try_into()?;
+
+        for row in sqlx::query("SELECT name, contact FROM contacts")
+            .fetch_all(&self.pool)
+            .await?
+        {
+            let contact: String = row.try_get("contact")?;
+            state
+                .contacts
+                .insert(row.try_get("name")?, contact.parse::<Contact>()?);
+        }
+
+        for row in sqlx::query(
+            "SELECT identity_key, epoch_index, checked_epoch_index FROM unavailable_rates",
+        )
+        .fetch_all(&self.pool)
+        .await?
+        {
+            let identity_key: String = row.try_get("identity_key")?;
+            state.unavailable_rates.insert(
+                (
+                    identity_key.parse()?,
+                    row.try_get::<i64, _>("epoch_index")? as u64,
+                ),
+                row.try_get::<i64, _>("checked_epoch_index")? as u64,
+            );
+        }
+
+        if let Some(row) = sqlx::query("SELECT height, hash FROM trusted_header WHERE id = 0")
+            .fetch_optional(&self.pool)
+            .await?
+        {
+            state.trusted_header = Some((
+                row.try_get::<i64, _>("height")? as u64,
+                row.try_get::<Vec<u8>, _>("hash")?
+                    .try_into()
+                    .map_err(|_| anyhow!("trusted header hash must be 32 bytes"))?,
+            ));
+        }
+
+        for row in sqlx::query("SELECT nullifier, note_commitment FROM nullifiers")
+            .fetch_all(&self.pool)
+            .await?
+        {
+            state.nullifier_map.insert(
+                row.try_get::<Vec<u8>, _>("nullifier")?
+                    .as_slice()
+                    .try_into()?,
+                commitment(&row)?,
+            );
+        }
+
+        for row in sqlx::query(
+            "SELECT note_commitment, note_set, note, timeout, unbonding_height, validator_identity_key
+            FROM notes",
+        )
+        .fetch_all(&self.pool)
+        .await?
+        {
+            let note_commitment = commitment(&row)?;
+            let note: Note = row.try_get::<Vec<u8>, _>("note")?.as_slice().try_into()?;
+            let timeout = || -> anyhow::Result<SystemTime> {
+                let timeout = row
+                    .try_get::<Option<i64>, _>("timeout")?
+                    .ok_or_else(|| anyhow!("missing timeout for submitted note"))?;
+                Ok(UNIX_EPOCH + Duration::from_millis(timeout as u64))
+            };
+            let quarantined = |note: Note| -> anyhow::Result<QuarantinedNote> {
+                Ok(QuarantinedNote {
+                    note,
+                    unbonding_height: row
+                        .try_get::<Option<i64>, _>("unbonding_height")?
+                        .ok_or_else(|| anyhow!("missing unbonding height for quarantined note"))?
+                        as u64,
+                    validator_identity_key: row
+                        .try_get::<Option<String>, _>("validator_identity_key")?
+                        .ok_or_else(|| anyhow!("missing validator for quarantined note"))?
+                        .parse()?,
+                })
+            };
+
+            match row.try_get::<String, _>("note_set")?.as_str() {
+                "unspent" => {
+                    state.unspent_set.insert(note_commitment, note);
+                }
+                "submitted_spend" => {
+                    state
+                        .submitted_spend_set
+                        .insert(note_commitment, (timeout()?, note));
+                }
+                "submitted_change" => {
+                    state
+                        .submitted_change_set
+                        .insert(note_commitment, (timeout()?, note));
+                }
+                "spent" => {
+                    state.spent_set.insert(note_commitment, note);
+                }
+                "quarantined" => {
+                    state
+                        .quarantined_set
+                        .insert(note_commitment, quarantined(note)?);
+                }
+                "reverted" => {
+                    state
+                        .reverted_set
+                        .insert(note_commitment, quarantined(note)?);
+                }
+                note_set => return Err(anyhow!("unknown note set {}", note_set)),
+            }
+        }
+
+        for row in sqlx::query(
+            "SELECT note_commitment, unbonding_height, validator_identity_key FROM quarantined_spends",
+        )
+        .fetch_all(&self.pool)
+        .await?
+        {
+            let validator_identity_key: String = row.try_get("validator_identity_key")?;
+            state.quarantined_spent_set.insert(
+                commitment(&row)?,
+                (
+                    row.try_get::<i64, _>("unbonding_height")? as u64,
+                    validator_identity_key.parse()?,
+                ),
+            );
+        }
+
+        for row in
+            sqlx::query("SELECT note_commitment, received_height, spent_height FROM note_heights")
+                .fetch_all(&self.pool)
+                .await?
+        {
+            state.note_heights.insert(
+                commitment(&row)?,
+                (
+                    row.try_get::<i64, _>("received_height")? as u64,
+                    row.try_get::<Option<i64>, _>("spent_height")?
+                        .map(|height| height as u64),
+                ),
+            );
+        }
+
+        for row in sqlx::query(
+            "SELECT note_commitment, received_exchange_rate, spent_exchange_rate FROM delegation_rates",
+        )
+        .fetch_all(&self.pool)
+        .await?
+        {
+            state.delegation_rates.insert(
+                commitment(&row)?,
+                (
+                    row.try_get::<Option<i64>, _>("received_exchange_rate")?
+                        .map(|rate| rate as u64),
+                    row.try_get::<Option<i64>, _>("spent_exchange_rate")?
+                        .map(|rate| rate as u64),
+                ),
+            );
+        }
+
+        for row in sqlx::query("SELECT note_commitment, memo FROM memos")
+            .fetch_all(&self.pool)
+            .await?
+        {
+            state
+                .memos
+                .insert(commitment(&row)?, decode_memo(row.try_get("memo")?)?);
+        }
+
+        for row in sqlx::query("SELECT note_commitment, account_index FROM note_accounts")
+            .fetch_all(&self.pool)
+            .await?
+        {
+            state.note_accounts.insert(
+                commitment(&row)?,
+                row.try_get::<i64, _>("account_index")? as u64,
+            );
+        }
+
+        for row in sqlx::query("SELECT note_commitment, label FROM note_labels")
+            .fetch_all(&self.pool)
+            .await?
+        {
+            state
+                .note_labels
+                .insert(commitment(&row)?, row.try_get("label")?);
+        }
+
+        for row in sqlx::query("SELECT note_commitment FROM frozen_notes")
+            .fetch_all(&self.pool)
+            .await?
+        {
+            state.frozen_notes.insert(commitment(&row)?);
+        }
+
+        for row in
+            sqlx::query("SELECT note_commitment, note, recipient, height, memo FROM sent_notes")
+                .fetch_all(&self.pool)
+                .await?
+        {
+            let recipient: String = row.try_get("recipient")?;
+            state.sent_set.insert(
+                commitment(&row)?,
+                SentNote {
+                    note: row.try_get::<Vec<u8>, _>("note")?.as_slice().try_into()?,
+                    recipient: recipient.parse()?,
+                    height: row.try_get::<i64, _>("height")? as u64,
+                    memo: row
+                        .try_get::<Option<Vec<u8>>, _>("memo")?
+                        .map(decode_memo)
+                        .transpose()?,
+                },
+            );
+        }
+
+        for row in sqlx::query("SELECT height, transaction_id FROM transactions")
+            .fetch_all(&self.pool)
+            .await?
+        {
+            state
+                .transaction_history
+                .insert(transaction_key(&row)?, Default::default());
+        }
+
+        for row in sqlx::query(
+            "SELECT height, transaction_id, role, note_commitment FROM transaction_notes",
+        )
+        .fetch_all(&self.pool)
+        .await?
+        {
+            let record = state
+                .transaction_history
+                .entry(transaction_key(&row)?)
+                .or_default();
+            let note_commitment = commitment(&row)?;
+            match row.try_get::<String, _>("role")?.as_str() {
+                "received" => record.received.insert(note_commitment),
+                "spent" => record.spent.insert(note_commitment),
+                "sent" => record.sent.insert(note_commitment),
+                role => return Err(anyhow!("unknown transaction note role {}", role)),
+            };
+        }
+
+        for row in sqlx::query(
+            "SELECT transaction_id, fee, delegations, undelegations FROM transaction_details",
+        )
+        .fetch_all(&self.pool)
+        .await?
+        {
+            let delegations: String = row.try_get("delegations")?;
+            let undelegations: String = row.try_get("undelegations")?;
+            state.transaction_details.insert(
+                transaction_id(&row)?,
+                TransactionDetails {
+                    fee: row.try_get::<i64, _>("fee")? as u64,
+                    delegations: serde_json::from_str(&delegations)?,
+                    undelegations: serde_json::from_str(&undelegations)?,
+                },
+            );
+        }
+
+        for row in sqlx::query(
+            "SELECT transaction_id, expiry_height, status, confirmed_height
+            FROM submitted_transactions",
+        )
+        .fetch_all(&self.pool)
+        .await?
+        {
+            let status = match row.try_get::<String, _>("status")?.as_str() {
+                "planned" => SubmittedTransactionStatus::Planned,
+                "pending" => SubmittedTransactionStatus::Pending,
+                "confirmed" => SubmittedTransactionStatus::Confirmed {
+                    height: row
+                        .try_get::<Option<i64>, _>("confirmed_height")?
+                        .ok_or_else(|| anyhow!("confirmed transaction has no height"))?
+                        as u64,
+                },
+                "expired" => SubmittedTransactionStatus::Expired,
+                "conflicted" => SubmittedTransactionStatus::Conflicted {
+                    height: row
+                        .try_get::<Option<i64>, _>("confirmed_height")?
+                        .ok_or_else(|| anyhow!("conflicted transaction has no height"))?
+                        as u64,
+                },
+                status => return Err(anyhow!("unknown submitted transaction status {}", status)),
+            };
+            state.submitted_transactions.insert(
+                transaction_id(&row)?,
+                SubmittedTransaction {
+                    spent: BTreeSet::new(),
+                    change: BTreeSet::new(),
+                    expiry_height: row
+                        .try_get::<Option<i64>, _>("expiry_height")?
+                        .map(|height| height as u64),
+                    status,
+                },
+            );
+        }
+
+        for row in sqlx::query(
+            "SELECT transaction_id, role, note_commitment FROM submitted_transaction_notes",
+        )
+        .fetch_all(&self.pool)
+        .await?
+        {
+            let submitted = state
+                .submitted_transactions
+                .get_mut(&transaction_id(&row)?)
+                .ok_or_else(|| anyhow!("note of unknown submitted transaction"))?;
+            let note_commitment = commitment(&row)?;
+            match row.try_get::<String, _>("role")?.as_str() {
+                "spent" => submitted.spent.insert(note_commitment),
+                "change" => submitted.change.insert(note_commitment),
+                role => return Err(anyhow!("unknown submitted transaction note role {}", role)),
+            };
+        }
+
+        Ok(state)
+    }
+
+    /// Writes the parts of the client state which have changed since it was loaded or last
+    /// committed, atomically.
+    pub async fn commit(&self, state: &mut ClientState) -> anyhow::Result<()> {
+        tracing::debug!("committing state");
+
+        let mut dbtx = self.pool.begin().await?;
+        write_changes(&mut dbtx, state, &state.changes, self.sealing_key.as_ref()).await?;
+        dbtx.commit().await?;
+
+        state.changes = Default::default();
+        Ok(())
+    }
+
+    /// Seals the secret key material in the database with `password`, removing the plaintext
+    /// spend seeds and seed phrase.
+    ///
+    /// If `seal_viewing_keys` is set, the full viewing keys are sealed too, so that the wallet
+    /// can't be loaded at all without the password; otherwise they are kept readable, so that it
+    /// can still be synced unattended. Either way, the notes and transactions already scanned
+    /// remain readable.
+    pub async fn encrypt(&mut self, password: &str, seal_viewing_keys: bool) -> anyhow::Result<()> {
+        if self.key_protection().await? != KeyProtection::Plaintext {
+            return Err(anyhow!("the wallet is already encrypted"));
+        }
+        let state = self.load_keys(None).await?;
+        if state
+            .accounts
+            .iter()
+            .any(|account| !account.wallet.is_unlocked())
+        {
+            return Err(anyhow!("a watch-only wallet has no spend keys to encrypt"));
+        }
+        let sealing_key = SealingKey::new(password)?;
+
+        let mut dbtx = self.pool.begin().await?;
+        write_keys(&mut dbtx, &state, Some((&sealing_key, seal_viewing_keys))).await?;
+        dbtx.commit().await?;
+        self.sealing_key = Some(sealing_key);
+
+        self.purge_freed_pages().await
+    }
+
+    /// Unseals the secret key material in the database with `password`, storing it in plaintext.
+    pub async fn decrypt(&mut self, password: &str) -> anyhow::Result<()> {
+        if self.key_protection().await? == KeyProtection::Plaintext {
+            return Err(anyhow!("the wallet is not encrypted"));
+        }
+        let state = self.load_keys(Some(password)).await?;
+
+        let mut dbtx = self.pool.begin().await?;
+        write_keys(&mut dbtx, &state, None).await?;
+        dbtx.commit().await?;
+        self.sealing_key = None;
+
+        Ok(())
+    }
+
+    /// Reseals the secret key material in the database with a new password.
+    pub async fn change_password(
+        &mut self,
+        old_password: &str,
+        new_password: &str,
+    ) -> anyhow::Result<()> {
+        let viewing_keys_sealed = match self.key_protection().await? {
+            KeyProtection::Plaintext => return Err(anyhow!("the wallet is not encrypted")),
+            KeyProtection::Encrypted {
+                viewing_keys_sealed,
+            } => viewing_keys_sealed,
+        };
+        let state = self.load_keys(Some(old_password)).await?;
+        let sealing_key = SealingKey::new(new_password)?;
+
+        let mut dbtx = self.pool.begin().await?;
+        write_keys(&mut dbtx, &state, Some((&sealing_key, viewing_keys_sealed))).await?;
+        dbtx.commit().await?;
+        self.sealing_key = Some(sealing_key);
+
+        self.purge_freed_pages().await
+    }
+
+    /// Rebuilds the database file and truncates its write-ahead log, so that key material which
+    /// has been overwritten doesn't linger in freed pages.
+    async fn purge_freed_pages(&self) -> anyhow::Result<()> {
+        sqlx::query("VACUUM").execute(&self.pool).await?;
+        sqlx::query("PRAGMA wal_checkpoint(TRUNCATE)")
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+}
+
+/// Restores the wallet of the given account from its row in the database, using the sealed
+/// secret key material if its spend seed isn't stored in plaintext.
+fn restore_wallet(
+    account: u64,
+    spend_seed: Option<Vec<u8>>,
+    full_viewing_key: Option<Vec<u8>>,
+    secrets: Option<&SecretKeys>,
+    address_labels: Vec<String>,
+) -> anyhow::Result<Wallet> {
+    let spend_seed = match spend_seed {
+        Some(spend_seed) => Some(SpendSeed::try_from(spend_seed.as_slice())?),
+        None => secrets
+            .map(|secrets| secrets.spend_seed(account))
+            .transpose()?,
+    };
+
+    match (spend_seed, full_viewing_key) {
+        (Some(spend_seed), None) => Ok(Wallet::from_parts(spend_seed, address_labels)),
+        (spend_seed, Some(full_viewing_key)) => {
+            let mut wallet =
+                Wallet::locked(full_viewing_key.as_slice().try_into()?, address_labels);
+            if let Some(spend_seed) = spend_seed {
+                wallet.unlock(spend_seed)?;
+            }
+            Ok(wallet)
+        }
+        (None, None) => Err(anyhow!(
+            "no keys for account {}: the wallet's keys are encrypted",
+            account
+        )),
+    }
+}
+
+/// Reads the sealed secret key material, and whether the full viewing keys are sealed with it, if
+/// the wallet is encrypted.
+async fn sealed_keys(conn: &mut SqliteConnection) -> anyhow::Result<Option<(SealedKeys, bool)>> {
+    let row = match sqlx::query(
+        "SELECT salt, memory_cost, time_cost, parallelism, nonce, ciphertext, viewing_keys_sealed
+        FROM sealed_keys WHERE id = 0",
+    )
+    .fetch_optional(conn)
+    .await?
+    {
+        Some(row) => row,
+        None => return Ok(None),
+    };
+
+    Ok(Some((
+        SealedKeys {
+            salt: row.try_get("salt")?,
+            params: KdfParams {
+                memory_cost: row.try_get::<i64, _>("memory_cost")? as u32,
+                time_cost: row.try_get::<i64, _>("time_cost")? as u32,
+                parallelism: row.try_get::<i64, _>("parallelism")? as u32,
+            },
+            nonce: row.try_get("nonce")?,
+            ciphertext: row.try_get("ciphertext")?,
+        },
+        row.try_get("viewing_keys_sealed")?,
+    )))
+}
+
+async fn key_protection(conn: &mut SqliteConnection) -> anyhow::Result<KeyProtection> {
+    Ok(match sealed_keys(conn).await? {
+        Some((_, viewing_keys_sealed)) => KeyProtection::Encrypted {
+            viewing_keys_sealed,
+        },
+        None => KeyProtection::Plaintext,
+    })
+}
+
+/// Writes the key material of every account, either in plaintext or sealed with the given key.
+///
+/// To seal the key material, the client state must be unlocked.
+async fn write_keys(
+    dbtx: &mut Transaction<'_, Sqlite>,
+    state: &ClientState,
+    sealing: Option<(&SealingKey, bool)>,
+) -> anyhow::Result<()> {
+    // Encrypted wallets keep their full viewing keys unless those are sealed too, while plaintext
+    // wallets derive them from their spend seeds, unless they are watch-only
+    let spend_seed = |wallet: &Wallet| match sealing {
+        Some(_) => None,
+        None => wallet
+            .spend_key()
+            .ok()
+            .map(|spend_key| spend_key.seed().0.to_vec()),
+    };
+    let full_viewing_key = |wallet: &Wallet| match sealing {
+        Some((_, false)) => Some(wallet.full_viewing_key().to_bytes().to_vec()),
+        Some((_, true)) => None,
+        None if wallet.is_unlocked() => None,
+        None => Some(wallet.full_viewing_key().to_bytes().to_vec()),
+    };
+
+    let wallet = &state.accounts[0].wallet;
+    sqlx::query(
+        "INSERT OR REPLACE INTO wallet
+        (id, spend_seed, seed_phrase, full_viewing_key, birthday_height)
+        VALUES (0, ?, ?, ?, ?)",
+    )
+    .bind(spend_seed(wallet))
+    .bind(match sealing {
+        Some(_) => None,
+        None => state.seed_phrase.as_ref().map(ToString::to_string),
+    })
+    .bind(full_viewing_key(wallet))
+    .bind(state.birthday_height.map(|height| height as i64))
+    .execute(&mut *dbtx)
+    .await?;
+
+    for (account_index, account) in state.accounts.iter().enumerate().skip(1) {
+        sqlx::query(
+            "INSERT OR REPLACE INTO accounts (account_index, label, spend_seed, full_viewing_key)
+            VALUES (?, ?, ?, ?)",
+        )
+        .bind(account_index as i64)
+        .bind(&account.label)
+        .bind(spend_seed(&account.wallet))
+        .bind(full_viewing_key(&account.wallet))
+        .execute(&mut *dbtx)
+        .await?;
+    }
+
+    match sealing {
+        Some((sealing_key, viewing_keys_sealed)) => {
+            let sealed = sealing_key.seal(&SecretKeys::from_state(state)?)?;
+            sqlx::query(
+                "INSERT OR REPLACE INTO sealed_keys
+                (id, salt, memory_cost, time_cost, parallelism, nonce, ciphertext,
+                viewing_keys_sealed)
+                VALUES (0, ?, ?, ?, ?, ?, ?, ?)",
+            )
+            .bind(sealed.salt)
+            .bind(sealed.params.memory_cost as i64)
+            .bind(sealed.params.time_cost as i64)
+            .bind(sealed.params.parallelism as i64)
+            .bind(sealed.nonce)
+            .bind(sealed.ciphertext)
+            .bind(viewing_keys_sealed)
+            .execute(&mut *dbtx)
+            .await?;
+        }
+        None => {
+            sqlx::query("DELETE FROM sealed_keys")
+                .execute(&mut *dbtx)
+                .await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns a set of changes covering the entire client state.
+fn all_changes(state: &ClientState) -> Changes {
+    let mut notes = BTreeSet::new();
+    notes.extend(state.unspent_set.keys());
+    notes.extend(state.submitted_spend_set.keys());
+    notes.extend(state.submitted_change_set.keys());
+    notes.extend(state.spent_set.keys());
+    notes.extend(state.quarantined_set.keys());
+    notes.extend(state.quarantined_spent_set.keys());
+    notes.extend(state.reverted_set.keys());
+    notes.extend(state.note_heights.keys());
+    notes.extend(state.delegation_rates.keys());
+    notes.extend(state.memos.keys());
+    notes.extend(state.note_accounts.keys());
+    notes.extend(state.note_labels.keys());
+    notes.extend(state.frozen_notes.iter());
+    notes.extend(state.sent_set.keys());
+
+    Changes {
+        notes,
+        nullifiers: state.nullifier_map.keys().copied().collect(),
+        transactions: state.transaction_history.keys().copied().collect(),
+        transaction_details: state.transaction_details.keys().copied().collect(),
+        submitted_transactions: state.submitted_transactions.keys().copied().collect(),
+        assets: true,
+        accounts: true,
+        chain_params: true,
+        sync_state: true,
+        contacts: true,
+        trusted_header: true,
+        unavailable_rates: true,
+    }
+}
+
+/// Writes the given changes to the client state.
+///
+/// The note commitment tree is rewritten as a whole whenever blocks have been scanned, so syncs
+/// commit only every so often rather than after every block.
+async fn write_changes(
+    dbtx: &mut Transaction<'_, Sqlite>,
+    state: &ClientState,
+    changes: &Changes,
+    sealing_key: Option<&SealingKey>,
+) -> anyhow::Result<()> {
+    if changes.sync_state || changes.chain_params {
+        let chain_params = if changes.chain_params {
+            state
+                .chain_params
+                .as_ref()
+                .map(serde_json::to_string)
+                .transpose()?
+        } else {
+            sqlx::query("SELECT chain_params FROM sync_state WHERE id = 0")
+                .fetch_optional(&mut *dbtx)
+                .await?
+                .map(|row| row.try_get("chain_params"))
+                .transpose()?
+                .flatten()
+        };
+        sqlx::query(
+            "INSERT OR REPLACE INTO sync_state (id, last_block_height, chain_params) \
+            VALUES (0, ?, ?)",
+        )
+        .bind(state.last_block_height.map(|height| height as i64))
+        .bind(chain_params)
+        .execute(&mut *dbtx)
+        .await?;
+    }
+
+    if changes.sync_state {
+        sqlx::query("INSERT OR REPLACE INTO note_commitment_tree (id, tree) VALUES (0, ?)")
+            .bind(bincode::serialize(&state.note_commitment_tree)?)
+            .execute(&mut *dbtx)
+            .await?;
+    }
+
+    if changes.accounts {
+        match (key_protection(&mut **dbtx).await?, sealing_key) {
+            (KeyProtection::Plaintext, _) => write_keys(dbtx, state, None).await?,
+            (
+                KeyProtection::Encrypted {
+                    viewing_keys_sealed,
+                },
+                Some(sealing_key),
+            ) => write_keys(dbtx, state, Some((sealing_key, viewing_keys_sealed))).await?,
+            // Without the password we can't seal the keys of new accounts, but a locked client
+            // state can't derive any, so only the labels of existing accounts and the birthday
+            // height can have changed
+            (KeyProtection::Encrypted { .. }, None) => {
+                sqlx::query("UPDATE wallet SET birthday_height = ? WHERE id = 0")
+                    .bind(state.birthday_height.map(|height| height as i64))
+                    .execute(&mut *dbtx)
+                    .await?;
+                for (account_index, account) in state.accounts.iter().enumerate().skip(1) {
+                    let result =
+                        sqlx::query("UPDATE accounts SET label = ? WHERE account_index = ?")
+                            .bind(&account.label)
+                            .bind(account_index as i64)
+                            .execute(&mut *dbtx)
+                            .await?;
+                    if result.rows_affected() == 0 {
+                        return Err(anyhow!(
+                            "cannot store the keys of account {} without the wallet password",
+                            account_index
+                        ));
+                    }
+                }
+            }
+        }
+
+        sqlx::query("DELETE FROM addresses")
+            .execute(&mut *dbtx)
+            .await?;
+        for (account_index, account) in state.accounts.iter().enumerate() {
+            for (address_index, label) in account.wallet.address_labels().iter().enumerate() {
+                sqlx::query(
+                    "INSERT INTO addresses (account_index, address_index, label) VALUES (?, ?, ?)",
+                )
+                .bind(account_index as i64)
+                .bind(address_index as i64)
+                .bind(label)
+                .execute(&mut *dbtx)
+                .await?;
+            }
+        }
+    }
+
+    if changes.assets {
+        for (asset_id, denom) in state.asset_cache.iter() {
+            sqlx::query("INSERT OR REPLACE INTO assets (asset_id, denom) VALUES (?, ?)")
+                .bind(asset_id.to_string())
+                .bind(denom.to_string())
+                .execute(&mut *dbtx)
+                .await?;
+        }
+    }
+
+    if changes.contacts {
+        sqlx::query("DELETE FROM contacts")
+            .execute(&mut *dbtx)
+            .await?;
+        for (name, contact) in &state.contacts {
+            sqlx::query("INSERT INTO contacts (name, contact) VALUES (?, ?)")
+                .bind(name)
+                .bind(contact.to_string())
+                .execute(&mut *dbtx)
+                .await?;
+        }
+    }
+
+    if changes.unavailable_rates {
+        sqlx::query("DELETE FROM unavailable_rates")
+            .execute(&mut *dbtx)
+            .await?;
+        for ((identity_key, epoch_index), checked_epoch_index) in &state.unavailable_rates {
+            sqlx::query(
+                "INSERT INTO unavailable_rates (identity_key, epoch_index, checked_epoch_index)
+                VALUES (?, ?, ?)",
+            )
+            .bind(identity_key.to_string())
+            .bind(*epoch_index as i64)
+            .bind(*checked_epoch_index as i64)
+            .execute(&mut *dbtx)
+            .await?;
+        }
+    }
+
+    if changes.trusted_header {
+        if let Some((height, hash)) = state.trusted_header {
+            sqlx::query(
+                "INSERT OR REPLACE INTO trusted_header (id, height, hash) VALUES (0, ?, ?)",
+            )
+            .bind(height as i64)
+            .bind(hash.to_vec())
+            .execute(&mut *dbtx)
+            .await?;
+        }
+    }
+
+    for nullifier in &changes.nullifiers {
+        if let Some(note_commitment) = state.nullifier_map.get(nullifier) {
+            sqlx::query(
+                "INSERT OR REPLACE INTO nullifiers (nullifier, note_commitment) VALUES (?, ?)",
+            )
+            .bind(nullifier.to_bytes().to_vec())
+            .bind(note_commitment.0.to_bytes().to_vec())
+            .execute(&mut *dbtx)
+            .await?;
+        } else {
+            sqlx::query("DELETE FROM nullifiers WHERE nullifier = ?")
+                .bind(nullifier.to_bytes().to_vec())
+                .execute(&mut *dbtx)
+                .await?;
+        }
+    }
+
+    for note_commitment in &changes.notes {
+        write_note(dbtx, state, note_commitment).await?;
+    }
+
+    for &(height, transaction_id) in &changes.transactions {
+        write_transaction(dbtx, state, height, transaction_id).await?;
+    }
+
+    for transaction_id in &changes.transaction_details {
+        if let Some(details) = state.transaction_details.get(transaction_id) {
+            sqlx::query(
+                "INSERT OR REPLACE INTO transaction_details
+                (transaction_id, fee, delegations, undelegations)
+                VALUES (?, ?, ?, ?)",
+            )
+            .bind(transaction_id.to_vec())
+            .bind(details.fee as i64)
+            .bind(serde_json::to_string(&details.delegations)?)
+            .bind(serde_json::to_string(&details.undelegations)?)
+            .execute(&mut *dbtx)
+            .await?;
+        } else {
+            sqlx::query("DELETE FROM transaction_details WHERE transaction_id = ?")
+                .bind(transaction_id.to_vec())
+                .execute(&mut *dbtx)
+                .await?;
+        }
+    }
+
+    for transaction_id in &changes.submitted_transactions {
+        write_submitted_transaction(dbtx, state, *transaction_id).await?;
+    }
+
+    Ok(())
+}
+
+/// Rewrites every row about the given note to match the client state.
+async fn write_note(
+    dbtx: &mut Transaction<'_, Sqlite>,
+    state: &ClientState,
+    note_commitment: &note::Commitment,
+) -> anyhow::Result<()> {
+    let commitment_bytes = note_commitment.0.to_bytes().to_vec();
+
+    for table in [
+        "notes",
+        "quarantined_spends",
+        "note_heights",
+        "delegation_rates",
+        "memos",
+        "note_accounts",
+        "note_labels",
+        "frozen_notes",
+        "sent_notes",
+    ] {
+        let query = format!("DELETE FROM {} WHERE note_commitment = ?", table);
+        sqlx::query(&query)
+            .bind(&commitment_bytes)
+            .execute(&mut *dbtx)
+            .await?;
+    }
+
+    let mut notes: Vec<(&str, &Note, Option<SystemTime>, Option<&QuarantinedNote>)> = Vec::new();
+    if let Some(note) = state.unspent_set.get(note_commitment) {
+        notes.push(("unspent", note, None, None));
+    }
+    if let Some((timeout, note)) = state.submitted_spend_set.get(note_commitment) {
+        notes.push(("submitted_spend", note, Some(*timeout), None));
+    }
+    if let Some((timeout, note)) = state.submitted_change_set.get(note_commitment) {
+        notes.push(("submitted_change", note, Some(*timeout), None));
+    }
+    if let Some(note) = state.spent_set.get(note_commitment) {
+        notes.push(("spent", note, None, None));
+    }
+    if let Some(quarantined) = state.quarantined_set.get(note_commitment) {
+        notes.push(("quarantined", &quarantined.note, None, Some(quarantined)));
+    }
+    if let Some(quarantined) = state.reverted_set.get(note_commitment) {
+        notes.push(("reverted", &quarantined.note, None, Some(quarantined)));
+    }
+    for (note_set, note, timeout, quarantined) in notes {
+        sqlx::query(
+            "INSERT INTO notes
+            (note_commitment, note_set, note, timeout, unbonding_height, validator_identity_key)
+            VALUES (?, ?, ?, ?, ?, ?)",
+        )
+        .bind(&commitment_bytes)
+        .bind(note_set)
+        .bind(note.to_bytes().to_vec())
+        .bind(timeout.map(|timeout| {
+            timeout
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis() as i64
+        }))
+        .bind(quarantined.map(|quarantined| quarantined.unbonding_height as i64))
+        .bind(quarantined.map(|quarantined| quarantined.validator_identity_key.to_string()))
+        .execute(&mut *dbtx)
+        .await?;
+    }
+
+    if let Some((unbonding_height, validator_identity_key)) =
+        state.quarantined_spent_set.get(note_commitment)
+    {
+        sqlx::query(
+            "INSERT INTO quarantined_spends
+            (note_commitment, unbonding_height, validator_identity_key)
+            VALUES (?, ?, ?)",
+        )
+        .bind(&commitment_bytes)
+        .bind(*unbonding_height as i64)
+        .bind(validator_identity_key.to_string())
+        .execute(&mut *dbtx)
+        .await?;
+    }
+
+    if let Some((received_height, spent_height)) = state.note_heights.get(note_commitment) {
+        sqlx::query(
+            "INSERT INTO note_heights (note_commitment, received_height, spent_height)
+            VALUES (?, ?, ?)",
+        )
+        .bind(&commitment_bytes)
+        .bind(*received_height as i64)
+        .bind(spent_height.map(|height| height as i64))
+        .execute(&mut *dbtx)
+        .await?;
+    }
+
+    if let Some((received_rate, spent_rate)) = state.delegation_rates.get(note_commitment) {
+        sqlx::query(
+            "INSERT INTO delegation_rates
+            (note_commitment, received_exchange_rate, spent_exchange_rate)
+            VALUES (?, ?, ?)",
+        )
+        .bind(&commitment_bytes)
+        .bind(received_rate.map(|rate| rate as i64))
+        .bind(spent_rate.map(|rate| rate as i64))
+        .execute(&mut *dbtx)
+        .await?;
+    }
+
+    if let Some(memo) = state.memos.get(note_commitment) {
+        sqlx::query("INSERT INTO memos (note_commitment, memo) VALUES (?, ?)")
+            .bind(&commitment_bytes)
+            .bind(memo.0.to_vec())
+            .execute(&mut *dbtx)
+            .await?;
+    }
+
+    if let Some(account) = state.note_accounts.get(note_commitment) {
+        sqlx::query("INSERT INTO note_accounts (note_commitment, account_index) VALUES (?, ?)")
+            .bind(&commitment_bytes)
+            .bind(*account as i64)
+            .execute(&mut *dbtx)
+            .await?;
+    }
+
+    if let Some(label) = state.note_labels.get(note_commitment) {
+        sqlx::query("INSERT INTO note_labels (note_commitment, label) VALUES (?, ?)")
+            .bind(&commitment_bytes)
+            .bind(label)
+            .execute(&mut *dbtx)
+            .await?;
+    }
+
+    if state.frozen_notes.contains(note_commitment) {
+        sqlx::query("INSERT INTO frozen_notes (note_commitment) VALUES (?)")
+            .bind(&commitment_bytes)
+            .execute(&mut *dbtx)
+            .await?;
+    }
+
+    if let Some(sent) = state.sent_set.get(note_commitment) {
+        sqlx::query(
+            "INSERT INTO sent_notes (note_commitment, note, recipient, height, memo)
+            VALUES (?, ?, ?, ?, ?)",
+        )
+        .bind(&commitment_bytes)
+        .bind(sent.note.to_bytes().to_vec())
+        .bind(sent.recipient.to_string())
+        .bind(sent.height as i64)
+        .bind(sent.memo.as_ref().map(|memo| memo.0.to_vec()))
+        .execute(&mut *dbtx)
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Rewrites the transaction history entry with the given key to match the client state.
+async fn write_transaction(
+    dbtx: &mut Transaction<'_, Sqlite>,
+    state: &ClientState,
+    height: u64,
+    transaction_id: [u8; 32],
+) -> anyhow::Result<()> {
+    for table in ["transaction_notes", "transactions"] {
+        let query = format!(
+            "DELETE FROM {} WHERE height = ? AND transaction_id = ?",
+            table
+        );
+        sqlx::query(&query)
+            .bind(height as i64)
+            .bind(transaction_id.to_vec())
+            .execute(&mut *dbtx)
+            .await?;
+    }
+
+    if let Some(record) = state.transaction_history.get(&(height, transaction_id)) {
+        sqlx::query("INSERT INTO transactions (height, transaction_id) VALUES (?, ?)")
+            .bind(height as i64)
+            .bind(transaction_id.to_vec())
+            .execute(&mut *dbtx)
+            .await?;
+
+        for (role, commitments) in [
+            ("received", &record.received),
+            ("spent", &record.spent),
+            ("sent", &record.sent),
+        ] {
+            for note_commitment in commitments {
+                sqlx::query(
+                    "INSERT INTO transaction_notes (height, transaction_id, role, note_commitment)
+                    VALUES (?, ?, ?, ?)",
+                )
+                .bind(height as i64)
+                .bind(transaction_id.to_vec())
+                .bind(role)
+                .bind(note_commitment.0.to_bytes().to_vec())
+                .execute(&mut *dbtx)
+                .await?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn commitment(row: &sqlx::sqlite::SqliteRow) -> anyhow::Result<note::Commitment> {
+    Ok(row
+        .try_get::<Vec<u8>, _>("note_commitment")?
+        .as_slice()
+        .try_into()?)
+}
+
+/// Rewrites the rows about the given submitted transaction to match the client state.
+async fn write_submitted_transaction(
+    dbtx: &mut Transaction<'_, Sqlite>,
+    state: &ClientState,
+    transaction_id: [u8; 32],
+) -> anyhow::Result<()> {
+    for table in ["submitted_transaction_notes", "submitted_transactions"] {
+        let query = format!("DELETE FROM {} WHERE transaction_id = ?", table);
+        sqlx::query(&query)
+            .bind(transaction_id.to_vec())
+            .execute(&mut *dbtx)
+            .await?;
+    }
+
+    if let Some(submitted) = state.submitted_transactions.get(&transaction_id) {
+        let (status, confirmed_height) = match submitted.status {
+            SubmittedTransactionStatus::Planned => ("planned", None),
+            SubmittedTransactionStatus::Pending => ("pending", None),
+            SubmittedTransactionStatus::Confirmed { height } => ("confirmed", Some(height as i64)),
+            SubmittedTransactionStatus::Expired => ("expired", None),
+            SubmittedTransactionStatus::Conflicted { height } => {
+                ("conflicted", Some(height as i64))
+            }
+        };
+        sqlx::query(
+            "INSERT INTO submitted_transactions
+            (transaction_id, expiry_height, status, confirmed_height)
+            VALUES (?, ?, ?, ?)",
+        )
+        .bind(transaction_id.to_vec())
+        .bind(submitted.expiry_height.map(|height| height as i64))
+        .bind(status)
+        .bind(confirmed_height)
+        .execute(&mut *dbtx)
+        .await?;
+
+        for (role, commitments) in [("spent", &submitted.spent), ("change", &submitted.change)] {
+            for note_commitment in commitments {
+                sqlx::query(
+                    "INSERT INTO submitted_transaction_notes
+                    (transaction_id, role, note_commitment)
+                    VALUES (?, ?, ?)",
+                )
+                .bind(transaction_id.to_vec())
+                .bind(role)
+                .bind(note_commitment.0.to_bytes().to_vec())
+                .execute(&mut *dbtx)
+                .await?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn transaction_id(row: &sqlx::sqlite::SqliteRow) -> anyhow::Result<[u8; 32]> {
+    row.try_get::<Vec<u8>, _>("transaction_id")?
+        .try_into()
+        .map_err(|_| anyhow!("invalid transaction id"))
+}
+
+fn transaction_key(row: &sqlx::sqlite::SqliteRow) -> anyhow::Result<(u64, [u8; 32])> {
+    Ok((
+        row.try_get::<i64, _>("height")? as u64,
+        transaction_id(row)?,
+    ))
+}
+
+fn decode_memo(memo: Vec<u8>) -> anyhow::Result<memo::MemoPlaintext> {
+    Ok(memo::MemoPlaintext(
+        memo.try_into()
+            .map_err(|_| anyhow!("invalid memo length"))?,
+    ))
+}
@@ -0,0 +1,153 @@
+use anyhow::anyhow;
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::{
+    aead::{Aead, NewAead},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use penumbra_crypto::keys::SpendSeed;
+use rand_core::{OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use serde_with::serde_as;
+
+use crate::ClientState;
+
+/// The length of the random salt used to derive a sealing key from a password.
+const SALT_LEN_BYTES: usize = 16;
+
+/// The length of the random nonce used each time secret key material is sealed.
+const NONCE_LEN_BYTES: usize = 12;
+
+/// The cost parameters of the Argon2id key derivation function.
+#[derive(Clone, Copy, Debug)]
+pub(super) struct KdfParams {
+    /// The memory cost, in KiB.
+    pub memory_cost: u32,
+    /// The number of passes over the memory.
+    pub time_cost: u32,
+    /// The number of lanes.
+    pub parallelism: u32,
+}
+
+impl Default for KdfParams {
+    fn default() -> Self {
+        Self {
+            memory_cost: 64 * 1024,
+            time_cost: 3,
+            parallelism: 1,
+        }
+    }
+}
+
+/// The secret key material of a wallet: its seed phrase, if it has one, and the spend seed of
+/// each of its accounts, in order of account index.
+#[serde_as]
+#[derive(Serialize, Deserialize)]
+pub(super) struct SecretKeys {
+    pub seed_phrase: Option<String>,
+    #[serde_as(as = "Vec<serde_with::hex::Hex>")]
+    pub spend_seeds: Vec<[u8; 32]>,
+}
+
+impl SecretKeys {
+    /// Collects the secret key material of the given client state, which must be unlocked.
+    pub fn from_state(state: &ClientState) -> anyhow::Result<Self> {
+        Ok(Self {
+            seed_phrase: state.seed_phrase.as_ref().map(ToString::to_string),
+            spend_seeds: state
+                .accounts
+                .iter()
+                .map(|account| -> anyhow::Result<[u8; 32]> {
+                    Ok(account.wallet.spend_key()?.seed().0)
+                })
+                .collect::<anyhow::Result<_>>()?,
+        })
+    }
+
+    /// Returns the spend seed of the given account.
+    pub fn spend_seed(&self, account: u64) -> anyhow::Result<SpendSeed> {
+        self.spend_seeds
+            .get(account as usize)
+            .map(|seed| SpendSeed(*seed))
+            .ok_or_else(|| anyhow!("no sealed spend seed for account {}", account))
+    }
+}
+
+/// A key derived from the wallet password, used to seal and open its secret key material.
+pub(super) struct SealingKey {
+    key: [u8; 32],
+    salt: Vec<u8>,
+    params: KdfParams,
+}
+
+impl SealingKey {
+    /// Derives a sealing key from a new password, with a fresh salt.
+    pub fn new(password: &str) -> anyhow::Result<Self> {
+        let mut salt = vec![0u8; SALT_LEN_BYTES];
+        OsRng.fill_bytes(&mut salt);
+        Self::derive(password, salt, KdfParams::default())
+    }
+
+    fn derive(password: &str, salt: Vec<u8>, params: KdfParams) -> anyhow::Result<Self> {
+        let argon2 = Argon2::new(
+            Algorithm::Argon2id,
+            Version::V0x13,
+            Params::new(
+                params.memory_cost,
+                params.time_cost,
+                params.parallelism,
+                Some(32),
+            )
+            .map_err(|e| anyhow!("invalid key derivation parameters: {}", e))?,
+        );
+        let mut key = [0u8; 32];
+        argon2
+            .hash_password_into(password.as_bytes(), &salt, &mut key)
+            .map_err(|e| anyhow!("could not derive key from password: {}", e))?;
+
+        Ok(Self { key, salt, params })
+    }
+
+    /// Seals the given secret key material under a fresh nonce.
+    pub fn seal(&self, secrets: &SecretKeys) -> anyhow::Result<SealedKeys> {
+        let mut nonce = vec![0u8; NONCE_LEN_BYTES];
+        OsRng.fill_bytes(&mut nonce);
+
+        let plaintext = serde_json::to_vec(secrets)?;
+        let ciphertext = ChaCha20Poly1305::new(Key::from_slice(&self.key))
+            .encrypt(Nonce::from_slice(&nonce), plaintext.as_ref())
+            .map_err(|_| anyhow!("could not seal secret key material"))?;
+
+        Ok(SealedKeys {
+            salt: self.salt.clone(),
+            params: self.params,
+            nonce,
+            ciphertext,
+        })
+    }
+}
+
+/// Secret key material sealed with a password, as stored in the wallet database.
+pub(super) struct SealedKeys {
+    pub salt: Vec<u8>,
+    pub params: KdfParams,
+    pub nonce: Vec<u8>,
+    pub ciphertext: Vec<u8>,
+}
+
+impl SealedKeys {
+    /// Opens the sealed key material with the given password, returning it along with the
+    /// sealing key so that it can be resealed later.
+    pub fn open(&self, password: &str) -> anyhow::Result<(SealingKey, SecretKeys)> {
+        if self.nonce.len() != NONCE_LEN_BYTES {
+            return Err(anyhow!("invalid nonce length for sealed keys"));
+        }
+
+        let key = SealingKey::derive(password, self.salt.clone(), self.params)?;
+        let plaintext = ChaCha20Poly1305::new(Key::from_slice(&key.key))
+            .decrypt(Nonce::from_slice(&self.nonce), self.ciphertext.as_ref())
+            .map_err(|_| anyhow!("incorrect password"))?;
+        let secrets = serde_json::from_slice(&plaintext)?;
+
+        Ok((key, secrets))
+    }
+}
@@ -0,0 +1,1088 @@
+use penumbra_crypto::{
+    keys::{SpendKey, SpendSeed},
+    rdsa::{SigningKey, SpendAuth},
+};
+use rand_core::OsRng;
+
+use super::*;
+
+/// A chain of synthetic compact blocks, with the note commitment tree needed to compute their
+/// anchors.
+struct TestChain {
+    tree: NoteCommitmentTree,
+    next_height: u64,
+}
+
+impl TestChain {
+    fn new() -> Self {
+        Self {
+            tree: NoteCommitmentTree::new(0),
+            next_height: 0,
+        }
+    }
+
+    /// Returns the next block, holding the given fragments and the nullifiers revealed by each
+    /// of the given transactions.
+    fn block(
+        &mut self,
+        fragments: Vec<StateFragment>,
+        nullifiers: Vec<(Nullifier, [u8; 32])>,
+    ) -> CompactBlock {
+        for fragment in &fragments {
+            self.tree
+                .append(&note::Commitment::try_from(&fragment.note_commitment[..]).unwrap());
+        }
+        let height = self.next_height;
+        self.next_height += 1;
+
+        CompactBlock {
+            height,
+            fragments,
+            nullifiers: nullifiers
+                .iter()
+                .map(|(nullifier, _)| nullifier.to_bytes().to_vec().into())
+                .collect(),
+            nullifier_transaction_ids: nullifiers
+                .iter()
+                .map(|(_, transaction_id)| transaction_id.to_vec().into())
+                .collect(),
+            nct_anchor: self.tree.root2().to_bytes().to_vec().into(),
+            ..Default::default()
+        }
+    }
+
+    /// Returns the next block, which is empty.
+    fn empty_block(&mut self) -> CompactBlock {
+        self.block(Vec::new(), Vec::new())
+    }
+}
+
+/// Returns the state fragment of a new note, included in the transaction with the given ID.
+fn fragment(note: &Note, transaction_id: [u8; 32]) -> StateFragment {
+    let esk = ka::Secret::new(&mut OsRng);
+    StateFragment {
+        note_commitment: note.commit().0.to_bytes().to_vec().into(),
+        ephemeral_key: esk
+            .diversified_public(&note.diversified_generator())
+            .0
+            .to_vec()
+            .into(),
+        encrypted_note: note.encrypt(&esk).to_vec().into(),
+        transaction_id: transaction_id.to_vec().into(),
+        ..Default::default()
+    }
+}
+
+fn upenumbra(amount: u64) -> Value {
+    Value {
+        amount,
+        asset_id: *STAKING_TOKEN_ASSET_ID,
+    }
+}
+
+/// Returns a new client state with the chain parameters and the staking token's denomination set.
+fn client_state() -> ClientState {
+    let mut state = ClientState::from_seed_phrase(SeedPhrase::generate(&mut OsRng));
+    *state.chain_params_mut() = Some(ChainParams {
+        chain_id: "penumbra-test".to_string(),
+        epoch_duration: 10,
+        ..Default::default()
+    });
+    state
+        .asset_cache_mut()
+        .extend([STAKING_TOKEN_DENOM.clone()]);
+    state
+}
+
+/// Returns the address with the given index of the given account of the client state.
+fn address(state: &ClientState, account: u64, index: usize) -> Address {
+    state
+        .account(account)
+        .unwrap()
+        .address_by_index(index)
+        .unwrap()
+        .1
+}
+
+/// Returns an address which doesn't belong to the client state.
+fn other_address() -> Address {
+    let sk = SpendKey::new(SpendSeed::from_seed_phrase(
+        SeedPhrase::generate(&mut OsRng),
+        0,
+    ));
+    sk.full_viewing_key()
+        .incoming()
+        .payment_address(0u64.into())
+        .0
+}
+
+/// Scans a block in which the client state receives a note of the given value to the given
+/// address, returning the note.
+fn receive(
+    state: &mut ClientState,
+    chain: &mut TestChain,
+    address: &Address,
+    value: Value,
+) -> Note {
+    let note = Note::generate(&mut OsRng, address, value);
+    state
+        .scan_block(chain.block(vec![fragment(&note, [1; 32])], Vec::new()))
+        .unwrap();
+    note
+}
+
+/// Returns the nullifier of one of the client state's notes.
+fn nullifier(state: &ClientState, note: &Note) -> Nullifier {
+    *state
+        .nullifier_map
+        .iter()
+        .find(|(_, note_commitment)| **note_commitment == note.commit())
+        .unwrap()
+        .0
+}
+
+/// Tracks a pending transaction with the given ID spending and creating the given notes, which
+/// are registered as submitted.
+fn submit(
+    state: &mut ClientState,
+    transaction_id: [u8; 32],
+    spent: &[&Note],
+    change: &[&Note],
+    expiry_height: u64,
+) {
+    for note in spent {
+        state.register_spend(note);
+    }
+    for note in change {
+        state.register_change(0, (*note).clone());
+    }
+    state.submitted_transactions.insert(
+        transaction_id,
+        SubmittedTransaction {
+            spent: spent.iter().map(|note| note.commit()).collect(),
+            change: change.iter().map(|note| note.commit()).collect(),
+            expiry_height: Some(expiry_height),
+            status: SubmittedTransactionStatus::Pending,
+        },
+    );
+}
+
+fn status(state: &ClientState, transaction_id: [u8; 32]) -> SubmittedTransactionStatus {
+    state.submitted_transaction(&transaction_id).unwrap().status
+}
+
+#[test]
+fn submitted_transaction_confirmed_by_nullifier() {
+    let mut state = client_state();
+    let mut chain = TestChain::new();
+    let note = receive(
+        &mut state,
+        &mut chain,
+        &address(&state, 0, 0),
+        upenumbra(10),
+    );
+
+    submit(&mut state, [2; 32], &[&note], &[], 10);
+    assert!(state.unspent_set.is_empty());
+
+    let nullifier = nullifier(&state, &note);
+    state
+        .scan_block(chain.block(Vec::new(), vec![(nullifier, [2; 32])]))
+        .unwrap();
+
+    assert_eq!(
+        status(&state, [2; 32]),
+        SubmittedTransactionStatus::Confirmed { height: 1 }
+    );
+    assert!(state.spent_set.contains_key(&note.commit()));
+    assert!(state.submitted_spend_set.is_empty());
+}
+
+#[test]
+fn submitted_transaction_confirmed_by_change() {
+    let mut state = client_state();
+    let mut chain = TestChain::new();
+    state.scan_block(chain.empty_block()).unwrap();
+
+    let change = Note::generate(&mut OsRng, &address(&state, 0, 0), upenumbra(5));
+    submit(&mut state, [2; 32], &[], &[&change], 10);
+
+    state
+        .scan_block(chain.block(vec![fragment(&change, [2; 32])], Vec::new()))
+        .unwrap();
+
+    assert_eq!(
+        status(&state, [2; 32]),
+        SubmittedTransactionStatus::Confirmed { height: 1 }
+    );
+    assert!(state.unspent_set.contains_key(&change.commit()));
+    assert!(state.submitted_change_set.is_empty());
+}
+
+#[test]
+fn submitted_transaction_expires_after_expiry_height() {
+    let mut state = client_state();
+    let mut chain = TestChain::new();
+    let note = receive(
+        &mut state,
+        &mut chain,
+        &address(&state, 0, 0),
+        upenumbra(10),
+    );
+    let change = Note::generate(&mut OsRng, &address(&state, 0, 0), upenumbra(5));
+
+    submit(&mut state, [2; 32], &[&note], &[&change], 2);
+
+    // The transaction can still be included in the block at its expiry height.
+    state.scan_block(chain.empty_block()).unwrap();
+    assert_eq!(status(&state, [2; 32]), SubmittedTransactionStatus::Pending);
+    assert!(state.submitted_spend_set.contains_key(&note.commit()));
+
+    // Once that block has been scanned without it, it has expired.
+    state.scan_block(chain.empty_block()).unwrap();
+    assert_eq!(status(&state, [2; 32]), SubmittedTransactionStatus::Expired);
+    assert!(state.unspent_set.contains_key(&note.commit()));
+    assert!(state.submitted_spend_set.is_empty());
+    assert!(state.submitted_change_set.is_empty());
+}
+
+#[test]
+fn submitted_transaction_confirmed_at_expiry_height() {
+    let mut state = client_state();
+    let mut chain = TestChain::new();
+    let note = receive(
+        &mut state,
+        &mut chain,
+        &address(&state, 0, 0),
+        upenumbra(10),
+    );
+
+    submit(&mut state, [2; 32], &[&note], &[], 2);
+    state.scan_block(chain.empty_block()).unwrap();
+
+    let nullifier = nullifier(&state, &note);
+    state
+        .scan_block(chain.block(Vec::new(), vec![(nullifier, [2; 32])]))
+        .unwrap();
+
+    assert_eq!(
+        status(&state, [2; 32]),
+        SubmittedTransactionStatus::Confirmed { height: 2 }
+    );
+    assert!(state.spent_set.contains_key(&note.commit()));
+}
+
+#[test]
+fn submitted_transaction_conflicts_with_other_spend() {
+    let mut state = client_state();
+    let mut chain = TestChain::new();
+    let note = receive(
+        &mut state,
+        &mut chain,
+        &address(&state, 0, 0),
+        upenumbra(10),
+    );
+    let change = Note::generate(&mut OsRng, &address(&state, 0, 0), upenumbra(5));
+
+    submit(&mut state, [2; 32], &[&note], &[&change], 10);
+
+    // Another transaction, perhaps built from a copy of the wallet, spends the same note.
+    let nullifier = nullifier(&state, &note);
+    state
+        .scan_block(chain.block(Vec::new(), vec![(nullifier, [3; 32])]))
+        .unwrap();
+
+    assert_eq!(
+        status(&state, [2; 32]),
+        SubmittedTransactionStatus::Conflicted { height: 1 }
+    );
+    assert!(state.spent_set.contains_key(&note.commit()));
+    assert!(state.submitted_change_set.is_empty());
+}
+
+#[test]
+fn prune_timeouts_leaves_tracked_transactions_alone() {
+    let mut state = client_state();
+    let mut chain = TestChain::new();
+    let tracked = receive(
+        &mut state,
+        &mut chain,
+        &address(&state, 0, 0),
+        upenumbra(10),
+    );
+    let untracked = receive(
+        &mut state,
+        &mut chain,
+        &address(&state, 0, 0),
+        upenumbra(20),
+    );
+
+    submit(&mut state, [2; 32], &[&tracked], &[], 10);
+    state.register_spend(&untracked);
+
+    // Both spends have timed out.
+    for (timeout, _) in state.submitted_spend_set.values_mut() {
+        *timeout = SystemTime::now() - Duration::from_secs(1);
+    }
+    state.prune_timeouts();
+
+    // Only the spend which isn't tracked until an expiry height is returned to the unspent set.
+    assert!(state.submitted_spend_set.contains_key(&tracked.commit()));
+    assert!(state.unspent_set.contains_key(&untracked.commit()));
+
+    // Once the tracked transaction expires, its spend is returned as well.
+    state
+        .submitted_transactions
+        .get_mut(&[2; 32])
+        .unwrap()
+        .expiry_height = Some(2);
+    state.scan_block(chain.empty_block()).unwrap();
+    assert_eq!(status(&state, [2; 32]), SubmittedTransactionStatus::Expired);
+    assert!(state.unspent_set.contains_key(&tracked.commit()));
+    state.prune_timeouts();
+    assert!(state.submitted_spend_set.is_empty());
+}
+
+#[test]
+fn prune_timeouts_leaves_planned_transactions_alone() {
+    let mut state = client_state();
+    let mut chain = TestChain::new();
+    receive(
+        &mut state,
+        &mut chain,
+        &address(&state, 0, 0),
+        upenumbra(10),
+    );
+
+    let plan = state
+        .plan_send(
+            &mut OsRng,
+            0,
+            &[Payment {
+                address: other_address(),
+                values: vec![upenumbra(4)],
+                memo: None,
+            }],
+            0,
+            NoteSource::default(),
+        )
+        .unwrap();
+    let plan_id = state.register_plan(&plan);
+    assert_eq!(status(&state, plan_id), SubmittedTransactionStatus::Planned);
+
+    for (timeout, _) in state.submitted_spend_set.values_mut() {
+        *timeout = SystemTime::now() - Duration::from_secs(1);
+    }
+    for (timeout, _) in state.submitted_change_set.values_mut() {
+        *timeout = SystemTime::now() - Duration::from_secs(1);
+    }
+    state.prune_timeouts();
+
+    assert!(state.unspent_set.is_empty());
+    assert_eq!(state.submitted_spend_set.len(), 1);
+    assert_eq!(state.submitted_change_set.len(), 1);
+}
+
+#[test]
+fn scan_block_before_birthday_finds_no_notes() {
+    let mut state = client_state();
+    let mut chain = TestChain::new();
+    state.set_birthday_height(2);
+
+    // Blocks before the birthday height are only added to the note commitment tree.
+    let early = receive(
+        &mut state,
+        &mut chain,
+        &address(&state, 0, 0),
+        upenumbra(10),
+    );
+    state.scan_block(chain.empty_block()).unwrap();
+    assert!(state.unspent_set.is_empty());
+    assert!(state.nullifier_map.is_empty());
+    assert_eq!(state.note_commitment_tree.root2(), chain.tree.root2());
+    assert_eq!(state.last_block_height(), Some(1));
+
+    // Blocks from the birthday height onwards are searched for notes.
+    let late = receive(
+        &mut state,
+        &mut chain,
+        &address(&state, 0, 0),
+        upenumbra(20),
+    );
+    assert!(!state.unspent_set.contains_key(&early.commit()));
+    assert!(state.unspent_set.contains_key(&late.commit()));
+    assert_eq!(state.note_commitment_tree.root2(), chain.tree.root2());
+}
+
+#[test]
+fn rescan_refuses_birthday_after_earliest_note() {
+    let mut state = client_state();
+    let mut chain = TestChain::new();
+    state.scan_block(chain.empty_block()).unwrap();
+    let note = receive(
+        &mut state,
+        &mut chain,
+        &address(&state, 0, 0),
+        upenumbra(10),
+    );
+
+    assert!(state.rescan(Some(2)).is_err());
+    assert!(state.unspent_set.contains_key(&note.commit()));
+    assert_eq!(state.birthday_height(), None);
+    assert_eq!(state.last_block_height(), Some(1));
+
+    state.rescan(Some(1)).unwrap();
+    assert!(state.unspent_set.is_empty());
+    assert_eq!(state.birthday_height(), Some(1));
+    assert_eq!(state.last_block_height(), None);
+}
+
+#[test]
+fn scan_block_rejects_wrong_anchor_without_changing_state() {
+    let mut state = client_state();
+    let mut chain = TestChain::new();
+    let spent = receive(
+        &mut state,
+        &mut chain,
+        &address(&state, 0, 0),
+        upenumbra(10),
+    );
+    let root = state.note_commitment_tree.root2();
+
+    let received = Note::generate(&mut OsRng, &address(&state, 0, 0), upenumbra(20));
+    let block = chain.block(
+        vec![fragment(&received, [2; 32])],
+        vec![(nullifier(&state, &spent), [2; 32])],
+    );
+    let mut wrong_block = block.clone();
+    wrong_block.nct_anchor = root.to_bytes().to_vec().into();
+
+    assert!(state.scan_block(wrong_block).is_err());
+    assert_eq!(state.last_block_height(), Some(0));
+    assert_eq!(state.note_commitment_tree.root2(), root);
+    assert!(state.unspent_set.contains_key(&spent.commit()));
+    assert!(!state.unspent_set.contains_key(&received.commit()));
+    assert!(state.spent_set.is_empty());
+    assert_eq!(state.nullifier_map.len(), 1);
+    assert_eq!(state.transaction_history.len(), 1);
+
+    // The right block is then scanned as if the wrong one had never been seen.
+    state.scan_block(block).unwrap();
+    assert_eq!(state.note_commitment_tree.root2(), chain.tree.root2());
+    assert!(state.spent_set.contains_key(&spent.commit()));
+    assert!(state.unspent_set.contains_key(&received.commit()));
+}
+
+#[test]
+fn build_send_makes_every_payment() {
+    let mut state = client_state();
+    let mut chain = TestChain::new();
+    let note = receive(
+        &mut state,
+        &mut chain,
+        &address(&state, 0, 0),
+        upenumbra(100),
+    );
+
+    let payments = [
+        Payment {
+            address: other_address(),
+            values: vec![upenumbra(10)],
+            memo: Some("first".to_string()),
+        },
+        Payment {
+            address: other_address(),
+            values: vec![upenumbra(20)],
+            memo: None,
+        },
+    ];
+    let transaction = state
+        .build_send(&mut OsRng, 0, &payments, 1, NoteSource::Any)
+        .unwrap();
+
+    // One output for each payment, and one for the change.
+    let outputs = transaction
+        .transaction_body()
+        .actions
+        .iter()
+        .filter(|action| matches!(action, Action::Output(_)))
+        .count();
+    assert_eq!(outputs, 3);
+    assert_eq!(transaction.transaction_body().fee.0, 1);
+
+    let change = state
+        .submitted_change_set
+        .values()
+        .map(|(_, note)| note.amount())
+        .collect::<Vec<_>>();
+    assert_eq!(change, vec![69]);
+    assert!(state.submitted_spend_set.contains_key(&note.commit()));
+
+    // The transaction is only tracked once it has been submitted.
+    assert!(state.submitted_transaction(&transaction.id()).is_none());
+    state.register_transaction(&transaction);
+    let submitted = state.submitted_transaction(&transaction.id()).unwrap();
+    assert_eq!(submitted.status, SubmittedTransactionStatus::Pending);
+    assert!(submitted.spent.contains(&note.commit()));
+    assert_eq!(submitted.change.len(), 1);
+    assert_eq!(state.transaction_details[&transaction.id()].fee, 1);
+}
+
+/// Returns the next block, which includes the given transaction.
+fn transaction_block(chain: &mut TestChain, transaction: &Transaction) -> CompactBlock {
+    let mut fragments = Vec::new();
+    let mut nullifiers = Vec::new();
+    for action in &transaction.transaction_body().actions {
+        match action {
+            Action::Output(output) => fragments.push(StateFragment {
+                note_commitment: output.body.note_commitment.0.to_bytes().to_vec().into(),
+                ephemeral_key: output.body.ephemeral_key.0.to_vec().into(),
+                encrypted_note: output.body.encrypted_note.to_vec().into(),
+                value_commitment: <[u8; 32]>::from(output.body.value_commitment)
+                    .to_vec()
+                    .into(),
+                ovk_wrapped_key: output.ovk_wrapped_key.to_vec().into(),
+                encrypted_memo: output.encrypted_memo.0.to_vec().into(),
+                transaction_id: transaction.id().to_vec().into(),
+            }),
+            Action::Spend(spend) => nullifiers.push((spend.body.nullifier, transaction.id())),
+            _ => {}
+        }
+    }
+    chain.block(fragments, nullifiers)
+}
+
+#[test]
+fn scanned_transaction_recovers_sent_memo() {
+    let mut state = client_state();
+    let mut chain = TestChain::new();
+    let note = receive(
+        &mut state,
+        &mut chain,
+        &address(&state, 0, 0),
+        upenumbra(100),
+    );
+
+    let payments = [Payment {
+        address: other_address(),
+        values: vec![upenumbra(10)],
+        memo: Some("thanks".to_string()),
+    }];
+    let transaction = state
+        .build_send(&mut OsRng, 0, &payments, 1, NoteSource::Any)
+        .unwrap();
+    state.register_transaction(&transaction);
+
+    state
+        .scan_block(transaction_block(&mut chain, &transaction))
+        .unwrap();
+    assert!(matches!(
+        status(&state, transaction.id()),
+        SubmittedTransactionStatus::Confirmed { .. }
+    ));
+    assert!(state.spent_set.contains_key(&note.commit()));
+
+    // The memo is recovered from the note we sent, and the fee from the recorded details.
+    let entry = state
+        .transaction_history()
+        .find(|entry| entry.transaction_id == transaction.id())
+        .unwrap();
+    assert_eq!(entry.memo.unwrap().to_string(), "thanks");
+    assert_eq!(entry.details.unwrap().fee, 1);
+    assert_eq!(entry.sent.len(), 2);
+}
+
+#[test]
+fn scanned_transaction_does_not_recover_dummy_outputs() {
+    let mut state = client_state();
+    let mut chain = TestChain::new();
+    receive(
+        &mut state,
+        &mut chain,
+        &address(&state, 0, 0),
+        upenumbra(11),
+    );
+
+    // With no change, the transaction is padded with a dummy output.
+    let payments = [Payment {
+        address: other_address(),
+        values: vec![upenumbra(10)],
+        memo: None,
+    }];
+    let transaction = state
+        .build_send(&mut OsRng, 0, &payments, 1, NoteSource::Any)
+        .unwrap();
+    let outputs = transaction
+        .transaction_body()
+        .actions
+        .iter()
+        .filter(|action| matches!(action, Action::Output(_)))
+        .count();
+    assert_eq!(outputs, 2);
+    state.register_transaction(&transaction);
+    state
+        .scan_block(transaction_block(&mut chain, &transaction))
+        .unwrap();
+
+    // Only the payment is recorded as sent.
+    assert_eq!(state.sent_set.len(), 1);
+    let (_, sent) = state.sent_set.iter().next().unwrap();
+    assert_eq!(sent.note.value(), upenumbra(10));
+    let entry = state
+        .transaction_history()
+        .find(|entry| entry.transaction_id == transaction.id())
+        .unwrap();
+    assert_eq!(entry.sent.len(), 1);
+}
+
+#[test]
+fn build_send_respects_address_linking() {
+    let mut state = client_state();
+    let mut chain = TestChain::new();
+    state
+        .account_mut(0)
+        .unwrap()
+        .new_address("second".to_string());
+    receive(
+        &mut state,
+        &mut chain,
+        &address(&state, 0, 0),
+        upenumbra(10),
+    );
+    receive(
+        &mut state,
+        &mut chain,
+        &address(&state, 0, 1),
+        upenumbra(10),
+    );
+
+    let payments = [Payment {
+        address: other_address(),
+        values: vec![upenumbra(15)],
+        memo: None,
+    }];
+
+    // No one address received enough to pay, and spending from both would link them.
+    assert!(state
+        .build_send(&mut OsRng, 0, &payments, 0, NoteSource::Any)
+        .is_err());
+
+    state.set_link_addresses(true);
+    let transaction = state
+        .build_send(&mut OsRng, 0, &payments, 0, NoteSource::Any)
+        .unwrap();
+    assert!(state.unspent_set.is_empty());
+
+    // Cancelling the transaction makes its notes spendable again.
+    state.cancel_transaction(&transaction);
+    assert_eq!(state.unspent_set.len(), 2);
+    assert!(state.submitted_spend_set.is_empty());
+    assert!(state.submitted_change_set.is_empty());
+    assert!(state.submitted_transaction(&transaction.id()).is_none());
+}
+
+/// Scans a block at which an undelegation from the given validator spends `spent` and creates
+/// `output`, both quarantined until `unbonding_height`.
+fn undelegate(
+    state: &mut ClientState,
+    chain: &mut TestChain,
+    spent: &Note,
+    output: &Note,
+    unbonding_height: u64,
+    validator_identity_key: &IdentityKey,
+) {
+    let nullifier = nullifier(state, spent);
+    let mut block = chain.block(Vec::new(), vec![(nullifier, [5; 32])]);
+    block.quarantined_fragments = vec![QuarantinedStateFragment {
+        fragment: Some(fragment(output, [5; 32])),
+        unbonding_height,
+        validator_identity_key: Some(validator_identity_key.clone().into()),
+    }];
+    block.quarantined_nullifiers = vec![QuarantinedNullifier {
+        nullifier: nullifier.to_bytes().to_vec().into(),
+        unbonding_height,
+        validator_identity_key: Some(validator_identity_key.clone().into()),
+    }];
+    state.scan_block(block).unwrap();
+}
+
+#[test]
+fn quarantined_undelegation_unbonds() {
+    let mut state = client_state();
+    let mut chain = TestChain::new();
+    let validator_identity_key = IdentityKey(SigningKey::<SpendAuth>::new(OsRng).into());
+    let spent = receive(
+        &mut state,
+        &mut chain,
+        &address(&state, 0, 0),
+        upenumbra(100),
+    );
+    let output = Note::generate(&mut OsRng, &address(&state, 0, 0), upenumbra(90));
+
+    undelegate(
+        &mut state,
+        &mut chain,
+        &spent,
+        &output,
+        5,
+        &validator_identity_key,
+    );
+    assert!(state.quarantined_set.contains_key(&output.commit()));
+    assert!(state.spent_set.contains_key(&spent.commit()));
+    assert!(state.quarantined_spent_set.contains_key(&spent.commit()));
+    assert!(state.unspent_set.is_empty());
+
+    // The output is added to the note commitment tree at its unbonding height.
+    for _ in 2..5 {
+        state.scan_block(chain.empty_block()).unwrap();
+    }
+    state
+        .scan_block(chain.block(vec![fragment(&output, [5; 32])], Vec::new()))
+        .unwrap();
+    assert!(state.quarantined_set.is_empty());
+    assert!(state.unspent_set.contains_key(&output.commit()));
+
+    // The spend becomes permanent at the end of the epoch.
+    for _ in 6..9 {
+        state.scan_block(chain.empty_block()).unwrap();
+    }
+    assert!(state.quarantined_spent_set.contains_key(&spent.commit()));
+    state.scan_block(chain.empty_block()).unwrap();
+    assert!(state.quarantined_spent_set.is_empty());
+    assert!(state.spent_set.contains_key(&spent.commit()));
+}
+
+#[test]
+fn quarantined_undelegation_reverted_by_slashing() {
+    let mut state = client_state();
+    let mut chain = TestChain::new();
+    let validator_identity_key = IdentityKey(SigningKey::<SpendAuth>::new(OsRng).into());
+    let other_identity_key = IdentityKey(SigningKey::<SpendAuth>::new(OsRng).into());
+    let spent = receive(
+        &mut state,
+        &mut chain,
+        &address(&state, 0, 0),
+        upenumbra(100),
+    );
+    let output = Note::generate(&mut OsRng, &address(&state, 0, 0), upenumbra(90));
+
+    undelegate(
+        &mut state,
+        &mut chain,
+        &spent,
+        &output,
+        5,
+        &validator_identity_key,
+    );
+
+    // Slashing another validator leaves the quarantine alone.
+    let mut block = chain.empty_block();
+    block.slashed = vec![other_identity_key.into()];
+    state.scan_block(block).unwrap();
+    assert!(state.quarantined_set.contains_key(&output.commit()));
+    assert!(state.spent_set.contains_key(&spent.commit()));
+
+    let mut block = chain.empty_block();
+    block.slashed = vec![validator_identity_key.into()];
+    state.scan_block(block).unwrap();
+
+    // The output will never unbond, and the spent note can be spent again.
+    assert!(state.quarantined_set.is_empty());
+    assert!(state.reverted_set.contains_key(&output.commit()));
+    assert!(state.quarantined_spent_set.is_empty());
+    assert!(state.spent_set.is_empty());
+    assert!(state.unspent_set.contains_key(&spent.commit()));
+    assert_eq!(state.note_heights[&spent.commit()], (0, None));
+    assert!(state
+        .transaction_history
+        .values()
+        .all(|record| record.spent.is_empty()));
+}
+
+#[test]
+fn notes_to_spend_does_not_link_addresses_by_default() {
+    let mut state = client_state();
+    let mut chain = TestChain::new();
+    for label in ["first", "second"] {
+        state.account_mut(0).unwrap().new_address(label.to_string());
+    }
+    receive(
+        &mut state,
+        &mut chain,
+        &address(&state, 0, 1),
+        upenumbra(60),
+    );
+    receive(
+        &mut state,
+        &mut chain,
+        &address(&state, 0, 2),
+        upenumbra(60),
+    );
+
+    // Neither address holds enough by itself, so the spend fails rather than linking them...
+    let error = state
+        .notes_to_spend(&mut OsRng, 0, 100, &STAKING_TOKEN_DENOM, None)
+        .unwrap_err();
+    assert!(error.to_string().contains("no single address"), "{}", error);
+    assert_eq!(state.unspent_set.len(), 2);
+
+    // ... unless linking addresses is enabled.
+    state.set_link_addresses(true);
+    let notes = state
+        .notes_to_spend(&mut OsRng, 0, 100, &STAKING_TOKEN_DENOM, None)
+        .unwrap();
+    assert_eq!(notes.len(), 2);
+}
+
+#[test]
+fn frozen_notes_are_not_spent() {
+    let mut state = client_state();
+    let mut chain = TestChain::new();
+    let note = receive(
+        &mut state,
+        &mut chain,
+        &address(&state, 0, 0),
+        upenumbra(10),
+    );
+    state.freeze_note(note.commit()).unwrap();
+
+    let payments = [Payment {
+        address: other_address(),
+        values: vec![upenumbra(5)],
+        memo: None,
+    }];
+    assert!(state
+        .build_send(&mut OsRng, 0, &payments, 0, NoteSource::Any)
+        .is_err());
+    assert!(state
+        .build_send(
+            &mut OsRng,
+            0,
+            &payments,
+            0,
+            NoteSource::Notes(vec![note.commit()])
+        )
+        .is_err());
+    assert!(state.unspent_set.contains_key(&note.commit()));
+
+    state.unfreeze_note(note.commit()).unwrap();
+    state
+        .build_send(
+            &mut OsRng,
+            0,
+            &payments,
+            0,
+            NoteSource::Notes(vec![note.commit()]),
+        )
+        .unwrap();
+    assert!(state.unspent_set.is_empty());
+}
+
+/// Returns a client state which has received a note of the given amount of the given validator's
+/// delegation token, along with that note.
+fn receive_delegation(
+    chain: &mut TestChain,
+    validator_identity_key: &IdentityKey,
+    amount: u64,
+) -> (ClientState, Note) {
+    let mut state = client_state();
+    let delegation_token = DelegationToken::new(validator_identity_key.clone());
+    state.asset_cache_mut().extend([delegation_token.denom()]);
+    let note = receive(
+        &mut state,
+        chain,
+        &address(&state, 0, 0),
+        Value {
+            amount,
+            asset_id: delegation_token.id(),
+        },
+    );
+    (state, note)
+}
+
+#[test]
+fn delegation_records_record_exchange_rates() {
+    let mut chain = TestChain::new();
+    let validator_identity_key = IdentityKey(SigningKey::<SpendAuth>::new(OsRng).into());
+    let (mut state, note) = receive_delegation(&mut chain, &validator_identity_key, 100);
+
+    // A note received in one epoch was delegated at the rate of the next.
+    assert_eq!(
+        state.missing_delegation_rates(),
+        [(validator_identity_key.clone(), 1)].into_iter().collect()
+    );
+    let record = state.delegation_records().next().unwrap();
+    assert_eq!(record.note_commitment, note.commit());
+    assert_eq!(record.received_epoch, 1);
+    assert_eq!(record.received_exchange_rate, None);
+    assert_eq!(record.spent_epoch, None);
+
+    // A rate of another epoch doesn't apply to the note.
+    state.record_delegation_rate(&RateData {
+        identity_key: validator_identity_key.clone(),
+        epoch_index: 2,
+        validator_reward_rate: 0,
+        validator_exchange_rate: 3_0000_0000,
+    });
+    assert_eq!(
+        state
+            .delegation_records()
+            .next()
+            .unwrap()
+            .received_exchange_rate,
+        None
+    );
+
+    state.record_delegation_rate(&RateData {
+        identity_key: validator_identity_key,
+        epoch_index: 1,
+        validator_reward_rate: 0,
+        validator_exchange_rate: 2_0000_0000,
+    });
+    assert_eq!(
+        state
+            .delegation_records()
+            .next()
+            .unwrap()
+            .received_exchange_rate,
+        Some(2_0000_0000)
+    );
+    assert!(state.missing_delegation_rates().is_empty());
+}
+
+#[test]
+fn unavailable_delegation_rates_are_skipped_until_the_next_epoch() {
+    let mut chain = TestChain::new();
+    let validator_identity_key = IdentityKey(SigningKey::<SpendAuth>::new(OsRng).into());
+    let (mut state, _) = receive_delegation(&mut chain, &validator_identity_key, 100);
+
+    state.record_unavailable_delegation_rate(validator_identity_key.clone(), 1);
+    assert!(state.missing_delegation_rates().is_empty());
+
+    // The rate isn't asked for again within the same epoch...
+    for _ in 1..10 {
+        state.scan_block(chain.empty_block()).unwrap();
+    }
+    assert!(state.missing_delegation_rates().is_empty());
+
+    // ...but is once the client state has synced to the next one.
+    state.scan_block(chain.empty_block()).unwrap();
+    assert_eq!(
+        state.missing_delegation_rates(),
+        [(validator_identity_key.clone(), 1)].into_iter().collect()
+    );
+
+    state.record_delegation_rate(&RateData {
+        identity_key: validator_identity_key,
+        epoch_index: 1,
+        validator_reward_rate: 0,
+        validator_exchange_rate: 2_0000_0000,
+    });
+    assert!(state.unavailable_rates.is_empty());
+    assert!(state.missing_delegation_rates().is_empty());
+}
+
+#[test]
+fn notes_are_recorded_for_the_receiving_account() {
+    let mut state = client_state();
+    let mut chain = TestChain::new();
+    assert_eq!(state.new_account("savings".to_string()).unwrap(), 1);
+    let first = receive(
+        &mut state,
+        &mut chain,
+        &address(&state, 0, 0),
+        upenumbra(10),
+    );
+    let second = receive(
+        &mut state,
+        &mut chain,
+        &address(&state, 1, 0),
+        upenumbra(20),
+    );
+
+    assert_eq!(state.note_account(&first.commit()), 0);
+    assert_eq!(state.note_account(&second.commit()), 1);
+
+    // Each account's balance holds only the notes it received.
+    for (account, note) in [(0, &first), (1, &second)] {
+        let notes = state.unspent_notes_by_address_and_denom(account);
+        assert_eq!(notes.len(), 1);
+        let notes = &notes[&0][&*STAKING_TOKEN_DENOM];
+        assert_eq!(notes.len(), 1);
+        assert_eq!(notes[0].as_ref().commit(), note.commit());
+    }
+}
+
+#[test]
+fn build_send_spends_only_the_sending_accounts_notes() {
+    let mut state = client_state();
+    let mut chain = TestChain::new();
+    state.new_account("savings".to_string()).unwrap();
+    receive(
+        &mut state,
+        &mut chain,
+        &address(&state, 0, 0),
+        upenumbra(10),
+    );
+    let note = receive(
+        &mut state,
+        &mut chain,
+        &address(&state, 1, 0),
+        upenumbra(20),
+    );
+    let payments = [Payment {
+        address: other_address(),
+        values: vec![upenumbra(15)],
+        memo: None,
+    }];
+
+    // Account 0 can't cover the payment, even though the wallet as a whole can.
+    assert!(state
+        .build_send(&mut OsRng, 0, &payments, 0, NoteSource::Any)
+        .is_err());
+
+    state
+        .build_send(&mut OsRng, 1, &payments, 0, NoteSource::Any)
+        .unwrap();
+    assert!(state.submitted_spend_set.contains_key(&note.commit()));
+
+    // The change goes back to the sending account.
+    let (change, _) = state.submitted_change_set.iter().next().unwrap();
+    assert_eq!(state.note_account(change), 1);
+    assert!(
+        state.unspent_notes_by_address_and_denom(0)[&0][&*STAKING_TOKEN_DENOM]
+            .iter()
+            .all(|note| matches!(note, UnspentNote::Ready(_)))
+    );
+}
+
+#[test]
+fn new_account_notes_are_found_by_rescanning() {
+    let mut state = client_state();
+    let mut chain = TestChain::new();
+    let seed_phrase = state.seed_phrase.clone().unwrap();
+    let account_address = Wallet::from_seed_phrase(seed_phrase, 1)
+        .address_by_index(0)
+        .unwrap()
+        .1;
+
+    // A note sent to the account before the wallet created it isn't found...
+    let note = Note::generate(&mut OsRng, &account_address, upenumbra(20));
+    let blocks = vec![
+        chain.block(vec![fragment(&note, [1; 32])], Vec::new()),
+        chain.empty_block(),
+    ];
+    for block in &blocks {
+        state.scan_block(block.clone()).unwrap();
+    }
+    state.new_account("savings".to_string()).unwrap();
+    assert!(state.unspent_set.is_empty());
+
+    // ... until the wallet is rescanned.
+    state.rescan(None).unwrap();
+    for block in blocks {
+        state.scan_block(block).unwrap();
+    }
+    assert!(state.unspent_set.contains_key(&note.commit()));
+    assert_eq!(state.note_account(&note.commit()), 1);
+}
@@ -1,4 +1,4 @@
-use anyhow::Context;
+use anyhow::{anyhow, Context};
 use penumbra_crypto::{
     fmd,
     keys::{
@@ -10,57 +10,112 @@ use serde::{Deserialize, Serialize};
 
 /// The contents of the wallet file that share a spend authority.
 #[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(from = "serde_helpers::WalletHelper")]
+#[serde(try_from = "serde_helpers::WalletHelper")]
 #[serde(into = "serde_helpers::WalletHelper")]
 pub struct Wallet {
     /// A list of human-readable labels for addresses.
     ///
     /// The label at index `i` is used for the address with `DiversifierIndex(i)`.
     address_labels: Vec<String>,
-    spend_key: SpendKey,
+    /// The spend key, unless the wallet's secret key material is encrypted and has not been
+    /// unlocked.
+    spend_key: Option<SpendKey>,
+    full_viewing_key: FullViewingKey,
 }
 
 impl Wallet {
-    /// Create a new wallet.
-    pub fn from_seed_phrase(seed_phrase: SeedPhrase) -> Self {
-        // Currently we support a single spend authority per wallet. In the future,
-        // we can derive multiple spend seeds from a single seed phrase.
-        let spend_seed = SpendSeed::from_seed_phrase(seed_phrase, 0);
-        let spend_key = SpendKey::new(spend_seed);
+    /// Create a new wallet for the account with the given index.
+    ///
+    /// Each account derived from a seed phrase has its own spend authority, and so its own
+    /// viewing keys and addresses.
+    pub fn from_seed_phrase(seed_phrase: SeedPhrase, account: u64) -> Self {
+        let spend_seed = SpendSeed::from_seed_phrase(seed_phrase, account);
+        Self::import(spend_seed)
+    }
+
+    /// Imports a wallet from a legacy [`SpendSeed`].
+    pub fn import(spend_seed: SpendSeed) -> Self {
+        Self::from_parts(spend_seed, vec!["Default".to_string()])
+    }
 
+    /// Creates a watch-only wallet from a [`FullViewingKey`].
+    ///
+    /// A watch-only wallet can scan the chain, show balances and history and plan transactions,
+    /// but never holds the spend key needed to authorize them.
+    pub fn from_full_viewing_key(full_viewing_key: FullViewingKey) -> Self {
+        Self::locked(full_viewing_key, vec!["Default".to_string()])
+    }
+
+    /// Restores a wallet from its spend seed and address labels.
+    pub(crate) fn from_parts(spend_seed: SpendSeed, address_labels: Vec<String>) -> Self {
+        let spend_key = SpendKey::from(spend_seed);
         Self {
-            spend_key,
-            address_labels: vec!["Default".to_string()],
+            full_viewing_key: spend_key.full_viewing_key().clone(),
+            spend_key: Some(spend_key),
+            address_labels,
         }
     }
 
-    /// Imports a wallet from a legacy [`SpendSeed`].
-    pub fn import(spend_seed: SpendSeed) -> Self {
-        let spend_key = spend_seed.into();
+    /// Restores a wallet whose spend key is locked from its full viewing key and address labels.
+    ///
+    /// The wallet can be used to scan the chain, but not to authorize transactions until it is
+    /// unlocked with [`Wallet::unlock`].
+    pub(crate) fn locked(full_viewing_key: FullViewingKey, address_labels: Vec<String>) -> Self {
         Self {
-            spend_key,
-            address_labels: vec!["Default".to_string()],
+            spend_key: None,
+            full_viewing_key,
+            address_labels,
+        }
+    }
+
+    /// Unlocks the wallet with its spend seed, checking that it matches the wallet's viewing key.
+    pub(crate) fn unlock(&mut self, spend_seed: SpendSeed) -> Result<(), anyhow::Error> {
+        let spend_key = SpendKey::from(spend_seed);
+        if spend_key.full_viewing_key().to_bytes() != self.full_viewing_key.to_bytes() {
+            return Err(anyhow!(
+                "spend seed does not match the wallet's full viewing key"
+            ));
         }
+        self.spend_key = Some(spend_key);
+        Ok(())
+    }
+
+    /// Returns the labels of the wallet's addresses, in order of index.
+    pub(crate) fn address_labels(&self) -> &[String] {
+        &self.address_labels
     }
 
     /// Incoming viewing key from this spend seed.
     pub fn incoming_viewing_key(&self) -> &IncomingViewingKey {
-        self.spend_key.full_viewing_key().incoming()
+        self.full_viewing_key.incoming()
     }
 
     /// Outgoing viewing key from this spend seed.
     pub fn outgoing_viewing_key(&self) -> &OutgoingViewingKey {
-        self.spend_key.full_viewing_key().outgoing()
+        self.full_viewing_key.outgoing()
     }
 
-    /// Returns the wallet's spend seed.
-    pub fn spend_key(&self) -> &SpendKey {
-        &self.spend_key
+    /// Returns the wallet's spend key, if it holds it and it is unlocked.
+    pub fn spend_key(&self) -> Result<&SpendKey, anyhow::Error> {
+        self.spend_key.as_ref().ok_or_else(|| {
+            anyhow!(
+                "the wallet's spend key is unavailable: the wallet is watch-only, or encrypted \
+                and locked"
+            )
+        })
+    }
+
+    /// Returns whether the wallet's spend key is available to authorize transactions.
+    ///
+    /// This is false for watch-only wallets, and for encrypted wallets which have not been
+    /// unlocked.
+    pub fn is_unlocked(&self) -> bool {
+        self.spend_key.is_some()
     }
 
     /// Get the full viewing key for this wallet.
     pub fn full_viewing_key(&self) -> &FullViewingKey {
-        self.spend_key.full_viewing_key()
+        &self.full_viewing_key
     }
 
     /// Generate a new diversified `Address` and its corresponding `DetectionKey`.
@@ -120,24 +175,46 @@ mod serde_helpers {
     #[derive(Deserialize, Serialize)]
     pub struct WalletHelper {
         address_labels: Vec<String>,
-        #[serde_as(as = "serde_with::hex::Hex")]
-        spend_seed: [u8; 32],
+        #[serde_as(as = "Option<serde_with::hex::Hex>")]
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        spend_seed: Option<[u8; 32]>,
+        #[serde_as(as = "Option<serde_with::hex::Hex>")]
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        full_viewing_key: Option<Vec<u8>>,
     }
 
-    impl From<WalletHelper> for Wallet {
-        fn from(w: WalletHelper) -> Self {
-            Self {
-                address_labels: w.address_labels,
-                spend_key: SpendKey::from(SpendSeed(w.spend_seed)),
+    impl TryFrom<WalletHelper> for Wallet {
+        type Error = anyhow::Error;
+
+        fn try_from(w: WalletHelper) -> Result<Self, Self::Error> {
+            match (w.spend_seed, w.full_viewing_key) {
+                (Some(spend_seed), _) => {
+                    Ok(Self::from_parts(SpendSeed(spend_seed), w.address_labels))
+                }
+                (None, Some(full_viewing_key)) => Ok(Self::locked(
+                    full_viewing_key.as_slice().try_into()?,
+                    w.address_labels,
+                )),
+                (None, None) => Err(anyhow!(
+                    "wallet has neither a spend seed nor a full viewing key"
+                )),
             }
         }
     }
 
     impl From<Wallet> for WalletHelper {
         fn from(w: Wallet) -> Self {
-            Self {
-                address_labels: w.address_labels,
-                spend_seed: w.spend_key.seed().clone().0,
+            match w.spend_key {
+                Some(spend_key) => Self {
+                    address_labels: w.address_labels,
+                    spend_seed: Some(spend_key.seed().clone().0),
+                    full_viewing_key: None,
+                },
+                None => Self {
+                    address_labels: w.address_labels,
+                    spend_seed: None,
+                    full_viewing_key: Some(w.full_viewing_key.to_bytes().to_vec()),
+                },
             }
         }
     }